@@ -21,7 +21,7 @@ fn criterion_benchmark(c: &mut Criterion) {
     let mut scalr_rng = ChaChaRng::from_seed(seed);
 
     let mut rng = RandGen::new();
-    rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+    rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
     let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
     let cl = CL_HSMqk::with_rand_gen(
@@ -37,7 +37,7 @@ fn criterion_benchmark(c: &mut Criterion) {
     let mut msg: Vec<Scalar> = Vec::with_capacity(l);
 
     for _ in 0..l {
-        let tmp = Scalar::random(scalr_rng.clone());
+        let tmp = Scalar::random(&mut scalr_rng);
         msg.push(tmp);
     }
 
@@ -46,22 +46,33 @@ fn criterion_benchmark(c: &mut Criterion) {
     // SEBBS+
     group.bench_function("Benchmarking keygen SEBBS+ for 10 parties", |b| {
         b.iter(|| {
-            let _ = n_out_of_n::setbbsplus::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+            let _ = n_out_of_n::setbbsplus::KeyGen::keygen(
+                &cl,
+                b"keygen",
+                n,
+                l,
+                &mut rng,
+                &mut scalr_rng,
+            );
         })
     });
 
     // WMC24
     group.bench_function("Benchmarking keygen wmc24 for 10 parties", |b| {
         b.iter(|| {
-            let _ = n_out_of_n::wmc24::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+            let _ =
+                n_out_of_n::wmc24::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng);
         })
     });
 
-    let key_msg = n_out_of_n::setbbsplus::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+    let key_msg =
+        n_out_of_n::setbbsplus::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng)
+            .unwrap();
     group.bench_function("Benchmarking sign SEBBS+ for 10 parties", |b| {
         b.iter(|| {
             let _ = n_out_of_n::setbbsplus::Sign::sign(
                 &cl,
+                b"sign",
                 n,
                 l,
                 &mut rng,
@@ -73,29 +84,52 @@ fn criterion_benchmark(c: &mut Criterion) {
         })
     });
 
-    let key_msg = n_out_of_n::wmc24::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+    let key_msg =
+        n_out_of_n::wmc24::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng).unwrap();
     group.bench_function("Benchmarking sign wmc24 for 10 parties", |b| {
         b.iter(|| {
-            let _ =
-                n_out_of_n::wmc24::Sign::sign(&cl, n, l, &mut rng, &mut scalr_rng, &key_msg, &msg);
+            let _ = n_out_of_n::wmc24::Sign::sign(
+                &cl,
+                b"sign",
+                n,
+                l,
+                &mut rng,
+                &mut scalr_rng,
+                &key_msg,
+                &msg,
+            );
         })
     });
 
-    let key_msg = n_out_of_n::setbbsplus::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
-    let sign_msg =
-        n_out_of_n::setbbsplus::Sign::sign(&cl, n, l, &mut rng, &mut scalr_rng, &key_msg, &msg, &q);
+    let key_msg =
+        n_out_of_n::setbbsplus::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng)
+            .unwrap();
+    let sign_msg = n_out_of_n::setbbsplus::Sign::sign(
+        &cl,
+        b"sign",
+        n,
+        l,
+        &mut rng,
+        &mut scalr_rng,
+        &key_msg,
+        &msg,
+        &q,
+    )
+    .unwrap();
     group.bench_function("Benchmarking client SEBBS+ for 10 parties", |b| {
         b.iter(|| {
-            n_out_of_n::setbbsplus::Sign::client(&cl, &sign_msg, &msg, l);
+            n_out_of_n::setbbsplus::Sign::client(&cl, &sign_msg, &msg, l).unwrap();
         })
     });
 
-    let key_msg = n_out_of_n::wmc24::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+    let key_msg =
+        n_out_of_n::wmc24::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng).unwrap();
     let sign_msg =
-        n_out_of_n::wmc24::Sign::sign(&cl, n, l, &mut rng, &mut scalr_rng, &key_msg, &msg);
+        n_out_of_n::wmc24::Sign::sign(&cl, b"sign", n, l, &mut rng, &mut scalr_rng, &key_msg, &msg)
+            .unwrap();
     group.bench_function("Benchmarking client wmc24 for 10 parties", |b| {
         b.iter(|| {
-            n_out_of_n::wmc24::Sign::client(&cl, &sign_msg);
+            n_out_of_n::wmc24::Sign::client(&cl, b"sign", &sign_msg, &key_msg, &msg, l).unwrap();
         })
     });
 
@@ -104,22 +138,33 @@ fn criterion_benchmark(c: &mut Criterion) {
     // SEBBS+
     group.bench_function("Benchmarking keygen SEBBS+ for 20 parties", |b| {
         b.iter(|| {
-            let _ = n_out_of_n::setbbsplus::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+            let _ = n_out_of_n::setbbsplus::KeyGen::keygen(
+                &cl,
+                b"keygen",
+                n,
+                l,
+                &mut rng,
+                &mut scalr_rng,
+            );
         })
     });
 
     // WMC24
     group.bench_function("Benchmarking keygen wmc24 for 20 parties", |b| {
         b.iter(|| {
-            let _ = n_out_of_n::wmc24::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+            let _ =
+                n_out_of_n::wmc24::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng);
         })
     });
 
-    let key_msg = n_out_of_n::setbbsplus::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+    let key_msg =
+        n_out_of_n::setbbsplus::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng)
+            .unwrap();
     group.bench_function("Benchmarking sign SEBBS+ for 20 parties", |b| {
         b.iter(|| {
             let _ = n_out_of_n::setbbsplus::Sign::sign(
                 &cl,
+                b"sign",
                 n,
                 l,
                 &mut rng,
@@ -131,29 +176,52 @@ fn criterion_benchmark(c: &mut Criterion) {
         })
     });
 
-    let key_msg = n_out_of_n::wmc24::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+    let key_msg =
+        n_out_of_n::wmc24::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng).unwrap();
     group.bench_function("Benchmarking sign wmc24 for 20 parties", |b| {
         b.iter(|| {
-            let _ =
-                n_out_of_n::wmc24::Sign::sign(&cl, n, l, &mut rng, &mut scalr_rng, &key_msg, &msg);
+            let _ = n_out_of_n::wmc24::Sign::sign(
+                &cl,
+                b"sign",
+                n,
+                l,
+                &mut rng,
+                &mut scalr_rng,
+                &key_msg,
+                &msg,
+            );
         })
     });
 
-    let key_msg = n_out_of_n::setbbsplus::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
-    let sign_msg =
-        n_out_of_n::setbbsplus::Sign::sign(&cl, n, l, &mut rng, &mut scalr_rng, &key_msg, &msg, &q);
+    let key_msg =
+        n_out_of_n::setbbsplus::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng)
+            .unwrap();
+    let sign_msg = n_out_of_n::setbbsplus::Sign::sign(
+        &cl,
+        b"sign",
+        n,
+        l,
+        &mut rng,
+        &mut scalr_rng,
+        &key_msg,
+        &msg,
+        &q,
+    )
+    .unwrap();
     group.bench_function("Benchmarking client SEBBS+ for 20 parties", |b| {
         b.iter(|| {
-            n_out_of_n::setbbsplus::Sign::client(&cl, &sign_msg, &msg, l);
+            n_out_of_n::setbbsplus::Sign::client(&cl, &sign_msg, &msg, l).unwrap();
         })
     });
 
-    let key_msg = n_out_of_n::wmc24::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+    let key_msg =
+        n_out_of_n::wmc24::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng).unwrap();
     let sign_msg =
-        n_out_of_n::wmc24::Sign::sign(&cl, n, l, &mut rng, &mut scalr_rng, &key_msg, &msg);
+        n_out_of_n::wmc24::Sign::sign(&cl, b"sign", n, l, &mut rng, &mut scalr_rng, &key_msg, &msg)
+            .unwrap();
     group.bench_function("Benchmarking client wmc24 for 20 parties", |b| {
         b.iter(|| {
-            n_out_of_n::wmc24::Sign::client(&cl, &sign_msg);
+            n_out_of_n::wmc24::Sign::client(&cl, b"sign", &sign_msg, &key_msg, &msg, l).unwrap();
         })
     });
 }
@@ -20,7 +20,7 @@ fn criterion_benchmark(c: &mut Criterion) {
     let mut scalr_rng = ChaChaRng::from_seed(seed);
 
     let mut rng = RandGen::new();
-    rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+    rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
     let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
     let cl = CL_HSMqk::with_rand_gen(
@@ -36,66 +36,106 @@ fn criterion_benchmark(c: &mut Criterion) {
     let mut msg: Vec<Scalar> = Vec::with_capacity(l);
 
     for _ in 0..l {
-        let tmp = Scalar::random(scalr_rng.clone());
+        let tmp = Scalar::random(&mut scalr_rng);
         msg.push(tmp);
     }
 
     let n = 10;
 
-    let key_msg = n_out_of_n::setbbsplus::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
-    let sign_msg =
-        n_out_of_n::setbbsplus::Sign::sign(&cl, n, l, &mut rng, &mut scalr_rng, &key_msg, &msg, &q);
+    let key_msg =
+        n_out_of_n::setbbsplus::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng)
+            .unwrap();
+    let sign_msg = n_out_of_n::setbbsplus::Sign::sign(
+        &cl,
+        b"sign",
+        n,
+        l,
+        &mut rng,
+        &mut scalr_rng,
+        &key_msg,
+        &msg,
+        &q,
+    )
+    .unwrap();
     group.bench_function(
         "Benchmarking client-side of 10 out of 10 parties SET-BBS+",
         |b| {
             b.iter(|| {
-                n_out_of_n::setbbsplus::Sign::client(&cl, &sign_msg, &msg, l);
+                n_out_of_n::setbbsplus::Sign::client(&cl, &sign_msg, &msg, l).unwrap();
             })
         },
     );
 
-    let key_msg = n_out_of_n::wmc24::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+    let key_msg =
+        n_out_of_n::wmc24::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng).unwrap();
     let sign_msg =
-        n_out_of_n::wmc24::Sign::sign(&cl, n, l, &mut rng, &mut scalr_rng, &key_msg, &msg);
+        n_out_of_n::wmc24::Sign::sign(&cl, b"sign", n, l, &mut rng, &mut scalr_rng, &key_msg, &msg)
+            .unwrap();
     group.bench_function(
         "Benchmarking client-side of 10 out of 10 parties WMC24",
         |b| {
             b.iter(|| {
-                n_out_of_n::wmc24::Sign::client(&cl, &sign_msg);
+                n_out_of_n::wmc24::Sign::client(&cl, b"sign", &sign_msg, &key_msg, &msg, l)
+                    .unwrap();
             })
         },
     );
 
     let n = 15;
     let t = 10;
-
-    let key_msg = t_out_of_n::setbbsplus::KeyGen::keygen(&cl, n, t, l, &mut rng, &mut scalr_rng);
-    let sign_msg =
-        t_out_of_n::setbbsplus::Sign::sign(&cl, t, l, &mut rng, &mut scalr_rng, &key_msg, &msg, &q);
+    let signers: Vec<usize> = (1..=t).collect();
+
+    let key_msg =
+        t_out_of_n::setbbsplus::KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng)
+            .unwrap();
+    let sign_msg = t_out_of_n::setbbsplus::Sign::sign(
+        &cl,
+        b"sign",
+        &signers,
+        l,
+        &mut rng,
+        &mut scalr_rng,
+        &key_msg,
+        &msg,
+        &q,
+    )
+    .unwrap();
     group.bench_function(
         "Benchmarking client-side of 10 out of 15 parties SET-BBS+",
         |b| {
             b.iter(|| {
-                t_out_of_n::setbbsplus::Sign::client(&cl, &sign_msg, &msg, l);
+                t_out_of_n::setbbsplus::Sign::client(&cl, &sign_msg, &msg, l).unwrap();
             })
         },
     );
 
-    let key_msg = t_out_of_n::wmc24::KeyGen::keygen(&cl, n, t, l, &mut rng, &mut scalr_rng);
-    let sign_msg =
-        t_out_of_n::wmc24::Sign::sign(&cl, t, l, &mut rng, &mut scalr_rng, &key_msg, &msg);
+    let key_msg =
+        t_out_of_n::wmc24::KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng)
+            .unwrap();
+    let sign_msg = t_out_of_n::wmc24::Sign::sign(
+        &cl,
+        b"sign",
+        &signers,
+        l,
+        &mut rng,
+        &mut scalr_rng,
+        &key_msg,
+        &msg,
+    )
+    .unwrap();
     group.bench_function(
         "Benchmarking client-side of 10 out of 15 parties WMC24",
         |b| {
             b.iter(|| {
-                t_out_of_n::wmc24::Sign::client(&cl, &sign_msg);
+                t_out_of_n::wmc24::Sign::client(&cl, b"sign", &sign_msg, &key_msg, &msg, l)
+                    .unwrap();
             })
         },
     );
 
     // let n = 20;
 
-    // let key_msg = n_out_of_n::sebbsplus::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+    // let key_msg = n_out_of_n::sebbsplus::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng);
     // group.bench_function("Benchmarking 20 parties signing phase of SET-BBS+", |b| {
     //     b.iter(|| {
     //         let _ = n_out_of_n::sebbsplus::Sign::sign(
@@ -111,11 +151,11 @@ fn criterion_benchmark(c: &mut Criterion) {
     //     })
     // });
 
-    // let key_msg = n_out_of_n::wmc24::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+    // let key_msg = n_out_of_n::wmc24::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng);
     // group.bench_function("Benchmarking 20 parties signing phase of WMC24", |b| {
     //     b.iter(|| {
     //         let _ =
-    //             n_out_of_n::wmc24::Sign::sign(&cl, n, l, &mut rng, &mut scalr_rng, &key_msg, &msg);
+    //             n_out_of_n::wmc24::Sign::sign(&cl, b"sign", n, l, &mut rng, &mut scalr_rng, &key_msg, &msg);
     //     })
     // });
 }
@@ -20,7 +20,7 @@ fn criterion_benchmark(c: &mut Criterion) {
     let mut scalr_rng = ChaChaRng::from_seed(seed);
 
     let mut rng = RandGen::new();
-    rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+    rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
     let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
     let cl = CL_HSMqk::with_rand_gen(
@@ -36,7 +36,7 @@ fn criterion_benchmark(c: &mut Criterion) {
     let mut msg: Vec<Scalar> = Vec::with_capacity(l);
 
     for _ in 0..l {
-        let tmp = Scalar::random(scalr_rng.clone());
+        let tmp = Scalar::random(&mut scalr_rng);
         msg.push(tmp);
     }
 
@@ -46,7 +46,14 @@ fn criterion_benchmark(c: &mut Criterion) {
         "Benchmarking 10 out of 10 parties keygen phase of SET-BBS+",
         |b| {
             b.iter(|| {
-                let _ = n_out_of_n::setbbsplus::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+                let _ = n_out_of_n::setbbsplus::KeyGen::keygen(
+                    &cl,
+                    b"keygen",
+                    n,
+                    l,
+                    &mut rng,
+                    &mut scalr_rng,
+                );
             })
         },
     );
@@ -56,7 +63,14 @@ fn criterion_benchmark(c: &mut Criterion) {
         "Benchmarking 10 out of 10 parties keygen phase of WMC24",
         |b| {
             b.iter(|| {
-                let _ = n_out_of_n::wmc24::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+                let _ = n_out_of_n::wmc24::KeyGen::keygen(
+                    &cl,
+                    b"keygen",
+                    n,
+                    l,
+                    &mut rng,
+                    &mut scalr_rng,
+                );
             })
         },
     );
@@ -65,14 +79,14 @@ fn criterion_benchmark(c: &mut Criterion) {
     // // SET-BBS+
     // group.bench_function("Benchmarking 20 parties keygen phase of SET-BBS+", |b| {
     //     b.iter(|| {
-    //         let _ = n_out_of_n::sebbsplus::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+    //         let _ = n_out_of_n::sebbsplus::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng);
     //     })
     // });
 
     // // WMC24
     // group.bench_function("Benchmarking 20 parties keygen phase of WMC24", |b| {
     //     b.iter(|| {
-    //         let _ = n_out_of_n::wmc24::KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
+    //         let _ = n_out_of_n::wmc24::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng);
     //     })
     // });
 }
@@ -0,0 +1,88 @@
+use bicycl::{CL_HSMqk, Mpz, RandGen};
+use bls12_381::Scalar;
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use curv::arithmetic::Converter;
+use curv::BigInt;
+use ff::Field;
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use threshold_bbsp::MODULUS;
+use threshold_bbsp::*;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Threshold BBS+ batch signing");
+    group
+        .sample_size(10)
+        .sampling_mode(criterion::SamplingMode::Auto);
+
+    let seed: [u8; 32] = [0u8; 32];
+    let mut scalr_rng = ChaChaRng::from_seed(seed);
+
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+    let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
+    let cl = CL_HSMqk::with_rand_gen(
+        &q,
+        1,
+        1827,
+        &mut rng,
+        &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+        false,
+    );
+
+    let l = 10;
+    let n = 5;
+    let t = 3;
+    let k = 10;
+    let signers: Vec<usize> = (1..=t).collect();
+    let msgs: Vec<Vec<Scalar>> = (0..k)
+        .map(|_| (0..l).map(|_| Scalar::random(&mut scalr_rng)).collect())
+        .collect();
+
+    let key_msg =
+        t_out_of_n::setbbsplus::KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng)
+            .unwrap();
+    group.bench_function(
+        "Benchmarking 3 out of 5 parties signing 10 messages one by one",
+        |b| {
+            b.iter(|| {
+                for msg in &msgs {
+                    let _ = t_out_of_n::setbbsplus::Sign::sign(
+                        &cl,
+                        b"sign",
+                        &signers,
+                        l,
+                        &mut rng,
+                        &mut scalr_rng,
+                        &key_msg,
+                        msg,
+                        &q,
+                    );
+                }
+            })
+        },
+    );
+    group.bench_function(
+        "Benchmarking 3 out of 5 parties signing 10 messages in a batch",
+        |b| {
+            b.iter(|| {
+                let _ = t_out_of_n::setbbsplus::Sign::sign_batch(
+                    &cl,
+                    b"sign",
+                    &signers,
+                    l,
+                    &mut rng,
+                    &mut scalr_rng,
+                    &key_msg,
+                    &msgs,
+                    &q,
+                );
+            })
+        },
+    );
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
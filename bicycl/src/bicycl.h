@@ -142,6 +142,7 @@ namespace BICYCL
 
         /* misc */
         void neg ();
+        void wipe ();
         mp_limb_t extract_bits (size_t, size_t) const;
         int tstbit (size_t) const;
         void setbit (size_t);
@@ -591,6 +592,16 @@ Mpz::Mpz (mpz_srcptr v)
         mpz_clear (mpz_);
     }
 
+/* Overwrites all the allocated limbs with zeros, then sets the value to 0 */
+    inline
+    void Mpz::wipe ()
+    {
+        volatile mp_limb_t *limbs = PTR (mpz_);
+        for (int i = 0; i < ALLOC (mpz_); i++)
+            limbs[i] = 0;
+        SIZ (mpz_) = 0;
+    }
+
 /* */
     inline
     Mpz & Mpz::operator= (const Mpz &v)
@@ -4899,6 +4910,8 @@ namespace BICYCL
         const Mpz & cleartext_bound () const;
         /** Return the bound for random exponents: same as #secretkey_bound */
         const Mpz & encrypt_randomness_bound () const;
+        /** Return the folded uniform distribution factor */
+        const Mpz & fud_factor () const;
         /**@}*/
 
         /**
@@ -5371,6 +5384,13 @@ namespace BICYCL
         return exponent_bound_;
     }
 
+/* */
+    inline
+    const Mpz & CL_HSMqk::fud_factor () const
+    {
+        return fud_factor_;
+    }
+
 /**
  * \param[out] r the quadratic form corresponding to #gen to the power of \p e
  * \param[in] e the exponent
@@ -262,6 +262,35 @@ impl Mpz {
         BICYCL::Mpz::new8(&*cxx_vec, cxx_vec.len() * 8).within_box()
     }
 
+    /// -1, 0 or 1 according to the sign. Note that the `PartialOrd` impl
+    /// compares absolute values.
+    pub fn sgn(&self) -> i32 {
+        self.mpz.sgn().0
+    }
+
+    /// Bit length of the absolute value.
+    pub fn nbits(&self) -> usize {
+        self.mpz.nbits()
+    }
+
+    /// Overwrites the limbs with zeros, leaving the value 0. Limbs that GMP
+    /// released earlier, when the value was reallocated, are not reached.
+    pub fn wipe(&mut self) {
+        self.mpz.as_mut().wipe();
+    }
+
+    pub fn is_prime(&self) -> bool {
+        self.mpz.is_prime(c_int(30))
+    }
+
+    pub fn kronecker(&self, other: &Mpz) -> i32 {
+        self.mpz.kronecker(&*other.mpz).0
+    }
+
+    pub fn mod4(&self) -> u64 {
+        self.mpz.mod4().0 as u64
+    }
+
     pub fn pow(&self, exponent: u64) -> Self {
         let mut res = BICYCL::Mpz::new().within_box();
         BICYCL::Mpz::pow(res.as_mut(), &*self.mpz, c_ulong::from(exponent));
@@ -593,8 +622,107 @@ impl CL_HSMqk {
             mpz: BICYCL::Mpz::copy_from(self.c.Delta()).within_box(),
         }
     }
+
+    pub fn k(&self) -> usize {
+        self.c.k()
+    }
+
+    pub fn p(&self) -> Mpz {
+        Mpz {
+            mpz: BICYCL::Mpz::copy_from(self.c.p()).within_box(),
+        }
+    }
+
+    pub fn fud_factor(&self) -> Mpz {
+        Mpz {
+            mpz: BICYCL::Mpz::copy_from(self.c.fud_factor()).within_box(),
+        }
+    }
+
+    pub fn compact_variant(&self) -> bool {
+        self.c.compact_variant()
+    }
+
+    /// The parameters the group is built from, e.g. to publish a group
+    /// generated with [`CL_HSMqk::with_rand_gen`].
+    pub fn params(&self) -> CLParams {
+        CLParams {
+            q: self.q(),
+            k: self.k(),
+            p: self.p(),
+            fud_factor: self.fud_factor(),
+            compact_variant: self.compact_variant(),
+        }
+    }
+
+    /// Rebuilds the group from published parameters, which must be for the
+    /// message space `Z/qZ`. Everything else, the generator `h` included, is
+    /// derived deterministically from them.
+    pub fn from_params(params: &CLParams, q: &Mpz) -> Result<Self, InvalidCLParams> {
+        params.verify(q)?;
+        Ok(Self::new(&params.q, params.k, &params.p, &params.fud_factor, params.compact_variant))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CLParams {
+    pub q: Mpz,
+    pub k: usize,
+    pub p: Mpz,
+    pub fud_factor: Mpz,
+    pub compact_variant: bool,
+}
+
+/// Size of the fundamental discriminant `-p*q` at the 128-bit security level.
+pub const MIN_DELTA_K_NBITS: usize = 1827;
+
+impl CLParams {
+    /// Checks that `q` is the expected message space order and the conditions
+    /// the C++ constructor would otherwise throw on: `p` is a prime, `-p*q = 1
+    /// mod 4`, `(q/p) = -1`, and `k` and the fud factor are positive. On top of
+    /// that `p` must be larger than `q` and the discriminant must be at least
+    /// [`MIN_DELTA_K_NBITS`] long, so that the class number stays unknown.
+    pub fn verify(&self, q: &Mpz) -> Result<(), InvalidCLParams> {
+        if self.q != *q || !self.q.is_prime() {
+            return Err(InvalidCLParams("q must be the expected prime"));
+        }
+        if self.p.sgn() <= 0 || !self.p.is_prime() {
+            return Err(InvalidCLParams("p must be a prime"));
+        }
+        if self.p.nbits() <= self.q.nbits() {
+            return Err(InvalidCLParams("p must be larger than q"));
+        }
+        // Counted like BICYCL does when it generates p for a given size.
+        if self.p.nbits() + self.q.nbits() < MIN_DELTA_K_NBITS {
+            return Err(InvalidCLParams("the discriminant is too small"));
+        }
+        if (self.p.clone() * self.q.clone()).mod4() != 3 {
+            return Err(InvalidCLParams("-p*q mod 4 must be 1"));
+        }
+        if self.q.kronecker(&self.p) != -1 {
+            return Err(InvalidCLParams("the Kronecker symbol of q and p must be -1"));
+        }
+        if self.k == 0 {
+            return Err(InvalidCLParams("k must be positive"));
+        }
+        if self.fud_factor.sgn() <= 0 {
+            return Err(InvalidCLParams("the fud factor must be positive"));
+        }
+        Ok(())
+    }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidCLParams(pub &'static str);
+
+impl std::fmt::Display for InvalidCLParams {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid CL parameters: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidCLParams {}
+
 unsafe impl Send for CL_HSMqk {
 }
 
@@ -0,0 +1,212 @@
+//! A signer node for threshold SET-BBS+, and the coordinator driving the nodes.
+//!
+//! ```text
+//! threshold-bbsp-node params   --out FILE
+//! threshold-bbsp-node identity --params FILE --out FILE
+//! threshold-bbsp-node run      --id ID --listen ADDR --identity FILE --params FILE
+//!                              --peers FILE --coordinator KEY --key-share FILE
+//! threshold-bbsp-node keygen   --identity FILE --params FILE --peers FILE --t T --l L --out FILE
+//! threshold-bbsp-node sign     --identity FILE --params FILE --peers FILE --public-key FILE
+//!                              --signers 1,3,4 --msg 1,2,3
+//! ```
+//!
+//! `params` generates the class group all parties share. `identity` generates
+//! a long-term key and prints its public key. The peers file has a line
+//! `ID ADDR KEY` for every node, where `KEY` is a public key as printed by
+//! `identity`; the coordinator's public key is given to the nodes with
+//! `--coordinator`. `keygen` writes the public key of the new signing key to
+//! `--out`, and `sign` prints the signature on the messages, which are decimal
+//! scalars. Identities and key shares are encrypted under the passphrase in
+//! `THRESHOLD_BBSP_PASSPHRASE`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::sync::Arc;
+
+use bicycl::{CLParams, CL_HSMqk, Mpz, MIN_DELTA_K_NBITS};
+use bls12_381::{G1Affine, G1Projective, Scalar};
+use ff::PrimeField;
+use rand::rngs::OsRng;
+use tokio::net::TcpListener;
+
+use threshold_bbsp::t_out_of_n::BBSPlusPublicKey;
+use threshold_bbsp::{
+    load_key_share, modulus, rand_gen, report, save_key_share, Coordinator, Node, NodeConfig,
+    NodeKey, Peer, Wire,
+};
+
+const PASSPHRASE_VAR: &str = "THRESHOLD_BBSP_PASSPHRASE";
+
+const USAGE: &str =
+    "usage: threshold-bbsp-node <params|identity|run|keygen|sign> [--flag value]...";
+
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = run(&args).await {
+        eprintln!("error: {}", report(err.as_ref()));
+        std::process::exit(1);
+    }
+}
+
+async fn run(args: &[String]) -> CliResult<()> {
+    let (command, flags) = args.split_first().ok_or(USAGE)?;
+    let flags = Flags::parse(flags)?;
+    match command.as_str() {
+        "params" => {
+            let mut rng = rand_gen(&mut OsRng);
+            let cl = CL_HSMqk::with_rand_gen(
+                &modulus(),
+                1,
+                MIN_DELTA_K_NBITS,
+                &mut rng,
+                &Mpz::from(1u64 << 40),
+                false,
+            );
+            fs::write(flags.get("out")?, cl.params().to_wire_bytes())?;
+        }
+        "identity" => {
+            let cl = load_cl(flags.get("params")?)?;
+            let key = NodeKey::generate(&mut OsRng);
+            save_key_share(flags.get("out")?, &cl, &key, &passphrase()?)?;
+            println!(
+                "{}",
+                to_hex(&G1Affine::from(key.public_key()).to_compressed())
+            );
+        }
+        "run" => {
+            let cl = load_cl(flags.get("params")?)?;
+            let passphrase = passphrase()?;
+            let config = NodeConfig {
+                id: flags.get("id")?.parse()?,
+                key: load_key_share(flags.get("identity")?, &cl, &passphrase)?,
+                peers: read_peers(flags.get("peers")?)?,
+                coordinator: parse_public_key(flags.get("coordinator")?)?,
+                key_share_path: flags.get("key-share")?.into(),
+                passphrase,
+            };
+            let listener = TcpListener::bind(flags.get("listen")?).await?;
+            let node = Arc::new(Node::new(cl, config)?);
+            match node.public_key() {
+                Some(public_key) => {
+                    eprintln!("serving key {}", to_hex(&public_key.to_wire_bytes()))
+                }
+                None => eprintln!("waiting for a key generation session"),
+            }
+            node.serve(listener).await?;
+        }
+        "keygen" => {
+            let coordinator = coordinator(&flags)?;
+            let public_key = coordinator
+                .keygen(flags.get("t")?.parse()?, flags.get("l")?.parse()?)
+                .await?;
+            fs::write(flags.get("out")?, public_key.to_wire_bytes())?;
+        }
+        "sign" => {
+            let coordinator = coordinator(&flags)?;
+            let public_key = BBSPlusPublicKey::from_wire_bytes_without_group(&fs::read(
+                flags.get("public-key")?,
+            )?)?;
+            let signers = flags
+                .get("signers")?
+                .split(',')
+                .map(str::parse)
+                .collect::<Result<Vec<usize>, _>>()?;
+            let msg = flags
+                .get("msg")?
+                .split(',')
+                .map(|m| Scalar::from_str_vartime(m).ok_or("invalid message"))
+                .collect::<Result<Vec<_>, _>>()?;
+            let sig = coordinator.sign(&public_key, &signers, &msg).await?;
+            println!("{}", to_hex(&sig.to_wire_bytes()));
+        }
+        _ => return Err(USAGE.into()),
+    }
+    Ok(())
+}
+
+struct Flags(HashMap<String, String>);
+
+impl Flags {
+    fn parse(args: &[String]) -> CliResult<Self> {
+        let mut flags = HashMap::new();
+        for pair in args.chunks(2) {
+            match pair {
+                [name, value] if name.starts_with("--") => {
+                    flags.insert(name[2..].to_string(), value.clone());
+                }
+                _ => return Err(USAGE.into()),
+            }
+        }
+        Ok(Self(flags))
+    }
+
+    fn get(&self, name: &str) -> CliResult<&str> {
+        self.0
+            .get(name)
+            .map(String::as_str)
+            .ok_or_else(|| format!("missing --{name}").into())
+    }
+}
+
+fn passphrase() -> CliResult<Vec<u8>> {
+    std::env::var(PASSPHRASE_VAR)
+        .map(String::into_bytes)
+        .map_err(|_| format!("{PASSPHRASE_VAR} is not set").into())
+}
+
+fn load_cl(path: &str) -> CliResult<CL_HSMqk> {
+    let params = CLParams::from_wire_bytes_without_group(&fs::read(path)?)?;
+    Ok(CL_HSMqk::from_params(&params, &modulus())?)
+}
+
+fn coordinator(flags: &Flags) -> CliResult<Coordinator> {
+    let cl = load_cl(flags.get("params")?)?;
+    let key = load_key_share(flags.get("identity")?, &cl, &passphrase()?)?;
+    Ok(Coordinator::new(cl, key, read_peers(flags.get("peers")?)?))
+}
+
+fn read_peers(path: &str) -> CliResult<BTreeMap<usize, Peer>> {
+    let mut peers = BTreeMap::new();
+    for line in fs::read_to_string(path)?.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [id, addr, public_key] = fields[..] else {
+            return Err(format!("invalid peer {line:?}").into());
+        };
+        let peer = Peer {
+            addr: addr.parse()?,
+            public_key: parse_public_key(public_key)?,
+        };
+        peers.insert(id.parse()?, peer);
+    }
+    Ok(peers)
+}
+
+fn parse_public_key(hex: &str) -> CliResult<G1Projective> {
+    let bytes: [u8; 48] = from_hex(hex)?
+        .try_into()
+        .map_err(|_| "a public key is 48 bytes")?;
+    Option::<G1Affine>::from(G1Affine::from_compressed(&bytes))
+        .map(G1Projective::from)
+        .ok_or_else(|| "invalid public key".into())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> CliResult<Vec<u8>> {
+    if !hex.is_ascii() || hex.len() % 2 != 0 {
+        return Err("invalid hex string".into());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| Ok(u8::from_str_radix(&hex[i..i + 2], 16)?))
+        .collect()
+}
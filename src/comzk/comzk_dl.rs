@@ -1,12 +1,10 @@
 use std::ops::Neg;
 
-use bicycl::{CL_HSMqk, Mpz, QFI};
+use crate::{commit_G1, commit_G2, commit_QFI, ResponseBound, Transcript};
+use bicycl::{CL_HSMqk, Mpz, RandGen, QFI};
 use bls12_381::{G1Affine, G1Projective, G2Affine, G2Projective, Scalar};
 use ff::Field;
-use rand_chacha::ChaChaRng;
-use sha2::{Digest, Sha256, Sha512};
-
-use crate::{commit_G1, commit_G2, commit_QFI};
+use rand_core::CryptoRngCore;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ComZkDlComClproof {
@@ -30,13 +28,23 @@ pub struct ComZkDlComEgproof {
 }
 
 impl ComZkDlComClproof {
-    pub fn prove(pp: &CL_HSMqk, rng: &mut ChaChaRng, pow1: &QFI, secret_keyi: &Mpz) -> Self {
-        let u_1 = Scalar::random(rng);
-
-        let U1 = pp.power_of_h(&Mpz::from(&u_1));
-
-        let e = Self::challenge(pow1, &U1);
-        let z_1 = Mpz::from(&u_1) + e.clone() * secret_keyi;
+    /// Bit length of the challenge.
+    pub const CHALLENGE_BITS: usize = 256;
+
+    pub fn prove(
+        pp: &CL_HSMqk,
+        transcript: &Transcript,
+        bound: &ResponseBound,
+        rng: &mut RandGen,
+        pow1: &QFI,
+        secret_keyi: &Mpz,
+    ) -> Self {
+        let u_1 = bound.sample_mask(rng, Self::CHALLENGE_BITS);
+
+        let U1 = pp.power_of_h(&u_1);
+
+        let e = Self::challenge(transcript, pow1, &U1);
+        let z_1 = u_1 + e.clone() * secret_keyi;
 
         Self {
             com: commit_QFI(pow1),
@@ -45,33 +53,44 @@ impl ComZkDlComClproof {
         }
     }
 
-    pub fn verify(&self, pp: &CL_HSMqk, pow1: &QFI) -> bool {
+    pub fn verify(
+        &self,
+        pp: &CL_HSMqk,
+        transcript: &Transcript,
+        bound: &ResponseBound,
+        pow1: &QFI,
+    ) -> bool {
+        if !bound.check(&self.z_1, Self::CHALLENGE_BITS) {
+            return false;
+        }
         let U1 = pp
             .power_of_h(&self.z_1)
             .compose(&pp, &pow1.exp(&pp, &self.e.clone().neg()));
 
-        let e = Self::challenge(pow1, &U1);
+        let e = Self::challenge(transcript, pow1, &U1);
         e == self.e
     }
 
-    fn challenge(pow1: &QFI, U1: &QFI) -> Mpz {
-        let mut hasher = Sha256::new();
-
-        for item in &[pow1.to_bytes(), U1.to_bytes()] {
-            hasher.update(item);
-        }
-
-        Mpz::from_bytes(&hasher.finalize())
+    fn challenge(transcript: &Transcript, pow1: &QFI, U1: &QFI) -> Mpz {
+        let mut transcript = transcript.proof(b"ComZkDlComClproof");
+        transcript.append(b"pow1", pow1);
+        transcript.append(b"U1", U1);
+        transcript.challenge_mpz(b"e", 32)
     }
 }
 
 impl ComZkDlComElproof {
-    pub fn prove(rng: &mut ChaChaRng, pow1: &G2Affine, secret_keyi: &Scalar) -> Self {
-        let u_1 = Scalar::random(rng);
+    pub fn prove(
+        transcript: &Transcript,
+        rng: &mut impl CryptoRngCore,
+        pow1: &G2Affine,
+        secret_keyi: &Scalar,
+    ) -> Self {
+        let u_1 = Scalar::random(&mut *rng);
 
         let U1 = G2Projective::generator() * u_1;
 
-        let e = Self::challenge(pow1, &U1.into());
+        let e = Self::challenge(transcript, pow1, &U1);
         let z_1 = u_1 + e.clone() * secret_keyi;
 
         Self {
@@ -81,38 +100,35 @@ impl ComZkDlComElproof {
         }
     }
 
-    pub fn verify(&self, pow1: &G2Affine) -> bool {
+    pub fn verify(&self, transcript: &Transcript, pow1: &G2Affine) -> bool {
         let pow: G2Projective = pow1.into();
 
         let U1 = G2Projective::generator() * &self.z_1 - pow * &self.e;
 
-        let e = Self::challenge(pow1, &U1.into());
+        let e = Self::challenge(transcript, pow1, &U1);
         e == self.e
     }
 
-    fn challenge(pow1: &G2Affine, U1: &G2Affine) -> Scalar {
-        // println!("{:?}",U1);
-        let mut hasher = Sha512::new();
-
-        for item in &[pow1.to_compressed(), U1.to_compressed()] {
-            hasher.update(item);
-        }
-        let tmp = hasher.finalize()[0..64].to_vec();
-        let tmp_2: [u8; 64] = tmp.try_into().unwrap();
-        // Scalar::from_bytes(hasher.finalize()[0..32].try_into().unwrap()).unwrap()
-        //Scalar::from_bytes(&tmp_2).unwrap()
-        Scalar::from_bytes_wide(&tmp_2)
-        //(&hasher.finalize())
+    fn challenge(transcript: &Transcript, pow1: &G2Affine, U1: &G2Projective) -> Scalar {
+        let mut transcript = transcript.proof(b"ComZkDlComElproof");
+        transcript.append(b"pow1", pow1);
+        transcript.append(b"U1", U1);
+        transcript.challenge_scalar(b"e")
     }
 }
 
 impl ComZkDlComEgproof {
-    pub fn prove(rng: &mut ChaChaRng, pow1: &G1Affine, secret_keyi: &Scalar) -> Self {
-        let u_1 = Scalar::random(rng);
+    pub fn prove(
+        transcript: &Transcript,
+        rng: &mut impl CryptoRngCore,
+        pow1: &G1Affine,
+        secret_keyi: &Scalar,
+    ) -> Self {
+        let u_1 = Scalar::random(&mut *rng);
 
         let U1 = G1Projective::generator() * u_1;
 
-        let e = Self::challenge(pow1, &U1.into());
+        let e = Self::challenge(transcript, pow1, &U1);
         let z_1 = u_1 + e.clone() * secret_keyi;
 
         Self {
@@ -122,28 +138,20 @@ impl ComZkDlComEgproof {
         }
     }
 
-    pub fn verify(&self, pow1: &G1Affine) -> bool {
+    pub fn verify(&self, transcript: &Transcript, pow1: &G1Affine) -> bool {
         let pow: G1Projective = pow1.into();
 
         let U1 = G1Projective::generator() * &self.z_1 - pow * &self.e;
 
-        let e = Self::challenge(pow1, &U1.into());
+        let e = Self::challenge(transcript, pow1, &U1);
         e == self.e
     }
 
-    fn challenge(pow1: &G1Affine, U1: &G1Affine) -> Scalar {
-        // println!("{:?}",U1);
-        let mut hasher = Sha512::new();
-
-        for item in &[pow1.to_compressed(), U1.to_compressed()] {
-            hasher.update(item);
-        }
-        let tmp = hasher.finalize()[0..64].to_vec();
-        let tmp_2: [u8; 64] = tmp.try_into().unwrap();
-        // Scalar::from_bytes(hasher.finalize()[0..32].try_into().unwrap()).unwrap()
-        //Scalar::from_bytes(&tmp_2).unwrap()
-        Scalar::from_bytes_wide(&tmp_2)
-        //(&hasher.finalize())
+    fn challenge(transcript: &Transcript, pow1: &G1Affine, U1: &G1Projective) -> Scalar {
+        let mut transcript = transcript.proof(b"ComZkDlComEgproof");
+        transcript.append(b"pow1", pow1);
+        transcript.append(b"U1", U1);
+        transcript.challenge_scalar(b"e")
     }
 }
 
@@ -152,6 +160,7 @@ mod tests {
     use bicycl::{CL_HSMqk, RandGen};
     use curv::{arithmetic::Converter, BigInt};
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use crate::MODULUS;
 
@@ -160,10 +169,10 @@ mod tests {
     #[test]
     fn test_clcom_nizk() {
         let seed = [0u8; 32];
-        let scalr_rng = ChaChaRng::from_seed(seed);
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -182,24 +191,39 @@ mod tests {
 
         let pow1 = pk_i.clone();
 
-        let proof = ComZkDlComClproof::prove(&cl, &mut scalr_rng.clone(), &pow1, &x_i);
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+        let transcript = Transcript::new(b"test_clcom_nizk", 1);
+        let proof = ComZkDlComClproof::prove(&cl, &transcript, &bound, &mut rng, &pow1, &x_i);
 
-        assert_eq!(true, proof.verify(&cl, &pow1))
+        assert_eq!(true, proof.verify(&cl, &transcript, &bound, &pow1));
+        assert_eq!(
+            false,
+            proof.verify(&cl, &Transcript::new(b"test_clcom_nizk", 2), &bound, &pow1)
+        );
+        assert_eq!(
+            false,
+            proof.verify(&cl, &transcript, &ResponseBound::scalar(), &pow1)
+        )
     }
 
     #[test]
     fn test_clcom_elnizk() {
         let seed = [0u8; 32];
-        let scalr_rng = ChaChaRng::from_seed(seed);
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
 
-        let x_i = Scalar::random(scalr_rng.clone());
+        let x_i = Scalar::random(&mut scalr_rng);
 
         let pk_i = G2Projective::generator() * x_i;
 
         //let pow1 = round2_msg.xk_ciphertexts.get(&1).unwrap().get(&1).unwrap();
 
-        let proof = ComZkDlComElproof::prove(&mut scalr_rng.clone(), &pk_i.into(), &x_i);
+        let transcript = Transcript::new(b"test_clcom_elnizk", 1);
+        let proof = ComZkDlComElproof::prove(&transcript, &mut scalr_rng, &pk_i.into(), &x_i);
 
-        assert_eq!(true, proof.verify(&pk_i.into()))
+        assert_eq!(true, proof.verify(&transcript, &pk_i.into()));
+        assert_eq!(
+            false,
+            proof.verify(&Transcript::new(b"test_clcom_elnizk", 2), &pk_i.into())
+        )
     }
 }
@@ -1,16 +1,10 @@
-use std::{
-    collections::BTreeMap,
-    ops::{Div, Mul},
-};
+use std::ops::{Div, Mul};
 
+use crate::{commit_G2, commit_QFI, ProofError, Transcript, LAMBDA};
 use bicycl::{CL_HSMqk, Mpz, RandGen, QFI};
 use bls12_381::Scalar;
-use cgenprime::cgenprime::genprime;
 use ff::{Field, PrimeField};
-use rand_chacha::ChaChaRng;
-use sha2::{Digest, Sha256};
-
-use crate::{commit_G2, commit_QFI, LAMBDA};
+use rand_core::CryptoRngCore;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ComZkDlYuanComClproof {
@@ -19,32 +13,24 @@ pub struct ComZkDlYuanComClproof {
     pub K: QFI,
     pub e_: Mpz,
     pub K_: QFI,
-    pub map: BTreeMap<Vec<u8>, Mpz>,
 }
 
 impl ComZkDlYuanComClproof {
     pub fn prove(
         pp: &CL_HSMqk,
+        transcript: &Transcript,
         rng: &mut RandGen,
-        chacharng: &mut ChaChaRng,
+        csprng: &mut impl CryptoRngCore,
         pow1: &QFI,
         secret_keyi: &Mpz,
         q: &Mpz,
     ) -> Self {
         let k = secret_keyi.clone().div(q);
         let e = secret_keyi - &k.clone().mul(q);
-        let mut map = BTreeMap::new();
 
         let K = pp.power_of_h(&k);
 
-        let p_bits: u32 = LAMBDA;
-        let p_min_bits: u32 = LAMBDA - 1;
-        let k: u32 = 1;
-        let tmp_seed: u32 = 3423;
-        let cq_: gmp::mpz::Mpz = genprime(p_bits, p_min_bits, k, tmp_seed);
-        let q_ = Mpz::from(cq_.to_string().as_str());
-
-        map.insert(Self::challenge(pow1, &K, &e), q_.clone());
+        let q_ = Self::challenge_prime(transcript, pow1, &K, &e);
 
         let k_ = secret_keyi.clone().div(q_.clone());
         let e_ = secret_keyi - &k_.clone().mul(q_);
@@ -56,32 +42,37 @@ impl ComZkDlYuanComClproof {
             K: K,
             e_: e_,
             K_: K_,
-            map: map,
         }
     }
 
-    pub fn verify(&self, pp: &CL_HSMqk, pow1: &QFI, q: &Mpz) {
+    pub fn verify(
+        &self,
+        pp: &CL_HSMqk,
+        transcript: &Transcript,
+        pow1: &QFI,
+        q: &Mpz,
+    ) -> Result<(), ProofError> {
         let KH = self.K.exp(&pp, &q).compose(&pp, &pp.power_of_h(&self.e));
 
-        assert_eq!(pow1, &KH);
-
-        if let Some(q_) = self.map.get(&Self::challenge(pow1, &self.K, &self.e)) {
-            let KH_ = self.K_.exp(&pp, &q_).compose(&pp, &pp.power_of_h(&self.e_));
-            assert_eq!(pow1, &KH_);
-        } else {
-            panic!("No q_");
+        if pow1 != &KH {
+            return Err(ProofError::Equation("q"));
         }
-    }
-
-    fn challenge(pow1: &QFI, U1: &QFI, e: &Mpz) -> Vec<u8> {
-        let mut hasher = Sha256::new();
 
-        for item in &[pow1.to_bytes(), U1.to_bytes(), e.to_bytes()] {
-            hasher.update(item);
+        let q_ = Self::challenge_prime(transcript, pow1, &self.K, &self.e);
+        let KH_ = self.K_.exp(&pp, &q_).compose(&pp, &pp.power_of_h(&self.e_));
+        if pow1 != &KH_ {
+            return Err(ProofError::Equation("q'"));
         }
+        Ok(())
+    }
 
-        // Mpz::from_bytes(&hasher.finalize()[..16])
-        hasher.finalize()[..16].to_vec()
+    /// The prime `q'` of the second decomposition, hashed from the first one.
+    fn challenge_prime(transcript: &Transcript, pow1: &QFI, U1: &QFI, e: &Mpz) -> Mpz {
+        let mut transcript = transcript.proof(b"ComZkDlYuanComClproof");
+        transcript.append(b"pow1", pow1);
+        transcript.append(b"K", U1);
+        transcript.append(b"e", e);
+        transcript.challenge_prime(b"q'", LAMBDA as usize / 8)
     }
 }
 
@@ -90,6 +81,7 @@ mod tests {
     use bicycl::{CL_HSMqk, RandGen};
     use curv::{arithmetic::Converter, BigInt};
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use crate::MODULUS;
 
@@ -98,10 +90,10 @@ mod tests {
     #[test]
     fn test_clcom_nizk() {
         let seed = [0u8; 32];
-        let scalr_rng = ChaChaRng::from_seed(seed);
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
         let cl = CL_HSMqk::with_rand_gen(
@@ -121,15 +113,21 @@ mod tests {
 
         let pow1 = pk_i.clone();
 
+        let transcript = Transcript::new(b"test_clcom_yuan_nizk", 1);
         let proof = ComZkDlYuanComClproof::prove(
             &cl,
-            &mut rng.clone(),
-            &mut scalr_rng.clone(),
+            &transcript,
+            &mut rng,
+            &mut scalr_rng,
             &pow1,
             &x_i,
             &q,
         );
 
-        proof.verify(&cl, &pow1, &q);
+        assert_eq!(Ok(()), proof.verify(&cl, &transcript, &pow1, &q));
+        assert_eq!(
+            Err(ProofError::Equation("q'")),
+            proof.verify(&cl, &Transcript::new(b"test_clcom_yuan_nizk", 2), &pow1, &q)
+        );
     }
 }
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+/// Errors returned by the key generation and signing protocols. A variant caused
+/// by misbehaving parties names their ids, so that the honest parties can
+/// exclude them and run the protocol again.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum ProtocolError {
+    #[error("invalid {proof} from parties {culprits:?}")]
+    InvalidProof {
+        proof: &'static str,
+        culprits: Vec<usize>,
+    },
+    #[error("parties {0:?} opened their commitments incorrectly")]
+    InvalidCommitment(Vec<usize>),
+    #[error("parties {0:?} dealt inconsistent secret shares")]
+    InvalidShare(Vec<usize>),
+    #[error("parties {0:?} sent malformed messages")]
+    MalformedMessage(Vec<usize>),
+    #[error("parties {0:?} disagree with the others on the signing randomness")]
+    Inconsistent(Vec<usize>),
+    #[error("no message from parties {0:?}")]
+    MissingMessage(Vec<usize>),
+    #[error("{0:?} is not a valid signing set")]
+    InvalidSignerSet(Vec<usize>),
+    #[error("the combined signature is degenerate")]
+    DegenerateSignature,
+    #[error("invalid signature")]
+    InvalidSignature,
+    #[error("invalid blind signing request")]
+    InvalidRequest,
+    #[error("the attributes do not match the schema")]
+    InvalidAttributes,
+}
+
+impl ProtocolError {
+    /// The ids of the parties responsible for the error, if it was caused by
+    /// someone misbehaving.
+    pub fn culprits(&self) -> &[usize] {
+        match self {
+            Self::InvalidProof { culprits, .. } => culprits,
+            Self::InvalidCommitment(culprits)
+            | Self::InvalidShare(culprits)
+            | Self::MalformedMessage(culprits)
+            | Self::Inconsistent(culprits)
+            | Self::MissingMessage(culprits) => culprits,
+            Self::InvalidSignerSet(_)
+            | Self::DegenerateSignature
+            | Self::InvalidSignature
+            | Self::InvalidRequest
+            | Self::InvalidAttributes => &[],
+        }
+    }
+}
+
+/// Fails with `err` if any party was found misbehaving.
+pub(crate) fn blame(
+    culprits: Vec<usize>,
+    err: impl FnOnce(Vec<usize>) -> ProtocolError,
+) -> Result<(), ProtocolError> {
+    if culprits.is_empty() {
+        Ok(())
+    } else {
+        Err(err(culprits))
+    }
+}
+
+/// Fails with [`ProtocolError::InvalidProof`] naming the parties whose `proof`
+/// did not verify.
+pub(crate) fn blame_proof(culprits: Vec<usize>, proof: &'static str) -> Result<(), ProtocolError> {
+    blame(culprits, |culprits| ProtocolError::InvalidProof {
+        proof,
+        culprits,
+    })
+}
+
+/// Fails unless `msgs` holds a message from each of `parties` and from no one
+/// else.
+pub(crate) fn check_parties<M>(
+    parties: &[usize],
+    msgs: &BTreeMap<usize, M>,
+) -> Result<(), ProtocolError> {
+    let strangers = msgs
+        .keys()
+        .filter(|j| !parties.contains(j))
+        .copied()
+        .collect();
+    blame(strangers, ProtocolError::MalformedMessage)?;
+    let missing = parties
+        .iter()
+        .filter(|j| !msgs.contains_key(j))
+        .copied()
+        .collect();
+    blame(missing, ProtocolError::MissingMessage)
+}
+
+/// Fails with [`ProtocolError::InvalidProof`] naming the parties `ids[k]` for
+/// the indices `k` at which a batch verification of `proof` failed.
+pub(crate) fn blame_batch(
+    result: Result<(), Vec<usize>>,
+    ids: &[usize],
+    proof: &'static str,
+) -> Result<(), ProtocolError> {
+    result.or_else(|failed| blame_proof(failed.into_iter().map(|k| ids[k]).collect(), proof))
+}
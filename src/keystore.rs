@@ -0,0 +1,231 @@
+//! Encrypted storage of a party's key share, so that signers survive restarts.
+//!
+//! A key share file is
+//!
+//! ```text
+//! magic "TBBSKEY" | version u8 | salt [16] | nonce [24] | ciphertext
+//! ```
+//!
+//! where the ciphertext is the XChaCha20-Poly1305 encryption, under a key derived
+//! from the passphrase and salt with Argon2id (default parameters), of the wire
+//! encoding of the discriminant of the CL group followed by the key share. The
+//! header is authenticated as associated data. The key share carries the party
+//! id, the threshold parameters, the public keys and the party's secret shares;
+//! the discriminant pins the class group it was generated in. On Unix the file
+//! is created with mode 0600.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use argon2::Argon2;
+use bicycl::{CL_HSMqk, Mpz};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::{Reader, Wire, WireError};
+
+pub const KEY_STORE_MAGIC: &[u8; 7] = b"TBBSKEY";
+pub const KEY_STORE_VERSION: u8 = 1;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = KEY_STORE_MAGIC.len() + 1 + SALT_LEN + NONCE_LEN;
+
+#[derive(Debug, Error)]
+pub enum KeyStoreError {
+    #[error("cannot access the key share file")]
+    Io(#[from] io::Error),
+    #[error("not a key share file, or of an unsupported version")]
+    Format,
+    #[error("cannot derive the key from the passphrase: {0}")]
+    KeyDerivation(argon2::Error),
+    #[error("wrong passphrase, or the file was tampered with")]
+    Decryption,
+    #[error("the key share belongs to another class group")]
+    GroupMismatch,
+    #[error("malformed key share")]
+    Wire(#[from] WireError),
+}
+
+fn derive_key(passphrase: &[u8], salt: &[u8]) -> Result<XChaCha20Poly1305, KeyStoreError> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::default()
+        .hash_password_into(passphrase, salt, &mut *key)
+        .map_err(KeyStoreError::KeyDerivation)?;
+    Ok(XChaCha20Poly1305::new(Key::from_slice(&*key)))
+}
+
+/// Encrypts `key_share`, generated in `cl`, under `passphrase`.
+pub fn seal_key_share<K: Wire>(
+    cl: &CL_HSMqk,
+    key_share: &K,
+    passphrase: &[u8],
+) -> Result<Vec<u8>, KeyStoreError> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(KEY_STORE_MAGIC);
+    header.push(KEY_STORE_VERSION);
+    let mut salt_nonce = [0u8; SALT_LEN + NONCE_LEN];
+    OsRng.fill_bytes(&mut salt_nonce);
+    header.extend_from_slice(&salt_nonce);
+
+    let mut plaintext = Zeroizing::new(Vec::new());
+    cl.discriminant().encode(&mut plaintext);
+    key_share.encode(&mut plaintext);
+
+    let (salt, nonce) = salt_nonce.split_at(SALT_LEN);
+    let ciphertext = derive_key(passphrase, salt)?
+        .encrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: &plaintext,
+                aad: &header,
+            },
+        )
+        .map_err(|_| KeyStoreError::Decryption)?;
+
+    header.extend_from_slice(&ciphertext);
+    Ok(header)
+}
+
+/// Decrypts a key share sealed with [`seal_key_share`], checking that it was
+/// generated in `cl`.
+pub fn open_key_share<K: Wire>(
+    cl: &CL_HSMqk,
+    sealed: &[u8],
+    passphrase: &[u8],
+) -> Result<K, KeyStoreError> {
+    if sealed.len() < HEADER_LEN
+        || &sealed[..KEY_STORE_MAGIC.len()] != KEY_STORE_MAGIC
+        || sealed[KEY_STORE_MAGIC.len()] != KEY_STORE_VERSION
+    {
+        return Err(KeyStoreError::Format);
+    }
+    let (header, ciphertext) = sealed.split_at(HEADER_LEN);
+    let (salt, nonce) = header[KEY_STORE_MAGIC.len() + 1..].split_at(SALT_LEN);
+
+    let plaintext = Zeroizing::new(
+        derive_key(passphrase, salt)?
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: header,
+                },
+            )
+            .map_err(|_| KeyStoreError::Decryption)?,
+    );
+
+    let mut r = Reader::new(&plaintext, Some(cl));
+    if Mpz::decode(&mut r)? != cl.discriminant() {
+        return Err(KeyStoreError::GroupMismatch);
+    }
+    let key_share = K::decode(&mut r)?;
+    r.finish()?;
+    Ok(key_share)
+}
+
+/// Writes the sealed `key_share` to `path`. The file is replaced atomically, so
+/// a crash never leaves a truncated key share behind.
+pub fn save_key_share<K: Wire>(
+    path: impl AsRef<Path>,
+    cl: &CL_HSMqk,
+    key_share: &K,
+    passphrase: &[u8],
+) -> Result<(), KeyStoreError> {
+    let path = path.as_ref();
+    let sealed = seal_key_share(cl, key_share, passphrase)?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    // A leftover from an interrupted save may have other permissions.
+    match fs::remove_file(&tmp) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(&tmp)?;
+    file.write_all(&sealed)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+pub fn load_key_share<K: Wire>(
+    path: impl AsRef<Path>,
+    cl: &CL_HSMqk,
+    passphrase: &[u8],
+) -> Result<K, KeyStoreError> {
+    open_key_share(cl, &fs::read(path)?, passphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use bicycl::RandGen;
+    use bls12_381::Scalar;
+    use curv::{arithmetic::Converter, BigInt};
+    use ff::Field;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    use crate::t_out_of_n::setbbsplus::KeyGen;
+    use crate::t_out_of_n::KeyShare;
+    use crate::MODULUS;
+
+    use super::*;
+
+    #[test]
+    fn test_key_store() {
+        let mut csprng = ChaChaRng::from_seed([0u8; 32]);
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut csprng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let key_msg = KeyGen::keygen(&cl, b"keygen", 3, 2, 4, &mut rng, &mut csprng).unwrap();
+        let key_share = key_msg.key_share(3);
+
+        let path = std::env::temp_dir().join(format!("key_share_{}", std::process::id()));
+        save_key_share(&path, &cl, &key_share, b"correct horse").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let loaded: KeyShare = load_key_share(&path, &cl, b"correct horse").unwrap();
+        assert_eq!(loaded.to_wire_bytes(), key_share.to_wire_bytes());
+
+        assert!(matches!(
+            load_key_share::<KeyShare>(&path, &cl, b"battery staple"),
+            Err(KeyStoreError::Decryption)
+        ));
+
+        let mut sealed = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(matches!(
+            open_key_share::<KeyShare>(&cl, &sealed, b"correct horse"),
+            Err(KeyStoreError::Decryption)
+        ));
+        assert!(matches!(
+            open_key_share::<KeyShare>(&cl, &sealed[..10], b"correct horse"),
+            Err(KeyStoreError::Format)
+        ));
+    }
+}
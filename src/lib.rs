@@ -4,7 +4,9 @@
 pub mod utils;
 
 pub mod n_out_of_n;
+use bicycl::Mpz;
 use bls12_381::G1Affine;
+use curv::{arithmetic::Converter, BigInt};
 pub use n_out_of_n::*;
 
 pub mod t_out_of_n;
@@ -19,8 +21,28 @@ pub use util::*;
 pub mod zk;
 pub use zk::*;
 
+pub mod error;
+pub use error::*;
+
+pub mod wire;
+pub use wire::*;
+
+pub mod keystore;
+pub use keystore::*;
+
+pub mod net;
+pub use net::*;
+
+pub mod sim;
+pub use sim::*;
+
 pub const MODULUS: &str = "73eda753299d7d483339d80809a1d80553bda402fffe5bfeffffffff00000001";
 
+/// [`MODULUS`] as an [`Mpz`], the `q` the CL groups must be built over.
+pub fn modulus() -> Mpz {
+    Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes())
+}
+
 pub const LAMBDA: u32 = 128;
 
 #[derive(Clone, Debug, PartialEq)]
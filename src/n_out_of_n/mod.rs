@@ -1,5 +1,8 @@
 pub mod setbbsplus;
 pub use setbbsplus::*;
 
+pub mod setbbs;
+pub use setbbs::*;
+
 pub mod wmc24;
 pub use wmc24::*;
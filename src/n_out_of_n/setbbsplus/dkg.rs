@@ -0,0 +1,496 @@
+use bicycl::QFI;
+use bls12_381::G1Affine;
+use futures::SinkExt;
+use round_based::rounds_router::simple_store::{RoundInput, RoundInputError};
+use round_based::rounds_router::{CompleteRoundError, RoundsRouter};
+use round_based::{Delivery, Mpc, MpcParty, Outgoing, PartyIndex, ProtocolMessage};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::error::{blame, blame_batch, blame_proof};
+use crate::{ComZkDlComClproof, ProtocolError, ResponseBound, Transcript};
+
+use super::*;
+
+/// Round 1 broadcast: a commitment to everything the party reveals in round 2,
+/// so that nobody can choose its contribution after seeing the others'.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DkgMsg1 {
+    pub com: Vec<u8>,
+}
+
+/// Round 2 broadcast: the party's CL public key share, BBS+ public key share and
+/// `H` contributions, with proofs of knowledge of the discrete logarithms.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DkgMsg2 {
+    pub cl_pk_i: QFI,
+    pub cl_proof: ComZkDlComClproof,
+    pub X_i: G2Projective,
+    pub x_proof: ComZkDlComElproof,
+    pub H_i: Vec<G1Projective>,
+}
+
+/// Round 3 broadcast: `x_i` encrypted under the joint CL public key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DkgMsg3 {
+    pub x_ciphertext: CipherText,
+    pub proof: CLEncProof,
+}
+
+#[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+pub enum DkgMsg {
+    Round1(DkgMsg1),
+    Round2(DkgMsg2),
+    Round3(DkgMsg3),
+}
+
+pub struct KeyGenParty<'a> {
+    cl: &'a CL_HSMqk,
+    session_id: &'a [u8],
+    i: usize,
+    l: usize,
+}
+
+pub struct KeyGenPartyRound1<'a> {
+    cl: &'a CL_HSMqk,
+    session_id: &'a [u8],
+    i: usize,
+    l: usize,
+    d_i: Mpz,
+    x_i: Scalar,
+    my_msg2: DkgMsg2,
+}
+
+pub struct KeyGenPartyRound2<'a> {
+    cl: &'a CL_HSMqk,
+    session_id: &'a [u8],
+    i: usize,
+    l: usize,
+    d_i: Mpz,
+    x_i: Scalar,
+    coms: BTreeMap<usize, Vec<u8>>,
+}
+
+pub struct KeyGenPartyRound3<'a> {
+    cl: &'a CL_HSMqk,
+    session_id: &'a [u8],
+    i: usize,
+    d_i: Mpz,
+    x_i: Scalar,
+    cl_pub_key: PublicKey,
+    X: G2Projective,
+    H: Vec<G1Projective>,
+    pub_shares: BTreeMap<usize, G2Projective>,
+}
+
+impl DkgMsg2 {
+    pub fn commit(&self, i: usize) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update((i as u64).to_be_bytes());
+        hasher.update(self.cl_pk_i.to_bytes());
+        hasher.update(G2Affine::from(self.X_i).to_compressed());
+        for H_ij in self.H_i.iter() {
+            hasher.update(G1Affine::from(H_ij).to_compressed());
+        }
+        hasher.finalize().to_vec()
+    }
+}
+
+impl<'a> KeyGenParty<'a> {
+    /// `session_id` must be unique to this run of the protocol and the same for
+    /// every party.
+    pub fn new(cl: &'a CL_HSMqk, session_id: &'a [u8], i: usize, l: usize) -> Self {
+        Self {
+            cl,
+            session_id,
+            i,
+            l,
+        }
+    }
+
+    pub fn round1(
+        self,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+    ) -> (KeyGenPartyRound1<'a>, DkgMsg1) {
+        let cl = self.cl;
+        let transcript = Transcript::new(self.session_id, self.i);
+
+        let d_i = rng.random_mpz(&cl.encrypt_randomness_bound());
+        let cl_pk_i = cl.power_of_h(&d_i);
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+        let cl_proof = ComZkDlComClproof::prove(&cl, &transcript, &bound, rng, &cl_pk_i, &d_i);
+
+        let x_i = Scalar::random(&mut *csprng);
+        let X_i = G2Projective::generator() * &x_i;
+        let x_proof = ComZkDlComElproof::prove(&transcript, csprng, &X_i.into(), &x_i);
+
+        let mut H_i = Vec::with_capacity(self.l + 1);
+        for _ in 0..=self.l {
+            let k_i = Scalar::random(&mut *csprng);
+            H_i.push(G1Projective::generator() * &k_i);
+        }
+
+        let my_msg2 = DkgMsg2 {
+            cl_pk_i,
+            cl_proof,
+            X_i,
+            x_proof,
+            H_i,
+        };
+        let com = my_msg2.commit(self.i);
+
+        (
+            KeyGenPartyRound1 {
+                cl,
+                session_id: self.session_id,
+                i: self.i,
+                l: self.l,
+                d_i,
+                x_i,
+                my_msg2,
+            },
+            DkgMsg1 { com },
+        )
+    }
+}
+
+impl<'a> KeyGenPartyRound1<'a> {
+    /// `msgs` holds the round 1 messages of every party, including our own.
+    pub fn round2(self, msgs: &BTreeMap<usize, DkgMsg1>) -> (KeyGenPartyRound2<'a>, DkgMsg2) {
+        let coms = msgs.iter().map(|(j, m)| (*j, m.com.clone())).collect();
+        (
+            KeyGenPartyRound2 {
+                cl: self.cl,
+                session_id: self.session_id,
+                i: self.i,
+                l: self.l,
+                d_i: self.d_i,
+                x_i: self.x_i,
+                coms,
+            },
+            self.my_msg2,
+        )
+    }
+}
+
+impl<'a> KeyGenPartyRound2<'a> {
+    /// `msgs` holds the round 2 messages of every party, including our own.
+    pub fn round3(
+        self,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+        msgs: &BTreeMap<usize, DkgMsg2>,
+    ) -> Result<(KeyGenPartyRound3<'a>, DkgMsg3), ProtocolError> {
+        let cl = self.cl;
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+
+        let malformed = msgs
+            .iter()
+            .filter(|(_, msg_j)| msg_j.H_i.len() != self.l + 1)
+            .map(|(j, _)| *j)
+            .collect();
+        blame(malformed, ProtocolError::MalformedMessage)?;
+
+        let mut bad_coms = Vec::new();
+        let mut bad_cl_proofs = Vec::new();
+        let mut bad_x_proofs = Vec::new();
+        for (j, msg_j) in msgs {
+            if *j == self.i {
+                continue;
+            }
+            if self.coms.get(j) != Some(&msg_j.commit(*j)) {
+                bad_coms.push(*j);
+            }
+            let transcript_j = Transcript::new(self.session_id, *j);
+            if !msg_j
+                .cl_proof
+                .verify(&cl, &transcript_j, &bound, &msg_j.cl_pk_i)
+            {
+                bad_cl_proofs.push(*j);
+            }
+            if !msg_j.x_proof.verify(&transcript_j, &msg_j.X_i.into()) {
+                bad_x_proofs.push(*j);
+            }
+        }
+        blame(bad_coms, ProtocolError::InvalidCommitment)?;
+        blame_proof(bad_cl_proofs, "ComZkDlComClproof")?;
+        blame_proof(bad_x_proofs, "ComZkDlComElproof")?;
+
+        let cl_pk = msgs
+            .values()
+            .map(|m| m.cl_pk_i.clone())
+            .reduce(|acc, pk_i| acc.compose(&cl, &pk_i))
+            .unwrap();
+        let cl_pub_key = PublicKey::from_qfi(&cl, &cl_pk);
+        let X = msgs
+            .values()
+            .map(|m| m.X_i)
+            .reduce(|acc, X_i| acc + X_i)
+            .unwrap();
+        let mut H = Vec::with_capacity(self.l + 1);
+        for k in 0..=self.l {
+            let H_k = msgs
+                .values()
+                .map(|m| m.H_i[k])
+                .reduce(|acc, H_ik| acc + H_ik)
+                .unwrap();
+            H.push(H_k);
+        }
+        let pub_shares = msgs.iter().map(|(j, m)| (*j, m.X_i)).collect();
+
+        let cl_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
+        let c1 = cl.power_of_h(&cl_rand);
+        let c2 = cl
+            .power_of_f(&Mpz::from(&self.x_i))
+            .compose(&cl, &cl_pub_key.exponentiation(&cl, &cl_rand));
+        let x_ciphertext = CipherText::new(&c1, &c2);
+        let proof = CLEncProof::prove(
+            &cl,
+            &Transcript::new(self.session_id, self.i),
+            &bound,
+            rng,
+            &cl_pub_key,
+            &x_ciphertext,
+            &(G2Projective::generator() * &self.x_i),
+            &self.x_i,
+            &cl_rand,
+            csprng,
+        );
+
+        Ok((
+            KeyGenPartyRound3 {
+                cl,
+                session_id: self.session_id,
+                i: self.i,
+                d_i: self.d_i,
+                x_i: self.x_i,
+                cl_pub_key,
+                X,
+                H,
+                pub_shares,
+            },
+            DkgMsg3 {
+                x_ciphertext,
+                proof,
+            },
+        ))
+    }
+}
+
+impl<'a> KeyGenPartyRound3<'a> {
+    /// `msgs` holds the round 3 messages of every party, including our own.
+    pub fn finish(self, msgs: &BTreeMap<usize, DkgMsg3>) -> Result<KeyShare, ProtocolError> {
+        let cl = self.cl;
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+
+        let mut culprits = Vec::new();
+        let mut ids = Vec::new();
+        let mut items = Vec::new();
+        for (j, msg_j) in msgs.iter().filter(|(j, _)| **j != self.i) {
+            match self.pub_shares.get(j) {
+                Some(X_j) => {
+                    ids.push(*j);
+                    items.push((
+                        Transcript::new(self.session_id, *j),
+                        &bound,
+                        &self.cl_pub_key,
+                        &msg_j.x_ciphertext,
+                        X_j,
+                        &msg_j.proof,
+                    ));
+                }
+                None => culprits.push(*j),
+            }
+        }
+        blame_proof(culprits, "CLEncProof")?;
+        blame_batch(CLEncProof::batch_verify(&cl, &items), &ids, "CLEncProof")?;
+
+        let x_ciphertext = msgs
+            .values()
+            .map(|m| m.x_ciphertext.clone())
+            .reduce(|acc, ct| {
+                CipherText::new(
+                    &acc.c1().compose(&cl, &ct.c1()),
+                    &acc.c2().compose(&cl, &ct.c2()),
+                )
+            })
+            .unwrap();
+
+        Ok(KeyShare {
+            i: self.i,
+            cl_sk_share: self.d_i,
+            cl_pub_key: self.cl_pub_key,
+            x_share: self.x_i,
+            x_ciphertext,
+            X: self.X,
+            H: self.H,
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum KeyGenPartyError<RecvErr, SendErr> {
+    #[error("send a message at round 1")]
+    Round1Send(#[source] SendErr),
+    #[error("receive messages at round 1")]
+    Round1Receive(#[source] CompleteRoundError<RoundInputError, RecvErr>),
+    #[error("send a message at round 2")]
+    Round2Send(#[source] SendErr),
+    #[error("receive messages at round 2")]
+    Round2Receive(#[source] CompleteRoundError<RoundInputError, RecvErr>),
+    #[error("send a message at round 3")]
+    Round3Send(#[source] SendErr),
+    #[error("receive messages at round 3")]
+    Round3Receive(#[source] CompleteRoundError<RoundInputError, RecvErr>),
+    #[error("protocol aborted")]
+    Protocol(#[source] ProtocolError),
+}
+
+/// Runs [`KeyGenParty`] over a `round_based` transport. The party with index `i`
+/// (out of `n` parties) ends up with the key share of party `i + 1`.
+pub async fn keygen_party<M>(
+    party: M,
+    i: PartyIndex,
+    n: u16,
+    cl: &CL_HSMqk,
+    session_id: &[u8],
+    l: usize,
+    rng: &mut RandGen,
+    csprng: &mut impl CryptoRngCore,
+) -> Result<KeyShare, KeyGenPartyError<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = DkgMsg>,
+{
+    let MpcParty { delivery, .. } = party.into_party();
+    let (incoming, mut outgoing) = delivery.split();
+
+    let mut rounds = RoundsRouter::<DkgMsg>::builder();
+    let round1 = rounds.add_round(RoundInput::<DkgMsg1>::broadcast(i, n));
+    let round2 = rounds.add_round(RoundInput::<DkgMsg2>::broadcast(i, n));
+    let round3 = rounds.add_round(RoundInput::<DkgMsg3>::broadcast(i, n));
+    let mut rounds = rounds.listen(incoming);
+
+    let (party_state, my_msg1) =
+        KeyGenParty::new(cl, session_id, usize::from(i) + 1, l).round1(rng, csprng);
+    outgoing
+        .send(Outgoing::broadcast(DkgMsg::Round1(my_msg1.clone())))
+        .await
+        .map_err(KeyGenPartyError::Round1Send)?;
+    let msgs1 = rounds
+        .complete(round1)
+        .await
+        .map_err(KeyGenPartyError::Round1Receive)?;
+    let msgs1 = by_party_id(msgs1.into_vec_including_me(my_msg1));
+
+    let (party_state, my_msg2) = party_state.round2(&msgs1);
+    outgoing
+        .send(Outgoing::broadcast(DkgMsg::Round2(my_msg2.clone())))
+        .await
+        .map_err(KeyGenPartyError::Round2Send)?;
+    let msgs2 = rounds
+        .complete(round2)
+        .await
+        .map_err(KeyGenPartyError::Round2Receive)?;
+    let msgs2 = by_party_id(msgs2.into_vec_including_me(my_msg2));
+
+    let (party_state, my_msg3) = party_state
+        .round3(rng, csprng, &msgs2)
+        .map_err(KeyGenPartyError::Protocol)?;
+    outgoing
+        .send(Outgoing::broadcast(DkgMsg::Round3(my_msg3.clone())))
+        .await
+        .map_err(KeyGenPartyError::Round3Send)?;
+    let msgs3 = rounds
+        .complete(round3)
+        .await
+        .map_err(KeyGenPartyError::Round3Receive)?;
+    let msgs3 = by_party_id(msgs3.into_vec_including_me(my_msg3));
+
+    party_state
+        .finish(&msgs3)
+        .map_err(KeyGenPartyError::Protocol)
+}
+
+fn by_party_id<T>(msgs: Vec<T>) -> BTreeMap<usize, T> {
+    msgs.into_iter()
+        .enumerate()
+        .map(|(j, m)| (j + 1, m))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::{arithmetic::Converter, BigInt};
+    use ff::PrimeField;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use round_based::simulation::Simulation;
+
+    use crate::MODULUS;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keygen_party() {
+        let seed: [u8; 32] = [0u8; 32];
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let n = 5;
+        let l = 10;
+
+        let mut simulation = Simulation::<DkgMsg>::new();
+        let mut outputs = Vec::with_capacity(n);
+        for i in 0..n {
+            let party = simulation.add_party();
+            let mut party_rng = RandGen::new();
+            party_rng.set_seed(&Mpz::from(i as u64 + 1));
+            let mut party_csprng = ChaChaRng::from_seed([i as u8 + 1; 32]);
+            let cl = &cl;
+            outputs.push(async move {
+                keygen_party(
+                    party,
+                    i as u16,
+                    n as u16,
+                    cl,
+                    b"test_keygen_party",
+                    l,
+                    &mut party_rng,
+                    &mut party_csprng,
+                )
+                .await
+            });
+        }
+        let key_shares = futures::future::try_join_all(outputs).await.unwrap();
+
+        for key_share in key_shares.iter() {
+            assert_eq!(key_share.X, key_shares[0].X);
+            assert_eq!(key_share.H, key_shares[0].H);
+            assert_eq!(key_share.x_ciphertext, key_shares[0].x_ciphertext);
+        }
+
+        let d = key_shares
+            .iter()
+            .map(|k| k.cl_sk_share.clone())
+            .reduce(|acc, d_i| acc + d_i)
+            .unwrap();
+        assert_eq!(key_shares[0].cl_pub_key.elt(), cl.power_of_h(&d));
+
+        let ct = &key_shares[0].x_ciphertext;
+        let fx = ct.c2().compose(&cl, &ct.c1().exp(&cl, &-d));
+        let x = Scalar::from_str_vartime(&cl.dlog_in_F(&fx).to_string()).unwrap();
+        assert_eq!(G2Projective::generator() * x, key_shares[0].X);
+    }
+}
@@ -5,24 +5,38 @@ use bicycl::PublicKey;
 use bicycl::RandGen;
 use bls12_381::{pairing, G1Projective, G2Affine, G2Projective, Scalar};
 use ff::Field;
-use rand_chacha::ChaChaRng;
+use rand_core::CryptoRngCore;
 use std::collections::BTreeMap;
 
+use crate::Attribute;
 use crate::CLEncProof;
 use crate::ComZkDlComElproof;
+use crate::MessageEncoder;
+use crate::ProtocolError;
+use crate::ResponseBound;
 
 pub mod keygen;
 pub use keygen::*;
 pub mod sign;
 pub use sign::*;
+pub mod dkg;
+pub use dkg::*;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct BBSPlusKey {
     pub x: Scalar,
     pub X: G2Projective,
     pub H: Vec<G1Projective>,
 }
 
+/// The public part of a [`BBSPlusKey`]. This is all a verifier needs, and all
+/// the threshold signers ever learn about the key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BBSPlusPublicKey {
+    pub X: G2Projective,
+    pub H: Vec<G1Projective>,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct BBSPlusSig {
     pub A: G1Projective,
@@ -31,25 +45,32 @@ pub struct BBSPlusSig {
 }
 
 impl BBSPlusKey {
-    pub fn keygen(rng: &mut ChaChaRng, l: usize) -> Self {
-        let x = Scalar::random(rng.clone());
+    pub fn keygen(rng: &mut impl CryptoRngCore, l: usize) -> Self {
+        let x = Scalar::random(&mut *rng);
         let X = G2Projective::generator() * x;
 
         let mut H: Vec<G1Projective> = Vec::with_capacity(l);
 
         for _ in 0..=l {
-            let tmp = Scalar::random(rng.clone());
+            let tmp = Scalar::random(&mut *rng);
             H.push(G1Projective::generator() * tmp);
         }
 
         Self { x, X, H }
     }
+
+    pub fn public_key(&self) -> BBSPlusPublicKey {
+        BBSPlusPublicKey {
+            X: self.X,
+            H: self.H.clone(),
+        }
+    }
 }
 
 impl BBSPlusSig {
-    pub fn sign(rng: &mut ChaChaRng, key: &BBSPlusKey, msg: &[Scalar], l: usize) -> Self {
-        let e = Scalar::random(rng.clone());
-        let s = Scalar::random(rng.clone());
+    pub fn sign(rng: &mut impl CryptoRngCore, key: &BBSPlusKey, msg: &[Scalar], l: usize) -> Self {
+        let e = Scalar::random(&mut *rng);
+        let s = Scalar::random(&mut *rng);
         let mut B = G1Projective::generator();
 
         for i in 0..l {
@@ -65,7 +86,12 @@ impl BBSPlusSig {
         Self { A: A.into(), e, s }
     }
 
-    pub fn verify(key: &BBSPlusKey, msg: &[Scalar], l: usize, sig: &BBSPlusSig) {
+    pub fn verify(
+        key: &BBSPlusPublicKey,
+        msg: &[Scalar],
+        l: usize,
+        sig: &BBSPlusSig,
+    ) -> Result<(), ProtocolError> {
         let mut B = G1Projective::generator();
         for i in 0..l {
             B = B + key.H[i] * msg[i];
@@ -78,7 +104,22 @@ impl BBSPlusSig {
         );
         let q = pairing(&B.into(), &G2Affine::generator());
 
-        assert_eq!(p, q);
+        if p == q {
+            Ok(())
+        } else {
+            Err(ProtocolError::InvalidSignature)
+        }
+    }
+
+    /// Verifies `sig` on `attributes`, encoded with `encoder`.
+    pub fn verify_attributes(
+        key: &BBSPlusPublicKey,
+        encoder: &MessageEncoder,
+        attributes: &[Attribute],
+        sig: &BBSPlusSig,
+    ) -> Result<(), ProtocolError> {
+        let msg = encoder.encode(attributes)?;
+        Self::verify(key, &msg, encoder.l(), sig)
     }
 }
 
@@ -105,23 +146,29 @@ mod tests {
         let mut msg: Vec<Scalar> = Vec::with_capacity(l);
 
         for _ in 0..l {
-            let tmp = Scalar::random(rng.clone());
+            let tmp = Scalar::random(&mut rng);
             msg.push(tmp);
         }
 
         let key = BBSPlusKey::keygen(&mut rng, l);
         let sig = BBSPlusSig::sign(&mut rng, &key, &msg, l);
 
-        BBSPlusSig::verify(&key, &msg, l, &sig);
+        BBSPlusSig::verify(&key.public_key(), &msg, l, &sig).unwrap();
+
+        let other_msg: Vec<Scalar> = msg.iter().map(|m| m + Scalar::one()).collect();
+        assert_eq!(
+            BBSPlusSig::verify(&key.public_key(), &other_msg, l, &sig),
+            Err(ProtocolError::InvalidSignature)
+        );
     }
 
     #[test]
     fn test_hash_tocurve() {
         let seed = [0u8; 32];
-        let rng = ChaChaRng::from_seed(seed);
+        let mut rng = ChaChaRng::from_seed(seed);
         let mut hasher = Sha256::new();
 
-        let x = Scalar::random(rng.clone());
+        let x = Scalar::random(&mut rng);
         let X = G2Projective::generator() * x;
 
         let X_A: G2Affine = X.into();
@@ -138,10 +185,10 @@ mod tests {
     #[test]
     fn test_encryption() {
         let seed = [0u8; 32];
-        let scalr_rng = ChaChaRng::from_seed(seed);
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -175,10 +222,10 @@ mod tests {
     #[test]
     fn test_scalar() {
         let seed = [0u8; 32];
-        let scalr_rng = ChaChaRng::from_seed(seed);
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -192,14 +239,14 @@ mod tests {
         let sk = rng.random_mpz(&cl.encrypt_randomness_bound());
         let pk = PublicKey::from_qfi(&cl, &cl.power_of_h(&sk));
 
-        let m = Scalar::random(scalr_rng.clone());
+        let m = Scalar::random(&mut scalr_rng);
         let cl_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
         let c1 = cl.power_of_h(&cl_rand);
         let c2 = cl
             .power_of_f(&Mpz::from(&m))
             .compose(&cl, &pk.exponentiation(&cl, &cl_rand));
 
-        let gamma = Scalar::random(scalr_rng.clone());
+        let gamma = Scalar::random(&mut scalr_rng);
         //let gamma = Scalar::one();
         let c1_s = c1.exp(&cl, &Mpz::from(&gamma));
         let c2_s = c2.exp(&cl, &Mpz::from(&gamma));
@@ -221,10 +268,10 @@ mod tests {
     #[test]
     fn test_size() {
         let seed = [0u8; 32];
-        let scalr_rng = ChaChaRng::from_seed(seed);
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -238,7 +285,7 @@ mod tests {
         let sk = rng.random_mpz(&cl.encrypt_randomness_bound());
         let pk = PublicKey::from_qfi(&cl, &cl.power_of_h(&sk));
 
-        let m = Scalar::random(scalr_rng.clone());
+        let m = Scalar::random(&mut scalr_rng);
         let cl_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
         let c1 = cl.power_of_h(&cl_rand);
         let c2 = cl
@@ -1,7 +1,8 @@
 use bicycl::QFI;
 use ff::PrimeField;
 
-use crate::{CLRandProof, CLRandYuanProof};
+use crate::error::{blame, blame_batch};
+use crate::{BlindRequest, CLRandProof, CLRandYuanProof, Transcript};
 
 use super::*;
 
@@ -18,14 +19,85 @@ pub struct Sign {
 impl Sign {
     pub fn sign(
         cl: &CL_HSMqk,
+        session_id: &[u8],
         n: usize,
         l: usize,
-        mut rng: &mut RandGen,
-        chacharng: &mut ChaChaRng,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
         key_msg: &KeyGen,
         msg: &[Scalar],
         q: &Mpz,
-    ) -> Self {
+    ) -> Result<Self, ProtocolError> {
+        let H = &key_msg.sign_keys.H;
+        let mut base = G1Projective::generator();
+        for i in 0..l {
+            base = base + H[i] * msg[i];
+        }
+        Self::sign_base(cl, session_id, n, rng, csprng, key_msg, q, |s| {
+            base + H[l] * s
+        })
+    }
+
+    /// Signs `attributes`, encoded with `encoder` into its `l` messages.
+    pub fn sign_attributes(
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        n: usize,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+        key_msg: &KeyGen,
+        encoder: &MessageEncoder,
+        attributes: &[Attribute],
+        q: &Mpz,
+    ) -> Result<Self, ProtocolError> {
+        let msg = encoder.encode(attributes)?;
+        Self::sign(
+            cl,
+            session_id,
+            n,
+            encoder.l(),
+            rng,
+            csprng,
+            key_msg,
+            &msg,
+            q,
+        )
+    }
+
+    /// Signs the messages the holder committed to in `request`, of which the
+    /// signers only see the `revealed` ones. The holder completes the signature
+    /// with [`Sign::client_blind`].
+    pub fn sign_blind(
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        n: usize,
+        l: usize,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+        key_msg: &KeyGen,
+        request: &BlindRequest,
+        revealed: &BTreeMap<usize, Scalar>,
+        q: &Mpz,
+    ) -> Result<Self, ProtocolError> {
+        let H = &key_msg.sign_keys.H;
+        request.verify(session_id, H, l)?;
+        let base = request.base(H, l, revealed)?;
+        Self::sign_base(cl, session_id, n, rng, csprng, key_msg, q, |s| {
+            base + H[l] * s
+        })
+    }
+
+    /// Signs with `B = B_of_s(s)` for the `s` the signers agree on.
+    pub(crate) fn sign_base(
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        n: usize,
+        mut rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+        key_msg: &KeyGen,
+        q: &Mpz,
+        B_of_s: impl Fn(Scalar) -> G1Projective,
+    ) -> Result<Self, ProtocolError> {
         let mut eis = Vec::with_capacity(n);
         let mut sis = Vec::with_capacity(n);
 
@@ -41,8 +113,8 @@ impl Sign {
         let mut Bis: BTreeMap<usize, G1Projective> = BTreeMap::new();
         let mut pdis: BTreeMap<usize, QFI> = BTreeMap::new();
         for _ in 1..=n {
-            eis.push(Scalar::random(chacharng.clone()));
-            sis.push(Scalar::random(chacharng.clone()));
+            eis.push(Scalar::random(&mut *csprng));
+            sis.push(Scalar::random(&mut *csprng));
         }
 
         for (i, item) in key_msg.each_party_x_ciphertexts.clone() {
@@ -57,7 +129,7 @@ impl Sign {
                 .reduce(|acc, e_i| acc + e_i)
                 .unwrap();
 
-            let gamma_i = Scalar::random(chacharng.clone());
+            let gamma_i = Scalar::random(&mut *csprng);
 
             each_party_gammai.insert(i, gamma_i);
 
@@ -78,6 +150,7 @@ impl Sign {
 
             let proof_i = CLRandYuanProof::prove(
                 &cl,
+                &Transcript::new(session_id, i),
                 &mut rng,
                 &ct1_pow,
                 &item.c1(),
@@ -93,29 +166,33 @@ impl Sign {
             each_party_gammaix_ct1.push((i, ct1_pow.clone(), item.c1().clone(), proof_i))
         }
 
-        for (i, _) in es.iter().enumerate() {
-            if i != 0 {
-                assert_eq!(es[i - 1].clone(), es[i].clone());
-                assert_eq!(ss[i - 1].clone(), ss[i].clone());
-            }
-        }
+        let culprits = (1..es.len())
+            .filter(|i| es[*i] != es[0] || ss[*i] != ss[0])
+            .map(|i| i + 1)
+            .collect();
+        blame(culprits, ProtocolError::Inconsistent)?;
 
         let mut each_party_gammax_ct1 = BTreeMap::new();
 
         for i in 1..=n {
-            let gammaix_ct1: Vec<_> = each_party_gammaix_ct1
+            let (ids, items): (Vec<usize>, Vec<_>) = each_party_gammaix_ct1
                 .iter()
-                .filter_map(|(_, ct1_pow, ct1_gen, proof)| {
-                    //if proof.verify(pp, &pp.pk, ki_ciphertext) && *j != i {
-                    // if proof.verify(&cl, &ct1_pow, ct1_gen, &q) {
-                    //     //Some((*j, gammai_ciphertext.clone(), proof.clone()))
-                    //     Some(ct1_pow.clone())
-                    // } else {
-                    //     None
-                    // }
-                    proof.verify(&cl, &ct1_pow, ct1_gen, &q);
-                    Some(ct1_pow.clone())
+                .filter(|(j, _, _, _)| *j != i)
+                .map(|(j, ct1_pow, ct1_gen, proof)| {
+                    (
+                        *j,
+                        (Transcript::new(session_id, *j), ct1_pow, ct1_gen, proof),
+                    )
                 })
+                .unzip();
+            blame_batch(
+                CLRandYuanProof::batch_verify(&cl, &q, &items),
+                &ids,
+                "CLRandYuanProof",
+            )?;
+            let gammaix_ct1: Vec<_> = each_party_gammaix_ct1
+                .iter()
+                .map(|(_, ct1_pow, _, _)| ct1_pow.clone())
                 .collect();
 
             let gammax_ct1 = gammaix_ct1
@@ -128,18 +205,14 @@ impl Sign {
         let H = key_msg.sign_keys.H.clone();
 
         for i in 1..=n {
-            let mut B = G1Projective::generator();
             let e = es[i - 1];
             let s = ss[i - 1];
-            let rho_i = Scalar::random(chacharng.clone());
+            let rho_i = Scalar::random(&mut *csprng);
             let gamma_i = each_party_gammai.get(&i).unwrap().clone();
             let ct1 = each_party_gammax_ct1.get(&i).unwrap().clone();
             let ct2 = each_party_gammaix_ct2.get(&i).unwrap().clone();
             let d_i = key_msg.cl_keys.sk_shares.get(&i).unwrap().clone();
-            for i in 0..l {
-                B = B + H[i] * msg[i];
-            }
-            B = B + H[l] * s;
+            let B = B_of_s(s);
 
             let Bi = gamma_i * B;
             let zi = gamma_i * e - rho_i;
@@ -154,7 +227,7 @@ impl Sign {
             pdis.insert(i, pd_i_v2);
         }
 
-        Self {
+        Ok(Self {
             H,
             X: key_msg.sign_keys.pub_key,
             e: es[0],
@@ -162,11 +235,54 @@ impl Sign {
             zis,
             Bis,
             pdis,
-        }
+        })
+    }
+
+    pub fn client(
+        cl: &CL_HSMqk,
+        sign_msg: &Sign,
+        msg: &[Scalar],
+        l: usize,
+    ) -> Result<BBSPlusSig, ProtocolError> {
+        Self::client_blind(cl, sign_msg, msg, l, &Scalar::zero())
+    }
+
+    /// [`Sign::client`] for a [`Sign::sign_attributes`].
+    pub fn client_attributes(
+        cl: &CL_HSMqk,
+        sign_msg: &Sign,
+        encoder: &MessageEncoder,
+        attributes: &[Attribute],
+    ) -> Result<BBSPlusSig, ProtocolError> {
+        let msg = encoder.encode(attributes)?;
+        Self::client(cl, sign_msg, &msg, encoder.l())
+    }
+
+    /// Completes a [`Sign::sign_blind`] for the holder, which knows all of `msg`
+    /// and the blinding `s_prime` of its [`BlindRequest`].
+    pub fn client_blind(
+        cl: &CL_HSMqk,
+        sign_msg: &Sign,
+        msg: &[Scalar],
+        l: usize,
+        s_prime: &Scalar,
+    ) -> Result<BBSPlusSig, ProtocolError> {
+        let A = sign_msg.combine(cl)?;
+        let e = sign_msg.e;
+        let s = sign_msg.s + s_prime;
+        let key = BBSPlusPublicKey {
+            X: sign_msg.X,
+            H: sign_msg.H.clone(),
+        };
+        let sig = BBSPlusSig { A, e, s };
+        BBSPlusSig::verify(&key, msg, l, &sig)?;
+        Ok(sig)
     }
 
-    pub fn client(cl: &CL_HSMqk, sign_msg: &Sign, msg: &[Scalar], l: usize) {
-        let B = sign_msg
+    /// `A = B / (x + e)` from the signers' shares, with `B` the sum of their
+    /// `B_i`.
+    pub(crate) fn combine(&self, cl: &CL_HSMqk) -> Result<G1Projective, ProtocolError> {
+        let B = self
             .Bis
             .values()
             .cloned()
@@ -174,7 +290,7 @@ impl Sign {
             .reduce(|acc, B_i| acc + B_i)
             .unwrap();
 
-        let pd = sign_msg
+        let pd = self
             .pdis
             .values()
             .cloned()
@@ -182,7 +298,7 @@ impl Sign {
             .reduce(|acc, pd_i| acc.compose(&cl, &pd_i))
             .unwrap();
 
-        let beta = sign_msg
+        let beta = self
             .zis
             .values()
             .cloned()
@@ -195,25 +311,20 @@ impl Sign {
         // let mut y_bytes:[u8; 64] = y.to_bytes().try_into().unwrap();
         // y_bytes.reverse();
         // let y_scalar = Scalar::from_bytes_wide(&y_bytes);
-        let y_scalar = Scalar::from_str_vartime(&y.to_string()).unwrap();
+        let y_scalar =
+            Scalar::from_str_vartime(&y.to_string()).ok_or(ProtocolError::DegenerateSignature)?;
 
-        let inv = (y_scalar + beta).invert().unwrap();
-        let A = B * inv;
-        let e = sign_msg.e;
-        let s = sign_msg.s;
-        let key = BBSPlusKey {
-            x: sign_msg.e,
-            X: sign_msg.X,
-            H: sign_msg.H.clone(),
-        };
-        let sig = BBSPlusSig { A, e, s };
-        BBSPlusSig::verify(&key, msg, l, &sig);
+        let inv = Option::<Scalar>::from((y_scalar + beta).invert())
+            .ok_or(ProtocolError::DegenerateSignature)?;
+        Ok(B * inv)
     }
 }
 
+#[cfg(test)]
 mod tests {
     use curv::{arithmetic::Converter, BigInt};
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use crate::MODULUS;
 
@@ -225,7 +336,7 @@ mod tests {
         let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
         let cl = CL_HSMqk::with_rand_gen(
@@ -241,11 +352,85 @@ mod tests {
         let mut msg: Vec<Scalar> = Vec::with_capacity(l);
 
         for i in 0..l {
-            let tmp = Scalar::random(scalr_rng.clone());
+            let tmp = Scalar::random(&mut scalr_rng);
             msg.push(tmp);
         }
-        let key_msg = KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
-        let sign_msg = Sign::sign(&cl, n, l, &mut rng, &mut scalr_rng, &key_msg, &msg, &q);
-        Sign::client(&cl, &sign_msg, &msg, l);
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng).unwrap();
+        let sign_msg = Sign::sign(
+            &cl,
+            b"sign",
+            n,
+            l,
+            &mut rng,
+            &mut scalr_rng,
+            &key_msg,
+            &msg,
+            &q,
+        )
+        .unwrap();
+        let sig = Sign::client(&cl, &sign_msg, &msg, l).unwrap();
+        BBSPlusSig::verify(&key_msg.public_key(), &msg, l, &sig).unwrap();
+    }
+
+    #[test]
+    fn test_sign_blind() {
+        let mut scalr_rng = ChaChaRng::from_seed([0u8; 32]);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
+        let cl = CL_HSMqk::with_rand_gen(
+            &q,
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let (n, l) = (5, 10);
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng).unwrap();
+        let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut scalr_rng)).collect();
+
+        let hidden: BTreeMap<usize, Scalar> = [0, 3].iter().map(|i| (*i, msg[*i])).collect();
+        let revealed: BTreeMap<usize, Scalar> = (0..l)
+            .filter(|i| !hidden.contains_key(i))
+            .map(|i| (i, msg[i]))
+            .collect();
+        let (request, s_prime) =
+            BlindRequest::new(b"blind", &key_msg.sign_keys.H, l, &hidden, &mut scalr_rng);
+        let sign_msg = Sign::sign_blind(
+            &cl,
+            b"blind",
+            n,
+            l,
+            &mut rng,
+            &mut scalr_rng,
+            &key_msg,
+            &request,
+            &revealed,
+            &q,
+        )
+        .unwrap();
+        let sig = Sign::client_blind(&cl, &sign_msg, &msg, l, &s_prime).unwrap();
+        BBSPlusSig::verify(&key_msg.public_key(), &msg, l, &sig).unwrap();
+
+        // The request was made for another session.
+        assert_eq!(
+            Sign::sign_blind(
+                &cl,
+                b"sign",
+                n,
+                l,
+                &mut rng,
+                &mut scalr_rng,
+                &key_msg,
+                &request,
+                &revealed,
+                &q,
+            )
+            .err(),
+            Some(ProtocolError::InvalidRequest)
+        );
     }
 }
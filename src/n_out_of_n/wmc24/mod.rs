@@ -5,14 +5,21 @@ use bicycl::PublicKey;
 use bicycl::RandGen;
 use bls12_381::{G1Affine, G1Projective, G2Projective, Scalar};
 use ff::Field;
-use rand_chacha::ChaChaRng;
+use rand_core::CryptoRngCore;
 use std::collections::BTreeMap;
 
 use crate::CLEncProof;
 use crate::ComZkDlComClproof;
 use crate::ComZkDlComElproof;
+use crate::ProtocolError;
+use crate::ResponseBound;
+
+use super::setbbsplus::{BBSPlusPublicKey, BBSPlusSig};
 pub mod keygen;
 pub use keygen::*;
 
+pub mod party;
+pub use party::*;
+
 pub mod sign;
 pub use sign::*;
@@ -3,18 +3,17 @@ use std::ops::Add;
 use bicycl::QFI;
 use ff::PrimeField;
 use rayon::iter::IntoParallelRefMutIterator;
-use tokio::time::Sleep;
 
-use crate::{CLELProof, CLEncSProof, CLPDProof, CLRandProof, ELPDProof, ElGCiphertext};
+use crate::error::{blame, blame_batch, blame_proof, check_parties};
+use crate::{CLELProof, CLEncSProof, CLPDProof, CLRandProof, ELPDProof, ElGCiphertext, Transcript};
 
 use super::*;
 
 pub struct Sign {
     pub each_party_gamma_e_x_ciphertext: BTreeMap<usize, CipherText>,
     pub each_party_eg_ciphertext: BTreeMap<usize, ElGCiphertext>,
-    pub each_party_pd_gamma_e_x_ciphertext: BTreeMap<usize, (QFI, QFI, QFI, CLPDProof)>,
-    pub each_party_pd_eg_ciphertext:
-        BTreeMap<usize, (G1Projective, G1Projective, G1Projective, ELPDProof)>,
+    pub each_party_pd_gamma_e_x_ciphertext: BTreeMap<usize, (QFI, CLPDProof)>,
+    pub each_party_pd_eg_ciphertext: BTreeMap<usize, (G1Projective, ELPDProof)>,
     pub e: Scalar,
     pub s: Scalar,
 }
@@ -22,19 +21,21 @@ pub struct Sign {
 impl Sign {
     pub fn sign(
         cl: &CL_HSMqk,
+        session_id: &[u8],
         n: usize,
         l: usize,
         mut rng: &mut RandGen,
-        chacharng: &mut ChaChaRng,
+        csprng: &mut impl CryptoRngCore,
         key_msg: &KeyGen,
         msgs: &[Scalar],
-    ) -> Self {
+    ) -> Result<Self, ProtocolError> {
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
         // let mut each_party_ei_ciphertext = Vec::with_capacity(n);
         let mut each_party_ciphertext = Vec::with_capacity(n);
 
         for i in 1..=n {
-            let e_i = Scalar::random(chacharng.clone());
-            let s_i = Scalar::random(chacharng.clone());
+            let e_i = Scalar::random(&mut *csprng);
+            let s_i = Scalar::random(&mut *csprng);
             let e_i_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
             let c1: QFI = cl.power_of_h(&e_i_rand);
             let c2 = cl
@@ -44,12 +45,14 @@ impl Sign {
 
             let proof_e_i = CLEncSProof::prove(
                 &cl,
+                &Transcript::new(session_id, i),
+                &bound,
                 rng,
                 &key_msg.cl_keys.pub_key,
                 &e_i_ciphertext,
                 &e_i,
                 &e_i_rand,
-                &mut chacharng.clone(),
+                csprng,
             );
 
             let s_i_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
@@ -61,12 +64,14 @@ impl Sign {
 
             let proof_s_i = CLEncSProof::prove(
                 &cl,
+                &Transcript::new(session_id, i),
+                &bound,
                 rng,
                 &key_msg.cl_keys.pub_key,
                 &s_i_ciphertext,
                 &s_i,
                 &s_i_rand,
-                &mut chacharng.clone(),
+                csprng,
             );
 
             each_party_ciphertext.push((
@@ -83,21 +88,34 @@ impl Sign {
         let mut each_party_e_x_ciphertexts = BTreeMap::new();
         for i in 1..=n {
             let x_ciphertext = key_msg.each_party_x_ciphertexts.get(&i).unwrap().clone();
-            let ciphertexts: Vec<_> = each_party_ciphertext
+            let culprits = each_party_ciphertext
                 .iter()
-                .filter_map(
+                .filter(
                     |(j, e_i_ciphertext, s_i_ciphertext, proof_e_i, proof_s_i)| {
-                        //if proof.verify(pp, &pp.pk, ki_ciphertext) && *j != i {
-                        if proof_e_i.verify(&cl, &key_msg.cl_keys.pub_key, &e_i_ciphertext)
-                            && proof_s_i.verify(&cl, &key_msg.cl_keys.pub_key, &s_i_ciphertext)
-                        {
-                            //Some((*j, gammai_ciphertext.clone(), proof.clone()))
-                            Some((e_i_ciphertext.clone(), s_i_ciphertext.clone()))
-                        } else {
-                            None
-                        }
+                        *j != i
+                            && !(proof_e_i.verify(
+                                &cl,
+                                &Transcript::new(session_id, *j),
+                                &bound,
+                                &key_msg.cl_keys.pub_key,
+                                &e_i_ciphertext,
+                            ) && proof_s_i.verify(
+                                &cl,
+                                &Transcript::new(session_id, *j),
+                                &bound,
+                                &key_msg.cl_keys.pub_key,
+                                &s_i_ciphertext,
+                            ))
                     },
                 )
+                .map(|(j, _, _, _, _)| *j)
+                .collect();
+            blame_proof(culprits, "CLEncSProof")?;
+            let ciphertexts: Vec<_> = each_party_ciphertext
+                .iter()
+                .map(|(_, e_i_ciphertext, s_i_ciphertext, _, _)| {
+                    (e_i_ciphertext.clone(), s_i_ciphertext.clone())
+                })
                 .collect();
 
             //each_party_k_ciphertexts.insert(i, per_cipher.get(&i).unwrap().clone());
@@ -187,7 +205,7 @@ impl Sign {
         let H = key_msg.sign_keys.H.clone();
         for i in 1..=n {
             let e_x_ciphertext = each_party_e_x_ciphertexts.get(&i).unwrap().clone();
-            let gamma_i = Scalar::random(chacharng.clone());
+            let gamma_i = Scalar::random(&mut *csprng);
             let cl_rand1 = rng.random_mpz(&cl.encrypt_randomness_bound());
             let ct_pow = CipherText::new(
                 &e_x_ciphertext
@@ -200,7 +218,7 @@ impl Sign {
                     .compose(&cl, &cl_pk.exponentiation(&cl, &cl_rand1)),
             );
             let s = each_party_s.get(&i).unwrap().clone();
-            let eg_rand = Scalar::random(chacharng.clone());
+            let eg_rand = Scalar::random(&mut *csprng);
             let mut B = G1Projective::generator();
             for i in 0..l {
                 B = B + H[i] * msgs[i];
@@ -216,8 +234,10 @@ impl Sign {
 
             let proof_i = CLELProof::prove(
                 &cl,
+                &Transcript::new(session_id, i),
+                &bound,
                 &mut rng,
-                &mut chacharng.clone(),
+                csprng,
                 &cl_pk,
                 &eg_pk,
                 &ct_pow,
@@ -241,28 +261,32 @@ impl Sign {
         let mut each_party_gamma_e_x_ciphertext = BTreeMap::new();
         let mut each_party_eg_ciphertext = BTreeMap::new();
         for i in 1..=n {
-            let ciphertexts: Vec<_> = msg
+            let (ids, items): (Vec<usize>, Vec<_>) = msg
                 .iter()
-                .filter_map(
+                .filter(|(j, _, _, _, _, _)| *j != i)
+                .map(
                     |(j, gamma_i_e_x_ciphertext, e_x_ciphertext, eg_ciphertext, proof, B)| {
-                        //if proof.verify(pp, &pp.pk, ki_ciphertext) && *j != i {
-                        if proof.verify(
-                            &cl,
-                            &mut rng,
+                        let item = (
+                            Transcript::new(session_id, *j),
+                            &bound,
                             &cl_pk,
                             &eg_pk,
-                            &gamma_i_e_x_ciphertext,
-                            &e_x_ciphertext,
-                            &eg_ciphertext,
-                            &B,
-                        ) {
-                            //Some((*j, gammai_ciphertext.clone(), proof.clone()))
-                            Some((gamma_i_e_x_ciphertext.clone(), eg_ciphertext.clone()))
-                        } else {
-                            None
-                        }
+                            gamma_i_e_x_ciphertext,
+                            e_x_ciphertext,
+                            eg_ciphertext,
+                            B,
+                            proof,
+                        );
+                        (*j, item)
                     },
                 )
+                .unzip();
+            blame_batch(CLELProof::batch_verify(&cl, &items), &ids, "CLELProof")?;
+            let ciphertexts: Vec<_> = msg
+                .iter()
+                .map(|(_, gamma_i_e_x_ciphertext, _, eg_ciphertext, _, _)| {
+                    (gamma_i_e_x_ciphertext.clone(), eg_ciphertext.clone())
+                })
                 .collect();
             let (gamma_e_x_ciphertext, eg_ciphertext) = ciphertexts
                 .into_iter()
@@ -287,10 +311,7 @@ impl Sign {
             each_party_eg_ciphertext.insert(i, eg_ciphertext);
         }
         let mut each_party_pd_gamma_e_x_ciphertext = BTreeMap::new();
-        let mut each_party_pd_eg_ciphertext: BTreeMap<
-            usize,
-            (G1Projective, G1Projective, G1Projective, ELPDProof),
-        > = BTreeMap::new();
+        let mut each_party_pd_eg_ciphertext = BTreeMap::new();
         for i in 1..=n {
             let gamma_e_x_ciphertext = each_party_gamma_e_x_ciphertext.get(&i).unwrap().clone();
             let eg_ciphertext = each_party_eg_ciphertext.get(&i).unwrap().clone();
@@ -302,6 +323,8 @@ impl Sign {
             let pd_gamma_e_x_ciphertext = gamma_e_x_ciphertext.c1().exp(&cl, &cl_d_i);
             let proof_pd_gamma_e_x = CLPDProof::prove(
                 &cl,
+                &Transcript::new(session_id, i),
+                &bound,
                 rng,
                 &cl_pub_share,
                 &pd_gamma_e_x_ciphertext,
@@ -309,33 +332,19 @@ impl Sign {
                 &cl_d_i,
             );
 
-            each_party_pd_gamma_e_x_ciphertext.insert(
-                i,
-                (
-                    cl_pub_share,
-                    pd_gamma_e_x_ciphertext,
-                    gamma_e_x_ciphertext.c1(),
-                    proof_pd_gamma_e_x,
-                ),
-            );
+            each_party_pd_gamma_e_x_ciphertext
+                .insert(i, (pd_gamma_e_x_ciphertext, proof_pd_gamma_e_x));
 
             let pd_eg_ciphertext = &eg_ciphertext.c1 * &eg_d_i;
             let proof_pd_eg = ELPDProof::prove(
-                &mut chacharng.clone(),
+                &Transcript::new(session_id, i),
+                csprng,
                 &pd_eg_ciphertext,
                 &eg_ciphertext.c1.into(),
                 &eg_pub_share,
                 &eg_d_i,
             );
-            each_party_pd_eg_ciphertext.insert(
-                i,
-                (
-                    pd_eg_ciphertext,
-                    eg_ciphertext.c1.into(),
-                    eg_pub_share,
-                    proof_pd_eg,
-                ),
-            );
+            each_party_pd_eg_ciphertext.insert(i, (pd_eg_ciphertext, proof_pd_eg));
         }
 
         // for i in 1..=n {
@@ -368,55 +377,102 @@ impl Sign {
 
         //     let A = EG * gamma_e_x.invert().unwrap();
         // }
-        Self {
+        Ok(Self {
             each_party_gamma_e_x_ciphertext,
             each_party_eg_ciphertext,
             each_party_pd_gamma_e_x_ciphertext,
             each_party_pd_eg_ciphertext,
             e: each_party_e.get(&1).unwrap().clone(),
             s: each_party_s.get(&1).unwrap().clone(),
-        }
+        })
     }
 
-    pub fn client(cl: &CL_HSMqk, sign_msg: &Sign) {
+    /// Collects the [`SignerPartial`]s of [`signer_party`], by party id, for
+    /// [`Sign::client`].
+    pub fn from_partials(partials: &BTreeMap<usize, SignerPartial>) -> Result<Self, ProtocolError> {
+        let first = partials
+            .values()
+            .next()
+            .ok_or(ProtocolError::MissingMessage(Vec::new()))?;
+        let culprits = partials
+            .iter()
+            .filter(|(_, p)| p.e != first.e || p.s != first.s)
+            .map(|(i, _)| *i)
+            .collect();
+        blame(culprits, ProtocolError::Inconsistent)?;
+
+        Ok(Self {
+            each_party_gamma_e_x_ciphertext: partials
+                .iter()
+                .map(|(i, p)| (*i, p.gamma_e_x_ciphertext.clone()))
+                .collect(),
+            each_party_eg_ciphertext: partials
+                .iter()
+                .map(|(i, p)| (*i, p.eg_ciphertext.clone()))
+                .collect(),
+            each_party_pd_gamma_e_x_ciphertext: partials
+                .iter()
+                .map(|(i, p)| (*i, (p.pd_gamma_e_x.clone(), p.pd_gamma_e_x_proof.clone())))
+                .collect(),
+            each_party_pd_eg_ciphertext: partials
+                .iter()
+                .map(|(i, p)| (*i, (p.pd_eg, p.pd_eg_proof.clone())))
+                .collect(),
+            e: first.e,
+            s: first.s,
+        })
+    }
+
+    /// Combines the signers' output into a BBS+ signature on `msg`, and checks it
+    /// against the joint public key of `key_msg`. The partial decryptions are
+    /// checked against the public key shares of `key_msg`, not against anything
+    /// the signers send.
+    pub fn client(
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        sign_msg: &Sign,
+        key_msg: &KeyGen,
+        msg: &[Scalar],
+        l: usize,
+    ) -> Result<BBSPlusSig, ProtocolError> {
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
         let gamma_e_x_ciphertext = sign_msg
             .each_party_gamma_e_x_ciphertext
             .get(&1)
-            .unwrap()
+            .ok_or(ProtocolError::MalformedMessage(vec![1]))?
             .clone();
-        let eg_ciphertext = sign_msg.each_party_eg_ciphertext.get(&1).unwrap().clone();
+        let eg_ciphertext = sign_msg
+            .each_party_eg_ciphertext
+            .get(&1)
+            .ok_or(ProtocolError::MalformedMessage(vec![1]))?
+            .clone();
+        let parties: Vec<usize> = key_msg.cl_keys.pk_shares.keys().copied().collect();
+        check_parties(&parties, &sign_msg.each_party_pd_gamma_e_x_ciphertext)?;
+        check_parties(&parties, &sign_msg.each_party_pd_eg_ciphertext)?;
 
-        let pd_gamma_e_xs: Vec<_> = sign_msg
+        let gamma_e_x_ciphertext_c1 = gamma_e_x_ciphertext.c1();
+        let (ids, items): (Vec<usize>, Vec<_>) = sign_msg
             .each_party_pd_gamma_e_x_ciphertext
-            .values()
-            .into_iter()
-            .filter_map(
-                |(
-                    cl_pub_share,
+            .iter()
+            .map(|(j, (pd_gamma_e_x_ciphertext, proof_pd_gamma_e_x))| {
+                let item = (
+                    Transcript::new(session_id, *j),
+                    &bound,
+                    &key_msg.cl_keys.pk_shares[j],
                     pd_gamma_e_x_ciphertext,
-                    gamma_e_x_ciphertext_c1,
+                    &gamma_e_x_ciphertext_c1,
                     proof_pd_gamma_e_x,
-                )| {
-                    //if proof.verify(pp, &pp.pk, ki_ciphertext) && *j != i {
-                    if proof_pd_gamma_e_x.verify(
-                        &cl,
-                        &cl_pub_share,
-                        &pd_gamma_e_x_ciphertext,
-                        &gamma_e_x_ciphertext_c1,
-                    ) {
-                        //Some((*j, gammai_ciphertext.clone(), proof.clone()))
-                        Some(pd_gamma_e_x_ciphertext.clone())
-                    } else {
-                        None
-                    }
-                },
-            )
-            .collect();
-
-        let pd_gamma_e_x = pd_gamma_e_xs
-            .into_iter()
+                );
+                (*j, item)
+            })
+            .unzip();
+        blame_batch(CLPDProof::batch_verify(&cl, &items), &ids, "CLPDProof")?;
+        let pd_gamma_e_x = sign_msg
+            .each_party_pd_gamma_e_x_ciphertext
+            .values()
+            .map(|(pd_gamma_e_x_ciphertext, _)| pd_gamma_e_x_ciphertext.clone())
             .reduce(|acc, pd_i| acc.compose(cl, &pd_i))
-            .unwrap();
+            .ok_or(ProtocolError::MissingMessage(Vec::new()))?;
 
         let gamma_e_x = cl.dlog_in_F(
             &gamma_e_x_ciphertext
@@ -424,36 +480,51 @@ impl Sign {
                 .compose(&cl, &pd_gamma_e_x.exp(&cl, &Mpz::from(-1i64))),
         );
 
-        let gamma_e_x = Scalar::from_str_vartime(&gamma_e_x.to_string()).unwrap();
+        let gamma_e_x = Scalar::from_str_vartime(&gamma_e_x.to_string())
+            .ok_or(ProtocolError::DegenerateSignature)?;
 
-        let pd_egs: Vec<_> = sign_msg
+        let eg_ciphertext_c1: G1Projective = eg_ciphertext.c1.into();
+        let culprits = sign_msg
             .each_party_pd_eg_ciphertext
-            .values()
-            .into_iter()
-            .filter_map(
-                |(pd_eg_ciphertext, eg_ciphertext_c1, eg_pub_share, proof_pd_eg)| {
-                    //if proof.verify(pp, &pp.pk, ki_ciphertext) && *j != i {
-                    if proof_pd_eg.verify(pd_eg_ciphertext, eg_ciphertext_c1, eg_pub_share) {
-                        //Some((*j, gammai_ciphertext.clone(), proof.clone()))
-                        Some(pd_eg_ciphertext.clone())
-                    } else {
-                        None
-                    }
-                },
-            )
+            .iter()
+            .filter(|(j, (pd_eg_ciphertext, proof_pd_eg))| {
+                !proof_pd_eg.verify(
+                    &Transcript::new(session_id, **j),
+                    pd_eg_ciphertext,
+                    &eg_ciphertext_c1,
+                    &key_msg.eg_keys.pub_shares[*j],
+                )
+            })
+            .map(|(j, _)| *j)
             .collect();
-
-        let pd_eg = pd_egs.into_iter().reduce(|acc, pd_i| acc + pd_i).unwrap();
+        blame_proof(culprits, "ELPDProof")?;
+        let pd_eg = sign_msg
+            .each_party_pd_eg_ciphertext
+            .values()
+            .map(|(pd_eg_ciphertext, _)| *pd_eg_ciphertext)
+            .reduce(|acc, pd_i| acc + pd_i)
+            .ok_or(ProtocolError::MissingMessage(Vec::new()))?;
 
         let EG = eg_ciphertext.c2 - pd_eg;
 
-        let A = EG * gamma_e_x.invert().unwrap();
+        let inv =
+            Option::<Scalar>::from(gamma_e_x.invert()).ok_or(ProtocolError::DegenerateSignature)?;
+        let A = EG * inv;
+        let sig = BBSPlusSig {
+            A,
+            e: sign_msg.e,
+            s: sign_msg.s,
+        };
+        BBSPlusSig::verify(&key_msg.public_key(), msg, l, &sig)?;
+        Ok(sig)
     }
 }
 
+#[cfg(test)]
 mod tests {
     use curv::{arithmetic::Converter, BigInt};
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use crate::MODULUS;
 
@@ -465,7 +536,7 @@ mod tests {
         let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -480,11 +551,74 @@ mod tests {
         let mut msg: Vec<Scalar> = Vec::with_capacity(l);
 
         for i in 0..l {
-            let tmp = Scalar::random(scalr_rng.clone());
+            let tmp = Scalar::random(&mut scalr_rng);
             msg.push(tmp);
         }
-        let key_msg = KeyGen::keygen(&cl, n, l, &mut rng, &mut scalr_rng);
-        let sign_msg = Sign::sign(&cl, n, l, &mut rng, &mut scalr_rng, &key_msg, &msg);
-        Sign::client(&cl, &sign_msg);
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng).unwrap();
+        let sign_msg =
+            Sign::sign(&cl, b"sign", n, l, &mut rng, &mut scalr_rng, &key_msg, &msg).unwrap();
+        Sign::client(&cl, b"sign", &sign_msg, &key_msg, &msg, l).unwrap();
+        msg[0] += Scalar::one();
+        assert_eq!(
+            Sign::client(&cl, b"sign", &sign_msg, &key_msg, &msg, l),
+            Err(ProtocolError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_client_key_shares() {
+        let seed: [u8; 32] = [0u8; 32];
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let n = 3;
+        let l = 2;
+        let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut scalr_rng)).collect();
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut scalr_rng).unwrap();
+        let mut sign_msg =
+            Sign::sign(&cl, b"sign", n, l, &mut rng, &mut scalr_rng, &key_msg, &msg).unwrap();
+
+        // Party 2 decrypts with a key of its own and proves it against the
+        // matching public key, which the client must not take from party 2.
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+        let d = rng.random_mpz(&cl.encrypt_randomness_bound());
+        let c1 = sign_msg.each_party_gamma_e_x_ciphertext[&1].c1();
+        let pd = c1.exp(&cl, &d);
+        let proof = CLPDProof::prove(
+            &cl,
+            &Transcript::new(b"sign", 2),
+            &bound,
+            &mut rng,
+            &cl.power_of_h(&d),
+            &pd,
+            &c1,
+            &d,
+        );
+        sign_msg
+            .each_party_pd_gamma_e_x_ciphertext
+            .insert(2, (pd, proof));
+        assert_eq!(
+            Sign::client(&cl, b"sign", &sign_msg, &key_msg, &msg, l),
+            Err(ProtocolError::InvalidProof {
+                proof: "CLPDProof",
+                culprits: vec![2],
+            })
+        );
+
+        sign_msg.each_party_pd_eg_ciphertext.remove(&3);
+        assert_eq!(
+            Sign::client(&cl, b"sign", &sign_msg, &key_msg, &msg, l),
+            Err(ProtocolError::MissingMessage(vec![3]))
+        );
     }
 }
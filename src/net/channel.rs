@@ -0,0 +1,392 @@
+//! Authenticated channels between nodes.
+//!
+//! Every node has a long-term [`NodeKey`], whose public key the others know. A
+//! channel starts with a handshake
+//!
+//! ```text
+//! initiator -> responder: magic "TBBSNET1" | initiator id u64 | responder id u64 | E_i
+//! responder -> initiator: E_r
+//! ```
+//!
+//! where `E_i`, `E_r` are fresh ephemeral keys in G1. Both ends hash the
+//! handshake, the long-term public keys and the `ee`, `es` and `se`
+//! Diffie-Hellman values (the Noise `KK` pattern) into a key for each direction,
+//! so only the holders of the two expected long-term keys can read or write the
+//! channel. After that, a frame is the length of its ciphertext as a `u32`
+//! followed by the ChaCha20-Poly1305 encryption of the wire encoding of a value,
+//! with the number of frames sent before in that direction as the nonce.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use bicycl::CL_HSMqk;
+use bls12_381::{G1Affine, G1Projective, Scalar};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use ff::Field;
+use rand_core::CryptoRngCore;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::time::sleep;
+use zeroize::Zeroize;
+
+use crate::{NetError, Wire};
+
+pub const HANDSHAKE_MAGIC: &[u8; 8] = b"TBBSNET1";
+
+/// Frames are rejected above this size, before anything gets allocated.
+pub const MAX_FRAME_LEN: usize = 1 << 24;
+
+const POINT_LEN: usize = 48;
+const HELLO_LEN: usize = HANDSHAKE_MAGIC.len() + 8 + 8 + POINT_LEN;
+
+/// Peers may be started in any order, so dialing retries for a while.
+const DIAL_ATTEMPTS: usize = 300;
+const DIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The long-term identity of a node or coordinator.
+#[derive(Clone)]
+pub struct NodeKey {
+    pub sk: Scalar,
+}
+
+impl NodeKey {
+    pub fn generate(csprng: &mut impl CryptoRngCore) -> Self {
+        Self {
+            sk: Scalar::random(&mut *csprng),
+        }
+    }
+
+    pub fn public_key(&self) -> G1Projective {
+        G1Projective::generator() * self.sk
+    }
+}
+
+pub struct Channel {
+    peer: usize,
+    reader: ChannelReader,
+    writer: ChannelWriter,
+}
+
+pub struct ChannelReader {
+    stream: OwnedReadHalf,
+    cipher: ChaCha20Poly1305,
+    counter: u64,
+}
+
+pub struct ChannelWriter {
+    stream: OwnedWriteHalf,
+    cipher: ChaCha20Poly1305,
+    counter: u64,
+}
+
+impl Channel {
+    /// Connects to `addr` and opens a channel over it with [`Channel::connect`].
+    pub async fn dial(
+        addr: SocketAddr,
+        key: &NodeKey,
+        id: usize,
+        peer: usize,
+        peer_key: &G1Projective,
+        csprng: &mut impl CryptoRngCore,
+    ) -> Result<Self, NetError> {
+        let mut attempt = 1;
+        let stream = loop {
+            match TcpStream::connect(addr).await {
+                Ok(stream) => break stream,
+                Err(err) if attempt == DIAL_ATTEMPTS => return Err(err.into()),
+                Err(_) => {
+                    attempt += 1;
+                    sleep(DIAL_BACKOFF).await;
+                }
+            }
+        };
+        Self::connect(stream, key, id, peer, peer_key, csprng).await
+    }
+
+    /// Opens a channel as party `id` to party `peer`, whose long-term public key
+    /// is `peer_key`.
+    pub async fn connect(
+        mut stream: TcpStream,
+        key: &NodeKey,
+        id: usize,
+        peer: usize,
+        peer_key: &G1Projective,
+        csprng: &mut impl CryptoRngCore,
+    ) -> Result<Self, NetError> {
+        stream.set_nodelay(true)?;
+        let mut e = Scalar::random(&mut *csprng);
+
+        let mut hello = Vec::with_capacity(HELLO_LEN);
+        hello.extend_from_slice(HANDSHAKE_MAGIC);
+        hello.extend_from_slice(&(id as u64).to_be_bytes());
+        hello.extend_from_slice(&(peer as u64).to_be_bytes());
+        hello.extend_from_slice(&G1Affine::from(G1Projective::generator() * e).to_compressed());
+        stream.write_all(&hello).await?;
+
+        let mut reply = [0u8; POINT_LEN];
+        stream.read_exact(&mut reply).await?;
+        let E_peer = read_point(&reply)?;
+
+        let (send, recv) = derive_keys(
+            &hello,
+            &reply,
+            &key.public_key(),
+            peer_key,
+            [E_peer * e, peer_key * e, E_peer * key.sk],
+        );
+        e.zeroize();
+        Ok(Self::new(stream, peer, send, recv))
+    }
+
+    /// Accepts a channel as party `id` from any of the parties in `peer_keys`,
+    /// which maps their ids to their long-term public keys.
+    pub async fn accept(
+        mut stream: TcpStream,
+        key: &NodeKey,
+        id: usize,
+        peer_keys: &BTreeMap<usize, G1Projective>,
+        csprng: &mut impl CryptoRngCore,
+    ) -> Result<Self, NetError> {
+        stream.set_nodelay(true)?;
+        let mut hello = [0u8; HELLO_LEN];
+        stream.read_exact(&mut hello).await?;
+
+        let (magic, rest) = hello.split_at(HANDSHAKE_MAGIC.len());
+        let peer = u64::from_be_bytes(rest[..8].try_into().unwrap()) as usize;
+        let to = u64::from_be_bytes(rest[8..16].try_into().unwrap());
+        if magic != HANDSHAKE_MAGIC || to != id as u64 {
+            return Err(NetError::Handshake);
+        }
+        let peer_key = peer_keys.get(&peer).ok_or(NetError::UnknownPeer(peer))?;
+        let E_peer = read_point(&rest[16..])?;
+
+        let mut e = Scalar::random(&mut *csprng);
+        let reply = G1Affine::from(G1Projective::generator() * e).to_compressed();
+        stream.write_all(&reply).await?;
+
+        let (recv, send) = derive_keys(
+            &hello,
+            &reply,
+            peer_key,
+            &key.public_key(),
+            [E_peer * e, E_peer * key.sk, peer_key * e],
+        );
+        e.zeroize();
+        Ok(Self::new(stream, peer, send, recv))
+    }
+
+    fn new(stream: TcpStream, peer: usize, send: ChaCha20Poly1305, recv: ChaCha20Poly1305) -> Self {
+        let (read, write) = stream.into_split();
+        Self {
+            peer,
+            reader: ChannelReader {
+                stream: read,
+                cipher: recv,
+                counter: 0,
+            },
+            writer: ChannelWriter {
+                stream: write,
+                cipher: send,
+                counter: 0,
+            },
+        }
+    }
+
+    /// The id of the party at the other end.
+    pub fn peer(&self) -> usize {
+        self.peer
+    }
+
+    pub async fn send<T: Wire>(&mut self, value: &T) -> Result<(), NetError> {
+        self.writer.send(value).await
+    }
+
+    pub async fn recv<T: Wire>(&mut self, cl: &CL_HSMqk) -> Result<T, NetError> {
+        self.reader.recv(cl).await
+    }
+
+    pub fn split(self) -> (ChannelReader, ChannelWriter) {
+        (self.reader, self.writer)
+    }
+}
+
+impl ChannelReader {
+    /// Receives a value, checking the forms in it against `cl`. Fails with
+    /// [`NetError::Closed`] if the peer closed the channel.
+    pub async fn recv<T: Wire>(&mut self, cl: &CL_HSMqk) -> Result<T, NetError> {
+        Ok(T::from_wire_bytes(&self.recv_frame().await?, cl)?)
+    }
+
+    pub(crate) async fn recv_frame(&mut self) -> Result<Vec<u8>, NetError> {
+        let mut len = [0u8; 4];
+        match self.stream.read_exact(&mut len).await {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Err(NetError::Closed),
+            result => result?,
+        };
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(NetError::FrameTooLarge(len));
+        }
+        let mut ciphertext = vec![0u8; len];
+        self.stream.read_exact(&mut ciphertext).await?;
+
+        let plaintext = self
+            .cipher
+            .decrypt(&nonce(self.counter), ciphertext.as_slice())
+            .map_err(|_| NetError::Decryption)?;
+        self.counter += 1;
+        Ok(plaintext)
+    }
+}
+
+impl ChannelWriter {
+    pub async fn send<T: Wire>(&mut self, value: &T) -> Result<(), NetError> {
+        self.send_frame(&value.to_wire_bytes()).await
+    }
+
+    pub(crate) async fn send_frame(&mut self, plaintext: &[u8]) -> Result<(), NetError> {
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce(self.counter), plaintext)
+            .map_err(|_| NetError::FrameTooLarge(plaintext.len()))?;
+        if ciphertext.len() > MAX_FRAME_LEN {
+            return Err(NetError::FrameTooLarge(ciphertext.len()));
+        }
+        self.counter += 1;
+
+        let mut frame = Vec::with_capacity(4 + ciphertext.len());
+        frame.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&ciphertext);
+        self.stream.write_all(&frame).await?;
+        Ok(())
+    }
+}
+
+fn nonce(counter: u64) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    *Nonce::from_slice(&nonce)
+}
+
+fn read_point(bytes: &[u8]) -> Result<G1Projective, NetError> {
+    Option::<G1Affine>::from(G1Affine::from_compressed(bytes.try_into().unwrap()))
+        .filter(|point| !bool::from(point.is_identity()))
+        .map(G1Projective::from)
+        .ok_or(NetError::Handshake)
+}
+
+/// The initiator-to-responder and responder-to-initiator ciphers.
+fn derive_keys(
+    hello: &[u8],
+    reply: &[u8],
+    initiator_key: &G1Projective,
+    responder_key: &G1Projective,
+    dhs: [G1Projective; 3],
+) -> (ChaCha20Poly1305, ChaCha20Poly1305) {
+    let mut hasher = Sha256::new();
+    hasher.update(HANDSHAKE_MAGIC);
+    hasher.update(hello);
+    hasher.update(reply);
+    hasher.update(G1Affine::from(initiator_key).to_compressed());
+    hasher.update(G1Affine::from(responder_key).to_compressed());
+    for dh in &dhs {
+        hasher.update(G1Affine::from(dh).to_compressed());
+    }
+    let mut secret: [u8; 32] = hasher.finalize().into();
+
+    let cipher = |direction: &[u8]| {
+        let mut key: [u8; 32] = Sha256::new()
+            .chain_update(secret)
+            .chain_update(direction)
+            .finalize()
+            .into();
+        let cipher = ChaCha20Poly1305::new(&key.into());
+        key.zeroize();
+        cipher
+    };
+    let ciphers = (cipher(b"initiator"), cipher(b"responder"));
+    secret.zeroize();
+    ciphers
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    async fn open(
+        initiator: &NodeKey,
+        responder: &NodeKey,
+        peer_keys: &BTreeMap<usize, G1Projective>,
+    ) -> (Result<Channel, NetError>, Result<Channel, NetError>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let responder_key = G1Projective::generator() * responder.sk;
+        tokio::join!(
+            Channel::dial(
+                addr,
+                initiator,
+                1,
+                2,
+                &responder_key,
+                &mut ChaChaRng::from_seed([1u8; 32]),
+            ),
+            async {
+                let (stream, _) = listener.accept().await.unwrap();
+                Channel::accept(
+                    stream,
+                    responder,
+                    2,
+                    peer_keys,
+                    &mut ChaChaRng::from_seed([2u8; 32]),
+                )
+                .await
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_channel() {
+        let mut csprng = ChaChaRng::from_seed([0u8; 32]);
+        let alice = NodeKey::generate(&mut csprng);
+        let bob = NodeKey::generate(&mut csprng);
+        let mallory = NodeKey::generate(&mut csprng);
+        let peer_keys = BTreeMap::from([(1, alice.public_key())]);
+
+        let (alice_end, bob_end) = open(&alice, &bob, &peer_keys).await;
+        let (mut alice_end, mut bob_end) = (alice_end.unwrap(), bob_end.unwrap());
+        assert_eq!((alice_end.peer(), bob_end.peer()), (2, 1));
+        for frame in [&b"ping"[..], &b""[..], &[7u8; 1000][..]] {
+            alice_end.writer.send_frame(frame).await.unwrap();
+            assert_eq!(bob_end.reader.recv_frame().await.unwrap(), frame);
+            bob_end.writer.send_frame(frame).await.unwrap();
+            assert_eq!(alice_end.reader.recv_frame().await.unwrap(), frame);
+        }
+        drop(alice_end);
+        assert!(matches!(
+            bob_end.reader.recv_frame().await,
+            Err(NetError::Closed)
+        ));
+
+        // Mallory claims to be party 1, but cannot derive the keys without
+        // Alice's long-term key.
+        let (mallory_end, bob_end) = open(&mallory, &bob, &peer_keys).await;
+        let (mut mallory_end, mut bob_end) = (mallory_end.unwrap(), bob_end.unwrap());
+        mallory_end.writer.send_frame(b"ping").await.unwrap();
+        assert!(matches!(
+            bob_end.reader.recv_frame().await,
+            Err(NetError::Decryption)
+        ));
+
+        let (_, bob_end) = open(&alice, &bob, &BTreeMap::new()).await;
+        assert!(matches!(bob_end, Err(NetError::UnknownPeer(1))));
+    }
+}
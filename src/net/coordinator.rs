@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+
+use bicycl::{CL_HSMqk, Mpz};
+use bls12_381::Scalar;
+use futures::future::try_join_all;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use tokio::time::timeout;
+
+use crate::error::blame;
+use crate::t_out_of_n::setbbsplus::Sign;
+use crate::t_out_of_n::{BBSPlusPublicKey, BBSPlusSig, PartialSig};
+use crate::{
+    Channel, CommitRequest, KeygenRequest, NetError, NodeError, NodeKey, Peer, ProtocolError,
+    Request, Response, SignRequest, COORDINATOR, SESSION_TIMEOUT,
+};
+
+/// Starts sessions on the [`Node`](crate::Node)s and collects their outputs.
+/// Every session gets a fresh random session id.
+pub struct Coordinator {
+    cl: CL_HSMqk,
+    key: NodeKey,
+    peers: BTreeMap<usize, Peer>,
+}
+
+impl Coordinator {
+    pub fn new(cl: CL_HSMqk, key: NodeKey, peers: BTreeMap<usize, Peer>) -> Self {
+        Self { cl, key, peers }
+    }
+
+    /// Runs the DKG among all the nodes and returns the public key they agree
+    /// on. The nodes only store their key shares once they all got the same
+    /// public parts out of it.
+    pub async fn keygen(&self, t: usize, l: usize) -> Result<BBSPlusPublicKey, NodeError> {
+        let session_id = session_id();
+        let request = Request::Keygen(KeygenRequest {
+            session_id: session_id.clone(),
+            t,
+            l,
+        });
+        let parties: Vec<usize> = self.peers.keys().cloned().collect();
+        let outputs = self
+            .request_all(&parties, &request)
+            .await?
+            .into_iter()
+            .map(|(j, response)| match response {
+                Response::Keygen(output) => Ok((j, output)),
+                _ => Err(NodeError::UnexpectedResponse(j)),
+            })
+            .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+        let first = outputs
+            .values()
+            .next()
+            .ok_or(NodeError::InvalidRequest("there are no nodes"))?;
+        let culprits = outputs
+            .iter()
+            .filter(|(_, output)| *output != first)
+            .map(|(j, _)| *j)
+            .collect();
+        blame(culprits, ProtocolError::Inconsistent)?;
+
+        let request = Request::Commit(CommitRequest {
+            session_id,
+            digest: first.digest.clone(),
+        });
+        for (j, response) in self.request_all(&parties, &request).await? {
+            match response {
+                Response::Committed(public_key) if public_key == first.public_key => {}
+                _ => return Err(NodeError::UnexpectedResponse(j)),
+            }
+        }
+        Ok(first.public_key.clone())
+    }
+
+    /// Runs a signing session over `msg` among the nodes in `signers`, and
+    /// returns their partial signatures, the input of [`Sign::from_partials`].
+    pub async fn partials(
+        &self,
+        signers: &[usize],
+        msg: &[Scalar],
+    ) -> Result<BTreeMap<usize, PartialSig>, NodeError> {
+        let request = Request::Sign(SignRequest {
+            session_id: session_id(),
+            signers: signers.to_vec(),
+            msg: msg.to_vec(),
+        });
+        self.request_all(signers, &request)
+            .await?
+            .into_iter()
+            .map(|(j, response)| match response {
+                Response::Sign(partial) => Ok((j, partial)),
+                _ => Err(NodeError::UnexpectedResponse(j)),
+            })
+            .collect()
+    }
+
+    /// Signs `msg` with the nodes in `signers`, and checks the signature against
+    /// `public_key`.
+    pub async fn sign(
+        &self,
+        public_key: &BBSPlusPublicKey,
+        signers: &[usize],
+        msg: &[Scalar],
+    ) -> Result<BBSPlusSig, NodeError> {
+        let partials = self.partials(signers, msg).await?;
+
+        let mut n_factorial = Mpz::from(1u64);
+        for i in 1..=self.peers.len() {
+            n_factorial = Mpz::from(i as u64) * &n_factorial;
+        }
+        let sign_msg = Sign::from_partials(&public_key.H, &public_key.X, &n_factorial, &partials)?;
+        Ok(Sign::client(&self.cl, &sign_msg, msg, msg.len())?)
+    }
+
+    async fn request_all(
+        &self,
+        parties: &[usize],
+        request: &Request,
+    ) -> Result<Vec<(usize, Response)>, NodeError> {
+        try_join_all(
+            parties
+                .iter()
+                .map(|j| async move { Ok::<_, NodeError>((*j, self.request(*j, request).await?)) }),
+        )
+        .await
+    }
+
+    async fn request(&self, j: usize, request: &Request) -> Result<Response, NodeError> {
+        let peer = self.peers.get(&j).ok_or(NetError::UnknownPeer(j))?;
+        let mut channel = Channel::dial(
+            peer.addr,
+            &self.key,
+            COORDINATOR,
+            j,
+            &peer.public_key,
+            &mut OsRng,
+        )
+        .await?;
+        channel.send(request).await?;
+        let response = timeout(SESSION_TIMEOUT, channel.recv(&self.cl))
+            .await
+            .map_err(|_| NetError::Timeout)??;
+        match response {
+            Response::Aborted(abort) => Err(NodeError::Aborted {
+                party: j,
+                reason: abort.reason,
+                culprits: abort.culprits,
+            }),
+            response => Ok(response),
+        }
+    }
+}
+
+fn session_id() -> Vec<u8> {
+    let mut session_id = vec![0u8; 16];
+    OsRng.fill_bytes(&mut session_id);
+    session_id
+}
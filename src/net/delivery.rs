@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use bicycl::CL_HSMqk;
+use futures::sink::{self, Sink};
+use futures::stream::{self, BoxStream, StreamExt};
+use round_based::{Incoming, MessageDestination, MessageType, Outgoing, PartyIndex};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::{Channel, ChannelReader, ChannelWriter, NetError, Reader, Wire, WireError};
+
+pub type Incomings<'a, M> = BoxStream<'a, Result<Incoming<M>, NetError>>;
+pub type Outgoings<'a, M> = Pin<Box<dyn Sink<Outgoing<M>, Error = NetError> + Send + 'a>>;
+
+/// A frame of a session: a protocol message with its [`MessageType`], or the
+/// `hash` of the `seq`-th broadcast we got from the party with index `sender`,
+/// echoed to the other parties.
+enum Envelope<M> {
+    Message {
+        broadcast: bool,
+        msg: M,
+    },
+    Echo {
+        sender: usize,
+        seq: usize,
+        hash: Vec<u8>,
+    },
+}
+
+impl<M: Wire> Wire for Envelope<M> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Message { broadcast, msg } => {
+                out.push(1);
+                broadcast.encode(out);
+                msg.encode(out);
+            }
+            Self::Echo { sender, seq, hash } => {
+                out.push(2);
+                sender.encode(out);
+                seq.encode(out);
+                hash.encode(out);
+            }
+        }
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        match u8::decode(r)? {
+            1 => Ok(Self::Message {
+                broadcast: Wire::decode(r)?,
+                msg: Wire::decode(r)?,
+            }),
+            2 => Ok(Self::Echo {
+                sender: Wire::decode(r)?,
+                seq: Wire::decode(r)?,
+                hash: Wire::decode(r)?,
+            }),
+            _ => Err(WireError::InvalidElement("frame tag")),
+        }
+    }
+}
+
+/// The broadcasts we received, held back until every other party has echoed
+/// the same hash of them, so that a sender cannot broadcast different messages
+/// to different parties.
+struct Echoes<M> {
+    peers: usize,
+    seqs: BTreeMap<PartyIndex, usize>,
+    messages: BTreeMap<(PartyIndex, usize), M>,
+    hashes: BTreeMap<(PartyIndex, usize), BTreeMap<PartyIndex, Vec<u8>>>,
+}
+
+impl<M> Echoes<M> {
+    fn new(peers: usize) -> Self {
+        Self {
+            peers,
+            seqs: BTreeMap::new(),
+            messages: BTreeMap::new(),
+            hashes: BTreeMap::new(),
+        }
+    }
+
+    /// Holds back a broadcast from `sender`, returning its sequence number.
+    fn receive(&mut self, sender: PartyIndex, msg: M) -> usize {
+        let next = self.seqs.entry(sender).or_default();
+        let seq = *next;
+        *next += 1;
+        self.messages.insert((sender, seq), msg);
+        seq
+    }
+
+    /// Records that `from` saw `hash` as broadcast `seq` of `sender`, and returns
+    /// the broadcast once all the peers agree on it. Fails with `sender` if two
+    /// of them saw different broadcasts.
+    fn record(
+        &mut self,
+        sender: PartyIndex,
+        seq: usize,
+        from: PartyIndex,
+        hash: Vec<u8>,
+    ) -> Result<Option<M>, PartyIndex> {
+        let hashes = self.hashes.entry((sender, seq)).or_default();
+        if hashes.values().any(|other| *other != hash) {
+            return Err(sender);
+        }
+        hashes.insert(from, hash);
+        if hashes.len() < self.peers {
+            return Ok(None);
+        }
+        let msg = self.messages.remove(&(sender, seq));
+        if msg.is_some() {
+            self.hashes.remove(&(sender, seq));
+        }
+        Ok(msg)
+    }
+}
+
+/// A `round_based` delivery for a session among `parties` over the `channels` to
+/// all of them but us. Party `parties[k]` gets index `k`, and received messages
+/// are checked against `cl`. Broadcasts are sent to every party in turn, and
+/// every party echoes the hash of each broadcast it gets to the others, so a
+/// broadcast is only delivered once all the parties are known to have got the
+/// same one.
+pub fn tcp_delivery<'a, M>(
+    cl: &'a CL_HSMqk,
+    parties: &[usize],
+    channels: BTreeMap<usize, Channel>,
+) -> Result<(Incomings<'a, M>, Outgoings<'a, M>), NetError>
+where
+    M: Wire + Send + 'a,
+{
+    let mut readers = Vec::with_capacity(channels.len());
+    let mut writers = BTreeMap::new();
+    for (j, channel) in channels {
+        let index = parties
+            .iter()
+            .position(|p| *p == j)
+            .ok_or(NetError::UnknownPeer(j))? as PartyIndex;
+        let (reader, writer) = channel.split();
+        readers.push(incoming_from(cl, index, reader));
+        writers.insert(index, writer);
+    }
+    let echoes = Echoes::new(writers.len());
+    let writers = Arc::new(Mutex::new(writers));
+    let parties = parties.to_vec();
+
+    let frames = stream::select_all(readers);
+    let incoming = stream::unfold(
+        (frames, echoes, writers.clone()),
+        move |(mut frames, mut echoes, writers)| {
+            let parties = parties.clone();
+            async move {
+                loop {
+                    let (from, envelope) = match frames.next().await? {
+                        Ok(frame) => frame,
+                        Err(err) => return Some((Err(err), (frames, echoes, writers))),
+                    };
+                    let (sender, recorded) = match envelope {
+                        Envelope::Message {
+                            broadcast: false,
+                            msg,
+                        } => {
+                            let msg = Ok((from, MessageType::P2P, msg));
+                            return Some((msg, (frames, echoes, writers)));
+                        }
+                        Envelope::Message {
+                            broadcast: true,
+                            msg,
+                        } => {
+                            let hash = Sha256::digest(msg.to_wire_bytes()).to_vec();
+                            let seq = echoes.receive(from, msg);
+                            let echo = Envelope::<M>::Echo {
+                                sender: from as usize,
+                                seq,
+                                hash: hash.clone(),
+                            }
+                            .to_wire_bytes();
+                            if let Err(err) = send_all(&writers, &echo, Some(from)).await {
+                                return Some((Err(err), (frames, echoes, writers)));
+                            }
+                            (from, echoes.record(from, seq, from, hash))
+                        }
+                        // A party echoing its own broadcast vouches for nothing.
+                        Envelope::Echo { sender, seq, hash } => {
+                            let sender = sender as PartyIndex;
+                            if sender as usize >= parties.len() || sender == from {
+                                continue;
+                            }
+                            (sender, echoes.record(sender, seq, from, hash))
+                        }
+                    };
+                    match recorded {
+                        Ok(Some(msg)) => {
+                            let msg = Ok((sender, MessageType::Broadcast, msg));
+                            return Some((msg, (frames, echoes, writers)));
+                        }
+                        Ok(None) => continue,
+                        Err(sender) => {
+                            let err = NetError::Equivocation(parties[sender as usize]);
+                            return Some((Err(err), (frames, echoes, writers)));
+                        }
+                    }
+                }
+            }
+        },
+    )
+    .enumerate()
+    .map(|(id, msg)| {
+        msg.map(|(sender, msg_type, msg)| Incoming {
+            id: id as u64,
+            sender,
+            msg_type,
+            msg,
+        })
+    })
+    .boxed();
+
+    let outgoing = sink::unfold(writers, |writers, outgoing: Outgoing<M>| async move {
+        match outgoing.recipient {
+            MessageDestination::AllParties => {
+                let frame = Envelope::Message {
+                    broadcast: true,
+                    msg: outgoing.msg,
+                }
+                .to_wire_bytes();
+                send_all(&writers, &frame, None).await?;
+            }
+            MessageDestination::OneParty(j) => {
+                let frame = Envelope::Message {
+                    broadcast: false,
+                    msg: outgoing.msg,
+                }
+                .to_wire_bytes();
+                writers
+                    .lock()
+                    .await
+                    .get_mut(&j)
+                    .ok_or(NetError::UnknownPeer(j.into()))?
+                    .send_frame(&frame)
+                    .await?;
+            }
+        }
+        Ok(writers)
+    });
+
+    Ok((incoming, Box::pin(outgoing)))
+}
+
+/// Sends `frame` to every party but `except`.
+async fn send_all(
+    writers: &Mutex<BTreeMap<PartyIndex, ChannelWriter>>,
+    frame: &[u8],
+    except: Option<PartyIndex>,
+) -> Result<(), NetError> {
+    let mut writers = writers.lock().await;
+    for (j, writer) in writers.iter_mut() {
+        if Some(*j) != except {
+            writer.send_frame(frame).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The frames from the party with index `sender`. A peer closing the channel is
+/// done with the session, so that just ends the stream; if we still miss a
+/// message from it, the round fails on its own.
+fn incoming_from<'a, M>(
+    cl: &'a CL_HSMqk,
+    sender: PartyIndex,
+    reader: ChannelReader,
+) -> BoxStream<'a, Result<(PartyIndex, Envelope<M>), NetError>>
+where
+    M: Wire + Send + 'a,
+{
+    stream::unfold(Some(reader), move |reader| async move {
+        let mut reader = reader?;
+        match reader.recv::<Envelope<M>>(cl).await {
+            Ok(envelope) => Some((Ok((sender, envelope)), Some(reader))),
+            Err(NetError::Closed) => None,
+            Err(err) => Some((Err(err), None)),
+        }
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_echoes() {
+        // Party 0 broadcasts to us and to parties 1 and 2.
+        let mut echoes = Echoes::new(3);
+        let seq = echoes.receive(0, 7u8);
+        assert_eq!(echoes.record(0, seq, 0, vec![7]), Ok(None));
+        assert_eq!(echoes.record(0, seq, 1, vec![7]), Ok(None));
+        assert_eq!(echoes.record(0, seq, 2, vec![7]), Ok(Some(7)));
+
+        // Its next broadcast, which party 2 saw differently.
+        assert_eq!(echoes.record(0, 1, 1, vec![8]), Ok(None));
+        let seq = echoes.receive(0, 8u8);
+        assert_eq!(seq, 1);
+        assert_eq!(echoes.record(0, seq, 0, vec![8]), Ok(None));
+        assert_eq!(echoes.record(0, seq, 2, vec![9]), Err(0));
+    }
+}
@@ -0,0 +1,61 @@
+//! Running the t-out-of-n SET-BBS+ protocols between separate processes.
+//!
+//! Every party runs a [`Node`] holding its key share. Nodes talk to each other,
+//! and to the [`Coordinator`] that starts sessions on them, over the
+//! authenticated and encrypted TCP [`Channel`]s. Within a session the channels
+//! are turned into a `round_based` delivery with [`tcp_delivery`], so the nodes
+//! run the very same [`keygen_party`](crate::t_out_of_n::keygen_party) and
+//! [`sign_party`](crate::t_out_of_n::sign_party) as the in-memory simulation.
+
+use std::error::Error as StdError;
+use std::io;
+
+use thiserror::Error;
+
+use crate::WireError;
+
+pub mod channel;
+pub use channel::*;
+
+pub mod delivery;
+pub use delivery::*;
+
+pub mod node;
+pub use node::*;
+
+pub mod coordinator;
+pub use coordinator::*;
+
+#[derive(Debug, Error)]
+pub enum NetError {
+    #[error("i/o error")]
+    Io(#[from] io::Error),
+    #[error("handshake failed")]
+    Handshake,
+    #[error("party {0} is not a known peer")]
+    UnknownPeer(usize),
+    #[error("frame of {0} bytes exceeds the limit")]
+    FrameTooLarge(usize),
+    #[error("message authentication failed")]
+    Decryption,
+    #[error("malformed message")]
+    Wire(#[from] WireError),
+    #[error("connection closed")]
+    Closed,
+    #[error("timed out")]
+    Timeout,
+    #[error("party {0} broadcast different messages to different parties")]
+    Equivocation(usize),
+}
+
+/// `err` followed by the chain of its sources, for reporting to a coordinator or
+/// an operator.
+pub fn report(err: &dyn StdError) -> String {
+    let mut reason = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        reason = format!("{reason}: {err}");
+        source = err.source();
+    }
+    reason
+}
@@ -0,0 +1,503 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bicycl::CL_HSMqk;
+use bls12_381::{G1Projective, Scalar};
+use rand::rngs::OsRng;
+use round_based::{MpcParty, PartyIndex};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::time::timeout;
+
+use crate::t_out_of_n::{
+    keygen_party, sign_party, BBSPlusPublicKey, KeyGenPartyError, KeyShare, PartialSig,
+    SignPartyError,
+};
+use crate::{
+    check_signers, load_key_share, rand_gen, report, save_key_share, tcp_delivery, Channel,
+    KeyStoreError, NetError, NodeKey, ProtocolError, Wire,
+};
+
+/// The party id of the coordinator on the channels to the nodes.
+pub const COORDINATOR: usize = 0;
+
+/// How long a node waits for the other parties to join a session and for the
+/// session to finish.
+pub const SESSION_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Peer {
+    pub addr: SocketAddr,
+    pub public_key: G1Projective,
+}
+
+pub struct NodeConfig {
+    pub id: usize,
+    pub key: NodeKey,
+    /// All the parties `1..=n`, including this one.
+    pub peers: BTreeMap<usize, Peer>,
+    /// The long-term public key of the [`Coordinator`](crate::Coordinator).
+    pub coordinator: G1Projective,
+    /// Where the key share is kept, encrypted under `passphrase`, as a
+    /// [`NodeState`].
+    pub key_share_path: PathBuf,
+    pub passphrase: Vec<u8>,
+}
+
+/// What a node keeps in its key share file: the key share, and the ids of the
+/// sessions it joined, so that it refuses to reuse them after a restart too.
+pub struct NodeState {
+    pub key_share: KeyShare,
+    pub claimed: BTreeSet<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeygenRequest {
+    pub session_id: Vec<u8>,
+    pub t: usize,
+    pub l: usize,
+}
+
+/// Sent by the coordinator once every node got the same `digest` out of the DKG
+/// `session_id`, for the nodes to store their key shares.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommitRequest {
+    pub session_id: Vec<u8>,
+    pub digest: Vec<u8>,
+}
+
+/// What a node got out of a DKG: the public key, and a hash of all the public
+/// parts of its key share, which is the same on every node unless they were
+/// fed different messages.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeygenOutput {
+    pub public_key: BBSPlusPublicKey,
+    pub digest: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignRequest {
+    pub session_id: Vec<u8>,
+    pub signers: Vec<usize>,
+    pub msg: Vec<Scalar>,
+}
+
+/// Why a node gave up on a session, and whom it blames for it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Abort {
+    pub reason: String,
+    pub culprits: Vec<usize>,
+}
+
+/// Sent by the coordinator to start a session on a node.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    Keygen(KeygenRequest),
+    Sign(SignRequest),
+    Commit(CommitRequest),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Response {
+    Keygen(KeygenOutput),
+    Sign(PartialSig),
+    Aborted(Abort),
+    Committed(BBSPlusPublicKey),
+}
+
+#[derive(Debug, Error)]
+pub enum NodeError {
+    #[error("network error")]
+    Net(#[from] NetError),
+    #[error("cannot access the key share")]
+    KeyStore(#[from] KeyStoreError),
+    #[error("invalid request: {0}")]
+    InvalidRequest(&'static str),
+    #[error("invalid node configuration: {0}")]
+    InvalidConfig(&'static str),
+    #[error("the session id was used before")]
+    SessionReused,
+    #[error("protocol aborted")]
+    Protocol(#[from] ProtocolError),
+    #[error("key generation failed")]
+    Keygen(#[from] KeyGenPartyError<NetError, NetError>),
+    #[error("signing failed")]
+    Sign(#[from] SignPartyError<NetError, NetError>),
+    #[error("unexpected response from party {0}")]
+    UnexpectedResponse(usize),
+    #[error("party {party} aborted: {reason}")]
+    Aborted {
+        party: usize,
+        reason: String,
+        culprits: Vec<usize>,
+    },
+}
+
+impl NodeError {
+    /// The ids of the parties responsible for the error, if it was caused by
+    /// someone misbehaving.
+    pub fn culprits(&self) -> &[usize] {
+        match self {
+            Self::Protocol(err)
+            | Self::Keygen(KeyGenPartyError::Protocol(err))
+            | Self::Sign(SignPartyError::Protocol(err)) => err.culprits(),
+            Self::Aborted { culprits, .. } => culprits,
+            _ => &[],
+        }
+    }
+}
+
+/// Hosts the key share of one party, and runs the DKG and signing sessions the
+/// coordinator asks for with the other nodes. The key share of a DKG is only
+/// stored once the coordinator confirms that all the nodes got the same.
+pub struct Node {
+    cl: CL_HSMqk,
+    config: NodeConfig,
+    key_share: Mutex<Option<KeyShare>>,
+    uncommitted: Mutex<Option<Uncommitted>>,
+    sessions: Mutex<Sessions>,
+}
+
+/// The output of the last DKG, until the coordinator commits it.
+struct Uncommitted {
+    session_id: Vec<u8>,
+    key_share: KeyShare,
+    digest: Vec<u8>,
+}
+
+/// The channels other nodes opened for a session, until we join it.
+#[derive(Default)]
+struct Sessions {
+    pending: HashMap<Vec<u8>, SessionQueue>,
+    claimed: BTreeSet<Vec<u8>>,
+}
+
+struct SessionQueue {
+    tx: UnboundedSender<Channel>,
+    rx: Option<UnboundedReceiver<Channel>>,
+}
+
+impl Sessions {
+    fn queue(&mut self, session_id: &[u8]) -> &mut SessionQueue {
+        self.pending.entry(session_id.to_vec()).or_insert_with(|| {
+            let (tx, rx) = unbounded_channel();
+            SessionQueue { tx, rx: Some(rx) }
+        })
+    }
+
+    fn deliver(&mut self, session_id: &[u8], channel: Channel) {
+        if self.claimed.contains(session_id) && !self.pending.contains_key(session_id) {
+            return;
+        }
+        let _ = self.queue(session_id).tx.send(channel);
+    }
+
+    fn claim(&mut self, session_id: &[u8]) -> Option<UnboundedReceiver<Channel>> {
+        if !self.claimed.insert(session_id.to_vec()) {
+            return None;
+        }
+        self.queue(session_id).rx.take()
+    }
+}
+
+impl Node {
+    /// Loads the key share from `config.key_share_path` if there is one, i.e. if
+    /// the node took part in a DKG before.
+    pub fn new(cl: CL_HSMqk, config: NodeConfig) -> Result<Self, NodeError> {
+        let n = config.peers.len();
+        if !config.peers.keys().cloned().eq(1..=n) {
+            return Err(NodeError::InvalidConfig(
+                "the peers must be the parties 1..=n",
+            ));
+        }
+        if !config.peers.contains_key(&config.id) {
+            return Err(NodeError::InvalidConfig("the node is not one of the peers"));
+        }
+        let mut sessions = Sessions::default();
+        let key_share = match load_key_share(&config.key_share_path, &cl, &config.passphrase) {
+            Ok(NodeState { key_share, claimed }) => {
+                sessions.claimed = claimed;
+                Some(key_share)
+            }
+            Err(KeyStoreError::Io(err)) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self {
+            cl,
+            config,
+            key_share: Mutex::new(key_share),
+            uncommitted: Mutex::default(),
+            sessions: Mutex::new(sessions),
+        })
+    }
+
+    pub fn public_key(&self) -> Option<BBSPlusPublicKey> {
+        self.key_share
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(KeyShare::public_key)
+    }
+
+    /// Accepts connections from the coordinator and the other nodes until
+    /// `listener` fails.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<(), NodeError> {
+        loop {
+            let (stream, _) = listener.accept().await.map_err(NetError::from)?;
+            let node = self.clone();
+            tokio::spawn(async move {
+                // A broken connection only fails the session it belongs to, and
+                // the coordinator hears about that from the nodes it asked.
+                let _ = node.handle(stream).await;
+            });
+        }
+    }
+
+    async fn handle(&self, stream: TcpStream) -> Result<(), NodeError> {
+        let mut peer_keys: BTreeMap<usize, G1Projective> = self
+            .config
+            .peers
+            .iter()
+            .filter(|(j, _)| **j != self.config.id)
+            .map(|(j, peer)| (*j, peer.public_key))
+            .collect();
+        peer_keys.insert(COORDINATOR, self.config.coordinator);
+        let mut channel = Channel::accept(
+            stream,
+            &self.config.key,
+            self.config.id,
+            &peer_keys,
+            &mut OsRng,
+        )
+        .await?;
+
+        if channel.peer() == COORDINATOR {
+            let request = channel.recv(&self.cl).await?;
+            let response = self.run(request).await.unwrap_or_else(|err| {
+                Response::Aborted(Abort {
+                    reason: report(&err),
+                    culprits: err.culprits().to_vec(),
+                })
+            });
+            channel.send(&response).await?;
+        } else {
+            let session_id: Vec<u8> = channel.recv(&self.cl).await?;
+            self.sessions.lock().unwrap().deliver(&session_id, channel);
+        }
+        Ok(())
+    }
+
+    async fn run(&self, request: Request) -> Result<Response, NodeError> {
+        match request {
+            Request::Keygen(request) => self.keygen(request).await.map(Response::Keygen),
+            Request::Sign(request) => self.sign(request).await.map(Response::Sign),
+            Request::Commit(request) => self.commit(request).map(Response::Committed),
+        }
+    }
+
+    async fn keygen(&self, request: KeygenRequest) -> Result<KeygenOutput, NodeError> {
+        let n = self.config.peers.len();
+        if request.t == 0 || request.t > n || request.l == 0 {
+            return Err(NodeError::InvalidRequest(
+                "invalid threshold or message length",
+            ));
+        }
+        // Overwriting a key share would lose the key for good.
+        if self.key_share.lock().unwrap().is_some() {
+            return Err(NodeError::InvalidRequest(
+                "the node already holds a key share",
+            ));
+        }
+        let parties: Vec<usize> = self.config.peers.keys().cloned().collect();
+        let channels = self.join(&request.session_id, &parties).await?;
+
+        let mut csprng = OsRng;
+        let mut rng = rand_gen(&mut csprng);
+        let party = MpcParty::connected(tcp_delivery(&self.cl, &parties, channels)?);
+        let key_share = timeout(
+            SESSION_TIMEOUT,
+            keygen_party(
+                party,
+                (self.config.id - 1) as PartyIndex,
+                request.t as u16,
+                n as u16,
+                &self.cl,
+                &request.session_id,
+                request.l,
+                &mut rng,
+                &mut csprng,
+            ),
+        )
+        .await
+        .map_err(|_| NetError::Timeout)??;
+
+        let output = KeygenOutput {
+            public_key: key_share.public_key(),
+            digest: digest(&key_share),
+        };
+        *self.uncommitted.lock().unwrap() = Some(Uncommitted {
+            session_id: request.session_id,
+            key_share,
+            digest: output.digest.clone(),
+        });
+        Ok(output)
+    }
+
+    /// Stores the key share of the DKG `request.session_id`, if all the nodes
+    /// got the same out of it as we did.
+    fn commit(&self, request: CommitRequest) -> Result<BBSPlusPublicKey, NodeError> {
+        let uncommitted = self
+            .uncommitted
+            .lock()
+            .unwrap()
+            .take()
+            .filter(|uncommitted| uncommitted.session_id == request.session_id)
+            .ok_or(NodeError::InvalidRequest(
+                "no such key generation to commit",
+            ))?;
+        if uncommitted.digest != request.digest {
+            return Err(NodeError::InvalidRequest(
+                "the nodes disagree on the key share",
+            ));
+        }
+        let mut key_share = self.key_share.lock().unwrap();
+        if key_share.is_some() {
+            return Err(NodeError::InvalidRequest(
+                "the node already holds a key share",
+            ));
+        }
+        self.store(&uncommitted.key_share)?;
+        let public_key = uncommitted.key_share.public_key();
+        *key_share = Some(uncommitted.key_share);
+        Ok(public_key)
+    }
+
+    /// Writes `key_share` and the sessions we claimed to the key share file.
+    fn store(&self, key_share: &KeyShare) -> Result<(), NodeError> {
+        let state = NodeState {
+            key_share: key_share.clone(),
+            claimed: self.sessions.lock().unwrap().claimed.clone(),
+        };
+        save_key_share(
+            &self.config.key_share_path,
+            &self.cl,
+            &state,
+            &self.config.passphrase,
+        )?;
+        Ok(())
+    }
+
+    async fn sign(&self, request: SignRequest) -> Result<PartialSig, NodeError> {
+        let key_share = self
+            .key_share
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(NodeError::InvalidRequest("the node holds no key share"))?;
+        check_signers(&request.signers, key_share.t, key_share.n)?;
+        let i = request
+            .signers
+            .iter()
+            .position(|j| *j == self.config.id)
+            .ok_or(NodeError::InvalidRequest("the node is not a signer"))?;
+        if request.msg.len() + 1 != key_share.H.len() {
+            return Err(NodeError::InvalidRequest(
+                "the message does not fit the key",
+            ));
+        }
+        let channels = self.join(&request.session_id, &request.signers).await?;
+
+        let mut csprng = OsRng;
+        let mut rng = rand_gen(&mut csprng);
+        let party = MpcParty::connected(tcp_delivery(&self.cl, &request.signers, channels)?);
+        let partial = timeout(
+            SESSION_TIMEOUT,
+            sign_party(
+                party,
+                i as PartyIndex,
+                &request.signers,
+                &self.cl,
+                &request.session_id,
+                &key_share,
+                &mut rng,
+                &mut csprng,
+                &request.msg,
+                request.msg.len(),
+            ),
+        )
+        .await
+        .map_err(|_| NetError::Timeout)??;
+        Ok(partial)
+    }
+
+    /// Opens the channels to the other `parties` of a session. We dial the
+    /// parties with a smaller id and wait for the others to dial us.
+    async fn join(
+        &self,
+        session_id: &[u8],
+        parties: &[usize],
+    ) -> Result<BTreeMap<usize, Channel>, NodeError> {
+        let mut incoming = self
+            .sessions
+            .lock()
+            .unwrap()
+            .claim(session_id)
+            .ok_or(NodeError::SessionReused)?;
+        // Stored before the session starts, so that a crash cannot make us
+        // forget it. A DKG's session id is stored with the key share it yields.
+        let key_share = self.key_share.lock().unwrap().clone();
+        if let Some(key_share) = key_share {
+            self.store(&key_share)?;
+        }
+
+        let id = self.config.id;
+        let channels = timeout(SESSION_TIMEOUT, async {
+            let mut channels = BTreeMap::new();
+            for j in parties.iter().filter(|j| **j < id) {
+                let peer = &self.config.peers[j];
+                let mut channel = Channel::dial(
+                    peer.addr,
+                    &self.config.key,
+                    id,
+                    *j,
+                    &peer.public_key,
+                    &mut OsRng,
+                )
+                .await?;
+                channel.send(&session_id.to_vec()).await?;
+                channels.insert(*j, channel);
+            }
+            while channels.len() + 1 < parties.len() {
+                let channel = incoming.recv().await.ok_or(NetError::Closed)?;
+                if channel.peer() > id && parties.contains(&channel.peer()) {
+                    channels.insert(channel.peer(), channel);
+                }
+            }
+            Ok::<_, NetError>(channels)
+        })
+        .await;
+
+        self.sessions.lock().unwrap().pending.remove(session_id);
+        Ok(channels.map_err(|_| NetError::Timeout)??)
+    }
+}
+
+/// A hash of the public parts of `key_share`, which all the parties of the DKG
+/// share.
+fn digest(key_share: &KeyShare) -> Vec<u8> {
+    let mut public = Vec::new();
+    key_share.t.encode(&mut public);
+    key_share.n.encode(&mut public);
+    key_share.n_factorial.encode(&mut public);
+    key_share.cl_pub_key.encode(&mut public);
+    key_share.x_ciphertext.encode(&mut public);
+    key_share.X.encode(&mut public);
+    key_share.H.encode(&mut public);
+    Sha256::digest(public).to_vec()
+}
@@ -0,0 +1,202 @@
+//! An in-memory network for testing the per-party protocols under adversarial
+//! scheduling.
+//!
+//! [`SimNetwork`] wraps `round_based`'s [`Simulation`] router. Every party gets
+//! its messages after a random number of scheduler turns, so they arrive out of
+//! order; a party can be made to crash after a number of messages, or to tamper
+//! with the messages it sends. All the parties are driven in the current task by
+//! [`SimNetwork::run`], which notices when none of them can make progress any
+//! more and then fails their pending receives with [`SimError::Stalled`].
+
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use futures::channel::oneshot;
+use futures::future::{self, Either, FutureExt, Shared};
+use futures::sink::{self, Sink, SinkExt};
+use futures::stream::{self, BoxStream, StreamExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use round_based::simulation::Simulation;
+use round_based::{Delivery, Incoming, MpcParty, Outgoing, PartyIndex};
+use thiserror::Error;
+use tokio::task::yield_now;
+
+/// Scheduler turns without any message being sent or delivered after which
+/// the network is considered stalled.
+const STALL_AFTER: usize = 64;
+
+/// Delayed messages a party has in flight at most.
+const MAX_IN_FLIGHT: usize = 1024;
+
+pub type SimIncoming<M> = BoxStream<'static, Result<Incoming<M>, SimError>>;
+pub type SimOutgoing<M> = Pin<Box<dyn Sink<Outgoing<M>, Error = SimError> + Send>>;
+pub type SimDelivery<M> = (SimIncoming<M>, SimOutgoing<M>);
+
+#[derive(Debug, Error)]
+pub enum SimError {
+    #[error("simulated network")]
+    Network(#[source] Box<dyn StdError + Send + Sync>),
+    #[error("the network stalled with messages still awaited")]
+    Stalled,
+}
+
+enum Fault<M> {
+    /// Sends the first `after` messages, and silently drops the others.
+    Crash {
+        after: usize,
+    },
+    Tamper(Box<dyn FnMut(&mut Outgoing<M>) + Send>),
+}
+
+pub struct SimNetwork<M> {
+    simulation: Simulation<M>,
+    rng: StdRng,
+    max_delay: usize,
+    faults: BTreeMap<PartyIndex, Fault<M>>,
+    next_party: PartyIndex,
+    activity: Arc<AtomicUsize>,
+    stall: Option<oneshot::Sender<()>>,
+    stalled: Shared<oneshot::Receiver<()>>,
+}
+
+impl<M> SimNetwork<M>
+where
+    M: Clone + Send + Unpin + 'static,
+{
+    /// A network delivering every message at once, in order. `seed` determines
+    /// the delays of [`with_delays`](Self::with_delays).
+    pub fn new(seed: u64) -> Self {
+        let (stall, stalled) = oneshot::channel();
+        Self {
+            simulation: Simulation::new(),
+            rng: StdRng::seed_from_u64(seed),
+            max_delay: 0,
+            faults: BTreeMap::new(),
+            next_party: 0,
+            activity: Arc::new(AtomicUsize::new(0)),
+            stall: Some(stall),
+            stalled: stalled.shared(),
+        }
+    }
+
+    /// Holds back every message for up to `max_delay` scheduler turns.
+    pub fn with_delays(mut self, max_delay: usize) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Makes party `i` go silent after sending `after` messages; `crash(i, 0)`
+    /// never hears from it at all.
+    pub fn crash(mut self, i: PartyIndex, after: usize) -> Self {
+        self.faults.insert(i, Fault::Crash { after });
+        self
+    }
+
+    /// Passes every message party `i` sends through `tamper` first.
+    pub fn tamper(
+        mut self,
+        i: PartyIndex,
+        tamper: impl FnMut(&mut Outgoing<M>) + Send + 'static,
+    ) -> Self {
+        self.faults.insert(i, Fault::Tamper(Box::new(tamper)));
+        self
+    }
+
+    /// Connects the next party, with index `0` for the first one.
+    pub fn add_party(&mut self) -> MpcParty<M, SimDelivery<M>> {
+        let i = self.next_party;
+        self.next_party += 1;
+        let MpcParty { delivery, .. } = self.simulation.add_party();
+        let (incoming, outgoing) = delivery.split();
+
+        let activity = self.activity.clone();
+        let mut rng = StdRng::seed_from_u64(self.rng.gen());
+        let max_delay = self.max_delay;
+        let incoming = incoming
+            .map(move |msg| {
+                let delay = rng.gen_range(0..=max_delay);
+                let activity = activity.clone();
+                async move {
+                    for _ in 0..delay {
+                        activity.fetch_add(1, Ordering::Relaxed);
+                        yield_now().await;
+                    }
+                    activity.fetch_add(1, Ordering::Relaxed);
+                    msg.map_err(|err| SimError::Network(err.into()))
+                }
+            })
+            .buffer_unordered(MAX_IN_FLIGHT)
+            .take_until(self.stalled.clone())
+            .chain(stream::once(async { Err(SimError::Stalled) }))
+            .boxed();
+
+        let activity = self.activity.clone();
+        let fault = self.faults.remove(&i);
+        let outgoing = sink::unfold(
+            (outgoing, fault, 0usize),
+            move |(mut outgoing, mut fault, sent), mut msg: Outgoing<M>| {
+                let activity = activity.clone();
+                async move {
+                    let crashed = matches!(fault, Some(Fault::Crash { after }) if sent >= after);
+                    if !crashed {
+                        if let Some(Fault::Tamper(tamper)) = &mut fault {
+                            tamper(&mut msg);
+                        }
+                        activity.fetch_add(1, Ordering::Relaxed);
+                        outgoing
+                            .send(msg)
+                            .await
+                            .map_err(|err| SimError::Network(err.into()))?;
+                    }
+                    Ok::<_, SimError>((outgoing, fault, sent + 1))
+                }
+            },
+        );
+
+        MpcParty::connected((incoming, Box::pin(outgoing) as SimOutgoing<M>))
+    }
+
+    /// Runs the `parties`, one per party added, to completion and returns their
+    /// outputs in the same order. If they stall, every receive still pending
+    /// fails with [`SimError::Stalled`], so honest parties stuck waiting on a
+    /// crashed one return an error rather than hang.
+    pub async fn run<F>(mut self, parties: impl IntoIterator<Item = F>) -> Vec<F::Output>
+    where
+        F: Future,
+    {
+        let parties = future::join_all(parties);
+        let watchdog = watchdog(self.activity.clone());
+        futures::pin_mut!(parties, watchdog);
+        match future::select(parties, watchdog).await {
+            Either::Left((outputs, _)) => outputs,
+            Either::Right(((), parties)) => {
+                if let Some(stall) = self.stall.take() {
+                    let _ = stall.send(());
+                }
+                parties.await
+            }
+        }
+    }
+}
+
+/// Completes once [`STALL_AFTER`] scheduler turns pass without `activity`
+/// changing.
+async fn watchdog(activity: Arc<AtomicUsize>) {
+    let mut last = activity.load(Ordering::Relaxed);
+    let mut idle = 0;
+    while idle < STALL_AFTER {
+        yield_now().await;
+        let now = activity.load(Ordering::Relaxed);
+        if now == last {
+            idle += 1;
+        } else {
+            idle = 0;
+            last = now;
+        }
+    }
+}
@@ -2,6 +2,9 @@ pub mod setbbsplus;
 use bls12_381::G1Affine;
 pub use setbbsplus::*;
 
+pub mod setbbs;
+pub use setbbs::*;
+
 pub mod wmc24;
 pub use wmc24::*;
 
@@ -0,0 +1,84 @@
+use bicycl::CL_HSMqk;
+use bicycl::Mpz;
+use bicycl::RandGen;
+use bls12_381::{pairing, G1Projective, G2Affine, G2Projective, Scalar};
+use ff::Field;
+use rand_core::CryptoRngCore;
+
+use crate::ProtocolError;
+
+use super::setbbsplus::{self, BBSPlusKey, BBSPlusPublicKey, KeyGen};
+
+pub mod sign;
+pub use sign::*;
+
+/// A BBS signature as in the IETF draft, `A = (g1 + sum_i H[i] m_i) / (x + e)`,
+/// which is BBS+ without `s`. It verifies as a
+/// `bbs_plus::signature_23::Signature23G1` with `g1`, `g2` the generators and
+/// `h` the first `l` of `H`. Keys are SET-BBS+ keys, whose `H[l]` is unused.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BBSSig {
+    pub A: G1Projective,
+    pub e: Scalar,
+}
+
+impl BBSSig {
+    pub fn sign(rng: &mut impl CryptoRngCore, key: &BBSPlusKey, msg: &[Scalar], l: usize) -> Self {
+        let e = Scalar::random(&mut *rng);
+        let mut B = G1Projective::generator();
+        for i in 0..l {
+            B = B + key.H[i] * msg[i];
+        }
+        let A = B * (key.x + e).invert().unwrap();
+        Self { A, e }
+    }
+
+    pub fn verify(
+        key: &BBSPlusPublicKey,
+        msg: &[Scalar],
+        l: usize,
+        sig: &BBSSig,
+    ) -> Result<(), ProtocolError> {
+        let mut B = G1Projective::generator();
+        for i in 0..l {
+            B = B + key.H[i] * msg[i];
+        }
+
+        let p = pairing(
+            &sig.A.into(),
+            &(key.X + G2Projective::generator() * sig.e).into(),
+        );
+        let q = pairing(&B.into(), &G2Affine::generator());
+
+        if p == q {
+            Ok(())
+        } else {
+            Err(ProtocolError::InvalidSignature)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+        let l = 5;
+        let key = BBSPlusKey::keygen(&mut rng, l);
+        let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut rng)).collect();
+        let sig = BBSSig::sign(&mut rng, &key, &msg, l);
+        BBSSig::verify(&key.public_key(), &msg, l, &sig).unwrap();
+
+        let mut other = msg.clone();
+        other[0] += Scalar::one();
+        assert_eq!(
+            BBSSig::verify(&key.public_key(), &other, l, &sig),
+            Err(ProtocolError::InvalidSignature)
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use super::*;
+
+/// The signers' shares of a BBS signature: those of SET-BBS+ with
+/// `B = g1 + sum_i H[i] m_i`, the `s` the signers agree on being unused.
+pub struct Sign(pub setbbsplus::Sign);
+
+impl Sign {
+    /// Runs the signing protocol among the parties in `signers`, any `t` or
+    /// more of the `n` key holders, listed in increasing order.
+    pub fn sign(
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        signers: &[usize],
+        l: usize,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+        key_msg: &KeyGen,
+        msg: &[Scalar],
+        q: &Mpz,
+    ) -> Result<Self, ProtocolError> {
+        let H = &key_msg.sign_keys.H;
+        let mut B = G1Projective::generator();
+        for i in 0..l {
+            B = B + H[i] * msg[i];
+        }
+        let sign_msg =
+            setbbsplus::Sign::sign_base(cl, session_id, signers, rng, csprng, key_msg, q, |_| B)?;
+        Ok(Self(sign_msg))
+    }
+
+    pub fn client(
+        cl: &CL_HSMqk,
+        sign_msg: &Sign,
+        msg: &[Scalar],
+        l: usize,
+    ) -> Result<BBSSig, ProtocolError> {
+        let sig = BBSSig {
+            A: sign_msg.0.combine(cl)?,
+            e: sign_msg.0.e,
+        };
+        let key = BBSPlusPublicKey {
+            X: sign_msg.0.X,
+            H: sign_msg.0.H.clone(),
+        };
+        BBSSig::verify(&key, msg, l, &sig)?;
+        Ok(sig)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::{arithmetic::Converter, BigInt};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    use crate::MODULUS;
+
+    use super::*;
+
+    #[test]
+    fn test_clsign() {
+        let mut scalr_rng = ChaChaRng::from_seed([0u8; 32]);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
+        let cl = CL_HSMqk::with_rand_gen(
+            &q,
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let (n, t, l) = (4, 2, 4);
+        let signers = [2, 3];
+        let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut scalr_rng)).collect();
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng).unwrap();
+        let sign_msg = Sign::sign(
+            &cl,
+            b"sign",
+            &signers,
+            l,
+            &mut rng,
+            &mut scalr_rng,
+            &key_msg,
+            &msg,
+            &q,
+        )
+        .unwrap();
+        let sig = Sign::client(&cl, &sign_msg, &msg, l).unwrap();
+        BBSSig::verify(&key_msg.public_key(), &msg, l, &sig).unwrap();
+
+        let mut other = msg.clone();
+        other[l - 1] += Scalar::one();
+        assert_eq!(
+            Sign::client(&cl, &sign_msg, &other, l).err(),
+            Some(ProtocolError::InvalidSignature)
+        );
+    }
+}
@@ -0,0 +1,712 @@
+use bicycl::QFI;
+use bls12_381::G1Affine;
+use futures::SinkExt;
+use round_based::rounds_router::simple_store::{RoundInput, RoundInputError};
+use round_based::rounds_router::{CompleteRoundError, RoundsRouter};
+use round_based::{Delivery, Mpc, MpcParty, Outgoing, PartyIndex, ProtocolMessage};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::error::{blame, blame_batch, blame_proof};
+use crate::{ComZkDlComClproof, ProtocolError, ResponseBound, Transcript, ZeroShare};
+
+use super::*;
+
+/// Round 1 broadcast: a commitment to everything the party broadcasts in round 2,
+/// so that nobody can choose its contribution after seeing the others'.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DkgMsg1 {
+    pub com: Vec<u8>,
+}
+
+/// Round 2 broadcast: the party's CL public key share (which is also the
+/// constant term of its PVSS dealing), the commitments to the other
+/// coefficients, its BBS+ public key share and `H` contributions, each with a
+/// proof of knowledge of the discrete logarithm.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DkgMsg2 {
+    pub cl_pk_i: QFI,
+    pub cl_proof: ComZkDlComClproof,
+    pub A: Vec<QFI>,
+    pub A_proofs: Vec<ComZkDlComClproof>,
+    pub X_i: G2Projective,
+    pub x_proof: ComZkDlComElproof,
+    pub H_i: Vec<G1Projective>,
+}
+
+/// Round 2 p2p: the recipient's PVSS share and its pairwise zero-sharing seed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DkgMsg2P2P {
+    pub s_ij: Mpz,
+    pub beta_prime_ij: Mpz,
+}
+
+/// Round 3 broadcast: `x_i` encrypted under the joint CL public key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DkgMsg3 {
+    pub x_ciphertext: CipherText,
+    pub proof: CLEncProof,
+}
+
+#[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+pub enum DkgMsg {
+    Round1(DkgMsg1),
+    Round2(DkgMsg2),
+    Round2P2P(DkgMsg2P2P),
+    Round3(DkgMsg3),
+}
+
+pub struct KeyGenParty<'a> {
+    cl: &'a CL_HSMqk,
+    session_id: &'a [u8],
+    i: usize,
+    t: usize,
+    n: usize,
+    l: usize,
+}
+
+pub struct KeyGenPartyRound1<'a> {
+    cl: &'a CL_HSMqk,
+    session_id: &'a [u8],
+    i: usize,
+    t: usize,
+    n: usize,
+    l: usize,
+    n_factorial: Mpz,
+    coes: Vec<Mpz>,
+    x_i: Scalar,
+    my_msg2: DkgMsg2,
+}
+
+pub struct KeyGenPartyRound2<'a> {
+    cl: &'a CL_HSMqk,
+    session_id: &'a [u8],
+    i: usize,
+    t: usize,
+    n: usize,
+    l: usize,
+    n_factorial: Mpz,
+    x_i: Scalar,
+    s_ii: Mpz,
+    beta_primes: BTreeMap<usize, Mpz>,
+    coms: BTreeMap<usize, Vec<u8>>,
+}
+
+pub struct KeyGenPartyRound3<'a> {
+    cl: &'a CL_HSMqk,
+    session_id: &'a [u8],
+    i: usize,
+    t: usize,
+    n: usize,
+    n_factorial: Mpz,
+    cl_sk_share: Mpz,
+    zero_shares: BTreeMap<usize, Mpz>,
+    x_i: Scalar,
+    cl_pub_key: PublicKey,
+    X: G2Projective,
+    H: Vec<G1Projective>,
+    pub_shares: BTreeMap<usize, G2Projective>,
+}
+
+impl DkgMsg2 {
+    pub fn commit(&self, i: usize) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update((i as u64).to_be_bytes());
+        hasher.update(self.cl_pk_i.to_bytes());
+        for A_k in self.A.iter() {
+            hasher.update(A_k.to_bytes());
+        }
+        hasher.update(G2Affine::from(self.X_i).to_compressed());
+        for H_ij in self.H_i.iter() {
+            hasher.update(G1Affine::from(H_ij).to_compressed());
+        }
+        hasher.finalize().to_vec()
+    }
+}
+
+impl<'a> KeyGenParty<'a> {
+    /// `session_id` must be unique to this run of the protocol and the same for
+    /// every party.
+    pub fn new(
+        cl: &'a CL_HSMqk,
+        session_id: &'a [u8],
+        i: usize,
+        t: usize,
+        n: usize,
+        l: usize,
+    ) -> Self {
+        Self {
+            cl,
+            session_id,
+            i,
+            t,
+            n,
+            l,
+        }
+    }
+
+    pub fn round1(
+        self,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+    ) -> (KeyGenPartyRound1<'a>, DkgMsg1) {
+        let cl = self.cl;
+        let transcript = Transcript::new(self.session_id, self.i);
+
+        let mut n_factorial = Mpz::from(1u64);
+        for i in 1..=self.n {
+            n_factorial = Mpz::from(i as u64) * &n_factorial;
+        }
+
+        // Bounds the key and the coefficients times n!, like in `PVSS::share`.
+        let bound = ResponseBound::new(&(n_factorial.clone() * &cl.encrypt_randomness_bound()));
+        let d_i = rng.random_mpz(&cl.encrypt_randomness_bound());
+        let cl_pk_i = cl.power_of_h(&d_i);
+        let cl_proof = ComZkDlComClproof::prove(&cl, &transcript, &bound, rng, &cl_pk_i, &d_i);
+
+        let mut coes = Vec::with_capacity(self.t);
+        let mut A = Vec::with_capacity(self.t - 1);
+        let mut A_proofs = Vec::with_capacity(self.t - 1);
+        coes.push(n_factorial.clone() * &d_i);
+        for _ in 1..self.t {
+            let coe = rng.random_mpz(&cl.encrypt_randomness_bound());
+            let tmp = n_factorial.clone() * &coe;
+            let A_k = cl.power_of_h(&tmp);
+            A_proofs.push(ComZkDlComClproof::prove(
+                &cl,
+                &transcript,
+                &bound,
+                rng,
+                &A_k,
+                &tmp,
+            ));
+            A.push(A_k);
+            coes.push(coe);
+        }
+
+        let x_i = Scalar::random(&mut *csprng);
+        let X_i = G2Projective::generator() * &x_i;
+        let x_proof = ComZkDlComElproof::prove(&transcript, csprng, &X_i.into(), &x_i);
+
+        let mut H_i = Vec::with_capacity(self.l + 1);
+        for _ in 0..=self.l {
+            let k_i = Scalar::random(&mut *csprng);
+            H_i.push(G1Projective::generator() * &k_i);
+        }
+
+        let my_msg2 = DkgMsg2 {
+            cl_pk_i,
+            cl_proof,
+            A,
+            A_proofs,
+            X_i,
+            x_proof,
+            H_i,
+        };
+        let com = my_msg2.commit(self.i);
+
+        (
+            KeyGenPartyRound1 {
+                cl,
+                session_id: self.session_id,
+                i: self.i,
+                t: self.t,
+                n: self.n,
+                l: self.l,
+                n_factorial,
+                coes,
+                x_i,
+                my_msg2,
+            },
+            DkgMsg1 { com },
+        )
+    }
+}
+
+impl<'a> KeyGenPartyRound1<'a> {
+    /// `msgs` holds the round 1 messages of every party, including our own. The
+    /// p2p messages are keyed by the recipient.
+    pub fn round2(
+        self,
+        rng: &mut RandGen,
+        msgs: &BTreeMap<usize, DkgMsg1>,
+    ) -> (KeyGenPartyRound2<'a>, DkgMsg2, BTreeMap<usize, DkgMsg2P2P>) {
+        let cl = self.cl;
+        let coms = msgs.iter().map(|(j, m)| (*j, m.com.clone())).collect();
+
+        let mut s_ii = Mpz::from(0u64);
+        let mut beta_primes = BTreeMap::new();
+        let mut p2p_msgs = BTreeMap::new();
+        for j in 1..=self.n {
+            let mut s_ij = Mpz::from(0u64);
+            for k in (0..self.t).rev() {
+                s_ij = s_ij * Mpz::from(j as u64) + self.coes[k].clone();
+            }
+            if j == self.i {
+                s_ii = s_ij;
+                continue;
+            }
+            let beta_prime_ij = rng.random_mpz(&cl.encrypt_randomness_bound());
+            beta_primes.insert(j, beta_prime_ij.clone());
+            p2p_msgs.insert(
+                j,
+                DkgMsg2P2P {
+                    s_ij,
+                    beta_prime_ij,
+                },
+            );
+        }
+
+        (
+            KeyGenPartyRound2 {
+                cl,
+                session_id: self.session_id,
+                i: self.i,
+                t: self.t,
+                n: self.n,
+                l: self.l,
+                n_factorial: self.n_factorial,
+                x_i: self.x_i,
+                s_ii,
+                beta_primes,
+                coms,
+            },
+            self.my_msg2,
+            p2p_msgs,
+        )
+    }
+}
+
+impl<'a> KeyGenPartyRound2<'a> {
+    /// `msgs` holds the round 2 broadcasts of every party, including our own,
+    /// and `p2p_msgs` the p2p messages addressed to us, keyed by the sender.
+    pub fn round3(
+        self,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+        msgs: &BTreeMap<usize, DkgMsg2>,
+        p2p_msgs: &BTreeMap<usize, DkgMsg2P2P>,
+    ) -> Result<(KeyGenPartyRound3<'a>, DkgMsg3), ProtocolError> {
+        let cl = self.cl;
+        let n_factorial = &self.n_factorial;
+        let n_sq = n_factorial.clone() * n_factorial;
+        let exp = Mpz::from(self.i as u64);
+        let com_bound = ResponseBound::new(&(n_factorial.clone() * &cl.encrypt_randomness_bound()));
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+
+        let malformed = msgs
+            .iter()
+            .filter(|(_, msg_j)| {
+                msg_j.H_i.len() != self.l + 1
+                    || msg_j.A.len() != self.t - 1
+                    || msg_j.A_proofs.len() != self.t - 1
+            })
+            .map(|(j, _)| *j)
+            .collect();
+        blame(malformed, ProtocolError::MalformedMessage)?;
+        let missing = msgs
+            .keys()
+            .filter(|j| **j != self.i && !p2p_msgs.contains_key(j))
+            .cloned()
+            .collect();
+        blame(missing, ProtocolError::MissingMessage)?;
+
+        let mut bad_coms = Vec::new();
+        let mut bad_cl_proofs = Vec::new();
+        let mut bad_x_proofs = Vec::new();
+        let mut bad_shares = Vec::new();
+        let mut cl_sk_share = self.s_ii.clone() * n_factorial;
+        let mut zero_shares = BTreeMap::new();
+        for (j, msg_j) in msgs {
+            if *j == self.i {
+                continue;
+            }
+            if self.coms.get(j) != Some(&msg_j.commit(*j)) {
+                bad_coms.push(*j);
+            }
+            let transcript_j = Transcript::new(self.session_id, *j);
+            if !msg_j
+                .cl_proof
+                .verify(&cl, &transcript_j, &com_bound, &msg_j.cl_pk_i)
+                || !msg_j
+                    .A_proofs
+                    .iter()
+                    .zip(msg_j.A.iter())
+                    .all(|(proof, A_k)| proof.verify(&cl, &transcript_j, &com_bound, A_k))
+            {
+                bad_cl_proofs.push(*j);
+            }
+            if !msg_j.x_proof.verify(&transcript_j, &msg_j.X_i.into()) {
+                bad_x_proofs.push(*j);
+            }
+
+            let p2p_msg = p2p_msgs.get(j).unwrap();
+            let mut pro = cl.power_of_h(&Mpz::from(0u64));
+            for k in (1..self.t).rev() {
+                pro = pro.exp(&cl, &exp).compose(&cl, &msg_j.A[k - 1]);
+            }
+            pro = pro
+                .exp(&cl, &exp)
+                .compose(&cl, &msg_j.cl_pk_i.exp(&cl, &n_sq));
+            let left = cl.power_of_h(&(p2p_msg.s_ij.clone() * n_factorial));
+            if left != pro {
+                bad_shares.push(*j);
+            }
+
+            cl_sk_share = cl_sk_share + p2p_msg.s_ij.clone() * n_factorial;
+
+            let beta_prime_ij = self.beta_primes.get(j).unwrap().clone();
+            let beta_prime_ji = p2p_msg.beta_prime_ij.clone();
+            zero_shares.insert(
+                *j,
+                ZeroShare::pairwise(self.i, *j, beta_prime_ij, beta_prime_ji),
+            );
+        }
+        blame(bad_coms, ProtocolError::InvalidCommitment)?;
+        blame_proof(bad_cl_proofs, "ComZkDlComClproof")?;
+        blame_proof(bad_x_proofs, "ComZkDlComElproof")?;
+        blame(bad_shares, ProtocolError::InvalidShare)?;
+
+        let cl_pk = msgs
+            .values()
+            .map(|m| m.cl_pk_i.clone())
+            .reduce(|acc, pk_i| acc.compose(&cl, &pk_i))
+            .unwrap();
+        let cl_pub_key = PublicKey::from_qfi(&cl, &cl_pk);
+        let X = msgs
+            .values()
+            .map(|m| m.X_i)
+            .reduce(|acc, X_i| acc + X_i)
+            .unwrap();
+        let mut H = Vec::with_capacity(self.l + 1);
+        for k in 0..=self.l {
+            let H_k = msgs
+                .values()
+                .map(|m| m.H_i[k])
+                .reduce(|acc, H_ik| acc + H_ik)
+                .unwrap();
+            H.push(H_k);
+        }
+        let pub_shares = msgs.iter().map(|(j, m)| (*j, m.X_i)).collect();
+
+        let cl_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
+        let c1 = cl.power_of_h(&cl_rand);
+        let c2 = cl
+            .power_of_f(&Mpz::from(&self.x_i))
+            .compose(&cl, &cl_pub_key.exponentiation(&cl, &cl_rand));
+        let x_ciphertext = CipherText::new(&c1, &c2);
+        let proof = CLEncProof::prove(
+            &cl,
+            &Transcript::new(self.session_id, self.i),
+            &bound,
+            rng,
+            &cl_pub_key,
+            &x_ciphertext,
+            &(G2Projective::generator() * &self.x_i),
+            &self.x_i,
+            &cl_rand,
+            csprng,
+        );
+
+        Ok((
+            KeyGenPartyRound3 {
+                cl,
+                session_id: self.session_id,
+                i: self.i,
+                t: self.t,
+                n: self.n,
+                n_factorial: self.n_factorial,
+                cl_sk_share,
+                zero_shares,
+                x_i: self.x_i,
+                cl_pub_key,
+                X,
+                H,
+                pub_shares,
+            },
+            DkgMsg3 {
+                x_ciphertext,
+                proof,
+            },
+        ))
+    }
+}
+
+impl<'a> KeyGenPartyRound3<'a> {
+    /// `msgs` holds the round 3 messages of every party, including our own.
+    pub fn finish(self, msgs: &BTreeMap<usize, DkgMsg3>) -> Result<KeyShare, ProtocolError> {
+        let cl = self.cl;
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+
+        let mut culprits = Vec::new();
+        let mut ids = Vec::new();
+        let mut items = Vec::new();
+        for (j, msg_j) in msgs.iter().filter(|(j, _)| **j != self.i) {
+            match self.pub_shares.get(j) {
+                Some(X_j) => {
+                    ids.push(*j);
+                    items.push((
+                        Transcript::new(self.session_id, *j),
+                        &bound,
+                        &self.cl_pub_key,
+                        &msg_j.x_ciphertext,
+                        X_j,
+                        &msg_j.proof,
+                    ));
+                }
+                None => culprits.push(*j),
+            }
+        }
+        blame_proof(culprits, "CLEncProof")?;
+        blame_batch(CLEncProof::batch_verify(&cl, &items), &ids, "CLEncProof")?;
+
+        let x_ciphertext = msgs
+            .values()
+            .map(|m| m.x_ciphertext.clone())
+            .reduce(|acc, ct| {
+                CipherText::new(
+                    &acc.c1().compose(&cl, &ct.c1()),
+                    &acc.c2().compose(&cl, &ct.c2()),
+                )
+            })
+            .unwrap();
+
+        Ok(KeyShare {
+            i: self.i,
+            t: self.t,
+            n: self.n,
+            cl_sk_share: self.cl_sk_share,
+            zero_shares: self.zero_shares,
+            cl_pub_key: self.cl_pub_key,
+            n_factorial: self.n_factorial,
+            x_share: self.x_i,
+            x_ciphertext,
+            X: self.X,
+            H: self.H,
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum KeyGenPartyError<RecvErr, SendErr> {
+    #[error("send a message at round 1")]
+    Round1Send(#[source] SendErr),
+    #[error("receive messages at round 1")]
+    Round1Receive(#[source] CompleteRoundError<RoundInputError, RecvErr>),
+    #[error("send a message at round 2")]
+    Round2Send(#[source] SendErr),
+    #[error("receive messages at round 2")]
+    Round2Receive(#[source] CompleteRoundError<RoundInputError, RecvErr>),
+    #[error("send a message at round 3")]
+    Round3Send(#[source] SendErr),
+    #[error("receive messages at round 3")]
+    Round3Receive(#[source] CompleteRoundError<RoundInputError, RecvErr>),
+    #[error("protocol aborted")]
+    Protocol(#[source] ProtocolError),
+}
+
+/// Runs [`KeyGenParty`] over a `round_based` transport. The party with index `i`
+/// (out of `n` parties) ends up with the key share of party `i + 1`.
+pub async fn keygen_party<M>(
+    party: M,
+    i: PartyIndex,
+    t: u16,
+    n: u16,
+    cl: &CL_HSMqk,
+    session_id: &[u8],
+    l: usize,
+    rng: &mut RandGen,
+    csprng: &mut impl CryptoRngCore,
+) -> Result<KeyShare, KeyGenPartyError<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = DkgMsg>,
+{
+    let MpcParty { delivery, .. } = party.into_party();
+    let (incoming, mut outgoing) = delivery.split();
+
+    let mut rounds = RoundsRouter::<DkgMsg>::builder();
+    let round1 = rounds.add_round(RoundInput::<DkgMsg1>::broadcast(i, n));
+    let round2 = rounds.add_round(RoundInput::<DkgMsg2>::broadcast(i, n));
+    let round2_p2p = rounds.add_round(RoundInput::<DkgMsg2P2P>::p2p(i, n));
+    let round3 = rounds.add_round(RoundInput::<DkgMsg3>::broadcast(i, n));
+    let mut rounds = rounds.listen(incoming);
+
+    let party_state = KeyGenParty::new(cl, session_id, usize::from(i) + 1, t.into(), n.into(), l);
+    let (party_state, my_msg1) = party_state.round1(rng, csprng);
+    outgoing
+        .send(Outgoing::broadcast(DkgMsg::Round1(my_msg1.clone())))
+        .await
+        .map_err(KeyGenPartyError::Round1Send)?;
+    let msgs1 = rounds
+        .complete(round1)
+        .await
+        .map_err(KeyGenPartyError::Round1Receive)?;
+    let msgs1 = by_party_id(msgs1.into_vec_including_me(my_msg1));
+
+    let (party_state, my_msg2, my_p2p_msgs) = party_state.round2(rng, &msgs1);
+    outgoing
+        .send(Outgoing::broadcast(DkgMsg::Round2(my_msg2.clone())))
+        .await
+        .map_err(KeyGenPartyError::Round2Send)?;
+    for (j, p2p_msg) in my_p2p_msgs {
+        outgoing
+            .send(Outgoing::p2p(
+                (j - 1) as PartyIndex,
+                DkgMsg::Round2P2P(p2p_msg),
+            ))
+            .await
+            .map_err(KeyGenPartyError::Round2Send)?;
+    }
+    let msgs2 = rounds
+        .complete(round2)
+        .await
+        .map_err(KeyGenPartyError::Round2Receive)?;
+    let msgs2 = by_party_id(msgs2.into_vec_including_me(my_msg2));
+    let p2p_msgs2 = rounds
+        .complete(round2_p2p)
+        .await
+        .map_err(KeyGenPartyError::Round2Receive)?;
+    let p2p_msgs2 = p2p_msgs2
+        .into_iter_indexed()
+        .map(|(j, _, m)| (usize::from(j) + 1, m))
+        .collect();
+
+    let (party_state, my_msg3) = party_state
+        .round3(rng, csprng, &msgs2, &p2p_msgs2)
+        .map_err(KeyGenPartyError::Protocol)?;
+    outgoing
+        .send(Outgoing::broadcast(DkgMsg::Round3(my_msg3.clone())))
+        .await
+        .map_err(KeyGenPartyError::Round3Send)?;
+    let msgs3 = rounds
+        .complete(round3)
+        .await
+        .map_err(KeyGenPartyError::Round3Receive)?;
+    let msgs3 = by_party_id(msgs3.into_vec_including_me(my_msg3));
+
+    party_state
+        .finish(&msgs3)
+        .map_err(KeyGenPartyError::Protocol)
+}
+
+fn by_party_id<T>(msgs: Vec<T>) -> BTreeMap<usize, T> {
+    msgs.into_iter()
+        .enumerate()
+        .map(|(j, m)| (j + 1, m))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::{arithmetic::Converter, BigInt};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use round_based::simulation::Simulation;
+
+    use crate::MODULUS;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keygen_party() {
+        let seed: [u8; 32] = [0u8; 32];
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let n = 5;
+        let t = 3;
+        let l = 10;
+        let mut msg: Vec<Scalar> = Vec::with_capacity(l);
+
+        for _ in 0..l {
+            msg.push(Scalar::random(&mut scalr_rng));
+        }
+
+        let mut simulation = Simulation::<DkgMsg>::new();
+        let mut outputs = Vec::with_capacity(n);
+        for i in 0..n {
+            let party = simulation.add_party();
+            let mut party_rng = RandGen::new();
+            party_rng.set_seed(&Mpz::from(i as u64 + 1));
+            let mut party_csprng = ChaChaRng::from_seed([i as u8 + 1; 32]);
+            let cl = &cl;
+            outputs.push(async move {
+                keygen_party(
+                    party,
+                    i as u16,
+                    t as u16,
+                    n as u16,
+                    cl,
+                    b"test_keygen_party",
+                    l,
+                    &mut party_rng,
+                    &mut party_csprng,
+                )
+                .await
+            });
+        }
+        let key_shares = futures::future::try_join_all(outputs).await.unwrap();
+
+        for key_share in key_shares.iter() {
+            assert_eq!(key_share.X, key_shares[0].X);
+            assert_eq!(key_share.H, key_shares[0].H);
+            assert_eq!(key_share.x_ciphertext, key_shares[0].x_ciphertext);
+        }
+
+        let signers = [2, 4, 5];
+        let mut simulation = Simulation::<SignMsg>::new();
+        let mut outputs = Vec::with_capacity(signers.len());
+        for (i, j) in signers.iter().enumerate() {
+            let key_share = &key_shares[j - 1];
+            let party = simulation.add_party();
+            let mut party_rng = RandGen::new();
+            party_rng.set_seed(&Mpz::from(i as u64 + 11));
+            let mut party_csprng = ChaChaRng::from_seed([i as u8 + 11; 32]);
+            let cl = &cl;
+            let msg = &msg;
+            let signers = &signers;
+            outputs.push(async move {
+                sign_party(
+                    party,
+                    i as u16,
+                    signers,
+                    cl,
+                    b"test_sign_party",
+                    key_share,
+                    &mut party_rng,
+                    &mut party_csprng,
+                    msg,
+                    l,
+                )
+                .await
+                .map(|partial| (signers[i], partial))
+            });
+        }
+        let partials = futures::future::try_join_all(outputs)
+            .await
+            .unwrap()
+            .into_iter()
+            .collect();
+
+        let sign_msg = Sign::from_partials(
+            &key_shares[0].H,
+            &key_shares[0].X,
+            &key_shares[0].n_factorial,
+            &partials,
+        )
+        .unwrap();
+        let sig = Sign::client(&cl, &sign_msg, &msg, l).unwrap();
+        BBSPlusSig::verify(&key_shares[0].public_key(), &msg, l, &sig).unwrap();
+    }
+}
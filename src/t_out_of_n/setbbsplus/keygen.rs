@@ -1,4 +1,5 @@
-use crate::{ComZkDlComClproof, ZeroShare, PVSS};
+use crate::error::{blame, blame_batch, blame_proof};
+use crate::{check_signers, ComZkDlComClproof, Generators, Transcript, ZeroShare, PVSS};
 
 use super::*;
 
@@ -8,12 +9,18 @@ pub struct KeyGen {
     pub each_party_x_ciphertexts: BTreeMap<usize, CipherText>,
 }
 
+/// `sk_shares` are the parties' points on the shared polynomial, scaled by `n!`.
+/// They are turned into shares of `n!^3 d` for a concrete signing set with
+/// [`CLKeys::signing_share`]. `bound` bounds the coefficients the points were
+/// dealt with, see [`PVSS::lagrange_share_bound`].
 #[derive(Clone)]
 pub struct CLKeys {
     pub sk_shares: BTreeMap<usize, Mpz>,
     pub pub_key: PublicKey,
     pub n_factorial: Mpz,
-    pub zero_shares: BTreeMap<usize, Mpz>,
+    pub zero_shares: BTreeMap<usize, BTreeMap<usize, Mpz>>,
+    pub t: usize,
+    pub bound: Mpz,
 }
 #[derive(Clone)]
 pub struct SignKeys {
@@ -21,21 +28,40 @@ pub struct SignKeys {
     pub pub_shares: BTreeMap<usize, G2Projective>,
     pub pub_key: G2Projective,
     pub H: Vec<G1Projective>,
-    pub x: Scalar,
+}
+
+/// The key material a single signer holds, i.e. what one party needs to run
+/// [`SignParty`] without seeing anybody else's shares. `cl_sk_share` is the
+/// party's point on the shared polynomial, scaled by `n!`, and `zero_shares` the
+/// values it shares pairwise with every other party.
+#[derive(Clone)]
+pub struct KeyShare {
+    pub i: usize,
+    pub t: usize,
+    pub n: usize,
+    pub cl_sk_share: Mpz,
+    pub zero_shares: BTreeMap<usize, Mpz>,
+    pub cl_pub_key: PublicKey,
+    pub n_factorial: Mpz,
+    pub x_share: Scalar,
+    pub x_ciphertext: CipherText,
+    pub X: G2Projective,
+    pub H: Vec<G1Projective>,
 }
 
 impl KeyGen {
     pub fn clkeygen(
         rng: &mut RandGen,
-        chacharng: &mut ChaChaRng,
         cl: &CL_HSMqk,
+        session_id: &[u8],
         n: usize,
         t: usize,
-    ) -> CLKeys {
+    ) -> Result<CLKeys, ProtocolError> {
         // let mut rng = RandGen::new();
         let mut each_party_gen = Vec::with_capacity(n as usize);
         let mut pk_shares = Vec::with_capacity(n as usize);
         let mut sk_shares = BTreeMap::new();
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
         for i in 1..=n {
             let d_i = rng.random_mpz(&cl.encrypt_randomness_bound());
 
@@ -45,38 +71,39 @@ impl KeyGen {
 
             sk_shares.insert(i, d_i.clone());
 
-            let proof_i = ComZkDlComClproof::prove(&cl, chacharng, &pk_i, &d_i);
+            let proof_i = ComZkDlComClproof::prove(
+                &cl,
+                &Transcript::new(session_id, i),
+                &bound,
+                rng,
+                &pk_i,
+                &d_i,
+            );
 
             each_party_gen.push((i, pk_i, proof_i));
         }
         let mut each_party_pubkey = Vec::with_capacity(n as usize);
-        let d = sk_shares
-            .values()
-            .cloned()
-            .into_iter()
-            .reduce(|acc, d_i| acc + d_i)
-            .unwrap();
         for i in 1..=n {
             let pk = pk_shares
                 .clone()
                 .into_iter()
                 .reduce(|acc, pk_i| acc.compose(&cl, &pk_i))
                 .unwrap();
-            for (j, pk_i, proof_i) in each_party_gen.iter() {
-                if *j == i {
-                    continue;
-                }
-                assert_eq!(true, proof_i.verify(&cl, &pk_i));
-            }
+            let culprits = each_party_gen
+                .iter()
+                .filter(|(j, pk_i, proof_i)| {
+                    *j != i && !proof_i.verify(&cl, &Transcript::new(session_id, *j), &bound, &pk_i)
+                })
+                .map(|(j, _, _)| *j)
+                .collect();
+            blame_proof(culprits, "ComZkDlComClproof")?;
             each_party_pubkey.push(pk);
         }
-        assert_eq!(each_party_pubkey[0], cl.power_of_h(&d));
-
-        for (i, _) in each_party_pubkey.iter().enumerate() {
-            if i != 0 {
-                assert_eq!(each_party_pubkey[i - 1], each_party_pubkey[i]);
-            }
-        }
+        let culprits = (1..each_party_pubkey.len())
+            .filter(|i| each_party_pubkey[*i] != each_party_pubkey[0])
+            .map(|i| i + 1)
+            .collect();
+        blame(culprits, ProtocolError::Inconsistent)?;
         let pub_key = PublicKey::from_qfi(&cl, &each_party_pubkey[0]);
 
         let mut n_factorial = Mpz::from(1u64);
@@ -85,18 +112,18 @@ impl KeyGen {
         }
         let pvssmsg = PVSS::share(
             &cl,
+            session_id,
             rng,
-            chacharng,
             &sk_shares,
             &cl.encrypt_randomness_bound(),
             t,
             n,
             &n_factorial,
-        );
+        )?;
 
-        (sk_shares, _) = PVSS::recover(&cl, &pvssmsg, t, n, &n_factorial);
+        sk_shares = PVSS::poly_shares(&pvssmsg, n, &n_factorial);
 
-        let zero_shares = ZeroShare::share(&cl, rng, t, n);
+        let mut zero_shares = ZeroShare::share(&cl, rng, n);
         // let left_sum = d * n_factorial.clone() * n_factorial.clone() * n_factorial.clone();
         // let mut right_sum = Mpz::from(0u64);
         // for (_, item) in sk_shares.clone() {
@@ -104,22 +131,25 @@ impl KeyGen {
         // }
         // assert_eq!(left_sum, right_sum);
 
-        let mut update_serect_keys = BTreeMap::new();
-
-        for i in 1..=t {
-            let mut sk = sk_shares.get(&i).unwrap().clone();
-            sk = sk + zero_shares.beta_t_is.get(&i).unwrap().clone();
-            update_serect_keys.insert(i, sk);
-        }
-        CLKeys {
-            sk_shares: update_serect_keys,
+        Ok(CLKeys {
+            sk_shares,
             pub_key,
             n_factorial,
-            zero_shares: zero_shares.beta_t_is,
-        }
+            zero_shares: std::mem::take(&mut zero_shares.beta_ijs),
+            t,
+            bound: cl.encrypt_randomness_bound(),
+        })
     }
 
-    pub fn signkeygen(chacharng: &mut ChaChaRng, n: usize, l: usize) -> SignKeys {
+    /// With `generators`, `H` are those instead of points the parties generate
+    /// jointly.
+    pub fn signkeygen(
+        session_id: &[u8],
+        csprng: &mut impl CryptoRngCore,
+        n: usize,
+        l: usize,
+        generators: Option<&Generators>,
+    ) -> Result<SignKeys, ProtocolError> {
         let mut each_party_gen = Vec::with_capacity(n as usize);
         let mut pk_shares = Vec::with_capacity(n as usize);
         let mut sk_shares = BTreeMap::new();
@@ -127,26 +157,28 @@ impl KeyGen {
         let mut pub_H_shares: BTreeMap<usize, Vec<G1Projective>> = BTreeMap::new();
         //let mut pub_H_share: Vec<Vec<G1Projective>> = Vec::with_capacity(n.into());
         for i in 1..=n {
-            for j in 0..=l {
-                if let Some(value) = pub_H_shares.get(&j) {
-                    let mut H_i = value.clone();
-                    let k_i = Scalar::random(chacharng.clone());
-                    let H_ii = G1Projective::generator() * &k_i;
-                    H_i.push(H_ii);
-                    pub_H_shares.insert(j, value.clone());
-                } else {
-                    let mut H_i = Vec::with_capacity(n.into());
-                    let k_i = Scalar::random(chacharng.clone());
-                    let H_ii = G1Projective::generator() * &k_i;
-                    H_i.push(H_ii);
-                    pub_H_shares.insert(j, H_i);
+            if generators.is_none() {
+                for j in 0..=l {
+                    if let Some(value) = pub_H_shares.get(&j) {
+                        let mut H_i = value.clone();
+                        let k_i = Scalar::random(&mut *csprng);
+                        let H_ii = G1Projective::generator() * &k_i;
+                        H_i.push(H_ii);
+                        pub_H_shares.insert(j, value.clone());
+                    } else {
+                        let mut H_i = Vec::with_capacity(n.into());
+                        let k_i = Scalar::random(&mut *csprng);
+                        let H_ii = G1Projective::generator() * &k_i;
+                        H_i.push(H_ii);
+                        pub_H_shares.insert(j, H_i);
+                    }
+                    // let k_i = Scalar::random(&mut *csprng);
+                    // let H_ii = G1Projective::generator() * &k_i;
+                    // pub_H_share[i as usize - 1][j] = H_ii;
                 }
-                // let k_i = Scalar::random(chacharng.clone());
-                // let H_ii = G1Projective::generator() * &k_i;
-                // pub_H_share[i as usize - 1][j] = H_ii;
             }
 
-            let x_i = Scalar::random(chacharng.clone());
+            let x_i = Scalar::random(&mut *csprng);
 
             let pk_i = G2Projective::generator() * &x_i;
 
@@ -156,18 +188,17 @@ impl KeyGen {
 
             pub_shares.insert(i, pk_i.clone());
 
-            let proof_i = ComZkDlComElproof::prove(&mut chacharng.clone(), &pk_i.into(), &x_i);
+            let proof_i = ComZkDlComElproof::prove(
+                &Transcript::new(session_id, i),
+                csprng,
+                &pk_i.into(),
+                &x_i,
+            );
 
             each_party_gen.push((i, pk_i, proof_i));
         }
         let mut each_party_pubkey = Vec::with_capacity(n as usize);
         let mut each_party_Hs = Vec::with_capacity(n as usize);
-        let x = sk_shares
-            .values()
-            .cloned()
-            .into_iter()
-            .reduce(|acc, d_i| acc + d_i)
-            .unwrap();
         for i in 1..=n {
             let mut H = Vec::with_capacity(l + 1);
             for (_, H_items) in pub_H_shares.clone() {
@@ -189,59 +220,88 @@ impl KeyGen {
                 .into_iter()
                 .reduce(|acc, pk_i| acc + pk_i)
                 .unwrap();
-            for (j, pk_i, proof_i) in each_party_gen.iter() {
-                if *j == i {
-                    continue;
-                }
-                assert_eq!(true, proof_i.verify(&pk_i.into()));
-            }
+            let culprits = each_party_gen
+                .iter()
+                .filter(|(j, pk_i, proof_i)| {
+                    *j != i && !proof_i.verify(&Transcript::new(session_id, *j), &pk_i.into())
+                })
+                .map(|(j, _, _)| *j)
+                .collect();
+            blame_proof(culprits, "ComZkDlComElproof")?;
             each_party_pubkey.push(pk);
         }
-        assert_eq!(each_party_pubkey[0], G2Projective::generator() * x.clone());
 
-        for (i, _) in each_party_Hs.iter().enumerate() {
-            if i != 0 {
-                for j in 0..=l {
-                    assert_eq!(each_party_Hs[i - 1][j], each_party_Hs[i][j]);
-                }
-            }
-        }
-
-        for (i, _) in each_party_pubkey.iter().enumerate() {
-            if i != 0 {
-                assert_eq!(each_party_pubkey[i - 1], each_party_pubkey[i]);
-            }
-        }
+        let culprits = (1..each_party_Hs.len())
+            .filter(|i| each_party_Hs[*i] != each_party_Hs[0])
+            .map(|i| i + 1)
+            .collect();
+        blame(culprits, ProtocolError::Inconsistent)?;
+
+        let culprits = (1..each_party_pubkey.len())
+            .filter(|i| each_party_pubkey[*i] != each_party_pubkey[0])
+            .map(|i| i + 1)
+            .collect();
+        blame(culprits, ProtocolError::Inconsistent)?;
         let pub_key = each_party_pubkey[0].clone();
-        let H = each_party_Hs[0].clone();
-        SignKeys {
+        let H = match generators {
+            Some(generators) => generators.H.clone(),
+            None => each_party_Hs[0].clone(),
+        };
+        Ok(SignKeys {
             sk_shares,
             pub_shares,
             pub_key,
             H,
-            x,
-        }
+        })
     }
 
     pub fn keygen(
         cl: &CL_HSMqk,
+        session_id: &[u8],
         n: usize,
         t: usize,
         l: usize,
         rng: &mut RandGen,
-        chacharng: &mut ChaChaRng,
-    ) -> Self {
-        let cl_keys = Self::clkeygen(rng, chacharng, cl, n, t);
-        let sign_keys = Self::signkeygen(chacharng, n, l);
+        csprng: &mut impl CryptoRngCore,
+    ) -> Result<Self, ProtocolError> {
+        Self::keygen_from(cl, session_id, n, t, l, None, rng, csprng)
+    }
 
+    /// [`KeyGen::keygen`] with the hashed `generators` as `H`, see
+    /// [`Generators::from_seed`].
+    pub fn keygen_with_generators(
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        n: usize,
+        t: usize,
+        generators: &Generators,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+    ) -> Result<Self, ProtocolError> {
+        let l = generators.l();
+        Self::keygen_from(cl, session_id, n, t, l, Some(generators), rng, csprng)
+    }
+
+    fn keygen_from(
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        n: usize,
+        t: usize,
+        l: usize,
+        generators: Option<&Generators>,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+    ) -> Result<Self, ProtocolError> {
+        let cl_keys = Self::clkeygen(rng, cl, session_id, n, t)?;
+        let sign_keys = Self::signkeygen(session_id, csprng, n, l, generators)?;
+
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
         let mut msgs = Vec::with_capacity(n as usize);
         let mut per_cipher = BTreeMap::new();
         let cl_pk = cl_keys.pub_key.clone();
-        let mut x = Scalar::zero();
         for i in 1..=n {
             let x_i = sign_keys.sk_shares.get(&i).unwrap().clone();
             let pk_i = sign_keys.pub_shares.get(&i).unwrap().clone();
-            x = x + &x_i;
             let cl_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
             let c1 = cl.power_of_h(&cl_rand);
             let c2 = cl
@@ -250,30 +310,40 @@ impl KeyGen {
             let xi_ciphertext = CipherText::new(&c1, &c2);
             let proof = CLEncProof::prove(
                 &cl,
+                &Transcript::new(session_id, i),
+                &bound,
                 rng,
                 &cl_pk,
                 &xi_ciphertext,
                 &pk_i,
                 &x_i,
                 &cl_rand,
-                chacharng,
+                csprng,
             );
             msgs.push((i, xi_ciphertext.clone(), pk_i.clone(), proof));
             per_cipher.insert(i, xi_ciphertext);
         }
         let mut each_party_x_ciphertexts = BTreeMap::new();
         for i in 1..=n {
-            let xi_ciphertexts: Vec<_> = msgs
+            let (ids, items): (Vec<usize>, Vec<_>) = msgs
                 .iter()
-                .filter_map(|(_, xi_ciphertext, pk_i, proof)| {
-                    //if proof.verify(pp, &pp.pk, ki_ciphertext) && *j != i {
-                    if proof.verify(&cl, &cl_pk, xi_ciphertext, pk_i) {
-                        //Some((*j, gammai_ciphertext.clone(), proof.clone()))
-                        Some(xi_ciphertext.clone())
-                    } else {
-                        None
-                    }
+                .filter(|(j, _, _, _)| *j != i)
+                .map(|(j, xi_ciphertext, pk_i, proof)| {
+                    let item = (
+                        Transcript::new(session_id, *j),
+                        &bound,
+                        &cl_pk,
+                        xi_ciphertext,
+                        pk_i,
+                        proof,
+                    );
+                    (*j, item)
                 })
+                .unzip();
+            blame_batch(CLEncProof::batch_verify(&cl, &items), &ids, "CLEncProof")?;
+            let xi_ciphertexts: Vec<_> = msgs
+                .iter()
+                .map(|(_, xi_ciphertext, _, _)| xi_ciphertext.clone())
                 .collect();
 
             //each_party_k_ciphertexts.insert(i, per_cipher.get(&i).unwrap().clone());
@@ -291,18 +361,91 @@ impl KeyGen {
             //msgs.rotate_left(1);
         }
         //Gen gamma x
-        Self {
+        Ok(Self {
             cl_keys,
             sign_keys,
             each_party_x_ciphertexts,
+        })
+    }
+
+    pub fn key_share(&self, i: usize) -> KeyShare {
+        KeyShare {
+            i,
+            t: self.cl_keys.t,
+            n: self.cl_keys.sk_shares.len(),
+            cl_sk_share: self.cl_keys.sk_shares.get(&i).unwrap().clone(),
+            zero_shares: self.cl_keys.zero_shares.get(&i).unwrap().clone(),
+            cl_pub_key: self.cl_keys.pub_key.clone(),
+            n_factorial: self.cl_keys.n_factorial.clone(),
+            x_share: self.sign_keys.sk_shares.get(&i).unwrap().clone(),
+            x_ciphertext: self.each_party_x_ciphertexts.get(&i).unwrap().clone(),
+            X: self.sign_keys.pub_key,
+            H: self.sign_keys.H.clone(),
+        }
+    }
+
+    pub fn public_key(&self) -> BBSPlusPublicKey {
+        BBSPlusPublicKey {
+            X: self.sign_keys.pub_key,
+            H: self.sign_keys.H.clone(),
+        }
+    }
+}
+
+impl CLKeys {
+    /// Party `i`'s share of `n!^3 d` for the signing set `signers`.
+    pub fn signing_share(&self, i: usize, signers: &[usize]) -> Result<Mpz, ProtocolError> {
+        check_signers(signers, self.t, self.sk_shares.len())?;
+        Ok(signing_share(
+            i,
+            self.sk_shares.get(&i).unwrap(),
+            self.zero_shares.get(&i).unwrap(),
+            &self.n_factorial,
+            signers,
+        ))
+    }
+}
+
+impl KeyShare {
+    pub fn public_key(&self) -> BBSPlusPublicKey {
+        BBSPlusPublicKey {
+            X: self.X,
+            H: self.H.clone(),
+        }
+    }
+
+    /// The party's share of `n!^3 d` for the signing set `signers`.
+    pub fn signing_share(&self, signers: &[usize]) -> Result<Mpz, ProtocolError> {
+        check_signers(signers, self.t, self.n)?;
+        if !signers.contains(&self.i) {
+            return Err(ProtocolError::InvalidSignerSet(signers.to_vec()));
         }
+        Ok(signing_share(
+            self.i,
+            &self.cl_sk_share,
+            &self.zero_shares,
+            &self.n_factorial,
+            signers,
+        ))
     }
 }
 
+fn signing_share(
+    i: usize,
+    cl_sk_share: &Mpz,
+    zero_shares: &BTreeMap<usize, Mpz>,
+    n_factorial: &Mpz,
+    signers: &[usize],
+) -> Mpz {
+    let lagi = PVSS::lagrange_coeff_times_n_factorial(i, signers, n_factorial);
+    lagi * cl_sk_share + ZeroShare::combine(i, zero_shares, signers)
+}
+
 #[cfg(test)]
 mod tests {
     use curv::{arithmetic::Converter, BigInt};
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use crate::MODULUS;
 
@@ -314,7 +457,7 @@ mod tests {
         let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -328,6 +471,58 @@ mod tests {
         let n = 5;
         let t = 3;
 
-        KeyGen::keygen(&cl, n, t, 5, &mut rng, &mut scalr_rng);
+        KeyGen::keygen(&cl, b"test_keygen", n, t, 5, &mut rng, &mut scalr_rng).unwrap();
+    }
+
+    #[test]
+    fn test_keygen_with_generators() {
+        let mut scalr_rng = ChaChaRng::from_seed([0u8; 32]);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
+        let cl = CL_HSMqk::with_rand_gen(
+            &q,
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let l = 3;
+        let generators = Generators::from_seed(b"test_keygen_with_generators", l);
+        let key_msg = KeyGen::keygen_with_generators(
+            &cl,
+            b"keygen",
+            4,
+            2,
+            &generators,
+            &mut rng,
+            &mut scalr_rng,
+        )
+        .unwrap();
+        assert_eq!(key_msg.sign_keys.H, generators.H);
+        assert!(Generators::verify(
+            b"test_keygen_with_generators",
+            &key_msg.sign_keys.H
+        ));
+
+        let signers = [1, 3];
+        let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut scalr_rng)).collect();
+        let sign_msg = Sign::sign(
+            &cl,
+            b"sign",
+            &signers,
+            l,
+            &mut rng,
+            &mut scalr_rng,
+            &key_msg,
+            &msg,
+            &q,
+        )
+        .unwrap();
+        let sig = Sign::client(&cl, &sign_msg, &msg, l).unwrap();
+        BBSPlusSig::verify(&key_msg.public_key(), &msg, l, &sig).unwrap();
     }
 }
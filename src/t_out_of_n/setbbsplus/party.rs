@@ -0,0 +1,579 @@
+use bicycl::QFI;
+use futures::SinkExt;
+use round_based::rounds_router::simple_store::{RoundInput, RoundInputError};
+use round_based::rounds_router::{CompleteRoundError, RoundsRouter};
+use round_based::{Delivery, Mpc, MpcParty, Outgoing, PartyIndex, ProtocolMessage};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::error::{blame, blame_batch};
+use crate::{CLRandYuanProof, ProtocolError, Transcript};
+
+use super::*;
+
+/// Round 1 broadcast: a commitment to the party's shares of `e`/`s`, so that
+/// nobody can choose them after seeing the others'.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignMsg1 {
+    pub com: Vec<u8>,
+}
+
+/// Round 2 broadcast: the party's shares of `e`/`s`, opening its commitment, and
+/// its gamma-re-randomized `c1` of the `x` ciphertext, together with the proof of
+/// correct re-randomization.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignMsg2 {
+    pub e_i: Scalar,
+    pub s_i: Scalar,
+    pub ct1_pow: QFI,
+    pub proof: CLRandYuanProof,
+}
+
+/// Output of a signer, sent to the client which combines them with [`Sign::from_partials`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartialSig {
+    pub e: Scalar,
+    pub s: Scalar,
+    pub B_i: G1Projective,
+    pub z_i: Scalar,
+    pub pd_i: QFI,
+}
+
+#[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+pub enum SignMsg {
+    Round1(SignMsg1),
+    Round2(SignMsg2),
+}
+
+pub struct SignParty<'a> {
+    cl: &'a CL_HSMqk,
+    session_id: &'a [u8],
+    key_share: &'a KeyShare,
+    signers: &'a [usize],
+}
+
+pub struct SignPartyRound1<'a> {
+    cl: &'a CL_HSMqk,
+    session_id: &'a [u8],
+    key_share: &'a KeyShare,
+    pub(crate) signers: &'a [usize],
+    pub(crate) gamma_i: Scalar,
+    ct2_pow: QFI,
+    my_msg2: SignMsg2,
+}
+
+pub struct SignPartyRound2<'a> {
+    cl: &'a CL_HSMqk,
+    session_id: &'a [u8],
+    key_share: &'a KeyShare,
+    pub(crate) signers: &'a [usize],
+    pub(crate) gamma_i: Scalar,
+    ct2_pow: QFI,
+    coms: BTreeMap<usize, Vec<u8>>,
+}
+
+impl SignMsg2 {
+    pub fn commit(&self, session_id: &[u8], i: usize) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update((session_id.len() as u64).to_be_bytes());
+        hasher.update(session_id);
+        hasher.update((i as u64).to_be_bytes());
+        hasher.update(self.e_i.to_bytes());
+        hasher.update(self.s_i.to_bytes());
+        hasher.finalize().to_vec()
+    }
+}
+
+impl<'a> SignParty<'a> {
+    /// `signers` are the ids of the parties taking part in this session, in
+    /// increasing order. It must contain the holder of `key_share`.
+    /// `session_id` must be unique to this session and the same for every signer.
+    pub fn new(
+        cl: &'a CL_HSMqk,
+        session_id: &'a [u8],
+        key_share: &'a KeyShare,
+        signers: &'a [usize],
+    ) -> Self {
+        Self {
+            cl,
+            session_id,
+            key_share,
+            signers,
+        }
+    }
+
+    pub fn round1(
+        self,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+    ) -> (SignPartyRound1<'a>, SignMsg1) {
+        let cl = self.cl;
+        let item = &self.key_share.x_ciphertext;
+
+        let e_i = Scalar::random(&mut *csprng);
+        let s_i = Scalar::random(&mut *csprng);
+        let gamma_i = Scalar::random(&mut *csprng);
+
+        let r = rng.random_mpz(&cl.encrypt_randomness_bound());
+
+        let ct1_pow = item
+            .c1()
+            .exp(&cl, &Mpz::from(&gamma_i))
+            .compose(&cl, &cl.power_of_h(&r));
+
+        let ct2_pow = item
+            .c2()
+            .exp(&cl, &Mpz::from(&gamma_i))
+            .compose(&cl, &self.key_share.cl_pub_key.exponentiation(cl, &r));
+
+        let proof = CLRandYuanProof::prove(
+            &cl,
+            &Transcript::new(self.session_id, self.key_share.i),
+            rng,
+            &ct1_pow,
+            &item.c1(),
+            &gamma_i,
+            &r,
+            &cl.q(),
+            &cl.encrypt_randomness_bound(),
+        );
+
+        let my_msg2 = SignMsg2 {
+            e_i,
+            s_i,
+            ct1_pow,
+            proof,
+        };
+        let com = my_msg2.commit(self.session_id, self.key_share.i);
+
+        (
+            SignPartyRound1 {
+                cl,
+                session_id: self.session_id,
+                key_share: self.key_share,
+                signers: self.signers,
+                gamma_i,
+                ct2_pow,
+                my_msg2,
+            },
+            SignMsg1 { com },
+        )
+    }
+}
+
+impl<'a> SignPartyRound1<'a> {
+    /// `msgs` holds the round 1 messages of every signer, including our own.
+    pub fn round2(self, msgs: &BTreeMap<usize, SignMsg1>) -> (SignPartyRound2<'a>, SignMsg2) {
+        let coms = msgs.iter().map(|(j, m)| (*j, m.com.clone())).collect();
+        (
+            SignPartyRound2 {
+                cl: self.cl,
+                session_id: self.session_id,
+                key_share: self.key_share,
+                signers: self.signers,
+                gamma_i: self.gamma_i,
+                ct2_pow: self.ct2_pow.clone(),
+                coms,
+            },
+            self.my_msg2.clone(),
+        )
+    }
+}
+
+impl<'a> SignPartyRound2<'a> {
+    /// `msgs` holds the round 2 messages of every signer, including our own.
+    pub fn round3(
+        self,
+        csprng: &mut impl CryptoRngCore,
+        msgs: &BTreeMap<usize, SignMsg2>,
+        msg: &[Scalar],
+        l: usize,
+    ) -> Result<PartialSig, ProtocolError> {
+        let H = &self.key_share.H;
+        Ok(self.presign(csprng, msgs)?.sign(H, msg, l))
+    }
+
+    /// Does all the work of [`SignPartyRound2::round3`] that does not depend on
+    /// the message, leaving only a few group operations for when it arrives.
+    pub fn presign(
+        self,
+        csprng: &mut impl CryptoRngCore,
+        msgs: &BTreeMap<usize, SignMsg2>,
+    ) -> Result<Presignature, ProtocolError> {
+        let cl = self.cl;
+        let d_i = self.key_share.signing_share(self.signers)?;
+        let missing = self
+            .signers
+            .iter()
+            .filter(|j| !msgs.contains_key(*j) || !self.coms.contains_key(*j))
+            .cloned()
+            .collect();
+        blame(missing, ProtocolError::MissingMessage)?;
+        let strangers = msgs
+            .keys()
+            .filter(|j| !self.signers.contains(*j))
+            .cloned()
+            .collect();
+        blame(strangers, ProtocolError::MalformedMessage)?;
+        let bad_coms = msgs
+            .iter()
+            .filter(|(j, msg_j)| self.coms[*j] != msg_j.commit(self.session_id, **j))
+            .map(|(j, _)| *j)
+            .collect();
+        blame(bad_coms, ProtocolError::InvalidCommitment)?;
+
+        let q = cl.q();
+        let ct1_gen = self.key_share.x_ciphertext.c1();
+
+        let (ids, items): (Vec<usize>, Vec<_>) = msgs
+            .iter()
+            .filter(|(j, _)| **j != self.key_share.i)
+            .map(|(j, msg_j)| {
+                let item = (
+                    Transcript::new(self.session_id, *j),
+                    &msg_j.ct1_pow,
+                    &ct1_gen,
+                    &msg_j.proof,
+                );
+                (*j, item)
+            })
+            .unzip();
+        blame_batch(
+            CLRandYuanProof::batch_verify(&cl, &q, &items),
+            &ids,
+            "CLRandYuanProof",
+        )?;
+
+        let e = msgs
+            .values()
+            .map(|m| m.e_i)
+            .reduce(|acc, e_i| acc + e_i)
+            .unwrap();
+        let s = msgs
+            .values()
+            .map(|m| m.s_i)
+            .reduce(|acc, s_i| acc + s_i)
+            .unwrap();
+        let ct1 = msgs
+            .values()
+            .map(|m| m.ct1_pow.clone())
+            .reduce(|acc, ct| acc.compose(&cl, &ct))
+            .unwrap();
+
+        let n_factorial = &self.key_share.n_factorial;
+        let n_cube = n_factorial.clone() * n_factorial.clone() * n_factorial.clone();
+
+        let rho_i = Scalar::random(&mut *csprng);
+        let z_i = self.gamma_i * e - rho_i;
+
+        let pd_i = ct1.exp(cl, &d_i);
+        let mut v_yi = self.ct2_pow.compose(cl, &cl.power_of_f(&Mpz::from(&rho_i)));
+        v_yi = v_yi.exp(cl, &n_cube);
+
+        let pd_i = v_yi.compose(&cl, &pd_i.exp(&cl, &Mpz::from(-1i64)));
+
+        Ok(Presignature::new(
+            self.signers.to_vec(),
+            e,
+            s,
+            self.gamma_i,
+            z_i,
+            pd_i,
+        ))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SignPartyError<RecvErr, SendErr> {
+    #[error("send a message at round 1")]
+    Round1Send(#[source] SendErr),
+    #[error("receive messages at round 1")]
+    Round1Receive(#[source] CompleteRoundError<RoundInputError, RecvErr>),
+    #[error("send a message at round 2")]
+    Round2Send(#[source] SendErr),
+    #[error("receive messages at round 2")]
+    Round2Receive(#[source] CompleteRoundError<RoundInputError, RecvErr>),
+    #[error("protocol aborted")]
+    Protocol(#[source] ProtocolError),
+}
+
+/// Runs [`SignParty`] over a `round_based` transport among the parties in
+/// `signers`. The signer with index `i` must hold the key share of party
+/// `signers[i]`.
+pub async fn sign_party<M>(
+    party: M,
+    i: PartyIndex,
+    signers: &[usize],
+    cl: &CL_HSMqk,
+    session_id: &[u8],
+    key_share: &KeyShare,
+    rng: &mut RandGen,
+    csprng: &mut impl CryptoRngCore,
+    msg: &[Scalar],
+    l: usize,
+) -> Result<PartialSig, SignPartyError<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = SignMsg>,
+{
+    let presignature =
+        presign_party(party, i, signers, cl, session_id, key_share, rng, csprng).await?;
+    Ok(presignature.sign(&key_share.H, msg, l))
+}
+
+/// Runs the message independent part of [`sign_party`], to be completed later
+/// with [`Presignature::sign`].
+pub async fn presign_party<M>(
+    party: M,
+    i: PartyIndex,
+    signers: &[usize],
+    cl: &CL_HSMqk,
+    session_id: &[u8],
+    key_share: &KeyShare,
+    rng: &mut RandGen,
+    csprng: &mut impl CryptoRngCore,
+) -> Result<Presignature, SignPartyError<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = SignMsg>,
+{
+    let MpcParty { delivery, .. } = party.into_party();
+    let (incoming, mut outgoing) = delivery.split();
+
+    let mut rounds = RoundsRouter::<SignMsg>::builder();
+    let round1 = rounds.add_round(RoundInput::<SignMsg1>::broadcast(i, signers.len() as u16));
+    let round2 = rounds.add_round(RoundInput::<SignMsg2>::broadcast(i, signers.len() as u16));
+    let mut rounds = rounds.listen(incoming);
+
+    let (signer, my_msg1) = SignParty::new(cl, session_id, key_share, signers).round1(rng, csprng);
+    outgoing
+        .send(Outgoing::broadcast(SignMsg::Round1(my_msg1.clone())))
+        .await
+        .map_err(SignPartyError::Round1Send)?;
+    let msgs1 = rounds
+        .complete(round1)
+        .await
+        .map_err(SignPartyError::Round1Receive)?;
+    let msgs1 = by_signer_id(signers, msgs1.into_vec_including_me(my_msg1));
+
+    let (signer, my_msg2) = signer.round2(&msgs1);
+    outgoing
+        .send(Outgoing::broadcast(SignMsg::Round2(my_msg2.clone())))
+        .await
+        .map_err(SignPartyError::Round2Send)?;
+    let msgs2 = rounds
+        .complete(round2)
+        .await
+        .map_err(SignPartyError::Round2Receive)?;
+    let msgs2 = by_signer_id(signers, msgs2.into_vec_including_me(my_msg2));
+
+    signer
+        .presign(csprng, &msgs2)
+        .map_err(SignPartyError::Protocol)
+}
+
+fn by_signer_id<T>(signers: &[usize], msgs: Vec<T>) -> BTreeMap<usize, T> {
+    msgs.into_iter()
+        .enumerate()
+        .map(|(j, m)| (signers[j], m))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::{arithmetic::Converter, BigInt};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use round_based::simulation::Simulation;
+
+    use crate::MODULUS;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sign_party() {
+        let seed: [u8; 32] = [0u8; 32];
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let n = 5;
+        let t = 3;
+        let l = 10;
+        let signers = [1, 3, 4, 5];
+        let mut msg: Vec<Scalar> = Vec::with_capacity(l);
+
+        for _ in 0..l {
+            msg.push(Scalar::random(&mut scalr_rng));
+        }
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng).unwrap();
+        let key_shares: Vec<_> = signers.iter().map(|i| key_msg.key_share(*i)).collect();
+
+        let mut simulation = Simulation::<SignMsg>::new();
+        let mut outputs = Vec::with_capacity(signers.len());
+        for (i, key_share) in key_shares.iter().enumerate() {
+            let party = simulation.add_party();
+            let mut party_rng = RandGen::new();
+            party_rng.set_seed(&Mpz::from(i as u64 + 1));
+            let mut party_csprng = ChaChaRng::from_seed([i as u8 + 1; 32]);
+            let cl = &cl;
+            let msg = &msg;
+            let signers = &signers;
+            outputs.push(async move {
+                sign_party(
+                    party,
+                    i as u16,
+                    signers,
+                    cl,
+                    b"test_sign_party",
+                    key_share,
+                    &mut party_rng,
+                    &mut party_csprng,
+                    msg,
+                    l,
+                )
+                .await
+                .map(|partial| (signers[i], partial))
+            });
+        }
+        let partials = futures::future::try_join_all(outputs)
+            .await
+            .unwrap()
+            .into_iter()
+            .collect();
+
+        let sign_msg = Sign::from_partials(
+            &key_msg.sign_keys.H,
+            &key_msg.sign_keys.pub_key,
+            &key_msg.cl_keys.n_factorial,
+            &partials,
+        )
+        .unwrap();
+        Sign::client(&cl, &sign_msg, &msg, l).unwrap();
+    }
+
+    #[test]
+    fn test_sign_party_bad_opening() {
+        let mut scalr_rng = ChaChaRng::from_seed([0u8; 32]);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let (n, t, l) = (3, 2, 2);
+        let signers = [1, 2, 3];
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng).unwrap();
+        let key_shares: Vec<_> = signers.iter().map(|i| key_msg.key_share(*i)).collect();
+
+        let mut parties = Vec::with_capacity(signers.len());
+        let mut msgs1 = BTreeMap::new();
+        for (i, key_share) in signers.iter().zip(&key_shares) {
+            let (party, msg1) =
+                SignParty::new(&cl, b"sign", key_share, &signers).round1(&mut rng, &mut scalr_rng);
+            parties.push(party);
+            msgs1.insert(*i, msg1);
+        }
+        let mut parties: Vec<_> = parties
+            .into_iter()
+            .map(|party| party.round2(&msgs1))
+            .collect();
+        let mut msgs2: BTreeMap<_, _> = signers
+            .iter()
+            .zip(parties.iter())
+            .map(|(i, (_, msg2))| (*i, msg2.clone()))
+            .collect();
+
+        // party 2 changes its share of e after seeing the others'
+        msgs2.get_mut(&2).unwrap().e_i += Scalar::one();
+        let (party, _) = parties.remove(0);
+        assert_eq!(
+            party.presign(&mut scalr_rng, &msgs2).err(),
+            Some(ProtocolError::InvalidCommitment(vec![2]))
+        );
+    }
+
+    #[test]
+    fn test_sign_party_distinct_secrets() {
+        fn distinct(xs: &[Scalar]) -> bool {
+            xs.iter()
+                .enumerate()
+                .all(|(k, x)| xs[k + 1..].iter().all(|y| y != x))
+        }
+
+        let mut scalr_rng = ChaChaRng::from_seed([0u8; 32]);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let (n, t, l) = (3, 2, 2);
+        let signers = [1, 2, 3];
+        let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut scalr_rng)).collect();
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng).unwrap();
+        let key_shares: Vec<_> = signers.iter().map(|i| key_msg.key_share(*i)).collect();
+
+        let mut parties = Vec::with_capacity(signers.len());
+        let mut msgs1 = BTreeMap::new();
+        for (i, key_share) in signers.iter().zip(&key_shares) {
+            let (party, msg1) =
+                SignParty::new(&cl, b"sign", key_share, &signers).round1(&mut rng, &mut scalr_rng);
+            parties.push(party);
+            msgs1.insert(*i, msg1);
+        }
+        let gamma_is: Vec<_> = parties.iter().map(|party| party.gamma_i).collect();
+        let parties: Vec<_> = parties
+            .into_iter()
+            .map(|party| party.round2(&msgs1))
+            .collect();
+        let msgs2: BTreeMap<_, _> = signers
+            .iter()
+            .zip(parties.iter())
+            .map(|(i, (_, msg2))| (*i, msg2.clone()))
+            .collect();
+
+        // rho_i is only visible through z_i = gamma_i e - rho_i
+        let rho_is: Vec<_> = parties
+            .into_iter()
+            .map(|(party, _)| {
+                let presig = party.presign(&mut scalr_rng, &msgs2).unwrap();
+                let gamma_i = presig.gamma_i;
+                let partial = presig.sign(&key_msg.sign_keys.H, &msg, l);
+                gamma_i * partial.e - partial.z_i
+            })
+            .collect();
+
+        let x_is: Vec<_> = key_shares
+            .iter()
+            .map(|key_share| key_share.x_share)
+            .collect();
+        let e_is: Vec<_> = msgs2.values().map(|msg2| msg2.e_i).collect();
+        let s_is: Vec<_> = msgs2.values().map(|msg2| msg2.s_i).collect();
+        assert!(distinct(&x_is));
+        assert!(distinct(&e_is));
+        assert!(distinct(&s_is));
+        assert!(distinct(&gamma_is));
+        assert!(distinct(&rho_is));
+    }
+}
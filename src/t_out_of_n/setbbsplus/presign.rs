@@ -0,0 +1,249 @@
+use bicycl::QFI;
+
+use crate::{BlindRequest, MaskedRequest};
+
+use super::*;
+
+/// A signer's share of a signature on a message that is not known yet, produced
+/// by [`SignPartyRound2::presign`] or [`presign_party`]. Signing two messages
+/// with the same presignature leaks the signer's `gamma_i`, hence
+/// [`Presignature::sign`] consumes it, it cannot be cloned, and it only lives in
+/// memory.
+pub struct Presignature {
+    pub(crate) signers: Vec<usize>,
+    e: Scalar,
+    s: Scalar,
+    pub(crate) gamma_i: Scalar,
+    z_i: Scalar,
+    pd_i: QFI,
+}
+
+impl Presignature {
+    pub(crate) fn new(
+        signers: Vec<usize>,
+        e: Scalar,
+        s: Scalar,
+        gamma_i: Scalar,
+        z_i: Scalar,
+        pd_i: QFI,
+    ) -> Self {
+        Self {
+            signers,
+            e,
+            s,
+            gamma_i,
+            z_i,
+            pd_i,
+        }
+    }
+
+    /// The ids of the parties that made the presignature together.
+    pub fn signers(&self) -> &[usize] {
+        &self.signers
+    }
+
+    /// The online phase: no interaction and no class group operations.
+    pub fn sign(self, H: &[G1Projective], msg: &[Scalar], l: usize) -> PartialSig {
+        let mut base = G1Projective::generator();
+        for i in 0..l {
+            base = base + H[i] * msg[i];
+        }
+        let B = base + H[l] * self.s;
+        self.partial(B)
+    }
+
+    /// Signs the messages committed to in `request`, made for the signing
+    /// session `session_id`, of which the signer only sees the `revealed` ones.
+    pub fn sign_blind(
+        self,
+        session_id: &[u8],
+        H: &[G1Projective],
+        l: usize,
+        request: &BlindRequest,
+        revealed: &BTreeMap<usize, Scalar>,
+    ) -> Result<PartialSig, ProtocolError> {
+        request.verify(session_id, H, l)?;
+        let B = request.base(H, l, revealed)? + H[l] * self.s;
+        Ok(self.partial(B))
+    }
+
+    /// Signs the masked `B'` of `request`, made for the signing session
+    /// `session_id`, with the messages that are not hidden being `revealed`.
+    pub fn sign_masked(
+        self,
+        session_id: &[u8],
+        H: &[G1Projective],
+        l: usize,
+        request: &MaskedRequest,
+        revealed: &BTreeMap<usize, Scalar>,
+    ) -> Result<PartialSig, ProtocolError> {
+        request.verify(session_id, H, l, revealed)?;
+        Ok(self.partial(request.B))
+    }
+
+    fn partial(self, B: G1Projective) -> PartialSig {
+        PartialSig {
+            e: self.e,
+            s: self.s,
+            B_i: self.gamma_i * B,
+            z_i: self.z_i,
+            pd_i: self.pd_i.clone(),
+        }
+    }
+}
+
+/// The presignatures a signer has computed ahead of time, by id. The signers of
+/// a session agree on the ids, e.g. by numbering their presigning sessions, and
+/// every signing request names the presignature to use. A presignature is
+/// removed from the pool when it is taken, so it can't be used twice, and the
+/// pool gives out no other access to it. There is deliberately no way to
+/// persist a pool: restoring an older copy would bring back spent
+/// presignatures.
+#[derive(Debug, Default)]
+pub struct PresignaturePool {
+    presignatures: BTreeMap<usize, Presignature>,
+}
+
+impl PresignaturePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, id: usize, presignature: Presignature) {
+        self.presignatures.insert(id, presignature);
+    }
+
+    pub fn take(&mut self, id: usize) -> Option<Presignature> {
+        self.presignatures.remove(&id)
+    }
+
+    /// Takes the presignature with the lowest id.
+    pub fn pop(&mut self) -> Option<(usize, Presignature)> {
+        self.presignatures.pop_first()
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        self.presignatures.contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.presignatures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.presignatures.is_empty()
+    }
+
+    /// Signs `msg` with presignature `id` if it is still in the pool.
+    pub fn sign(
+        &mut self,
+        id: usize,
+        H: &[G1Projective],
+        msg: &[Scalar],
+        l: usize,
+    ) -> Option<PartialSig> {
+        Some(self.take(id)?.sign(H, msg, l))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::{arithmetic::Converter, BigInt};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use round_based::simulation::Simulation;
+
+    use crate::MODULUS;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_presign() {
+        let mut scalr_rng = ChaChaRng::from_seed([0u8; 32]);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let (n, t, l) = (4, 2, 5);
+        let signers = [2, 4];
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng).unwrap();
+        let key_shares: Vec<_> = signers.iter().map(|i| key_msg.key_share(*i)).collect();
+
+        // offline: two presigning sessions, filling every signer's pool
+        let mut pools: Vec<_> = signers.iter().map(|_| PresignaturePool::new()).collect();
+        for id in 0..2 {
+            let mut simulation = Simulation::<SignMsg>::new();
+            let mut outputs = Vec::with_capacity(signers.len());
+            for (i, key_share) in key_shares.iter().enumerate() {
+                let party = simulation.add_party();
+                let mut party_rng = RandGen::new();
+                party_rng.set_seed(&Mpz::from((10 * id + i) as u64 + 1));
+                let mut party_csprng = ChaChaRng::from_seed([(10 * id + i) as u8 + 1; 32]);
+                let cl = &cl;
+                let signers = &signers;
+                outputs.push(async move {
+                    presign_party(
+                        party,
+                        i as u16,
+                        signers,
+                        cl,
+                        &(id as u64).to_be_bytes(),
+                        key_share,
+                        &mut party_rng,
+                        &mut party_csprng,
+                    )
+                    .await
+                });
+            }
+            let presignatures = futures::future::try_join_all(outputs).await.unwrap();
+            for (pool, presignature) in pools.iter_mut().zip(presignatures) {
+                pool.insert(id, presignature);
+            }
+        }
+
+        assert!(pools.iter().all(|pool| pool.len() == 2 && pool.contains(1)));
+
+        // online: one message per presignature, taken by id or lowest first
+        for id in [1, 0] {
+            let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut scalr_rng)).collect();
+            let partials = pools
+                .iter_mut()
+                .zip(signers)
+                .map(|(pool, i)| {
+                    let partial = if id == 1 {
+                        pool.sign(id, &key_msg.sign_keys.H, &msg, l)
+                    } else {
+                        let (popped, presignature) = pool.pop().unwrap();
+                        assert_eq!(popped, id);
+                        Some(presignature.sign(&key_msg.sign_keys.H, &msg, l))
+                    };
+                    (i, partial.unwrap())
+                })
+                .collect();
+            let sign_msg = Sign::from_partials(
+                &key_msg.sign_keys.H,
+                &key_msg.sign_keys.pub_key,
+                &key_msg.cl_keys.n_factorial,
+                &partials,
+            )
+            .unwrap();
+            Sign::client(&cl, &sign_msg, &msg, l).unwrap();
+        }
+
+        // each presignature is given out once
+        for pool in pools.iter_mut() {
+            assert!(pool.is_empty());
+            assert!(pool.take(0).is_none());
+            assert!(pool.sign(1, &key_msg.sign_keys.H, &[], 0).is_none());
+            assert!(pool.pop().is_none());
+        }
+    }
+}
@@ -0,0 +1,397 @@
+use bicycl::QFI;
+
+use crate::error::{blame, blame_batch, blame_proof};
+use crate::{Transcript, ZeroShare, PVSS};
+
+use super::*;
+
+/// The public part of a [`KeyGen::refresh`], from which anyone who knows the old
+/// public key shares can check that the refreshed keys still belong to the same
+/// public keys.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Refresh {
+    /// Commitments to the coefficients of every party's sharing of zero for the
+    /// CL key, the constant term first.
+    pub cl_commitments: BTreeMap<usize, Vec<QFI>>,
+    /// `x_deltas[&i][&j]` is `g2` times what party `i` adds to the BBS+ share of
+    /// party `j`. The values dealt by one party sum to zero.
+    pub x_deltas: BTreeMap<usize, BTreeMap<usize, G2Projective>>,
+    pub x_shares: EncryptedShares,
+}
+
+/// Every party's new BBS+ share `x_j`, as `g2 x_j` with a proof of knowledge of
+/// `x_j`, and encrypted under the CL public key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncryptedShares {
+    pub pub_shares: BTreeMap<usize, G2Projective>,
+    pub proofs: BTreeMap<usize, ComZkDlComElproof>,
+    pub ciphertexts: BTreeMap<usize, CipherText>,
+    pub ciphertext_proofs: BTreeMap<usize, CLEncProof>,
+}
+
+impl KeyGen {
+    /// Re-randomizes every party's shares of the CL and BBS+ secret keys, and the
+    /// encryption of `x` they sign with, keeping both public keys. Shares from
+    /// before the refresh are useless together with shares from after it.
+    /// `session_id` must be fresh, as for [`KeyGen::keygen`].
+    pub fn refresh(
+        &self,
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+    ) -> Result<(Self, Refresh), ProtocolError> {
+        let n = self.cl_keys.sk_shares.len();
+        let t = self.cl_keys.t;
+        let n_factorial = &self.cl_keys.n_factorial;
+        let cl_pk = &self.cl_keys.pub_key;
+
+        // Every party deals a sharing of zero, and adds the points it gets to its
+        // point on the polynomial of `d`.
+        let zeros = (1..=n).map(|i| (i, Mpz::from(0u64))).collect();
+        let pvss = PVSS::share(
+            cl,
+            session_id,
+            rng,
+            &zeros,
+            &cl.encrypt_randomness_bound(),
+            t,
+            n,
+            n_factorial,
+        )?;
+        let deltas = PVSS::poly_shares(&pvss, n, n_factorial);
+        let cl_sk_shares = self
+            .cl_keys
+            .sk_shares
+            .iter()
+            .map(|(i, s_i)| (*i, s_i.clone() + deltas.get(i).unwrap().clone()))
+            .collect();
+        let mut zero_shares = ZeroShare::share(cl, rng, n);
+
+        // Every party deals additive shares of zero to the others' BBS+ shares.
+        let mut x_deltas = BTreeMap::new();
+        let mut sk_shares = self.sign_keys.sk_shares.clone();
+        for i in 1..=n {
+            let commitments = deal_additive(Scalar::zero(), n, csprng)
+                .into_iter()
+                .map(|(j, rho)| {
+                    *sk_shares.get_mut(&j).unwrap() += rho;
+                    (j, G2Projective::generator() * rho)
+                })
+                .collect();
+            x_deltas.insert(i, commitments);
+        }
+
+        let refresh = Refresh {
+            cl_commitments: pvss.A.clone(),
+            x_deltas,
+            x_shares: EncryptedShares::encrypt(cl, session_id, cl_pk, &sk_shares, rng, csprng),
+        };
+        refresh.verify(cl, session_id, cl_pk, &self.sign_keys.pub_shares)?;
+
+        let x_ciphertext = refresh.x_shares.x_ciphertext(cl);
+        let key_gen = Self {
+            cl_keys: CLKeys {
+                sk_shares: cl_sk_shares,
+                pub_key: cl_pk.clone(),
+                n_factorial: n_factorial.clone(),
+                zero_shares: std::mem::take(&mut zero_shares.beta_ijs),
+                t,
+                bound: self.cl_keys.bound.clone() + cl.encrypt_randomness_bound(),
+            },
+            sign_keys: SignKeys {
+                sk_shares,
+                pub_shares: refresh.x_shares.pub_shares.clone(),
+                pub_key: self.sign_keys.pub_key,
+                H: self.sign_keys.H.clone(),
+            },
+            each_party_x_ciphertexts: (1..=n).map(|i| (i, x_ciphertext.clone())).collect(),
+        };
+        Ok((key_gen, refresh))
+    }
+}
+
+impl Refresh {
+    /// Checks the refresh of the keys whose BBS+ public key shares were
+    /// `old_pub_shares`: every party dealt sharings of zero, and proved that it
+    /// knows and encrypted under `cl_pk` its new BBS+ share. The parties check
+    /// the points they were dealt against the commitments themselves.
+    pub fn verify(
+        &self,
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        cl_pk: &PublicKey,
+        old_pub_shares: &BTreeMap<usize, G2Projective>,
+    ) -> Result<(), ProtocolError> {
+        let parties: Vec<usize> = old_pub_shares.keys().cloned().collect();
+        let malformed = parties
+            .iter()
+            .filter(|j| {
+                !self.cl_commitments.contains_key(*j)
+                    || self.x_deltas.get(*j).map(|deltas| deltas.len()) != Some(parties.len())
+            })
+            .cloned()
+            .collect();
+        blame(malformed, ProtocolError::MalformedMessage)?;
+
+        let one = cl.power_of_h(&Mpz::from(0u64));
+        let bad_shares = parties
+            .iter()
+            .filter(|i| {
+                self.cl_commitments[*i].first() != Some(&one)
+                    || sum(self.x_deltas[*i].values()) != G2Projective::identity()
+            })
+            .cloned()
+            .collect();
+        blame(bad_shares, ProtocolError::InvalidShare)?;
+
+        let new_pub_shares = parties
+            .iter()
+            .map(|j| (*j, old_pub_shares[j] + received(&self.x_deltas, *j)))
+            .collect();
+        self.x_shares.verify(cl, session_id, cl_pk, &new_pub_shares)
+    }
+}
+
+impl EncryptedShares {
+    /// Publishes and encrypts the BBS+ shares `sk_shares` under `cl_pk`.
+    pub fn encrypt(
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        cl_pk: &PublicKey,
+        sk_shares: &BTreeMap<usize, Scalar>,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+    ) -> Self {
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+        let mut pub_shares = BTreeMap::new();
+        let mut proofs = BTreeMap::new();
+        let mut ciphertexts = BTreeMap::new();
+        let mut ciphertext_proofs = BTreeMap::new();
+        for (j, x_j) in sk_shares {
+            let transcript = Transcript::new(session_id, *j);
+            let X_j = G2Projective::generator() * x_j;
+            let proof = ComZkDlComElproof::prove(&transcript, csprng, &X_j.into(), x_j);
+
+            let cl_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
+            let c1 = cl.power_of_h(&cl_rand);
+            let c2 = cl
+                .power_of_f(&Mpz::from(x_j))
+                .compose(cl, &cl_pk.exponentiation(cl, &cl_rand));
+            let ciphertext = CipherText::new(&c1, &c2);
+            let ciphertext_proof = CLEncProof::prove(
+                cl,
+                &transcript,
+                &bound,
+                rng,
+                cl_pk,
+                &ciphertext,
+                &X_j,
+                x_j,
+                &cl_rand,
+                csprng,
+            );
+            pub_shares.insert(*j, X_j);
+            proofs.insert(*j, proof);
+            ciphertexts.insert(*j, ciphertext);
+            ciphertext_proofs.insert(*j, ciphertext_proof);
+        }
+        Self {
+            pub_shares,
+            proofs,
+            ciphertexts,
+            ciphertext_proofs,
+        }
+    }
+
+    /// Checks that the shares are `expected_pub_shares`, and that every party
+    /// proved it knows and encrypted under `cl_pk` its share.
+    pub fn verify(
+        &self,
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        cl_pk: &PublicKey,
+        expected_pub_shares: &BTreeMap<usize, G2Projective>,
+    ) -> Result<(), ProtocolError> {
+        let parties: Vec<usize> = expected_pub_shares.keys().cloned().collect();
+        let malformed = parties
+            .iter()
+            .filter(|j| {
+                !self.pub_shares.contains_key(*j)
+                    || !self.proofs.contains_key(*j)
+                    || !self.ciphertexts.contains_key(*j)
+                    || !self.ciphertext_proofs.contains_key(*j)
+            })
+            .cloned()
+            .collect();
+        blame(malformed, ProtocolError::MalformedMessage)?;
+
+        let inconsistent = parties
+            .iter()
+            .filter(|j| self.pub_shares[*j] != expected_pub_shares[*j])
+            .cloned()
+            .collect();
+        blame(inconsistent, ProtocolError::Inconsistent)?;
+
+        let bad_proofs = parties
+            .iter()
+            .filter(|j| {
+                !self.proofs[*j].verify(
+                    &Transcript::new(session_id, **j),
+                    &self.pub_shares[*j].into(),
+                )
+            })
+            .cloned()
+            .collect();
+        blame_proof(bad_proofs, "ComZkDlComElproof")?;
+
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+        let items: Vec<_> = parties
+            .iter()
+            .map(|j| {
+                (
+                    Transcript::new(session_id, *j),
+                    &bound,
+                    cl_pk,
+                    &self.ciphertexts[j],
+                    &self.pub_shares[j],
+                    &self.ciphertext_proofs[j],
+                )
+            })
+            .collect();
+        blame_batch(CLEncProof::batch_verify(cl, &items), &parties, "CLEncProof")
+    }
+
+    /// The encryption of `x`, the sum of the encrypted shares.
+    pub fn x_ciphertext(&self, cl: &CL_HSMqk) -> CipherText {
+        self.ciphertexts
+            .values()
+            .cloned()
+            .reduce(|acc, ct| {
+                CipherText::new(
+                    &acc.c1().compose(cl, &ct.c1()),
+                    &acc.c2().compose(cl, &ct.c2()),
+                )
+            })
+            .unwrap()
+    }
+}
+
+/// Splits `value` into `n` random additive shares for the parties `1..=n`.
+pub(super) fn deal_additive(
+    value: Scalar,
+    n: usize,
+    csprng: &mut impl CryptoRngCore,
+) -> BTreeMap<usize, Scalar> {
+    let mut shares: BTreeMap<usize, Scalar> =
+        (2..=n).map(|j| (j, Scalar::random(&mut *csprng))).collect();
+    let first = shares.values().fold(value, |acc, share| acc - share);
+    shares.insert(1, first);
+    shares
+}
+
+/// The sum of what the dealers in `deals` dealt to party `j`.
+pub(super) fn received(
+    deals: &BTreeMap<usize, BTreeMap<usize, G2Projective>>,
+    j: usize,
+) -> G2Projective {
+    sum(deals.values().filter_map(|deal| deal.get(&j)))
+}
+
+pub(super) fn sum<'a>(points: impl Iterator<Item = &'a G2Projective>) -> G2Projective {
+    points.fold(G2Projective::identity(), |acc, point| acc + point)
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::{arithmetic::Converter, BigInt};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    use crate::MODULUS;
+
+    use super::*;
+
+    #[test]
+    fn test_refresh() {
+        let seed: [u8; 32] = [0u8; 32];
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let n = 5;
+        let t = 3;
+        let l = 10;
+        let mut msg: Vec<Scalar> = Vec::with_capacity(l);
+
+        for _ in 0..l {
+            msg.push(Scalar::random(&mut scalr_rng));
+        }
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng).unwrap();
+        let (refreshed, refresh) = key_msg
+            .refresh(&cl, b"refresh", &mut rng, &mut scalr_rng)
+            .unwrap();
+        refresh
+            .verify(
+                &cl,
+                b"refresh",
+                &key_msg.cl_keys.pub_key,
+                &key_msg.sign_keys.pub_shares,
+            )
+            .unwrap();
+
+        assert_eq!(refreshed.public_key(), key_msg.public_key());
+        assert_eq!(
+            refreshed.cl_keys.pub_key.elt(),
+            key_msg.cl_keys.pub_key.elt()
+        );
+        for i in 1..=n {
+            assert_ne!(
+                refreshed.sign_keys.sk_shares[&i],
+                key_msg.sign_keys.sk_shares[&i]
+            );
+            assert_ne!(
+                refreshed.cl_keys.sk_shares[&i],
+                key_msg.cl_keys.sk_shares[&i]
+            );
+        }
+
+        let signers = [2, 3, 5];
+        let sign_msg = Sign::sign(
+            &cl,
+            b"sign",
+            &signers,
+            l,
+            &mut rng,
+            &mut scalr_rng,
+            &refreshed,
+            &msg,
+            &cl.q(),
+        )
+        .unwrap();
+        let sig = Sign::client(&cl, &sign_msg, &msg, l).unwrap();
+        BBSPlusSig::verify(&key_msg.public_key(), &msg, l, &sig).unwrap();
+
+        let mut forged = refresh.clone();
+        let delta = forged.x_deltas.get_mut(&4).unwrap().get_mut(&1).unwrap();
+        *delta += G2Projective::generator();
+        assert_eq!(
+            forged.verify(
+                &cl,
+                b"refresh",
+                &key_msg.cl_keys.pub_key,
+                &key_msg.sign_keys.pub_shares,
+            ),
+            Err(ProtocolError::InvalidShare(vec![4]))
+        );
+    }
+}
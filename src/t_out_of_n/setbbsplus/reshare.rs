@@ -0,0 +1,427 @@
+use bicycl::QFI;
+use ff::PrimeField;
+
+use crate::error::{blame, blame_batch};
+use crate::{check_signers, CLPDProof, Transcript, ZeroShare, PVSS};
+
+use super::refresh::sum;
+use super::*;
+
+/// The public part of a [`KeyGen::reshare`], from which anyone who knows the old
+/// public keys can check that the new committee's keys belong to the same BBS+
+/// public key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reshare {
+    /// The old parties that dealt their signing shares of the CL key.
+    pub dealers: Vec<usize>,
+    /// Commitments to the coefficients of the polynomials the `dealers` dealt
+    /// with, the constant term first.
+    pub cl_commitments: BTreeMap<usize, Vec<QFI>>,
+    /// The new parties' random masks `r_j`, encrypted under the new CL public
+    /// key.
+    pub x_masks: EncryptedShares,
+    /// The `dealers`' partial decryptions of the encryption of `x - sum_j r_j`
+    /// under the new CL public key, with proofs that they used their signing
+    /// shares.
+    pub pds: BTreeMap<usize, QFI>,
+    pub pd_proofs: BTreeMap<usize, CLPDProof>,
+    /// `x - sum_j r_j`. New party `j`'s BBS+ share is `r_j + x_offset / n`.
+    pub x_offset: Scalar,
+}
+
+impl KeyGen {
+    /// Moves the keys to a new committee of `n` parties, any `t` of which can
+    /// sign, keeping `X` and `H`. The old parties in `dealers`, which must be a
+    /// signing set, reshare their shares of `n!^3 d` for the old `n`, so the new
+    /// committee's CL secret key is `n!^3 d` and its public key changes with it.
+    /// The new parties mask `x` with random shares, and the `dealers` decrypt the
+    /// masked `x` from the old encryption of `x`, so the other old parties need
+    /// not take part.
+    pub fn reshare(
+        &self,
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        dealers: &[usize],
+        n: usize,
+        t: usize,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+    ) -> Result<(Self, Reshare), ProtocolError> {
+        let old_n_factorial = &self.cl_keys.n_factorial;
+        let mut n_factorial = Mpz::from(1u64);
+        for i in 1..=n {
+            n_factorial = Mpz::from(i as u64) * &n_factorial;
+        }
+
+        let signing_shares = dealers
+            .iter()
+            .map(|i| Ok((*i, self.cl_keys.signing_share(*i, dealers)?)))
+            .collect::<Result<BTreeMap<_, _>, ProtocolError>>()?;
+        let bound = signing_share_bound(cl, &self.cl_keys, dealers.len());
+        let pvss = PVSS::share(
+            cl,
+            session_id,
+            rng,
+            &signing_shares,
+            &bound,
+            t,
+            n,
+            &n_factorial,
+        )?;
+        let mut zero_shares = ZeroShare::share(cl, rng, n);
+
+        let cl_pk = reshared_cl_pub_key(cl, &self.cl_keys.pub_key, old_n_factorial);
+        let masks: BTreeMap<usize, Scalar> =
+            (1..=n).map(|j| (j, Scalar::random(&mut *csprng))).collect();
+        let x_masks = EncryptedShares::encrypt(cl, session_id, &cl_pk, &masks, rng, csprng);
+        let masked = masked_x_ciphertext(cl, self, &x_masks);
+        let pd_bound = ResponseBound::new(&bound);
+        let mut pds = BTreeMap::new();
+        let mut pd_proofs = BTreeMap::new();
+        for (i, d_i) in &signing_shares {
+            let pd_i = masked.c1().exp(cl, d_i);
+            let proof = CLPDProof::prove(
+                cl,
+                &Transcript::new(session_id, *i),
+                &pd_bound,
+                rng,
+                &pvss.A[i][0],
+                &pd_i,
+                &masked.c1(),
+                d_i,
+            );
+            pds.insert(*i, pd_i);
+            pd_proofs.insert(*i, proof);
+        }
+        let x_offset = decrypt(cl, &masked, pds.values());
+
+        let reshare = Reshare {
+            dealers: dealers.to_vec(),
+            cl_commitments: pvss.A.clone(),
+            x_masks,
+            pds,
+            pd_proofs,
+            x_offset,
+        };
+        reshare.verify(cl, session_id, self, n)?;
+
+        let offset = x_offset * Scalar::from(n as u64).invert().unwrap();
+        let x_ciphertext = reshare.x_masks.x_ciphertext(cl);
+        let x_ciphertext = CipherText::new(
+            &x_ciphertext.c1(),
+            &x_ciphertext
+                .c2()
+                .compose(cl, &cl.power_of_f(&Mpz::from(&x_offset))),
+        );
+        let key_gen = Self {
+            cl_keys: CLKeys {
+                sk_shares: PVSS::poly_shares(&pvss, n, &n_factorial),
+                pub_key: cl_pk,
+                n_factorial,
+                zero_shares: std::mem::take(&mut zero_shares.beta_ijs),
+                t,
+                bound: bound * Mpz::from(dealers.len() as u64),
+            },
+            sign_keys: SignKeys {
+                sk_shares: masks.iter().map(|(j, r_j)| (*j, r_j + offset)).collect(),
+                pub_shares: reshare
+                    .x_masks
+                    .pub_shares
+                    .iter()
+                    .map(|(j, R_j)| (*j, R_j + G2Projective::generator() * offset))
+                    .collect(),
+                pub_key: self.sign_keys.pub_key,
+                H: self.sign_keys.H.clone(),
+            },
+            each_party_x_ciphertexts: (1..=n).map(|i| (i, x_ciphertext.clone())).collect(),
+        };
+        Ok((key_gen, reshare))
+    }
+}
+
+impl Reshare {
+    /// Checks the resharing of the keys `old` to `n` new parties, using only
+    /// their public parts: the dealers' commitments add up to the new CL key, the
+    /// new parties proved that they know and encrypted their masks, the dealers
+    /// proved their partial decryptions, and `x_offset` and the masks add up to
+    /// `x`. The new parties check the points they were dealt against the
+    /// commitments themselves.
+    pub fn verify(
+        &self,
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        old: &KeyGen,
+        n: usize,
+    ) -> Result<(), ProtocolError> {
+        check_signers(&self.dealers, old.cl_keys.t, old.cl_keys.sk_shares.len())?;
+        let malformed_dealers = self
+            .dealers
+            .iter()
+            .filter(|i| {
+                self.cl_commitments.get(*i).map_or(true, Vec::is_empty)
+                    || !self.pds.contains_key(*i)
+                    || !self.pd_proofs.contains_key(*i)
+            })
+            .cloned()
+            .collect();
+        blame(malformed_dealers, ProtocolError::MalformedMessage)?;
+        let malformed = (1..=n)
+            .filter(|j| !self.x_masks.pub_shares.contains_key(j))
+            .chain(
+                self.x_masks
+                    .pub_shares
+                    .keys()
+                    .filter(|j| !(1..=n).contains(*j))
+                    .cloned(),
+            )
+            .collect();
+        blame(malformed, ProtocolError::MalformedMessage)?;
+
+        let cl_pk = reshared_cl_pub_key(cl, &old.cl_keys.pub_key, &old.cl_keys.n_factorial);
+        let dealt = self
+            .dealers
+            .iter()
+            .map(|i| self.cl_commitments[i][0].clone())
+            .reduce(|acc, A_i0| acc.compose(cl, &A_i0));
+        if dealt != Some(cl_pk.elt()) {
+            blame(self.dealers.clone(), ProtocolError::Inconsistent)?;
+        }
+
+        self.x_masks
+            .verify(cl, session_id, &cl_pk, &self.x_masks.pub_shares)?;
+
+        let masked = masked_x_ciphertext(cl, old, &self.x_masks);
+        let c1 = masked.c1();
+        let bound = ResponseBound::new(&signing_share_bound(cl, &old.cl_keys, self.dealers.len()));
+        let items: Vec<_> = self
+            .dealers
+            .iter()
+            .map(|i| {
+                (
+                    Transcript::new(session_id, *i),
+                    &bound,
+                    &self.cl_commitments[i][0],
+                    &self.pds[i],
+                    &c1,
+                    &self.pd_proofs[i],
+                )
+            })
+            .collect();
+        blame_batch(
+            CLPDProof::batch_verify(cl, &items),
+            &self.dealers,
+            "CLPDProof",
+        )?;
+
+        let x_offset = decrypt(cl, &masked, self.dealers.iter().map(|i| &self.pds[i]));
+        let X = sum(self.x_masks.pub_shares.values()) + G2Projective::generator() * self.x_offset;
+        if x_offset != self.x_offset || X != old.sign_keys.pub_key {
+            blame(self.dealers.clone(), ProtocolError::Inconsistent)?;
+        }
+        Ok(())
+    }
+}
+
+/// `old_cl_pk^(n!^3)`, the public key of the shares of `n!^3 d` the old parties
+/// sign with.
+fn reshared_cl_pub_key(cl: &CL_HSMqk, old_cl_pk: &PublicKey, old_n_factorial: &Mpz) -> PublicKey {
+    let n_cube = old_n_factorial.clone() * old_n_factorial.clone() * old_n_factorial.clone();
+    PublicKey::from_qfi(cl, &old_cl_pk.elt().exp(cl, &n_cube))
+}
+
+/// Bounds the signing shares of `dealers` parties: the Lagrange share bound,
+/// plus the bound on a share of zero.
+fn signing_share_bound(cl: &CL_HSMqk, cl_keys: &CLKeys, dealers: usize) -> Mpz {
+    let old_n = cl_keys.sk_shares.len();
+    PVSS::lagrange_share_bound(&cl_keys.bound, dealers, old_n, &cl_keys.n_factorial)
+        + Mpz::from(old_n as u64) * cl.encrypt_randomness_bound()
+}
+
+/// The encryption of `x - sum_j r_j` under the new CL public key `cl_pk^N`, for
+/// `N = n!^3` of the `old` keys, from the old encryption of `x` under `cl_pk` and
+/// the encrypted masks. If `(c1, c2)` encrypts `x` under `cl_pk`, then
+/// `(c1^M, c2^(N M))` encrypts `N M x = x` under `cl_pk^N` for `M = 1 / N mod q`.
+fn masked_x_ciphertext(cl: &CL_HSMqk, old: &KeyGen, x_masks: &EncryptedShares) -> CipherText {
+    let old_n = old.cl_keys.sk_shares.len() as u64;
+    let n_factorial = (1..=old_n).fold(Scalar::one(), |acc, i| acc * Scalar::from(i));
+    let n_cube_inv = Mpz::from(&(n_factorial * n_factorial * n_factorial).invert().unwrap());
+    let n_cube = old.cl_keys.n_factorial.pow(3);
+    let x_ciphertext = old.each_party_x_ciphertexts.values().next().unwrap();
+    let masks = x_masks.x_ciphertext(cl);
+    let minus_one = Mpz::from(-1i64);
+    CipherText::new(
+        &x_ciphertext
+            .c1()
+            .exp(cl, &n_cube_inv)
+            .compose(cl, &masks.c1().exp(cl, &minus_one)),
+        &x_ciphertext
+            .c2()
+            .exp(cl, &(n_cube * &n_cube_inv))
+            .compose(cl, &masks.c2().exp(cl, &minus_one)),
+    )
+}
+
+/// The message of `ciphertext`, from the partial decryptions `pds` with shares of
+/// the secret key.
+fn decrypt<'a>(
+    cl: &CL_HSMqk,
+    ciphertext: &CipherText,
+    pds: impl Iterator<Item = &'a QFI>,
+) -> Scalar {
+    let pd = pds
+        .cloned()
+        .reduce(|acc, pd_i| acc.compose(cl, &pd_i))
+        .unwrap();
+    let m = cl.dlog_in_F(&ciphertext.c2().compose(cl, &pd.exp(cl, &Mpz::from(-1i64))));
+    Scalar::from_str_vartime(&m.to_string()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::{arithmetic::Converter, BigInt};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    use crate::MODULUS;
+
+    use super::*;
+
+    #[test]
+    fn test_reshare() {
+        let seed: [u8; 32] = [0u8; 32];
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let l = 10;
+        let mut msg: Vec<Scalar> = Vec::with_capacity(l);
+
+        for _ in 0..l {
+            msg.push(Scalar::random(&mut scalr_rng));
+        }
+        let key_msg = KeyGen::keygen(&cl, b"keygen", 4, 2, l, &mut rng, &mut scalr_rng).unwrap();
+        let (reshared, reshare) = key_msg
+            .reshare(&cl, b"reshare", &[1, 3], 5, 3, &mut rng, &mut scalr_rng)
+            .unwrap();
+        reshare.verify(&cl, b"reshare", &key_msg, 5).unwrap();
+        assert_eq!(reshared.public_key(), key_msg.public_key());
+        assert_eq!(reshared.cl_keys.sk_shares.len(), 5);
+        assert_eq!(reshared.cl_keys.t, 3);
+
+        // The new committee signs, and signs again after resharing back down.
+        let signers = [1, 4, 5];
+        let sign_msg = Sign::sign(
+            &cl,
+            b"sign",
+            &signers,
+            l,
+            &mut rng,
+            &mut scalr_rng,
+            &reshared,
+            &msg,
+            &cl.q(),
+        )
+        .unwrap();
+        let sig = Sign::client(&cl, &sign_msg, &msg, l).unwrap();
+        BBSPlusSig::verify(&key_msg.public_key(), &msg, l, &sig).unwrap();
+
+        let (reshared, _) = reshared
+            .reshare(&cl, b"reshare2", &[2, 3, 5], 3, 2, &mut rng, &mut scalr_rng)
+            .unwrap();
+        let sign_msg = Sign::sign(
+            &cl,
+            b"sign2",
+            &[1, 3],
+            l,
+            &mut rng,
+            &mut scalr_rng,
+            &reshared,
+            &msg,
+            &cl.q(),
+        )
+        .unwrap();
+        let sig = Sign::client(&cl, &sign_msg, &msg, l).unwrap();
+        BBSPlusSig::verify(&key_msg.public_key(), &msg, l, &sig).unwrap();
+
+        let mut forged = reshare.clone();
+        forged.x_offset += Scalar::one();
+        assert_eq!(
+            forged.verify(&cl, b"reshare", &key_msg, 5),
+            Err(ProtocolError::Inconsistent(vec![1, 3]))
+        );
+        let mut forged = reshare.clone();
+        let pd = forged.pds.get_mut(&3).unwrap();
+        *pd = pd.compose(&cl, &cl.power_of_f(&Mpz::from(1u64)));
+        assert_eq!(
+            forged.verify(&cl, b"reshare", &key_msg, 5),
+            Err(ProtocolError::InvalidProof {
+                proof: "CLPDProof",
+                culprits: vec![3]
+            })
+        );
+    }
+
+    #[test]
+    fn test_reshare_without_absent_party() {
+        let mut scalr_rng = ChaChaRng::from_seed([0u8; 32]);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let l = 3;
+        let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut scalr_rng)).collect();
+        let key_msg = KeyGen::keygen(&cl, b"keygen", 4, 2, l, &mut rng, &mut scalr_rng).unwrap();
+
+        // Party 4 is gone, and with it its shares of both keys.
+        let mut cl_keys = key_msg.cl_keys.clone();
+        cl_keys.sk_shares.insert(4, Mpz::from(0u64));
+        cl_keys.zero_shares.remove(&4);
+        let mut sign_keys = key_msg.sign_keys.clone();
+        sign_keys.sk_shares.remove(&4);
+        let without_4 = KeyGen {
+            cl_keys,
+            sign_keys,
+            each_party_x_ciphertexts: key_msg.each_party_x_ciphertexts.clone(),
+        };
+        let (reshared, reshare) = without_4
+            .reshare(&cl, b"reshare", &[1, 2], 3, 2, &mut rng, &mut scalr_rng)
+            .unwrap();
+        reshare.verify(&cl, b"reshare", &key_msg, 3).unwrap();
+        assert_eq!(
+            sum(reshared.sign_keys.pub_shares.values()),
+            key_msg.sign_keys.pub_key
+        );
+
+        let sign_msg = Sign::sign(
+            &cl,
+            b"sign",
+            &[2, 3],
+            l,
+            &mut rng,
+            &mut scalr_rng,
+            &reshared,
+            &msg,
+            &cl.q(),
+        )
+        .unwrap();
+        let sig = Sign::client(&cl, &sign_msg, &msg, l).unwrap();
+        BBSPlusSig::verify(&key_msg.public_key(), &msg, l, &sig).unwrap();
+    }
+}
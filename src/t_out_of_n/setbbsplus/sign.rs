@@ -1,7 +1,8 @@
 use bicycl::QFI;
 use ff::PrimeField;
 
-use crate::{CLRandProof, CLRandYuanProof};
+use crate::error::{blame, blame_batch};
+use crate::{BlindRequest, CLRandProof, CLRandYuanProof, MaskedRequest, RequestMask, Transcript};
 
 use super::*;
 
@@ -17,16 +18,117 @@ pub struct Sign {
 }
 
 impl Sign {
+    /// Runs the signing protocol among the parties in `signers`, which can be any
+    /// `t` or more of the `n` key holders, listed in increasing order.
     pub fn sign(
         cl: &CL_HSMqk,
-        n: usize,
+        session_id: &[u8],
+        signers: &[usize],
         l: usize,
-        mut rng: &mut RandGen,
-        chacharng: &mut ChaChaRng,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
         key_msg: &KeyGen,
         msg: &[Scalar],
         q: &Mpz,
-    ) -> Self {
+    ) -> Result<Self, ProtocolError> {
+        let H = &key_msg.sign_keys.H;
+        let mut base = G1Projective::generator();
+        for i in 0..l {
+            base = base + H[i] * msg[i];
+        }
+        Self::sign_base(cl, session_id, signers, rng, csprng, key_msg, q, |s| {
+            base + H[l] * s
+        })
+    }
+
+    /// Signs `attributes`, encoded with `encoder` into its `l` messages.
+    pub fn sign_attributes(
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        signers: &[usize],
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+        key_msg: &KeyGen,
+        encoder: &MessageEncoder,
+        attributes: &[Attribute],
+        q: &Mpz,
+    ) -> Result<Self, ProtocolError> {
+        let msg = encoder.encode(attributes)?;
+        Self::sign(
+            cl,
+            session_id,
+            signers,
+            encoder.l(),
+            rng,
+            csprng,
+            key_msg,
+            &msg,
+            q,
+        )
+    }
+
+    /// Signs the messages the holder committed to in `request`, of which the
+    /// signers only see the `revealed` ones. The holder completes the signature
+    /// with [`Sign::client_blind`].
+    pub fn sign_blind(
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        signers: &[usize],
+        l: usize,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+        key_msg: &KeyGen,
+        request: &BlindRequest,
+        revealed: &BTreeMap<usize, Scalar>,
+        q: &Mpz,
+    ) -> Result<Self, ProtocolError> {
+        let H = &key_msg.sign_keys.H;
+        request.verify(session_id, H, l)?;
+        let base = request.base(H, l, revealed)?;
+        Self::sign_base(cl, session_id, signers, rng, csprng, key_msg, q, |s| {
+            base + H[l] * s
+        })
+    }
+
+    /// Weakly partially blind signing: signs the requester's masked `B'`, with
+    /// the messages that are not hidden in `request` being `revealed`. The
+    /// signers learn nothing about the hidden messages, and the requester
+    /// completes the signature with [`Sign::client_masked`].
+    pub fn sign_masked(
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        signers: &[usize],
+        l: usize,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+        key_msg: &KeyGen,
+        request: &MaskedRequest,
+        revealed: &BTreeMap<usize, Scalar>,
+        q: &Mpz,
+    ) -> Result<Self, ProtocolError> {
+        request.verify(session_id, &key_msg.sign_keys.H, l, revealed)?;
+        Self::sign_base(cl, session_id, signers, rng, csprng, key_msg, q, |_| {
+            request.B
+        })
+    }
+
+    /// Signs with `B = B_of_s(s)` for the `s` the signers agree on.
+    pub(crate) fn sign_base(
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        signers: &[usize],
+        mut rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+        key_msg: &KeyGen,
+        q: &Mpz,
+        B_of_s: impl Fn(Scalar) -> G1Projective,
+    ) -> Result<Self, ProtocolError> {
+        let n = signers.len();
+        let mut signing_shares = BTreeMap::new();
+        for i in signers {
+            signing_shares.insert(*i, key_msg.cl_keys.signing_share(*i, signers)?);
+        }
+
         let mut eis = Vec::with_capacity(n);
         let mut sis = Vec::with_capacity(n);
 
@@ -42,12 +144,12 @@ impl Sign {
         let mut Bis: BTreeMap<usize, G1Projective> = BTreeMap::new();
         let mut pdis: BTreeMap<usize, QFI> = BTreeMap::new();
         for _ in 1..=n {
-            eis.push(Scalar::random(chacharng.clone()));
-            sis.push(Scalar::random(chacharng.clone()));
+            eis.push(Scalar::random(&mut *csprng));
+            sis.push(Scalar::random(&mut *csprng));
         }
 
         // for (i, item) in key_msg.each_party_x_ciphertexts.clone() {
-        for i in 1..=n {
+        for &i in signers {
             let item = key_msg.each_party_x_ciphertexts.get(&i).unwrap().clone();
             let e = eis
                 .clone()
@@ -60,7 +162,7 @@ impl Sign {
                 .reduce(|acc, e_i| acc + e_i)
                 .unwrap();
 
-            let gamma_i = Scalar::random(chacharng.clone());
+            let gamma_i = Scalar::random(&mut *csprng);
 
             each_party_gammai.insert(i, gamma_i);
 
@@ -81,6 +183,7 @@ impl Sign {
 
             let proof_i = CLRandYuanProof::prove(
                 &cl,
+                &Transcript::new(session_id, i),
                 &mut rng,
                 &ct1_pow,
                 &item.c1(),
@@ -96,29 +199,33 @@ impl Sign {
             each_party_gammaix_ct1.push((i, ct1_pow.clone(), item.c1().clone(), proof_i))
         }
 
-        for (i, _) in es.iter().enumerate() {
-            if i != 0 {
-                assert_eq!(es[i - 1].clone(), es[i].clone());
-                assert_eq!(ss[i - 1].clone(), ss[i].clone());
-            }
-        }
+        let culprits = (1..es.len())
+            .filter(|k| es[*k] != es[0] || ss[*k] != ss[0])
+            .map(|k| signers[k])
+            .collect();
+        blame(culprits, ProtocolError::Inconsistent)?;
 
         let mut each_party_gammax_ct1 = BTreeMap::new();
 
-        for i in 1..=n {
-            let gammaix_ct1: Vec<_> = each_party_gammaix_ct1
+        for &i in signers {
+            let (ids, items): (Vec<usize>, Vec<_>) = each_party_gammaix_ct1
                 .iter()
-                .filter_map(|(_, ct1_pow, ct1_gen, proof)| {
-                    //if proof.verify(pp, &pp.pk, ki_ciphertext) && *j != i {
-                    // if proof.verify(&cl, &ct1_pow, ct1_gen, &q) {
-                    //     //Some((*j, gammai_ciphertext.clone(), proof.clone()))
-                    //     Some(ct1_pow.clone())
-                    // } else {
-                    //     None
-                    // }
-                    proof.verify(&cl, &ct1_pow, ct1_gen, &q);
-                    Some(ct1_pow.clone())
+                .filter(|(j, _, _, _)| *j != i)
+                .map(|(j, ct1_pow, ct1_gen, proof)| {
+                    (
+                        *j,
+                        (Transcript::new(session_id, *j), ct1_pow, ct1_gen, proof),
+                    )
                 })
+                .unzip();
+            blame_batch(
+                CLRandYuanProof::batch_verify(&cl, &q, &items),
+                &ids,
+                "CLRandYuanProof",
+            )?;
+            let gammaix_ct1: Vec<_> = each_party_gammaix_ct1
+                .iter()
+                .map(|(_, ct1_pow, _, _)| ct1_pow.clone())
                 .collect();
 
             let gammax_ct1 = gammaix_ct1
@@ -133,19 +240,15 @@ impl Sign {
         let n_cube = key_msg.cl_keys.n_factorial.clone()
             * key_msg.cl_keys.n_factorial.clone()
             * key_msg.cl_keys.n_factorial.clone();
-        for i in 1..=n {
-            let mut B = G1Projective::generator();
-            let e = es[i - 1];
-            let s = ss[i - 1];
-            let rho_i = Scalar::random(chacharng.clone());
+        for (k, &i) in signers.iter().enumerate() {
+            let e = es[k];
+            let s = ss[k];
+            let rho_i = Scalar::random(&mut *csprng);
             let gamma_i = each_party_gammai.get(&i).unwrap().clone();
             let ct1 = each_party_gammax_ct1.get(&i).unwrap().clone();
             let ct2 = each_party_gammaix_ct2.get(&i).unwrap().clone();
-            let d_i = key_msg.cl_keys.sk_shares.get(&i).unwrap().clone();
-            for i in 0..l {
-                B = B + H[i] * msg[i];
-            }
-            B = B + H[l] * s;
+            let d_i = signing_shares.get(&i).unwrap().clone();
+            let B = B_of_s(s);
 
             let Bi = gamma_i * B;
             let zi = gamma_i * e - rho_i;
@@ -161,7 +264,7 @@ impl Sign {
             pdis.insert(i, pd_i_v2);
         }
 
-        Self {
+        Ok(Self {
             H,
             X: key_msg.sign_keys.pub_key,
             e: es[0],
@@ -170,11 +273,273 @@ impl Sign {
             Bis,
             pdis,
             n_cube,
+        })
+    }
+
+    /// Signs every message vector of `msgs` in one session. The signatures are
+    /// independent, each with its own `e`, `s` and `gamma_i`s, but every signer
+    /// proves all its re-randomizations with a single [`CLRandYuanProof`], so the
+    /// proofs are generated and verified once per signer instead of once per
+    /// signature. Each returned [`Sign`] is completed with [`Sign::client`]; an
+    /// empty `msgs` gives no signatures.
+    pub fn sign_batch(
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        signers: &[usize],
+        l: usize,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+        key_msg: &KeyGen,
+        msgs: &[Vec<Scalar>],
+        q: &Mpz,
+    ) -> Result<Vec<Self>, ProtocolError> {
+        if msgs.is_empty() {
+            return Ok(vec![]);
+        }
+        let k = msgs.len();
+        let mut signing_shares = BTreeMap::new();
+        for i in signers {
+            signing_shares.insert(*i, key_msg.cl_keys.signing_share(*i, signers)?);
         }
+        let H = &key_msg.sign_keys.H;
+        let n_cube = key_msg.cl_keys.n_factorial.clone()
+            * key_msg.cl_keys.n_factorial.clone()
+            * key_msg.cl_keys.n_factorial.clone();
+
+        let mut es = vec![Scalar::zero(); k];
+        let mut ss = vec![Scalar::zero(); k];
+        let mut gammas = BTreeMap::new();
+        let mut ct1_pows = BTreeMap::new();
+        let mut ct2_pows = BTreeMap::new();
+        let mut proofs = BTreeMap::new();
+        for &i in signers {
+            let item = key_msg.each_party_x_ciphertexts.get(&i).unwrap();
+            let mut gamma_is = Vec::with_capacity(k);
+            let mut rs = Vec::with_capacity(k);
+            let mut ct1_pow_is = Vec::with_capacity(k);
+            let mut ct2_pow_is = Vec::with_capacity(k);
+            for j in 0..k {
+                es[j] += Scalar::random(&mut *csprng);
+                ss[j] += Scalar::random(&mut *csprng);
+
+                let gamma_i = Scalar::random(&mut *csprng);
+                let r = rng.random_mpz(&cl.encrypt_randomness_bound());
+                ct1_pow_is.push(
+                    item.c1()
+                        .exp(&cl, &Mpz::from(&gamma_i))
+                        .compose(&cl, &cl.power_of_h(&r)),
+                );
+                ct2_pow_is.push(
+                    item.c2()
+                        .exp(&cl, &Mpz::from(&gamma_i))
+                        .compose(&cl, &key_msg.cl_keys.pub_key.exponentiation(cl, &r)),
+                );
+                gamma_is.push(gamma_i);
+                rs.push(r);
+            }
+            let proof = CLRandYuanProof::prove_batch(
+                &cl,
+                &Transcript::new(session_id, i),
+                rng,
+                &ct1_pow_is,
+                &item.c1(),
+                &gamma_is,
+                &rs,
+                &q,
+                &cl.encrypt_randomness_bound(),
+            );
+            gammas.insert(i, gamma_is);
+            ct1_pows.insert(i, ct1_pow_is);
+            ct2_pows.insert(i, ct2_pow_is);
+            proofs.insert(i, proof);
+        }
+
+        let ct1_gens: BTreeMap<usize, QFI> = signers
+            .iter()
+            .map(|j| (*j, key_msg.each_party_x_ciphertexts.get(j).unwrap().c1()))
+            .collect();
+        let statements: BTreeMap<usize, QFI> = signers
+            .iter()
+            .map(|j| {
+                let (ct1_pow, _) = CLRandYuanProof::batch_statement(
+                    &cl,
+                    &Transcript::new(session_id, *j),
+                    &ct1_pows[j],
+                    &ct1_gens[j],
+                );
+                (*j, ct1_pow)
+            })
+            .collect();
+        for &i in signers {
+            let (ids, items): (Vec<usize>, Vec<_>) = signers
+                .iter()
+                .filter(|j| **j != i)
+                .map(|j| {
+                    (
+                        *j,
+                        (
+                            Transcript::new(session_id, *j),
+                            &statements[j],
+                            &ct1_gens[j],
+                            &proofs[j],
+                        ),
+                    )
+                })
+                .unzip();
+            blame_batch(
+                CLRandYuanProof::batch_verify(&cl, &q, &items),
+                &ids,
+                "CLRandYuanProof",
+            )?;
+        }
+
+        let mut signs = Vec::with_capacity(k);
+        for (j, msg) in msgs.iter().enumerate() {
+            let ct1 = signers
+                .iter()
+                .map(|i| ct1_pows[i][j].clone())
+                .reduce(|acc, ct| acc.compose(&cl, &ct))
+                .unwrap();
+
+            let mut B = G1Projective::generator();
+            for m in 0..l {
+                B = B + H[m] * msg[m];
+            }
+            B = B + H[l] * ss[j];
+
+            let mut zis = BTreeMap::new();
+            let mut Bis = BTreeMap::new();
+            let mut pdis = BTreeMap::new();
+            for &i in signers {
+                let gamma_i = gammas[&i][j];
+                let rho_i = Scalar::random(&mut *csprng);
+
+                let pd_i = ct1.exp(cl, &signing_shares[&i]);
+                let v_yi = ct2_pows[&i][j]
+                    .compose(cl, &cl.power_of_f(&Mpz::from(&rho_i)))
+                    .exp(cl, &n_cube);
+
+                Bis.insert(i, gamma_i * B);
+                zis.insert(i, gamma_i * es[j] - rho_i);
+                pdis.insert(i, v_yi.compose(&cl, &pd_i.exp(&cl, &Mpz::from(-1i64))));
+            }
+
+            signs.push(Self {
+                H: H.clone(),
+                X: key_msg.sign_keys.pub_key,
+                e: es[j],
+                s: ss[j],
+                zis,
+                Bis,
+                pdis,
+                n_cube: n_cube.clone(),
+            });
+        }
+        Ok(signs)
+    }
+
+    pub fn from_partials(
+        H: &[G1Projective],
+        X: &G2Projective,
+        n_factorial: &Mpz,
+        partials: &BTreeMap<usize, PartialSig>,
+    ) -> Result<Self, ProtocolError> {
+        let first = partials
+            .values()
+            .next()
+            .ok_or(ProtocolError::MissingMessage(Vec::new()))?;
+        let culprits = partials
+            .iter()
+            .filter(|(_, p)| p.e != first.e || p.s != first.s)
+            .map(|(i, _)| *i)
+            .collect();
+        blame(culprits, ProtocolError::Inconsistent)?;
+
+        let zis = partials.iter().map(|(i, p)| (*i, p.z_i)).collect();
+        let Bis = partials.iter().map(|(i, p)| (*i, p.B_i)).collect();
+        let pdis = partials.iter().map(|(i, p)| (*i, p.pd_i.clone())).collect();
+
+        Ok(Self {
+            H: H.to_vec(),
+            X: *X,
+            e: first.e,
+            s: first.s,
+            zis,
+            Bis,
+            pdis,
+            n_cube: n_factorial.clone() * n_factorial.clone() * n_factorial.clone(),
+        })
+    }
+
+    pub fn client(
+        cl: &CL_HSMqk,
+        sign_msg: &Sign,
+        msg: &[Scalar],
+        l: usize,
+    ) -> Result<BBSPlusSig, ProtocolError> {
+        Self::client_blind(cl, sign_msg, msg, l, &Scalar::zero())
+    }
+
+    /// [`Sign::client`] for a [`Sign::sign_attributes`].
+    pub fn client_attributes(
+        cl: &CL_HSMqk,
+        sign_msg: &Sign,
+        encoder: &MessageEncoder,
+        attributes: &[Attribute],
+    ) -> Result<BBSPlusSig, ProtocolError> {
+        let msg = encoder.encode(attributes)?;
+        Self::client(cl, sign_msg, &msg, encoder.l())
     }
 
-    pub fn client(cl: &CL_HSMqk, sign_msg: &Sign, msg: &[Scalar], l: usize) {
-        let B = sign_msg
+    /// Completes a [`Sign::sign_blind`] for the holder, which knows all of `msg`
+    /// and the blinding `s_prime` of its [`BlindRequest`].
+    pub fn client_blind(
+        cl: &CL_HSMqk,
+        sign_msg: &Sign,
+        msg: &[Scalar],
+        l: usize,
+        s_prime: &Scalar,
+    ) -> Result<BBSPlusSig, ProtocolError> {
+        Self::unmask(cl, sign_msg, msg, l, &Scalar::one(), sign_msg.s + s_prime)
+    }
+
+    /// Completes a [`Sign::sign_masked`] for the requester, which knows all of
+    /// `msg` and the `mask` of its [`MaskedRequest`].
+    pub fn client_masked(
+        cl: &CL_HSMqk,
+        sign_msg: &Sign,
+        msg: &[Scalar],
+        l: usize,
+        mask: &RequestMask,
+    ) -> Result<BBSPlusSig, ProtocolError> {
+        let r_inv = Option::<Scalar>::from(mask.r.invert()).ok_or(ProtocolError::InvalidRequest)?;
+        Self::unmask(cl, sign_msg, msg, l, &r_inv, mask.s)
+    }
+
+    /// The signature `(r_inv A, e, s)` for the `A` of the combined shares.
+    fn unmask(
+        cl: &CL_HSMqk,
+        sign_msg: &Sign,
+        msg: &[Scalar],
+        l: usize,
+        r_inv: &Scalar,
+        s: Scalar,
+    ) -> Result<BBSPlusSig, ProtocolError> {
+        let A = sign_msg.combine(cl)? * r_inv;
+        let e = sign_msg.e;
+        let key = BBSPlusPublicKey {
+            X: sign_msg.X,
+            H: sign_msg.H.clone(),
+        };
+        let sig = BBSPlusSig { A, e, s };
+        BBSPlusSig::verify(&key, msg, l, &sig)?;
+        Ok(sig)
+    }
+
+    /// `A = B / (x + e)` from the signers' shares, with `B` the sum of their
+    /// `B_i`.
+    pub(crate) fn combine(&self, cl: &CL_HSMqk) -> Result<G1Projective, ProtocolError> {
+        let B = self
             .Bis
             .values()
             .cloned()
@@ -182,7 +547,7 @@ impl Sign {
             .reduce(|acc, B_i| acc + B_i)
             .unwrap();
 
-        let pd = sign_msg
+        let pd = self
             .pdis
             .values()
             .cloned()
@@ -190,7 +555,7 @@ impl Sign {
             .reduce(|acc, pd_i| acc.compose(&cl, &pd_i))
             .unwrap();
 
-        let beta = sign_msg
+        let beta = self
             .zis
             .values()
             .cloned()
@@ -200,31 +565,26 @@ impl Sign {
 
         let y = cl.dlog_in_F(&pd);
 
-        let mut y_scalar = Scalar::from_str_vartime(&y.to_string()).unwrap();
+        let mut y_scalar =
+            Scalar::from_str_vartime(&y.to_string()).ok_or(ProtocolError::DegenerateSignature)?;
 
-        let mut f_cube = Scalar::from_str_vartime(&sign_msg.n_cube.to_string()).unwrap();
+        let mut f_cube = Scalar::from_str_vartime(&self.n_cube.to_string()).unwrap();
         f_cube = f_cube.invert().unwrap().clone();
         y_scalar = y_scalar * f_cube;
 
-        let inv = (y_scalar + beta).invert().unwrap();
-        let A = B * inv;
-        let e = sign_msg.e;
-        let s = sign_msg.s;
-        let key = BBSPlusKey {
-            x: sign_msg.e,
-            X: sign_msg.X,
-            H: sign_msg.H.clone(),
-        };
-        let sig = BBSPlusSig { A, e, s };
-        BBSPlusSig::verify(&key, msg, l, &sig);
+        let inv = Option::<Scalar>::from((y_scalar + beta).invert())
+            .ok_or(ProtocolError::DegenerateSignature)?;
+        Ok(B * inv)
     }
 }
 
+#[cfg(test)]
 mod tests {
     use curv::{arithmetic::Converter, BigInt};
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
-    use crate::MODULUS;
+    use crate::{AttributeType, MODULUS};
 
     use super::*;
 
@@ -234,7 +594,7 @@ mod tests {
         let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
         let cl = CL_HSMqk::with_rand_gen(
@@ -248,14 +608,323 @@ mod tests {
         let n = 6;
         let t = 4;
         let l = 10;
+        let signers = [2, 3, 5, 6];
         let mut msg: Vec<Scalar> = Vec::with_capacity(l);
 
         for _ in 0..l {
-            let tmp = Scalar::random(scalr_rng.clone());
+            let tmp = Scalar::random(&mut scalr_rng);
             msg.push(tmp);
         }
-        let key_msg = KeyGen::keygen(&cl, t, n, l, &mut rng, &mut scalr_rng);
-        let sign_msg = Sign::sign(&cl, t, l, &mut rng, &mut scalr_rng, &key_msg, &msg, &q);
-        Sign::client(&cl, &sign_msg, &msg, l);
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng).unwrap();
+        let sign_msg = Sign::sign(
+            &cl,
+            b"sign",
+            &signers,
+            l,
+            &mut rng,
+            &mut scalr_rng,
+            &key_msg,
+            &msg,
+            &q,
+        )
+        .unwrap();
+        Sign::client(&cl, &sign_msg, &msg, l).unwrap();
+
+        assert_eq!(
+            Sign::sign(
+                &cl,
+                b"sign",
+                &[1, 2, 3],
+                l,
+                &mut rng,
+                &mut scalr_rng,
+                &key_msg,
+                &msg,
+                &q
+            )
+            .err(),
+            Some(ProtocolError::InvalidSignerSet(vec![1, 2, 3]))
+        );
+    }
+
+    #[test]
+    fn test_sign_blind() {
+        let mut scalr_rng = ChaChaRng::from_seed([0u8; 32]);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
+        let cl = CL_HSMqk::with_rand_gen(
+            &q,
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let (n, t, l) = (6, 4, 10);
+        let signers = [2, 3, 5, 6];
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng).unwrap();
+        let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut scalr_rng)).collect();
+
+        let hidden: BTreeMap<usize, Scalar> = [1, 2].iter().map(|i| (*i, msg[*i])).collect();
+        let mut revealed: BTreeMap<usize, Scalar> = (0..l)
+            .filter(|i| !hidden.contains_key(i))
+            .map(|i| (i, msg[i]))
+            .collect();
+        let (request, s_prime) =
+            BlindRequest::new(b"blind", &key_msg.sign_keys.H, l, &hidden, &mut scalr_rng);
+        let sign_msg = Sign::sign_blind(
+            &cl,
+            b"blind",
+            &signers,
+            l,
+            &mut rng,
+            &mut scalr_rng,
+            &key_msg,
+            &request,
+            &revealed,
+            &q,
+        )
+        .unwrap();
+        let sig = Sign::client_blind(&cl, &sign_msg, &msg, l, &s_prime).unwrap();
+        BBSPlusSig::verify(&key_msg.public_key(), &msg, l, &sig).unwrap();
+
+        // A hidden message is revealed too.
+        revealed.insert(1, msg[1]);
+        assert_eq!(
+            Sign::sign_blind(
+                &cl,
+                b"blind",
+                &signers,
+                l,
+                &mut rng,
+                &mut scalr_rng,
+                &key_msg,
+                &request,
+                &revealed,
+                &q,
+            )
+            .err(),
+            Some(ProtocolError::InvalidRequest)
+        );
+    }
+
+    #[test]
+    fn test_sign_batch() {
+        let mut scalr_rng = ChaChaRng::from_seed([0u8; 32]);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
+        let cl = CL_HSMqk::with_rand_gen(
+            &q,
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let (n, t, l) = (6, 4, 10);
+        let signers = [2, 3, 5, 6];
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng).unwrap();
+
+        let msgs: Vec<Vec<Scalar>> = (0..3)
+            .map(|_| (0..l).map(|_| Scalar::random(&mut scalr_rng)).collect())
+            .collect();
+        let sign_msgs = Sign::sign_batch(
+            &cl,
+            b"sign",
+            &signers,
+            l,
+            &mut rng,
+            &mut scalr_rng,
+            &key_msg,
+            &msgs,
+            &q,
+        )
+        .unwrap();
+        assert_eq!(sign_msgs.len(), msgs.len());
+        for (sign_msg, msg) in sign_msgs.iter().zip(&msgs) {
+            Sign::client(&cl, sign_msg, msg, l).unwrap();
+        }
+
+        assert!(Sign::sign_batch(
+            &cl,
+            b"sign",
+            &signers,
+            l,
+            &mut rng,
+            &mut scalr_rng,
+            &key_msg,
+            &[],
+            &q,
+        )
+        .unwrap()
+        .is_empty());
+    }
+
+    #[test]
+    fn test_sign_masked() {
+        let seed: [u8; 32] = [0u8; 32];
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
+        let cl = CL_HSMqk::with_rand_gen(
+            &q,
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let (n, t, l) = (4, 2, 5);
+        let signers = [1, 4];
+        let mut key_msg =
+            KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng).unwrap();
+        // Generators with known discrete logarithms, so that the requester can
+        // pick masks under which two requests look the same to the signers.
+        let hs: Vec<Scalar> = (0..=l).map(|_| Scalar::random(&mut scalr_rng)).collect();
+        key_msg.sign_keys.H = hs.iter().map(|h| G1Projective::generator() * h).collect();
+        let dlog_B = |msg: &[Scalar], s: &Scalar| {
+            (0..l).fold(Scalar::one() + hs[l] * s, |acc, i| acc + hs[i] * msg[i])
+        };
+
+        let hidden = [0, 2];
+        let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut scalr_rng)).collect();
+        let mut other_msg = msg.clone();
+        for i in hidden {
+            other_msg[i] = Scalar::random(&mut scalr_rng);
+        }
+        let revealed: BTreeMap<usize, Scalar> = (0..l)
+            .filter(|i| !hidden.contains(i))
+            .map(|i| (i, msg[i]))
+            .collect();
+
+        let H = &key_msg.sign_keys.H;
+        let (request, mask) = MaskedRequest::new(b"masked", H, l, &msg, &hidden, &mut scalr_rng);
+        let other_mask = RequestMask {
+            r: mask.r * dlog_B(&msg, &mask.s) * dlog_B(&other_msg, &mask.s).invert().unwrap(),
+            s: mask.s,
+        };
+        let other_request = MaskedRequest::with_mask(
+            b"masked",
+            H,
+            l,
+            &other_msg,
+            &hidden,
+            &other_mask,
+            &mut scalr_rng,
+        );
+        assert_eq!(request.B, other_request.B);
+
+        // With the same randomness, the signers answer both requests alike.
+        let sign = |request: &MaskedRequest, revealed: &BTreeMap<usize, Scalar>| {
+            let mut rng = RandGen::new();
+            rng.set_seed(&Mpz::from(7u64));
+            let mut csprng = ChaChaRng::from_seed([7u8; 32]);
+            Sign::sign_masked(
+                &cl,
+                b"masked",
+                &signers,
+                l,
+                &mut rng,
+                &mut csprng,
+                &key_msg,
+                request,
+                revealed,
+                &q,
+            )
+        };
+        let sign_msg = sign(&request, &revealed).unwrap();
+        let other_sign_msg = sign(&other_request, &revealed).unwrap();
+        assert_eq!(sign_msg.Bis, other_sign_msg.Bis);
+        assert_eq!(sign_msg.zis, other_sign_msg.zis);
+        assert_eq!(sign_msg.pdis, other_sign_msg.pdis);
+        assert_eq!(
+            (sign_msg.e, sign_msg.s),
+            (other_sign_msg.e, other_sign_msg.s)
+        );
+
+        let sig = Sign::client_masked(&cl, &sign_msg, &msg, l, &mask).unwrap();
+        BBSPlusSig::verify(&key_msg.public_key(), &msg, l, &sig).unwrap();
+        let other_sig =
+            Sign::client_masked(&cl, &other_sign_msg, &other_msg, l, &other_mask).unwrap();
+        BBSPlusSig::verify(&key_msg.public_key(), &other_msg, l, &other_sig).unwrap();
+
+        // The revealed messages are not the ones in B'.
+        let mut forged = revealed.clone();
+        *forged.get_mut(&1).unwrap() += Scalar::one();
+        assert_eq!(
+            sign(&request, &forged).err(),
+            Some(ProtocolError::InvalidRequest)
+        );
+    }
+
+    #[test]
+    fn test_sign_attributes() {
+        let mut scalr_rng = ChaChaRng::from_seed([0u8; 32]);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
+        let cl = CL_HSMqk::with_rand_gen(
+            &q,
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let encoder = MessageEncoder::new(
+            b"test_sign_attributes",
+            &[
+                ("name", AttributeType::String),
+                ("birth", AttributeType::Date),
+                ("level", AttributeType::Integer),
+            ],
+        )
+        .unwrap();
+        let (n, t) = (4, 2);
+        let signers = [1, 4];
+        let key_msg =
+            KeyGen::keygen(&cl, b"keygen", n, t, encoder.l(), &mut rng, &mut scalr_rng).unwrap();
+        let attributes = [
+            Attribute::String("alice".to_string()),
+            Attribute::Date(631152000),
+            Attribute::Integer(3),
+        ];
+        let sign_msg = Sign::sign_attributes(
+            &cl,
+            b"sign",
+            &signers,
+            &mut rng,
+            &mut scalr_rng,
+            &key_msg,
+            &encoder,
+            &attributes,
+            &q,
+        )
+        .unwrap();
+        let sig = Sign::client_attributes(&cl, &sign_msg, &encoder, &attributes).unwrap();
+        BBSPlusSig::verify_attributes(&key_msg.public_key(), &encoder, &attributes, &sig).unwrap();
+
+        let mut other = attributes.clone();
+        other[2] = Attribute::Integer(4);
+        assert_eq!(
+            BBSPlusSig::verify_attributes(&key_msg.public_key(), &encoder, &other, &sig),
+            Err(ProtocolError::InvalidSignature)
+        );
+        other[2] = Attribute::String("3".to_string());
+        assert_eq!(
+            BBSPlusSig::verify_attributes(&key_msg.public_key(), &encoder, &other, &sig),
+            Err(ProtocolError::InvalidAttributes)
+        );
     }
 }
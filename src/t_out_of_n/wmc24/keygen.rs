@@ -1,6 +1,7 @@
 use bicycl::QFI;
 
-use crate::{ComZkDlComEgproof, PVSS, PVSSG};
+use crate::error::{blame, blame_batch, blame_proof};
+use crate::{ComZkDlComEgproof, Transcript, PVSS, PVSSG};
 
 use super::*;
 
@@ -11,11 +12,16 @@ pub struct KeyGen {
     pub eg_keys: EgKeys,
 }
 
+/// `sk_shares` are the parties' points on the shared polynomial, scaled by `n!`.
+/// The signers scale them by their Lagrange coefficients once the signing set
+/// is known.
 #[derive(Clone)]
 pub struct CLKeys {
     pub sk_shares: BTreeMap<usize, Mpz>,
     pub pk_shares: BTreeMap<usize, QFI>,
     pub pub_key: PublicKey,
+    pub n_factorial: Mpz,
+    pub t: usize,
 }
 #[derive(Clone)]
 pub struct SignKeys {
@@ -23,7 +29,6 @@ pub struct SignKeys {
     pub pub_shares: BTreeMap<usize, G2Projective>,
     pub pub_key: G2Projective,
     pub H: Vec<G1Projective>,
-    pub x: Scalar,
 }
 
 #[derive(Clone)]
@@ -31,21 +36,21 @@ pub struct EgKeys {
     pub sk_shares: BTreeMap<usize, Scalar>,
     pub pub_shares: BTreeMap<usize, G1Projective>,
     pub pub_key: G1Projective,
-    pub x: Scalar,
 }
 
 impl KeyGen {
     pub fn clkeygen(
         rng: &mut RandGen,
-        chacharng: &mut ChaChaRng,
         cl: &CL_HSMqk,
+        session_id: &[u8],
         n: usize,
         t: usize,
-    ) -> CLKeys {
+    ) -> Result<CLKeys, ProtocolError> {
         // let mut rng = RandGen::new();
         let mut each_party_gen = Vec::with_capacity(n as usize);
         let mut pk_shares = BTreeMap::new();
         let mut sk_shares = BTreeMap::new();
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
         for i in 1..=n {
             let d_i = rng.random_mpz(&cl.encrypt_randomness_bound());
 
@@ -55,17 +60,18 @@ impl KeyGen {
 
             sk_shares.insert(i, d_i.clone());
 
-            let proof_i = ComZkDlComClproof::prove(&cl, chacharng, &pk_i, &d_i);
+            let proof_i = ComZkDlComClproof::prove(
+                &cl,
+                &Transcript::new(session_id, i),
+                &bound,
+                rng,
+                &pk_i,
+                &d_i,
+            );
 
             each_party_gen.push((i, pk_i, proof_i));
         }
         let mut each_party_pubkey = Vec::with_capacity(n as usize);
-        let d = sk_shares
-            .values()
-            .cloned()
-            .into_iter()
-            .reduce(|acc, d_i| acc + d_i)
-            .unwrap();
         for i in 1..=n {
             let pk = pk_shares
                 .values()
@@ -73,21 +79,21 @@ impl KeyGen {
                 .into_iter()
                 .reduce(|acc, pk_i| acc.compose(&cl, &pk_i))
                 .unwrap();
-            for (j, pk_i, proof_i) in each_party_gen.iter() {
-                if *j == i {
-                    continue;
-                }
-                assert_eq!(true, proof_i.verify(&cl, &pk_i));
-            }
+            let culprits = each_party_gen
+                .iter()
+                .filter(|(j, pk_i, proof_i)| {
+                    *j != i && !proof_i.verify(&cl, &Transcript::new(session_id, *j), &bound, &pk_i)
+                })
+                .map(|(j, _, _)| *j)
+                .collect();
+            blame_proof(culprits, "ComZkDlComClproof")?;
             each_party_pubkey.push(pk);
         }
-        assert_eq!(each_party_pubkey[0], cl.power_of_h(&d));
-
-        for (i, _) in each_party_pubkey.iter().enumerate() {
-            if i != 0 {
-                assert_eq!(each_party_pubkey[i - 1], each_party_pubkey[i]);
-            }
-        }
+        let culprits = (1..each_party_pubkey.len())
+            .filter(|i| each_party_pubkey[*i] != each_party_pubkey[0])
+            .map(|i| i + 1)
+            .collect();
+        blame(culprits, ProtocolError::Inconsistent)?;
         let pub_key = PublicKey::from_qfi(&cl, &each_party_pubkey[0]);
 
         let mut n_factorial = Mpz::from(1u64);
@@ -96,24 +102,35 @@ impl KeyGen {
         }
         let pvssmsg = PVSS::share(
             &cl,
+            session_id,
             rng,
-            chacharng,
             &sk_shares,
             &cl.encrypt_randomness_bound(),
             t,
             n,
             &n_factorial,
-        );
-
-        (sk_shares, pk_shares) = PVSS::recover(&cl, &pvssmsg, t, n, &n_factorial);
-        CLKeys {
+        )?;
+
+        sk_shares = PVSS::poly_shares(&pvssmsg, n, &n_factorial);
+        pk_shares = sk_shares
+            .iter()
+            .map(|(i, d_i)| (*i, cl.power_of_h(d_i)))
+            .collect();
+        Ok(CLKeys {
             sk_shares,
             pub_key,
             pk_shares,
-        }
+            n_factorial,
+            t,
+        })
     }
 
-    pub fn signkeygen(chacharng: &mut ChaChaRng, n: usize, l: usize) -> SignKeys {
+    pub fn signkeygen(
+        session_id: &[u8],
+        csprng: &mut impl CryptoRngCore,
+        n: usize,
+        l: usize,
+    ) -> Result<SignKeys, ProtocolError> {
         let mut each_party_gen = Vec::with_capacity(n as usize);
         let mut pk_shares = Vec::with_capacity(n as usize);
         let mut sk_shares = BTreeMap::new();
@@ -124,23 +141,23 @@ impl KeyGen {
             for j in 0..=l {
                 if let Some(value) = pub_H_shares.get(&j) {
                     let mut H_i = value.clone();
-                    let k_i = Scalar::random(chacharng.clone());
+                    let k_i = Scalar::random(&mut *csprng);
                     let H_ii = G1Projective::generator() * &k_i;
                     H_i.push(H_ii);
                     pub_H_shares.insert(j, value.clone());
                 } else {
                     let mut H_i = Vec::with_capacity(n.into());
-                    let k_i = Scalar::random(chacharng.clone());
+                    let k_i = Scalar::random(&mut *csprng);
                     let H_ii = G1Projective::generator() * &k_i;
                     H_i.push(H_ii);
                     pub_H_shares.insert(j, H_i);
                 }
-                // let k_i = Scalar::random(chacharng.clone());
+                // let k_i = Scalar::random(&mut *csprng);
                 // let H_ii = G1Projective::generator() * &k_i;
                 // pub_H_share[i as usize - 1][j] = H_ii;
             }
 
-            let x_i = Scalar::random(chacharng.clone());
+            let x_i = Scalar::random(&mut *csprng);
 
             let pk_i = G2Projective::generator() * &x_i;
 
@@ -150,18 +167,17 @@ impl KeyGen {
 
             pub_shares.insert(i, pk_i.clone());
 
-            let proof_i = ComZkDlComElproof::prove(&mut chacharng.clone(), &pk_i.into(), &x_i);
+            let proof_i = ComZkDlComElproof::prove(
+                &Transcript::new(session_id, i),
+                csprng,
+                &pk_i.into(),
+                &x_i,
+            );
 
             each_party_gen.push((i, pk_i, proof_i));
         }
         let mut each_party_pubkey = Vec::with_capacity(n as usize);
         let mut each_party_Hs = Vec::with_capacity(n as usize);
-        let x = sk_shares
-            .values()
-            .cloned()
-            .into_iter()
-            .reduce(|acc, d_i| acc + d_i)
-            .unwrap();
         for i in 1..=n {
             let mut H = Vec::with_capacity(l + 1);
             for (_, H_items) in pub_H_shares.clone() {
@@ -176,48 +192,51 @@ impl KeyGen {
                 .into_iter()
                 .reduce(|acc, pk_i| acc + pk_i)
                 .unwrap();
-            for (j, pk_i, proof_i) in each_party_gen.iter() {
-                if *j == i {
-                    continue;
-                }
-                assert_eq!(true, proof_i.verify(&pk_i.into()));
-            }
+            let culprits = each_party_gen
+                .iter()
+                .filter(|(j, pk_i, proof_i)| {
+                    *j != i && !proof_i.verify(&Transcript::new(session_id, *j), &pk_i.into())
+                })
+                .map(|(j, _, _)| *j)
+                .collect();
+            blame_proof(culprits, "ComZkDlComElproof")?;
             each_party_pubkey.push(pk);
         }
-        assert_eq!(each_party_pubkey[0], G2Projective::generator() * x.clone());
 
-        for (i, _) in each_party_Hs.iter().enumerate() {
-            if i != 0 {
-                for j in 0..=l {
-                    assert_eq!(each_party_Hs[i - 1][j], each_party_Hs[i][j]);
-                }
-            }
-        }
-
-        for (i, _) in each_party_pubkey.iter().enumerate() {
-            if i != 0 {
-                assert_eq!(each_party_pubkey[i - 1], each_party_pubkey[i]);
-            }
-        }
+        let culprits = (1..each_party_Hs.len())
+            .filter(|i| each_party_Hs[*i] != each_party_Hs[0])
+            .map(|i| i + 1)
+            .collect();
+        blame(culprits, ProtocolError::Inconsistent)?;
+
+        let culprits = (1..each_party_pubkey.len())
+            .filter(|i| each_party_pubkey[*i] != each_party_pubkey[0])
+            .map(|i| i + 1)
+            .collect();
+        blame(culprits, ProtocolError::Inconsistent)?;
         let pub_key = each_party_pubkey[0].clone();
         let H = each_party_Hs[0].clone();
-        SignKeys {
+        Ok(SignKeys {
             sk_shares,
             pub_shares,
             pub_key,
             H,
-            x,
-        }
+        })
     }
 
-    pub fn egkeygen(chacharng: &mut ChaChaRng, n: usize, t: usize) -> EgKeys {
+    pub fn egkeygen(
+        session_id: &[u8],
+        csprng: &mut impl CryptoRngCore,
+        n: usize,
+        t: usize,
+    ) -> Result<EgKeys, ProtocolError> {
         let mut each_party_gen = Vec::with_capacity(n as usize);
         let mut pk_shares = Vec::with_capacity(n as usize);
         let mut sk_shares = BTreeMap::new();
         let mut pub_shares = BTreeMap::new();
         //let mut pub_H_share: Vec<Vec<G1Projective>> = Vec::with_capacity(n.into());
         for i in 1..=n {
-            let x_i = Scalar::random(chacharng.clone());
+            let x_i = Scalar::random(&mut *csprng);
 
             let pk_i = G1Projective::generator() * &x_i;
 
@@ -227,72 +246,75 @@ impl KeyGen {
 
             pub_shares.insert(i, pk_i.clone());
 
-            let proof_i = ComZkDlComEgproof::prove(&mut chacharng.clone(), &pk_i.into(), &x_i);
+            let proof_i = ComZkDlComEgproof::prove(
+                &Transcript::new(session_id, i),
+                csprng,
+                &pk_i.into(),
+                &x_i,
+            );
 
             each_party_gen.push((i, pk_i, proof_i));
         }
         let mut each_party_pubkey = Vec::with_capacity(n as usize);
-        let x = sk_shares
-            .values()
-            .cloned()
-            .into_iter()
-            .reduce(|acc, d_i| acc + d_i)
-            .unwrap();
         for i in 1..=n {
             let pk = pk_shares
                 .clone()
                 .into_iter()
                 .reduce(|acc, pk_i| acc + pk_i)
                 .unwrap();
-            for (j, pk_i, proof_i) in each_party_gen.iter() {
-                if *j == i {
-                    continue;
-                }
-                assert_eq!(true, proof_i.verify(&pk_i.into()));
-            }
+            let culprits = each_party_gen
+                .iter()
+                .filter(|(j, pk_i, proof_i)| {
+                    *j != i && !proof_i.verify(&Transcript::new(session_id, *j), &pk_i.into())
+                })
+                .map(|(j, _, _)| *j)
+                .collect();
+            blame_proof(culprits, "ComZkDlComEgproof")?;
             each_party_pubkey.push(pk);
         }
-        assert_eq!(each_party_pubkey[0], G1Projective::generator() * x.clone());
 
-        for (i, _) in each_party_pubkey.iter().enumerate() {
-            if i != 0 {
-                assert_eq!(each_party_pubkey[i - 1], each_party_pubkey[i]);
-            }
-        }
+        let culprits = (1..each_party_pubkey.len())
+            .filter(|i| each_party_pubkey[*i] != each_party_pubkey[0])
+            .map(|i| i + 1)
+            .collect();
+        blame(culprits, ProtocolError::Inconsistent)?;
         let pub_key = each_party_pubkey[0].clone();
 
-        let pvssmsg = PVSSG::share(chacharng, &sk_shares, t, n);
+        let pvssmsg = PVSSG::share(session_id, csprng, &sk_shares, t, n)?;
 
-        (sk_shares, pub_shares) = PVSSG::recover(&pvssmsg, t, n);
+        sk_shares = PVSSG::poly_shares(&pvssmsg, n);
+        pub_shares = sk_shares
+            .iter()
+            .map(|(i, x_i)| (*i, G1Projective::generator() * x_i))
+            .collect();
 
-        EgKeys {
+        Ok(EgKeys {
             sk_shares,
             pub_shares,
             pub_key,
-            x,
-        }
+        })
     }
 
     pub fn keygen(
         cl: &CL_HSMqk,
+        session_id: &[u8],
         n: usize,
         t: usize,
         l: usize,
         rng: &mut RandGen,
-        chacharng: &mut ChaChaRng,
-    ) -> Self {
-        let cl_keys = Self::clkeygen(rng, chacharng, cl, n, t);
-        let sign_keys = Self::signkeygen(chacharng, n, l);
-        let eg_keys = Self::egkeygen(chacharng, n, t);
+        csprng: &mut impl CryptoRngCore,
+    ) -> Result<Self, ProtocolError> {
+        let cl_keys = Self::clkeygen(rng, cl, session_id, n, t)?;
+        let sign_keys = Self::signkeygen(session_id, csprng, n, l)?;
+        let eg_keys = Self::egkeygen(session_id, csprng, n, t)?;
 
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
         let mut msgs = Vec::with_capacity(n as usize);
         let mut per_cipher = BTreeMap::new();
         let cl_pk = cl_keys.pub_key.clone();
-        let mut x = Scalar::zero();
         for i in 1..=n {
             let x_i = sign_keys.sk_shares.get(&i).unwrap().clone();
             let pk_i = sign_keys.pub_shares.get(&i).unwrap().clone();
-            x = x + &x_i;
             let cl_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
             let c1 = cl.power_of_h(&cl_rand);
             let c2 = cl
@@ -301,30 +323,40 @@ impl KeyGen {
             let xi_ciphertext = CipherText::new(&c1, &c2);
             let proof = CLEncProof::prove(
                 &cl,
+                &Transcript::new(session_id, i),
+                &bound,
                 rng,
                 &cl_pk,
                 &xi_ciphertext,
                 &pk_i,
                 &x_i,
                 &cl_rand,
-                chacharng,
+                csprng,
             );
             msgs.push((i, xi_ciphertext.clone(), pk_i.clone(), proof));
             per_cipher.insert(i, xi_ciphertext);
         }
         let mut each_party_x_ciphertexts = BTreeMap::new();
         for i in 1..=n {
-            let xi_ciphertexts: Vec<_> = msgs
+            let (ids, items): (Vec<usize>, Vec<_>) = msgs
                 .iter()
-                .filter_map(|(_, xi_ciphertext, pk_i, proof)| {
-                    //if proof.verify(pp, &pp.pk, ki_ciphertext) && *j != i {
-                    if proof.verify(&cl, &cl_pk, xi_ciphertext, pk_i) {
-                        //Some((*j, gammai_ciphertext.clone(), proof.clone()))
-                        Some(xi_ciphertext.clone())
-                    } else {
-                        None
-                    }
+                .filter(|(j, _, _, _)| *j != i)
+                .map(|(j, xi_ciphertext, pk_i, proof)| {
+                    let item = (
+                        Transcript::new(session_id, *j),
+                        &bound,
+                        &cl_pk,
+                        xi_ciphertext,
+                        pk_i,
+                        proof,
+                    );
+                    (*j, item)
                 })
+                .unzip();
+            blame_batch(CLEncProof::batch_verify(&cl, &items), &ids, "CLEncProof")?;
+            let xi_ciphertexts: Vec<_> = msgs
+                .iter()
+                .map(|(_, xi_ciphertext, _, _)| xi_ciphertext.clone())
                 .collect();
 
             //each_party_k_ciphertexts.insert(i, per_cipher.get(&i).unwrap().clone());
@@ -342,11 +374,18 @@ impl KeyGen {
             //msgs.rotate_left(1);
         }
         //Gen gamma x
-        Self {
+        Ok(Self {
             cl_keys,
             sign_keys,
             each_party_x_ciphertexts,
             eg_keys,
+        })
+    }
+
+    pub fn public_key(&self) -> BBSPlusPublicKey {
+        BBSPlusPublicKey {
+            X: self.sign_keys.pub_key,
+            H: self.sign_keys.H.clone(),
         }
     }
 }
@@ -355,6 +394,7 @@ impl KeyGen {
 mod tests {
     use curv::{arithmetic::Converter, BigInt};
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use crate::MODULUS;
 
@@ -366,7 +406,7 @@ mod tests {
         let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -378,6 +418,6 @@ mod tests {
         );
         let n = 5;
         let t = 3;
-        KeyGen::keygen(&cl, n, t, 5, &mut rng, &mut scalr_rng);
+        KeyGen::keygen(&cl, b"test_keygen", n, t, 5, &mut rng, &mut scalr_rng).unwrap();
     }
 }
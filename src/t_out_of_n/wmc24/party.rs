@@ -0,0 +1,690 @@
+use bicycl::QFI;
+use ff::PrimeField;
+use futures::SinkExt;
+use round_based::rounds_router::simple_store::{RoundInput, RoundInputError};
+use round_based::rounds_router::{CompleteRoundError, RoundsRouter};
+use round_based::{Delivery, Mpc, MpcParty, Outgoing, PartyIndex, ProtocolMessage};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::error::{blame_batch, blame_proof, check_parties};
+
+use crate::{
+    check_signers, CLELProof, CLEncSProof, CLPDProof, ELPDProof, ElGCiphertext, Transcript, PVSS,
+    PVSSG,
+};
+
+use super::sign::pd_response_bound;
+use super::*;
+
+/// The key material of party `i` for [`Signer`], cut out of a [`KeyGen`] with
+/// [`KeyGen::signer_key`].
+pub struct SignerKey {
+    pub i: usize,
+    pub t: usize,
+    pub n: usize,
+    pub n_factorial: Mpz,
+    pub cl_sk_share: Mpz,
+    pub cl_pub_key: PublicKey,
+    pub x_ciphertext: CipherText,
+    pub eg_sk_share: Scalar,
+    pub eg_pub_key: G1Projective,
+    pub H: Vec<G1Projective>,
+}
+
+impl KeyGen {
+    pub fn signer_key(&self, i: usize) -> SignerKey {
+        SignerKey {
+            i,
+            t: self.cl_keys.t,
+            n: self.cl_keys.sk_shares.len(),
+            n_factorial: self.cl_keys.n_factorial.clone(),
+            cl_sk_share: self.cl_keys.sk_shares[&i].clone(),
+            cl_pub_key: self.cl_keys.pub_key.clone(),
+            x_ciphertext: self.each_party_x_ciphertexts[&i].clone(),
+            eg_sk_share: self.eg_keys.sk_shares[&i],
+            eg_pub_key: self.eg_keys.pub_key,
+            H: self.sign_keys.H.clone(),
+        }
+    }
+}
+
+impl SignerKey {
+    /// The party's CL and ElGamal key shares, scaled by its Lagrange coefficient
+    /// for the signing set `signers`.
+    pub fn signing_shares(&self, signers: &[usize]) -> Result<(Mpz, Scalar), ProtocolError> {
+        check_signers(signers, self.t, self.n)?;
+        if !signers.contains(&self.i) {
+            return Err(ProtocolError::InvalidSignerSet(signers.to_vec()));
+        }
+        let cl_lag = &PVSS::lagrange_coeffs_times_n_factorial(signers, &self.n_factorial)[&self.i];
+        let eg_lag = PVSSG::lagrange_coeffs(signers)[&self.i];
+        Ok((self.cl_sk_share.clone() * cl_lag, self.eg_sk_share * eg_lag))
+    }
+}
+
+/// Round 1 broadcast: the party's shares of `e`/`s`, encrypted under the joint
+/// CL key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignerMsg1 {
+    pub e_ciphertext: CipherText,
+    pub s_ciphertext: CipherText,
+    pub e_proof: CLEncSProof,
+    pub s_proof: CLEncSProof,
+}
+
+/// Round 2 broadcast: the party's partial decryptions of the joint `e`/`s`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignerMsg2 {
+    pub pd_e: QFI,
+    pub pd_s: QFI,
+}
+
+/// Round 3 broadcast: the party's gamma-re-randomized `x + e` ciphertext and its
+/// ElGamal encryption of `gamma_i B`, together with the proof that they use the
+/// same `gamma_i`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignerMsg3 {
+    pub ct_pow: CipherText,
+    pub eg_ciphertext: ElGCiphertext,
+    pub proof: CLELProof,
+}
+
+/// Output of a signer, sent to the client which combines them with
+/// [`Sign::from_partials`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignerPartial {
+    pub e: Scalar,
+    pub s: Scalar,
+    pub gamma_e_x_ciphertext: CipherText,
+    pub eg_ciphertext: ElGCiphertext,
+    pub pd_gamma_e_x: QFI,
+    pub pd_gamma_e_x_proof: CLPDProof,
+    pub pd_eg: G1Projective,
+    pub pd_eg_proof: ELPDProof,
+}
+
+#[derive(Clone, Debug, PartialEq, ProtocolMessage, Serialize, Deserialize)]
+pub enum SignerMsg {
+    Round1(SignerMsg1),
+    Round2(SignerMsg2),
+    Round3(SignerMsg3),
+}
+
+/// One party of [`Sign::sign`] among `signers`, as a state machine over its
+/// three rounds.
+pub struct Signer<'a> {
+    cl: &'a CL_HSMqk,
+    session_id: &'a [u8],
+    key: &'a SignerKey,
+    signers: &'a [usize],
+}
+
+pub struct SignerRound1<'a> {
+    cl: &'a CL_HSMqk,
+    session_id: &'a [u8],
+    key: &'a SignerKey,
+    signers: &'a [usize],
+}
+
+pub struct SignerRound2<'a> {
+    cl: &'a CL_HSMqk,
+    session_id: &'a [u8],
+    key: &'a SignerKey,
+    signers: &'a [usize],
+    e_ciphertext: CipherText,
+    s_ciphertext: CipherText,
+    e_x_ciphertext: CipherText,
+}
+
+pub struct SignerRound3<'a> {
+    cl: &'a CL_HSMqk,
+    session_id: &'a [u8],
+    key: &'a SignerKey,
+    signers: &'a [usize],
+    e: Scalar,
+    s: Scalar,
+    e_x_ciphertext: CipherText,
+    B: G1Projective,
+}
+
+impl<'a> Signer<'a> {
+    pub fn new(
+        cl: &'a CL_HSMqk,
+        session_id: &'a [u8],
+        key: &'a SignerKey,
+        signers: &'a [usize],
+    ) -> Self {
+        Self {
+            cl,
+            session_id,
+            key,
+            signers,
+        }
+    }
+
+    pub fn round1(
+        self,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+    ) -> (SignerRound1<'a>, SignerMsg1) {
+        let (e_ciphertext, e_proof) =
+            encrypt_share(self.cl, self.session_id, self.key, rng, csprng);
+        let (s_ciphertext, s_proof) =
+            encrypt_share(self.cl, self.session_id, self.key, rng, csprng);
+        (
+            SignerRound1 {
+                cl: self.cl,
+                session_id: self.session_id,
+                key: self.key,
+                signers: self.signers,
+            },
+            SignerMsg1 {
+                e_ciphertext,
+                s_ciphertext,
+                e_proof,
+                s_proof,
+            },
+        )
+    }
+}
+
+impl<'a> SignerRound1<'a> {
+    /// `msgs` holds the round 1 messages of every signer, including our own.
+    pub fn round2(
+        self,
+        msgs: &BTreeMap<usize, SignerMsg1>,
+    ) -> Result<(SignerRound2<'a>, SignerMsg2), ProtocolError> {
+        let cl = self.cl;
+        check_parties(self.signers, msgs)?;
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+        let culprits = msgs
+            .iter()
+            .filter(|(j, msg_j)| {
+                let transcript = Transcript::new(self.session_id, **j);
+                **j != self.key.i
+                    && !(msg_j.e_proof.verify(
+                        cl,
+                        &transcript,
+                        &bound,
+                        &self.key.cl_pub_key,
+                        &msg_j.e_ciphertext,
+                    ) && msg_j.s_proof.verify(
+                        cl,
+                        &transcript,
+                        &bound,
+                        &self.key.cl_pub_key,
+                        &msg_j.s_ciphertext,
+                    ))
+            })
+            .map(|(j, _)| *j)
+            .collect();
+        blame_proof(culprits, "CLEncSProof")?;
+
+        let e_ciphertext = add_ciphertexts(cl, msgs.values().map(|m| &m.e_ciphertext));
+        let s_ciphertext = add_ciphertexts(cl, msgs.values().map(|m| &m.s_ciphertext));
+        let e_x_ciphertext = add_ciphertexts(cl, [&self.key.x_ciphertext, &e_ciphertext]);
+        let (cl_d_i, _) = self.key.signing_shares(self.signers)?;
+        let my_msg2 = SignerMsg2 {
+            pd_e: e_ciphertext.c1().exp(cl, &cl_d_i),
+            pd_s: s_ciphertext.c1().exp(cl, &cl_d_i),
+        };
+        Ok((
+            SignerRound2 {
+                cl,
+                session_id: self.session_id,
+                key: self.key,
+                signers: self.signers,
+                e_ciphertext,
+                s_ciphertext,
+                e_x_ciphertext,
+            },
+            my_msg2,
+        ))
+    }
+}
+
+impl<'a> SignerRound2<'a> {
+    /// `msgs` holds the round 2 messages of every signer, including our own.
+    pub fn round3(
+        self,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+        msgs: &BTreeMap<usize, SignerMsg2>,
+        msg: &[Scalar],
+        l: usize,
+    ) -> Result<(SignerRound3<'a>, SignerMsg3), ProtocolError> {
+        let cl = self.cl;
+        check_parties(self.signers, msgs)?;
+        let e = decrypt(cl, &self.e_ciphertext, msgs.values().map(|m| &m.pd_e))?;
+        let s = decrypt(cl, &self.s_ciphertext, msgs.values().map(|m| &m.pd_s))?;
+
+        let mut B = G1Projective::generator();
+        for i in 0..l {
+            B = B + self.key.H[i] * msg[i];
+        }
+        B = B + self.key.H[l] * s;
+
+        let gamma_i = Scalar::random(&mut *csprng);
+        let cl_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
+        let ct_pow = CipherText::new(
+            &self
+                .e_x_ciphertext
+                .c1()
+                .exp(cl, &Mpz::from(&gamma_i))
+                .compose(cl, &cl.power_of_h(&cl_rand)),
+            &self
+                .e_x_ciphertext
+                .c2()
+                .exp(cl, &Mpz::from(&gamma_i))
+                .compose(cl, &self.key.cl_pub_key.exponentiation(cl, &cl_rand)),
+        );
+        let eg_rand = Scalar::random(&mut *csprng);
+        let eg_ciphertext = ElGCiphertext {
+            c1: (G1Projective::generator() * eg_rand).into(),
+            c2: (B * gamma_i + self.key.eg_pub_key * eg_rand).into(),
+        };
+        let proof = CLELProof::prove(
+            cl,
+            &Transcript::new(self.session_id, self.key.i),
+            &ResponseBound::new(&cl.encrypt_randomness_bound()),
+            rng,
+            csprng,
+            &self.key.cl_pub_key,
+            &self.key.eg_pub_key,
+            &ct_pow,
+            &self.e_x_ciphertext,
+            &eg_ciphertext,
+            &B,
+            &gamma_i,
+            &eg_rand,
+            &cl_rand,
+        );
+        Ok((
+            SignerRound3 {
+                cl,
+                session_id: self.session_id,
+                key: self.key,
+                signers: self.signers,
+                e,
+                s,
+                e_x_ciphertext: self.e_x_ciphertext,
+                B,
+            },
+            SignerMsg3 {
+                ct_pow,
+                eg_ciphertext,
+                proof,
+            },
+        ))
+    }
+}
+
+impl<'a> SignerRound3<'a> {
+    /// `msgs` holds the round 3 messages of every signer, including our own.
+    pub fn partial(
+        self,
+        rng: &mut RandGen,
+        csprng: &mut impl CryptoRngCore,
+        msgs: &BTreeMap<usize, SignerMsg3>,
+    ) -> Result<SignerPartial, ProtocolError> {
+        let cl = self.cl;
+        check_parties(self.signers, msgs)?;
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+        let (ids, items): (Vec<usize>, Vec<_>) = msgs
+            .iter()
+            .filter(|(j, _)| **j != self.key.i)
+            .map(|(j, msg_j)| {
+                let item = (
+                    Transcript::new(self.session_id, *j),
+                    &bound,
+                    &self.key.cl_pub_key,
+                    &self.key.eg_pub_key,
+                    &msg_j.ct_pow,
+                    &self.e_x_ciphertext,
+                    &msg_j.eg_ciphertext,
+                    &self.B,
+                    &msg_j.proof,
+                );
+                (*j, item)
+            })
+            .unzip();
+        blame_batch(CLELProof::batch_verify(cl, &items), &ids, "CLELProof")?;
+
+        let gamma_e_x_ciphertext = add_ciphertexts(cl, msgs.values().map(|m| &m.ct_pow));
+        let eg_ciphertext = msgs
+            .values()
+            .map(|m| m.eg_ciphertext.clone())
+            .reduce(|acc, ct| ElGCiphertext {
+                c1: (G1Projective::from(acc.c1) + G1Projective::from(ct.c1)).into(),
+                c2: (G1Projective::from(acc.c2) + G1Projective::from(ct.c2)).into(),
+            })
+            .unwrap();
+
+        let (cl_d_i, eg_d_i) = self.key.signing_shares(self.signers)?;
+        let transcript = Transcript::new(self.session_id, self.key.i);
+        let c1 = gamma_e_x_ciphertext.c1();
+        let pd_gamma_e_x = c1.exp(cl, &cl_d_i);
+        let pd_gamma_e_x_proof = CLPDProof::prove(
+            cl,
+            &transcript,
+            &pd_response_bound(cl, self.key.t, self.key.n, &self.key.n_factorial),
+            rng,
+            &cl.power_of_h(&cl_d_i),
+            &pd_gamma_e_x,
+            &c1,
+            &cl_d_i,
+        );
+        let eg_c1 = G1Projective::from(eg_ciphertext.c1);
+        let pd_eg = eg_c1 * eg_d_i;
+        let pd_eg_proof = ELPDProof::prove(
+            &transcript,
+            csprng,
+            &pd_eg,
+            &eg_c1,
+            &(G1Projective::generator() * eg_d_i),
+            &eg_d_i,
+        );
+        Ok(SignerPartial {
+            e: self.e,
+            s: self.s,
+            gamma_e_x_ciphertext,
+            eg_ciphertext,
+            pd_gamma_e_x,
+            pd_gamma_e_x_proof,
+            pd_eg,
+            pd_eg_proof,
+        })
+    }
+}
+
+/// A fresh random share encrypted under the joint CL key, with its proof.
+fn encrypt_share(
+    cl: &CL_HSMqk,
+    session_id: &[u8],
+    key: &SignerKey,
+    rng: &mut RandGen,
+    csprng: &mut impl CryptoRngCore,
+) -> (CipherText, CLEncSProof) {
+    let share = Scalar::random(&mut *csprng);
+    let cl_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
+    let ciphertext = CipherText::new(
+        &cl.power_of_h(&cl_rand),
+        &cl.power_of_f(&Mpz::from(&share))
+            .compose(cl, &key.cl_pub_key.exponentiation(cl, &cl_rand)),
+    );
+    let proof = CLEncSProof::prove(
+        cl,
+        &Transcript::new(session_id, key.i),
+        &ResponseBound::new(&cl.encrypt_randomness_bound()),
+        rng,
+        &key.cl_pub_key,
+        &ciphertext,
+        &share,
+        &cl_rand,
+        csprng,
+    );
+    (ciphertext, proof)
+}
+
+fn add_ciphertexts<'c>(
+    cl: &CL_HSMqk,
+    ciphertexts: impl IntoIterator<Item = &'c CipherText>,
+) -> CipherText {
+    ciphertexts
+        .into_iter()
+        .cloned()
+        .reduce(|acc, ct| {
+            CipherText::new(
+                &acc.c1().compose(cl, &ct.c1()),
+                &acc.c2().compose(cl, &ct.c2()),
+            )
+        })
+        .unwrap()
+}
+
+/// Decrypts `ciphertext` with the partial decryptions `pds` of all the parties.
+fn decrypt<'c>(
+    cl: &CL_HSMqk,
+    ciphertext: &CipherText,
+    pds: impl Iterator<Item = &'c QFI>,
+) -> Result<Scalar, ProtocolError> {
+    let pd = pds
+        .cloned()
+        .reduce(|acc, pd_i| acc.compose(cl, &pd_i))
+        .ok_or(ProtocolError::MissingMessage(Vec::new()))?;
+    let m = cl.dlog_in_F(&ciphertext.c2().compose(cl, &pd.exp(cl, &Mpz::from(-1i64))));
+    Scalar::from_str_vartime(&m.to_string()).ok_or(ProtocolError::DegenerateSignature)
+}
+
+#[derive(Debug, Error)]
+pub enum SignerError<RecvErr, SendErr> {
+    #[error("send a message at round 1")]
+    Round1Send(#[source] SendErr),
+    #[error("receive messages at round 1")]
+    Round1Receive(#[source] CompleteRoundError<RoundInputError, RecvErr>),
+    #[error("send a message at round 2")]
+    Round2Send(#[source] SendErr),
+    #[error("receive messages at round 2")]
+    Round2Receive(#[source] CompleteRoundError<RoundInputError, RecvErr>),
+    #[error("send a message at round 3")]
+    Round3Send(#[source] SendErr),
+    #[error("receive messages at round 3")]
+    Round3Receive(#[source] CompleteRoundError<RoundInputError, RecvErr>),
+    #[error("protocol aborted")]
+    Protocol(#[source] ProtocolError),
+}
+
+/// Runs [`Signer`] over a `round_based` transport among the parties in
+/// `signers`. The signer with index `i` must hold the key of party `signers[i]`.
+pub async fn signer_party<M>(
+    party: M,
+    i: PartyIndex,
+    signers: &[usize],
+    cl: &CL_HSMqk,
+    session_id: &[u8],
+    key: &SignerKey,
+    rng: &mut RandGen,
+    csprng: &mut impl CryptoRngCore,
+    msg: &[Scalar],
+    l: usize,
+) -> Result<SignerPartial, SignerError<M::ReceiveError, M::SendError>>
+where
+    M: Mpc<ProtocolMessage = SignerMsg>,
+{
+    let MpcParty { delivery, .. } = party.into_party();
+    let (incoming, mut outgoing) = delivery.split();
+
+    let n = signers.len() as u16;
+    let mut rounds = RoundsRouter::<SignerMsg>::builder();
+    let round1 = rounds.add_round(RoundInput::<SignerMsg1>::broadcast(i, n));
+    let round2 = rounds.add_round(RoundInput::<SignerMsg2>::broadcast(i, n));
+    let round3 = rounds.add_round(RoundInput::<SignerMsg3>::broadcast(i, n));
+    let mut rounds = rounds.listen(incoming);
+
+    let (signer, my_msg1) = Signer::new(cl, session_id, key, signers).round1(rng, csprng);
+    outgoing
+        .send(Outgoing::broadcast(SignerMsg::Round1(my_msg1.clone())))
+        .await
+        .map_err(SignerError::Round1Send)?;
+    let msgs1 = rounds
+        .complete(round1)
+        .await
+        .map_err(SignerError::Round1Receive)?;
+    let msgs1 = by_signer_id(signers, msgs1.into_vec_including_me(my_msg1));
+
+    let (signer, my_msg2) = signer.round2(&msgs1).map_err(SignerError::Protocol)?;
+    outgoing
+        .send(Outgoing::broadcast(SignerMsg::Round2(my_msg2.clone())))
+        .await
+        .map_err(SignerError::Round2Send)?;
+    let msgs2 = rounds
+        .complete(round2)
+        .await
+        .map_err(SignerError::Round2Receive)?;
+    let msgs2 = by_signer_id(signers, msgs2.into_vec_including_me(my_msg2));
+
+    let (signer, my_msg3) = signer
+        .round3(rng, csprng, &msgs2, msg, l)
+        .map_err(SignerError::Protocol)?;
+    outgoing
+        .send(Outgoing::broadcast(SignerMsg::Round3(my_msg3.clone())))
+        .await
+        .map_err(SignerError::Round3Send)?;
+    let msgs3 = rounds
+        .complete(round3)
+        .await
+        .map_err(SignerError::Round3Receive)?;
+    let msgs3 = by_signer_id(signers, msgs3.into_vec_including_me(my_msg3));
+
+    signer
+        .partial(rng, csprng, &msgs3)
+        .map_err(SignerError::Protocol)
+}
+
+fn by_signer_id<T>(signers: &[usize], msgs: Vec<T>) -> BTreeMap<usize, T> {
+    msgs.into_iter()
+        .enumerate()
+        .map(|(j, m)| (signers[j], m))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::{arithmetic::Converter, BigInt};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use round_based::simulation::Simulation;
+
+    use crate::MODULUS;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_signer_party() {
+        let seed: [u8; 32] = [0u8; 32];
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let (n, t, l) = (5, 3, 4);
+        let signers = [1, 3, 4, 5];
+        let mut msg: Vec<Scalar> = Vec::with_capacity(l);
+        for _ in 0..l {
+            msg.push(Scalar::random(&mut scalr_rng));
+        }
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng).unwrap();
+        let keys: Vec<_> = signers.iter().map(|i| key_msg.signer_key(*i)).collect();
+
+        let mut simulation = Simulation::<SignerMsg>::new();
+        let mut outputs = Vec::with_capacity(signers.len());
+        for (i, key) in keys.iter().enumerate() {
+            let party = simulation.add_party();
+            let mut party_rng = RandGen::new();
+            party_rng.set_seed(&Mpz::from(i as u64 + 1));
+            let mut party_csprng = ChaChaRng::from_seed([i as u8 + 1; 32]);
+            let cl = &cl;
+            let msg = &msg;
+            let signers = &signers;
+            outputs.push(async move {
+                signer_party(
+                    party,
+                    i as u16,
+                    signers,
+                    cl,
+                    b"sign",
+                    key,
+                    &mut party_rng,
+                    &mut party_csprng,
+                    msg,
+                    l,
+                )
+                .await
+                .map(|partial| (signers[i], partial))
+            });
+        }
+        let partials = futures::future::try_join_all(outputs)
+            .await
+            .unwrap()
+            .into_iter()
+            .collect();
+
+        let sign_msg = Sign::from_partials(&partials).unwrap();
+        Sign::client(&cl, b"sign", &sign_msg, &key_msg, &msg, l).unwrap();
+    }
+
+    #[test]
+    fn test_signer_bad_proof() {
+        let seed: [u8; 32] = [0u8; 32];
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let (n, t, l) = (3, 2, 2);
+        let signers = [1, 2, 3];
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng).unwrap();
+        let keys: Vec<_> = signers.iter().map(|i| key_msg.signer_key(*i)).collect();
+
+        let mut parties = Vec::with_capacity(signers.len());
+        let mut msgs1 = BTreeMap::new();
+        for key in &keys {
+            let (party, msg1) =
+                Signer::new(&cl, b"sign", key, &signers).round1(&mut rng, &mut scalr_rng);
+            parties.push(party);
+            msgs1.insert(key.i, msg1);
+        }
+
+        // party 2 sends a proof that does not match its ciphertext
+        msgs1.get_mut(&2).unwrap().e_proof.e += Scalar::one();
+        assert_eq!(
+            parties.remove(0).round2(&msgs1).err(),
+            Some(ProtocolError::InvalidProof {
+                proof: "CLEncSProof",
+                culprits: vec![2],
+            })
+        );
+    }
+
+    #[test]
+    fn test_signer_not_in_set() {
+        let seed: [u8; 32] = [0u8; 32];
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let (n, t, l) = (3, 2, 2);
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng).unwrap();
+        let key = key_msg.signer_key(3);
+        assert_eq!(
+            key.signing_shares(&[1, 2]).err(),
+            Some(ProtocolError::InvalidSignerSet(vec![1, 2]))
+        );
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{CLELProof, CLEncSProof, CLPDProof, ELPDProof, ElGCiphertext};
+use crate::error::{blame, blame_batch, blame_proof, check_parties};
+use crate::{
+    check_signers, CLELProof, CLEncSProof, CLPDProof, ELPDProof, ElGCiphertext, Transcript, PVSS,
+    PVSSG,
+};
 use bicycl::QFI;
 use ff::PrimeField;
 
@@ -7,29 +11,56 @@ use super::*;
 pub struct Sign {
     pub each_party_gamma_e_x_ciphertext: BTreeMap<usize, CipherText>,
     pub each_party_eg_ciphertext: BTreeMap<usize, ElGCiphertext>,
-    pub each_party_pd_gamma_e_x_ciphertext: BTreeMap<usize, (QFI, QFI, QFI, CLPDProof)>,
-    pub each_party_pd_eg_ciphertext:
-        BTreeMap<usize, (G1Projective, G1Projective, G1Projective, ELPDProof)>,
+    pub each_party_pd_gamma_e_x_ciphertext: BTreeMap<usize, (QFI, CLPDProof)>,
+    pub each_party_pd_eg_ciphertext: BTreeMap<usize, (G1Projective, ELPDProof)>,
     pub e: Scalar,
     pub s: Scalar,
 }
 
 impl Sign {
+    /// Runs the signing protocol among the parties in `signers`, which can be any
+    /// `t` or more of the `n` key holders, listed in increasing order.
     pub fn sign(
         cl: &CL_HSMqk,
-        n: usize,
+        session_id: &[u8],
+        signers: &[usize],
         l: usize,
         mut rng: &mut RandGen,
-        chacharng: &mut ChaChaRng,
+        csprng: &mut impl CryptoRngCore,
         key_msg: &KeyGen,
         msgs: &[Scalar],
-    ) -> Self {
+    ) -> Result<Self, ProtocolError> {
+        check_signers(signers, key_msg.cl_keys.t, key_msg.cl_keys.sk_shares.len())?;
+        let n = signers.len();
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+        let pd_bound = pd_response_bound(
+            cl,
+            key_msg.cl_keys.t,
+            key_msg.cl_keys.pk_shares.len(),
+            &key_msg.cl_keys.n_factorial,
+        );
+        let cl_lag_coes =
+            PVSS::lagrange_coeffs_times_n_factorial(signers, &key_msg.cl_keys.n_factorial);
+        let eg_lag_coes = PVSSG::lagrange_coeffs(signers);
+        let mut cl_sk_shares = BTreeMap::new();
+        let mut eg_sk_shares = BTreeMap::new();
+        for i in signers {
+            let cl_lagi = cl_lag_coes.get(i).unwrap();
+            let eg_lagi = eg_lag_coes.get(i).unwrap();
+            cl_sk_shares.insert(
+                *i,
+                key_msg.cl_keys.sk_shares.get(i).unwrap().clone() * cl_lagi,
+            );
+            eg_sk_shares.insert(*i, key_msg.eg_keys.sk_shares.get(i).unwrap() * eg_lagi);
+        }
+        let (cl_pk_shares, eg_pub_shares) = signer_pub_shares(cl, key_msg, signers);
+
         // let mut each_party_ei_ciphertext = Vec::with_capacity(n);
         let mut each_party_ciphertext = Vec::with_capacity(n);
 
-        for i in 1..=n {
-            let e_i = Scalar::random(chacharng.clone());
-            let s_i = Scalar::random(chacharng.clone());
+        for &i in signers {
+            let e_i = Scalar::random(&mut *csprng);
+            let s_i = Scalar::random(&mut *csprng);
             let e_i_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
             let c1: QFI = cl.power_of_h(&e_i_rand);
             let c2 = cl
@@ -39,12 +70,14 @@ impl Sign {
 
             let proof_e_i = CLEncSProof::prove(
                 &cl,
+                &Transcript::new(session_id, i),
+                &bound,
                 rng,
                 &key_msg.cl_keys.pub_key,
                 &e_i_ciphertext,
                 &e_i,
                 &e_i_rand,
-                &mut chacharng.clone(),
+                csprng,
             );
 
             let s_i_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
@@ -56,12 +89,14 @@ impl Sign {
 
             let proof_s_i = CLEncSProof::prove(
                 &cl,
+                &Transcript::new(session_id, i),
+                &bound,
                 rng,
                 &key_msg.cl_keys.pub_key,
                 &s_i_ciphertext,
                 &s_i,
                 &s_i_rand,
-                &mut chacharng.clone(),
+                csprng,
             );
 
             each_party_ciphertext.push((
@@ -76,23 +111,36 @@ impl Sign {
         let mut each_party_e_ciphertexts = BTreeMap::new();
         let mut each_party_s_ciphertexts = BTreeMap::new();
         let mut each_party_e_x_ciphertexts = BTreeMap::new();
-        for i in 1..=n {
+        for &i in signers {
             let x_ciphertext = key_msg.each_party_x_ciphertexts.get(&i).unwrap().clone();
-            let ciphertexts: Vec<_> = each_party_ciphertext
+            let culprits = each_party_ciphertext
                 .iter()
-                .filter_map(
-                    |(_, e_i_ciphertext, s_i_ciphertext, proof_e_i, proof_s_i)| {
-                        //if proof.verify(pp, &pp.pk, ki_ciphertext) && *j != i {
-                        if proof_e_i.verify(&cl, &key_msg.cl_keys.pub_key, &e_i_ciphertext)
-                            && proof_s_i.verify(&cl, &key_msg.cl_keys.pub_key, &s_i_ciphertext)
-                        {
-                            //Some((*j, gammai_ciphertext.clone(), proof.clone()))
-                            Some((e_i_ciphertext.clone(), s_i_ciphertext.clone()))
-                        } else {
-                            None
-                        }
+                .filter(
+                    |(j, e_i_ciphertext, s_i_ciphertext, proof_e_i, proof_s_i)| {
+                        *j != i
+                            && !(proof_e_i.verify(
+                                &cl,
+                                &Transcript::new(session_id, *j),
+                                &bound,
+                                &key_msg.cl_keys.pub_key,
+                                &e_i_ciphertext,
+                            ) && proof_s_i.verify(
+                                &cl,
+                                &Transcript::new(session_id, *j),
+                                &bound,
+                                &key_msg.cl_keys.pub_key,
+                                &s_i_ciphertext,
+                            ))
                     },
                 )
+                .map(|(j, _, _, _, _)| *j)
+                .collect();
+            blame_proof(culprits, "CLEncSProof")?;
+            let ciphertexts: Vec<_> = each_party_ciphertext
+                .iter()
+                .map(|(_, e_i_ciphertext, s_i_ciphertext, _, _)| {
+                    (e_i_ciphertext.clone(), s_i_ciphertext.clone())
+                })
                 .collect();
 
             //each_party_k_ciphertexts.insert(i, per_cipher.get(&i).unwrap().clone());
@@ -123,8 +171,8 @@ impl Sign {
         }
         let mut each_party_pde_ciphertexts = BTreeMap::new();
         let mut each_party_pds_ciphertexts = BTreeMap::new();
-        for i in 1..=n {
-            let d_i = key_msg.cl_keys.sk_shares.get(&i).unwrap().clone();
+        for &i in signers {
+            let d_i = cl_sk_shares.get(&i).unwrap().clone();
             let e = each_party_e_ciphertexts.get(&i).unwrap().clone();
             let s = each_party_s_ciphertexts.get(&i).unwrap().clone();
 
@@ -137,7 +185,7 @@ impl Sign {
 
         let mut each_party_e = BTreeMap::new();
         let mut each_party_s = BTreeMap::new();
-        for i in 1..=n {
+        for &i in signers {
             let ec = each_party_e_ciphertexts.get(&i).unwrap().clone();
             let sc = each_party_s_ciphertexts.get(&i).unwrap().clone();
 
@@ -170,9 +218,9 @@ impl Sign {
         let eg_pk = key_msg.eg_keys.pub_key.clone();
         let mut msg = Vec::with_capacity(n);
         let H = key_msg.sign_keys.H.clone();
-        for i in 1..=n {
+        for &i in signers {
             let e_x_ciphertext = each_party_e_x_ciphertexts.get(&i).unwrap().clone();
-            let gamma_i = Scalar::random(chacharng.clone());
+            let gamma_i = Scalar::random(&mut *csprng);
             let cl_rand1 = rng.random_mpz(&cl.encrypt_randomness_bound());
             let ct_pow = CipherText::new(
                 &e_x_ciphertext
@@ -185,7 +233,7 @@ impl Sign {
                     .compose(&cl, &cl_pk.exponentiation(&cl, &cl_rand1)),
             );
             let s = each_party_s.get(&i).unwrap().clone();
-            let eg_rand = Scalar::random(chacharng.clone());
+            let eg_rand = Scalar::random(&mut *csprng);
             let mut B = G1Projective::generator();
             for i in 0..l {
                 B = B + H[i] * msgs[i];
@@ -201,8 +249,10 @@ impl Sign {
 
             let proof_i = CLELProof::prove(
                 &cl,
+                &Transcript::new(session_id, i),
+                &bound,
                 &mut rng,
-                &mut chacharng.clone(),
+                csprng,
                 &cl_pk,
                 &eg_pk,
                 &ct_pow,
@@ -225,29 +275,33 @@ impl Sign {
 
         let mut each_party_gamma_e_x_ciphertext = BTreeMap::new();
         let mut each_party_eg_ciphertext = BTreeMap::new();
-        for i in 1..=n {
-            let ciphertexts: Vec<_> = msg
+        for &i in signers {
+            let (ids, items): (Vec<usize>, Vec<_>) = msg
                 .iter()
-                .filter_map(
-                    |(_, gamma_i_e_x_ciphertext, e_x_ciphertext, eg_ciphertext, proof, B)| {
-                        //if proof.verify(pp, &pp.pk, ki_ciphertext) && *j != i {
-                        if proof.verify(
-                            &cl,
-                            &mut rng,
+                .filter(|(j, _, _, _, _, _)| *j != i)
+                .map(
+                    |(j, gamma_i_e_x_ciphertext, e_x_ciphertext, eg_ciphertext, proof, B)| {
+                        let item = (
+                            Transcript::new(session_id, *j),
+                            &bound,
                             &cl_pk,
                             &eg_pk,
-                            &gamma_i_e_x_ciphertext,
-                            &e_x_ciphertext,
+                            gamma_i_e_x_ciphertext,
+                            e_x_ciphertext,
                             eg_ciphertext,
-                            &B,
-                        ) {
-                            //Some((*j, gammai_ciphertext.clone(), proof.clone()))
-                            Some((gamma_i_e_x_ciphertext.clone(), eg_ciphertext.clone()))
-                        } else {
-                            None
-                        }
+                            B,
+                            proof,
+                        );
+                        (*j, item)
                     },
                 )
+                .unzip();
+            blame_batch(CLELProof::batch_verify(&cl, &items), &ids, "CLELProof")?;
+            let ciphertexts: Vec<_> = msg
+                .iter()
+                .map(|(_, gamma_i_e_x_ciphertext, _, eg_ciphertext, _, _)| {
+                    (gamma_i_e_x_ciphertext.clone(), eg_ciphertext.clone())
+                })
                 .collect();
             let (gamma_e_x_ciphertext, eg_ciphertext) = ciphertexts
                 .into_iter()
@@ -272,21 +326,20 @@ impl Sign {
             each_party_eg_ciphertext.insert(i, eg_ciphertext);
         }
         let mut each_party_pd_gamma_e_x_ciphertext = BTreeMap::new();
-        let mut each_party_pd_eg_ciphertext: BTreeMap<
-            usize,
-            (G1Projective, G1Projective, G1Projective, ELPDProof),
-        > = BTreeMap::new();
-        for i in 1..=n {
+        let mut each_party_pd_eg_ciphertext = BTreeMap::new();
+        for &i in signers {
             let gamma_e_x_ciphertext = each_party_gamma_e_x_ciphertext.get(&i).unwrap().clone();
             let eg_ciphertext = each_party_eg_ciphertext.get(&i).unwrap().clone();
-            let cl_pub_share = key_msg.cl_keys.pk_shares.get(&i).unwrap().clone();
-            let eg_pub_share = key_msg.eg_keys.pub_shares.get(&i).unwrap().clone();
-            let cl_d_i = key_msg.cl_keys.sk_shares.get(&i).unwrap().clone();
-            let eg_d_i = key_msg.eg_keys.sk_shares.get(&i).unwrap().clone();
+            let cl_pub_share = cl_pk_shares.get(&i).unwrap().clone();
+            let eg_pub_share = eg_pub_shares.get(&i).unwrap().clone();
+            let cl_d_i = cl_sk_shares.get(&i).unwrap().clone();
+            let eg_d_i = eg_sk_shares.get(&i).unwrap().clone();
 
             let pd_gamma_e_x_ciphertext = gamma_e_x_ciphertext.c1().exp(&cl, &cl_d_i);
             let proof_pd_gamma_e_x = CLPDProof::prove(
                 &cl,
+                &Transcript::new(session_id, i),
+                &pd_bound,
                 rng,
                 &cl_pub_share,
                 &pd_gamma_e_x_ciphertext,
@@ -294,33 +347,19 @@ impl Sign {
                 &cl_d_i,
             );
 
-            each_party_pd_gamma_e_x_ciphertext.insert(
-                i,
-                (
-                    cl_pub_share,
-                    pd_gamma_e_x_ciphertext,
-                    gamma_e_x_ciphertext.c1(),
-                    proof_pd_gamma_e_x,
-                ),
-            );
+            each_party_pd_gamma_e_x_ciphertext
+                .insert(i, (pd_gamma_e_x_ciphertext, proof_pd_gamma_e_x));
 
             let pd_eg_ciphertext = &eg_ciphertext.c1 * &eg_d_i;
             let proof_pd_eg = ELPDProof::prove(
-                &mut chacharng.clone(),
+                &Transcript::new(session_id, i),
+                csprng,
                 &pd_eg_ciphertext,
                 &eg_ciphertext.c1.into(),
                 &eg_pub_share,
                 &eg_d_i,
             );
-            each_party_pd_eg_ciphertext.insert(
-                i,
-                (
-                    pd_eg_ciphertext,
-                    eg_ciphertext.c1.into(),
-                    eg_pub_share,
-                    proof_pd_eg,
-                ),
-            );
+            each_party_pd_eg_ciphertext.insert(i, (pd_eg_ciphertext, proof_pd_eg));
         }
 
         // for i in 1..=n {
@@ -353,55 +392,114 @@ impl Sign {
 
         //     let A = EG * gamma_e_x.invert().unwrap();
         // }
-        Self {
+        Ok(Self {
             each_party_gamma_e_x_ciphertext,
             each_party_eg_ciphertext,
             each_party_pd_gamma_e_x_ciphertext,
             each_party_pd_eg_ciphertext,
-            e: each_party_e.get(&1).unwrap().clone(),
-            s: each_party_s.get(&1).unwrap().clone(),
-        }
+            e: each_party_e.get(&signers[0]).unwrap().clone(),
+            s: each_party_s.get(&signers[0]).unwrap().clone(),
+        })
     }
 
-    pub fn client(cl: &CL_HSMqk, sign_msg: &Sign) {
+    /// Collects the [`SignerPartial`]s of [`signer_party`], by signer id, for
+    /// [`Sign::client`].
+    pub fn from_partials(partials: &BTreeMap<usize, SignerPartial>) -> Result<Self, ProtocolError> {
+        let first = partials
+            .values()
+            .next()
+            .ok_or(ProtocolError::MissingMessage(Vec::new()))?;
+        let culprits = partials
+            .iter()
+            .filter(|(_, p)| p.e != first.e || p.s != first.s)
+            .map(|(i, _)| *i)
+            .collect();
+        blame(culprits, ProtocolError::Inconsistent)?;
+
+        Ok(Self {
+            each_party_gamma_e_x_ciphertext: partials
+                .iter()
+                .map(|(i, p)| (*i, p.gamma_e_x_ciphertext.clone()))
+                .collect(),
+            each_party_eg_ciphertext: partials
+                .iter()
+                .map(|(i, p)| (*i, p.eg_ciphertext.clone()))
+                .collect(),
+            each_party_pd_gamma_e_x_ciphertext: partials
+                .iter()
+                .map(|(i, p)| (*i, (p.pd_gamma_e_x.clone(), p.pd_gamma_e_x_proof.clone())))
+                .collect(),
+            each_party_pd_eg_ciphertext: partials
+                .iter()
+                .map(|(i, p)| (*i, (p.pd_eg, p.pd_eg_proof.clone())))
+                .collect(),
+            e: first.e,
+            s: first.s,
+        })
+    }
+
+    /// Combines the signers' output into a BBS+ signature on `msg`, and checks it
+    /// against the joint public key of `key_msg`. The partial decryptions are
+    /// checked against the public key shares of `key_msg`, scaled for the signers
+    /// that sent them, not against anything the signers send.
+    pub fn client(
+        cl: &CL_HSMqk,
+        session_id: &[u8],
+        sign_msg: &Sign,
+        key_msg: &KeyGen,
+        msg: &[Scalar],
+        l: usize,
+    ) -> Result<BBSPlusSig, ProtocolError> {
         let gamma_e_x_ciphertext = sign_msg
             .each_party_gamma_e_x_ciphertext
-            .get(&1)
-            .unwrap()
+            .values()
+            .next()
+            .ok_or(ProtocolError::MissingMessage(Vec::new()))?
             .clone();
-        let eg_ciphertext = sign_msg.each_party_eg_ciphertext.get(&1).unwrap().clone();
+        let eg_ciphertext = sign_msg
+            .each_party_eg_ciphertext
+            .values()
+            .next()
+            .ok_or(ProtocolError::MissingMessage(Vec::new()))?
+            .clone();
+        let signers: Vec<usize> = sign_msg
+            .each_party_pd_gamma_e_x_ciphertext
+            .keys()
+            .copied()
+            .collect();
+        check_signers(&signers, key_msg.cl_keys.t, key_msg.cl_keys.pk_shares.len())?;
+        check_parties(&signers, &sign_msg.each_party_pd_eg_ciphertext)?;
+        let pd_bound = pd_response_bound(
+            cl,
+            key_msg.cl_keys.t,
+            key_msg.cl_keys.pk_shares.len(),
+            &key_msg.cl_keys.n_factorial,
+        );
+        let (cl_pk_shares, eg_pub_shares) = signer_pub_shares(cl, key_msg, &signers);
 
-        let pd_gamma_e_xs: Vec<_> = sign_msg
+        let gamma_e_x_ciphertext_c1 = gamma_e_x_ciphertext.c1();
+        let (ids, items): (Vec<usize>, Vec<_>) = sign_msg
             .each_party_pd_gamma_e_x_ciphertext
-            .values()
-            .into_iter()
-            .filter_map(
-                |(
-                    cl_pub_share,
+            .iter()
+            .map(|(j, (pd_gamma_e_x_ciphertext, proof_pd_gamma_e_x))| {
+                let item = (
+                    Transcript::new(session_id, *j),
+                    &pd_bound,
+                    &cl_pk_shares[j],
                     pd_gamma_e_x_ciphertext,
-                    gamma_e_x_ciphertext_c1,
+                    &gamma_e_x_ciphertext_c1,
                     proof_pd_gamma_e_x,
-                )| {
-                    //if proof.verify(pp, &pp.pk, ki_ciphertext) && *j != i {
-                    if proof_pd_gamma_e_x.verify(
-                        &cl,
-                        &cl_pub_share,
-                        &pd_gamma_e_x_ciphertext,
-                        &gamma_e_x_ciphertext_c1,
-                    ) {
-                        //Some((*j, gammai_ciphertext.clone(), proof.clone()))
-                        Some(pd_gamma_e_x_ciphertext.clone())
-                    } else {
-                        None
-                    }
-                },
-            )
-            .collect();
-
-        let pd_gamma_e_x = pd_gamma_e_xs
-            .into_iter()
+                );
+                (*j, item)
+            })
+            .unzip();
+        blame_batch(CLPDProof::batch_verify(&cl, &items), &ids, "CLPDProof")?;
+        let pd_gamma_e_x = sign_msg
+            .each_party_pd_gamma_e_x_ciphertext
+            .values()
+            .map(|(pd_gamma_e_x_ciphertext, _)| pd_gamma_e_x_ciphertext.clone())
             .reduce(|acc, pd_i| acc.compose(cl, &pd_i))
-            .unwrap();
+            .ok_or(ProtocolError::MissingMessage(Vec::new()))?;
 
         let gamma_e_x = cl.dlog_in_F(
             &gamma_e_x_ciphertext
@@ -409,36 +507,87 @@ impl Sign {
                 .compose(&cl, &pd_gamma_e_x.exp(&cl, &Mpz::from(-1i64))),
         );
 
-        let gamma_e_x = Scalar::from_str_vartime(&gamma_e_x.to_string()).unwrap();
+        let gamma_e_x = Scalar::from_str_vartime(&gamma_e_x.to_string())
+            .ok_or(ProtocolError::DegenerateSignature)?;
 
-        let pd_egs: Vec<_> = sign_msg
+        let eg_ciphertext_c1: G1Projective = eg_ciphertext.c1.into();
+        let culprits = sign_msg
             .each_party_pd_eg_ciphertext
-            .values()
-            .into_iter()
-            .filter_map(
-                |(pd_eg_ciphertext, eg_ciphertext_c1, eg_pub_share, proof_pd_eg)| {
-                    //if proof.verify(pp, &pp.pk, ki_ciphertext) && *j != i {
-                    if proof_pd_eg.verify(pd_eg_ciphertext, eg_ciphertext_c1, eg_pub_share) {
-                        //Some((*j, gammai_ciphertext.clone(), proof.clone()))
-                        Some(pd_eg_ciphertext.clone())
-                    } else {
-                        None
-                    }
-                },
-            )
+            .iter()
+            .filter(|(j, (pd_eg_ciphertext, proof_pd_eg))| {
+                !proof_pd_eg.verify(
+                    &Transcript::new(session_id, **j),
+                    pd_eg_ciphertext,
+                    &eg_ciphertext_c1,
+                    &eg_pub_shares[*j],
+                )
+            })
+            .map(|(j, _)| *j)
             .collect();
-
-        let pd_eg = pd_egs.into_iter().reduce(|acc, pd_i| acc + pd_i).unwrap();
+        blame_proof(culprits, "ELPDProof")?;
+        let pd_eg = sign_msg
+            .each_party_pd_eg_ciphertext
+            .values()
+            .map(|(pd_eg_ciphertext, _)| *pd_eg_ciphertext)
+            .reduce(|acc, pd_i| acc + pd_i)
+            .ok_or(ProtocolError::MissingMessage(Vec::new()))?;
 
         let EG = eg_ciphertext.c2 - pd_eg;
 
-        let A = EG * gamma_e_x.invert().unwrap();
+        let inv =
+            Option::<Scalar>::from(gamma_e_x.invert()).ok_or(ProtocolError::DegenerateSignature)?;
+        let A = EG * inv;
+        let sig = BBSPlusSig {
+            A,
+            e: sign_msg.e,
+            s: sign_msg.s,
+        };
+        BBSPlusSig::verify(&key_msg.public_key(), msg, l, &sig)?;
+        Ok(sig)
     }
 }
 
+/// The response bound of the [`CLPDProof`]s, from the public `t`, `n` and `n!`.
+pub(super) fn pd_response_bound(
+    cl: &CL_HSMqk,
+    t: usize,
+    n: usize,
+    n_factorial: &Mpz,
+) -> ResponseBound {
+    ResponseBound::new(&PVSS::lagrange_share_bound(
+        &cl.encrypt_randomness_bound(),
+        t,
+        n,
+        n_factorial,
+    ))
+}
+
+/// The CL and ElGamal public key shares of `signers`, scaled by their Lagrange
+/// coefficients like the secret shares they decrypt with.
+fn signer_pub_shares(
+    cl: &CL_HSMqk,
+    key_msg: &KeyGen,
+    signers: &[usize],
+) -> (BTreeMap<usize, QFI>, BTreeMap<usize, G1Projective>) {
+    let cl_lag_coes =
+        PVSS::lagrange_coeffs_times_n_factorial(signers, &key_msg.cl_keys.n_factorial);
+    let eg_lag_coes = PVSSG::lagrange_coeffs(signers);
+    signers
+        .iter()
+        .map(|i| {
+            (
+                (*i, key_msg.cl_keys.pk_shares[i].exp(&cl, &cl_lag_coes[i])),
+                (*i, key_msg.eg_keys.pub_shares[i] * eg_lag_coes[i]),
+            )
+        })
+        .unzip()
+}
+
+#[cfg(test)]
 mod tests {
     use curv::{arithmetic::Converter, BigInt};
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use crate::MODULUS;
 
@@ -450,7 +599,7 @@ mod tests {
         let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -466,11 +615,100 @@ mod tests {
         let mut msg: Vec<Scalar> = Vec::with_capacity(l);
 
         for i in 0..l {
-            let tmp = Scalar::random(scalr_rng.clone());
+            let tmp = Scalar::random(&mut scalr_rng);
             msg.push(tmp);
         }
-        let key_msg = KeyGen::keygen(&cl, n, t, l, &mut rng, &mut scalr_rng);
-        let sign_msg = Sign::sign(&cl, t, l, &mut rng, &mut scalr_rng, &key_msg, &msg);
-        Sign::client(&cl, &sign_msg);
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng).unwrap();
+        let signers = [1, 2, 4];
+        let sign_msg = Sign::sign(
+            &cl,
+            b"sign",
+            &signers,
+            l,
+            &mut rng,
+            &mut scalr_rng,
+            &key_msg,
+            &msg,
+        )
+        .unwrap();
+        Sign::client(&cl, b"sign", &sign_msg, &key_msg, &msg, l).unwrap();
+        msg[0] += Scalar::one();
+        assert_eq!(
+            Sign::client(&cl, b"sign", &sign_msg, &key_msg, &msg, l),
+            Err(ProtocolError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_client_key_shares() {
+        let seed: [u8; 32] = [0u8; 32];
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
+
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        let n = 4;
+        let t = 2;
+        let l = 2;
+        let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut scalr_rng)).collect();
+        let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut scalr_rng).unwrap();
+        let signers = [1, 2, 4];
+        let mut sign_msg = Sign::sign(
+            &cl,
+            b"sign",
+            &signers,
+            l,
+            &mut rng,
+            &mut scalr_rng,
+            &key_msg,
+            &msg,
+        )
+        .unwrap();
+
+        // Party 2 decrypts with a key of its own and proves it against the
+        // matching public key, which the client must not take from party 2.
+        let bound = pd_response_bound(
+            &cl,
+            key_msg.cl_keys.t,
+            key_msg.cl_keys.pk_shares.len(),
+            &key_msg.cl_keys.n_factorial,
+        );
+        let d = rng.random_mpz(&cl.encrypt_randomness_bound());
+        let c1 = sign_msg.each_party_gamma_e_x_ciphertext[&1].c1();
+        let pd = c1.exp(&cl, &d);
+        let proof = CLPDProof::prove(
+            &cl,
+            &Transcript::new(b"sign", 2),
+            &bound,
+            &mut rng,
+            &cl.power_of_h(&d),
+            &pd,
+            &c1,
+            &d,
+        );
+        sign_msg
+            .each_party_pd_gamma_e_x_ciphertext
+            .insert(2, (pd, proof));
+        assert_eq!(
+            Sign::client(&cl, b"sign", &sign_msg, &key_msg, &msg, l),
+            Err(ProtocolError::InvalidProof {
+                proof: "CLPDProof",
+                culprits: vec![2],
+            })
+        );
+
+        sign_msg.each_party_pd_eg_ciphertext.remove(&4);
+        assert_eq!(
+            Sign::client(&cl, b"sign", &sign_msg, &key_msg, &msg, l),
+            Err(ProtocolError::MissingMessage(vec![4]))
+        );
     }
 }
@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+
+use bls12_381::{G1Projective, Scalar};
+use ff::Field;
+use rand_core::CryptoRngCore;
+
+use crate::{PedersenOpeningProof, ProtocolError, Transcript};
+
+/// A holder's request for a signature on messages of which it keeps the ones at
+/// the indices `hidden` to itself. It commits to them as
+/// `C = sum_{i in hidden} H[i] m_i + H[l] s'` and proves it can open `C`, with
+/// the transcript of party `0` of the signing session. The signers add the
+/// revealed messages and their own `s''` to `C`, and the holder completes the
+/// signature with `s = s' + s''`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlindRequest {
+    pub hidden: Vec<usize>,
+    pub C: G1Projective,
+    pub proof: PedersenOpeningProof,
+}
+
+impl BlindRequest {
+    /// Commits to the messages `hidden`, by index below `l`. Returns the request
+    /// and the blinding `s'`, which the holder keeps for
+    /// [`Sign::client_blind`](crate::t_out_of_n::setbbsplus::Sign::client_blind).
+    pub fn new(
+        session_id: &[u8],
+        H: &[G1Projective],
+        l: usize,
+        hidden: &BTreeMap<usize, Scalar>,
+        csprng: &mut impl CryptoRngCore,
+    ) -> (Self, Scalar) {
+        let s_prime = Scalar::random(&mut *csprng);
+        let bases = Self::bases(H, l, hidden.keys());
+        let w: Vec<Scalar> = hidden.values().chain([&s_prime]).cloned().collect();
+        let C = bases
+            .iter()
+            .zip(&w)
+            .fold(G1Projective::identity(), |acc, (base, w_k)| {
+                acc + base * w_k
+            });
+        let proof =
+            PedersenOpeningProof::prove(&Transcript::new(session_id, 0), csprng, &bases, &C, &w);
+        let request = Self {
+            hidden: hidden.keys().cloned().collect(),
+            C,
+            proof,
+        };
+        (request, s_prime)
+    }
+
+    /// Checks that the hidden indices are distinct and below `l`, and the proof
+    /// of opening.
+    pub fn verify(
+        &self,
+        session_id: &[u8],
+        H: &[G1Projective],
+        l: usize,
+    ) -> Result<(), ProtocolError> {
+        if H.len() <= l
+            || self.hidden.iter().any(|i| *i >= l)
+            || self.hidden.windows(2).any(|w| w[0] >= w[1])
+        {
+            return Err(ProtocolError::InvalidRequest);
+        }
+        let bases = Self::bases(H, l, &self.hidden);
+        if !self
+            .proof
+            .verify(&Transcript::new(session_id, 0), &bases, &self.C)
+        {
+            return Err(ProtocolError::InvalidRequest);
+        }
+        Ok(())
+    }
+
+    /// `g1 + C + sum_{i not hidden} H[i] m_i` for the `revealed` messages, which
+    /// must be exactly the ones not hidden. The signers add `H[l] s''` to get
+    /// `B`.
+    pub fn base(
+        &self,
+        H: &[G1Projective],
+        l: usize,
+        revealed: &BTreeMap<usize, Scalar>,
+    ) -> Result<G1Projective, ProtocolError> {
+        if self.hidden.len() + revealed.len() != l
+            || revealed
+                .keys()
+                .any(|i| *i >= l || self.hidden.binary_search(i).is_ok())
+        {
+            return Err(ProtocolError::InvalidRequest);
+        }
+        Ok(revealed_base(H, revealed) + self.C)
+    }
+
+    fn bases<'a>(
+        H: &[G1Projective],
+        l: usize,
+        hidden: impl IntoIterator<Item = &'a usize>,
+    ) -> Vec<G1Projective> {
+        hidden.into_iter().map(|i| H[*i]).chain([H[l]]).collect()
+    }
+}
+
+/// A requester's `B' = r B` for `B = g1 + sum_i H[i] m_i + H[l] s`, for weakly
+/// partially blind signing. The signers see the messages that are not `hidden`
+/// and `B'`, which is uniformly random whatever the hidden messages and `s` are,
+/// and sign `B'` with an `e` of their own. The requester unmasks the signature
+/// with its [`RequestMask`]. The proof shows that the requester knows `r^-1`,
+/// the hidden messages and `s` with
+/// `r^-1 B' - sum_{i in hidden} H[i] m_i - H[l] s = g1 + sum_{i not hidden} H[i] m_i`,
+/// with the transcript of party `0` of the signing session.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaskedRequest {
+    pub hidden: Vec<usize>,
+    pub B: G1Projective,
+    pub proof: PedersenOpeningProof,
+}
+
+/// The mask `r` and the `s` of a [`MaskedRequest`], which only the requester
+/// knows.
+#[derive(Clone)]
+pub struct RequestMask {
+    pub r: Scalar,
+    pub s: Scalar,
+}
+
+impl MaskedRequest {
+    /// Requests a signature on `msg`, hiding the messages at the indices
+    /// `hidden`, in increasing order.
+    pub fn new(
+        session_id: &[u8],
+        H: &[G1Projective],
+        l: usize,
+        msg: &[Scalar],
+        hidden: &[usize],
+        csprng: &mut impl CryptoRngCore,
+    ) -> (Self, RequestMask) {
+        let mask = RequestMask {
+            r: Scalar::random(&mut *csprng),
+            s: Scalar::random(&mut *csprng),
+        };
+        let request = Self::with_mask(session_id, H, l, msg, hidden, &mask, csprng);
+        (request, mask)
+    }
+
+    /// [`MaskedRequest::new`] with a given mask, whose `r` must not be zero.
+    pub fn with_mask(
+        session_id: &[u8],
+        H: &[G1Projective],
+        l: usize,
+        msg: &[Scalar],
+        hidden: &[usize],
+        mask: &RequestMask,
+        csprng: &mut impl CryptoRngCore,
+    ) -> Self {
+        let mut B = G1Projective::generator();
+        for i in 0..l {
+            B = B + H[i] * msg[i];
+        }
+        B = (B + H[l] * mask.s) * mask.r;
+
+        let revealed = (0..l)
+            .filter(|i| !hidden.contains(i))
+            .map(|i| (i, msg[i]))
+            .collect();
+        let w: Vec<Scalar> = [mask.r.invert().unwrap()]
+            .into_iter()
+            .chain(hidden.iter().map(|i| -msg[*i]))
+            .chain([-mask.s])
+            .collect();
+        let proof = PedersenOpeningProof::prove(
+            &Transcript::new(session_id, 0),
+            csprng,
+            &Self::bases(&B, H, l, hidden),
+            &revealed_base(H, &revealed),
+            &w,
+        );
+        Self {
+            hidden: hidden.to_vec(),
+            B,
+            proof,
+        }
+    }
+
+    /// Checks that `revealed` are exactly the messages that are not hidden, and
+    /// the proof of well-formedness of `B'`.
+    pub fn verify(
+        &self,
+        session_id: &[u8],
+        H: &[G1Projective],
+        l: usize,
+        revealed: &BTreeMap<usize, Scalar>,
+    ) -> Result<(), ProtocolError> {
+        if H.len() <= l
+            || bool::from(self.B.is_identity())
+            || self.hidden.iter().any(|i| *i >= l)
+            || self.hidden.windows(2).any(|w| w[0] >= w[1])
+            || self.hidden.len() + revealed.len() != l
+            || revealed
+                .keys()
+                .any(|i| *i >= l || self.hidden.binary_search(i).is_ok())
+        {
+            return Err(ProtocolError::InvalidRequest);
+        }
+        if !self.proof.verify(
+            &Transcript::new(session_id, 0),
+            &Self::bases(&self.B, H, l, &self.hidden),
+            &revealed_base(H, revealed),
+        ) {
+            return Err(ProtocolError::InvalidRequest);
+        }
+        Ok(())
+    }
+
+    fn bases(
+        B: &G1Projective,
+        H: &[G1Projective],
+        l: usize,
+        hidden: &[usize],
+    ) -> Vec<G1Projective> {
+        [*B].into_iter()
+            .chain(hidden.iter().map(|i| H[*i]))
+            .chain([H[l]])
+            .collect()
+    }
+}
+
+/// `g1 + sum_i H[i] m_i` over the `revealed` messages.
+fn revealed_base(H: &[G1Projective], revealed: &BTreeMap<usize, Scalar>) -> G1Projective {
+    revealed
+        .iter()
+        .fold(G1Projective::generator(), |acc, (i, m_i)| acc + H[*i] * m_i)
+}
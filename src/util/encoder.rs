@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+
+use bls12_381::Scalar;
+use sha2::{Digest, Sha512};
+
+use crate::ProtocolError;
+
+/// How an attribute is mapped to a scalar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttributeType {
+    Bytes,
+    String,
+    Integer,
+    Date,
+}
+
+/// The value of an attribute. A `Date` is in seconds since the Unix epoch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Attribute {
+    Bytes(Vec<u8>),
+    String(String),
+    Integer(i64),
+    Date(i64),
+}
+
+impl Attribute {
+    pub fn ty(&self) -> AttributeType {
+        match self {
+            Self::Bytes(_) => AttributeType::Bytes,
+            Self::String(_) => AttributeType::String,
+            Self::Integer(_) => AttributeType::Integer,
+            Self::Date(_) => AttributeType::Date,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttributeSpec {
+    pub name: String,
+    pub ty: AttributeType,
+}
+
+/// Maps attributes to the messages of a BBS+ signature, the attribute at index
+/// `i` of the schema being signed under `H[i]`. Issuers and verifiers agree on
+/// the schema and on `dst`.
+///
+/// Bytes and strings are hashed to a scalar with SHA-512, framed by `dst` and
+/// the attribute type. Integers and dates are mapped to `v + 2^63`, which is
+/// below `2^64` and preserves their order, so range proofs on the messages are
+/// range proofs on the values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageEncoder {
+    pub dst: Vec<u8>,
+    pub schema: Vec<AttributeSpec>,
+}
+
+impl MessageEncoder {
+    /// Fails with [`ProtocolError::InvalidAttributes`] if two attributes have
+    /// the same name.
+    pub fn new(dst: &[u8], schema: &[(&str, AttributeType)]) -> Result<Self, ProtocolError> {
+        let schema: Vec<AttributeSpec> = schema
+            .iter()
+            .map(|(name, ty)| AttributeSpec {
+                name: name.to_string(),
+                ty: *ty,
+            })
+            .collect();
+        if (1..schema.len()).any(|i| schema[..i].iter().any(|spec| spec.name == schema[i].name)) {
+            return Err(ProtocolError::InvalidAttributes);
+        }
+        Ok(Self {
+            dst: dst.to_vec(),
+            schema,
+        })
+    }
+
+    /// The number of messages, `l`.
+    pub fn l(&self) -> usize {
+        self.schema.len()
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.schema.iter().position(|spec| spec.name == name)
+    }
+
+    /// Encodes `value` as the attribute at index `i`.
+    pub fn encode_attribute(&self, i: usize, value: &Attribute) -> Result<Scalar, ProtocolError> {
+        match self.schema.get(i) {
+            Some(spec) if spec.ty == value.ty() => {}
+            _ => return Err(ProtocolError::InvalidAttributes),
+        }
+        Ok(match value {
+            Attribute::Bytes(bytes) => self.hash_to_scalar(b"bytes", bytes),
+            Attribute::String(string) => self.hash_to_scalar(b"string", string.as_bytes()),
+            Attribute::Integer(v) | Attribute::Date(v) => Self::encode_integer(*v),
+        })
+    }
+
+    /// Encodes all the attributes, in the order of the schema.
+    pub fn encode(&self, values: &[Attribute]) -> Result<Vec<Scalar>, ProtocolError> {
+        if values.len() != self.l() {
+            return Err(ProtocolError::InvalidAttributes);
+        }
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| self.encode_attribute(i, value))
+            .collect()
+    }
+
+    /// Encodes some of the attributes by index, e.g. the revealed ones of a
+    /// [`BlindRequest`](crate::BlindRequest).
+    pub fn encode_revealed(
+        &self,
+        values: &BTreeMap<usize, Attribute>,
+    ) -> Result<BTreeMap<usize, Scalar>, ProtocolError> {
+        values
+            .iter()
+            .map(|(i, value)| Ok((*i, self.encode_attribute(*i, value)?)))
+            .collect()
+    }
+
+    pub fn encode_integer(v: i64) -> Scalar {
+        Scalar::from((v as u64) ^ (1 << 63))
+    }
+
+    fn hash_to_scalar(&self, ty: &[u8], bytes: &[u8]) -> Scalar {
+        let mut hasher = Sha512::new();
+        for item in [&self.dst[..], ty, bytes] {
+            hasher.update((item.len() as u64).to_be_bytes());
+            hasher.update(item);
+        }
+        let mut wide = [0u8; 64];
+        wide.copy_from_slice(&hasher.finalize());
+        Scalar::from_bytes_wide(&wide)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoder() {
+        let encoder = MessageEncoder::new(
+            b"test_encoder",
+            &[
+                ("name", AttributeType::String),
+                ("photo", AttributeType::Bytes),
+                ("birth", AttributeType::Date),
+                ("score", AttributeType::Integer),
+            ],
+        )
+        .unwrap();
+        assert_eq!(encoder.l(), 4);
+        assert_eq!(encoder.index("birth"), Some(2));
+        assert_eq!(encoder.index("age"), None);
+
+        let values = [
+            Attribute::String("alice".to_string()),
+            Attribute::Bytes(b"alice".to_vec()),
+            Attribute::Date(-86400),
+            Attribute::Integer(7),
+        ];
+        let msg = encoder.encode(&values).unwrap();
+        assert_eq!(msg, encoder.encode(&values).unwrap());
+        assert_ne!(msg[0], msg[1]);
+        assert_ne!(
+            msg[0],
+            MessageEncoder::new(b"other_encoder", &[("name", AttributeType::String)])
+                .unwrap()
+                .encode_attribute(0, &values[0])
+                .unwrap()
+        );
+
+        // The encodings are below 2^64 and in the order of the values.
+        let encoded: Vec<u64> = [i64::MIN, -86400, -1, 0, 1, i64::MAX]
+            .iter()
+            .map(|v| {
+                let bytes = MessageEncoder::encode_integer(*v).to_bytes();
+                assert!(bytes[8..].iter().all(|b| *b == 0));
+                u64::from_le_bytes(bytes[..8].try_into().unwrap())
+            })
+            .collect();
+        assert!(encoded.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(encoded[3], 1 << 63);
+
+        let revealed: BTreeMap<usize, Attribute> = [(3, values[3].clone())].into();
+        assert_eq!(
+            encoder.encode_revealed(&revealed).unwrap(),
+            [(3, msg[3])].into()
+        );
+
+        assert_eq!(
+            encoder.encode_attribute(2, &Attribute::Integer(0)),
+            Err(ProtocolError::InvalidAttributes)
+        );
+        assert_eq!(
+            encoder.encode(&values[..3]),
+            Err(ProtocolError::InvalidAttributes)
+        );
+        assert_eq!(
+            MessageEncoder::new(
+                b"test_encoder",
+                &[("a", AttributeType::Bytes), ("a", AttributeType::Integer)]
+            ),
+            Err(ProtocolError::InvalidAttributes)
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use bls12_381::hash_to_curve::{ExpandMsgXmd, HashToCurve};
+use bls12_381::G1Projective;
+use sha2::Sha256;
+
+/// The message generators `H` of a BBS+ key, `H[l]` being the one for `s`,
+/// hashed to G1 from a domain separation tag instead of generated jointly by
+/// the parties. Anyone can recompute them from the tag, and nobody knows their
+/// discrete logarithms.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Generators {
+    pub H: Vec<G1Projective>,
+}
+
+impl Generators {
+    /// `H[j] = hash_to_curve(j, dst)` for `j` in `0..=l`, with the
+    /// `BLS12381G1_XMD:SHA-256_SSWU_RO_` suite.
+    pub fn from_seed(dst: &[u8], l: usize) -> Self {
+        let H = (0..=l)
+            .map(|j| {
+                <G1Projective as HashToCurve<ExpandMsgXmd<Sha256>>>::hash_to_curve(
+                    (j as u64).to_be_bytes(),
+                    dst,
+                )
+            })
+            .collect();
+        Self { H }
+    }
+
+    /// The number of messages the generators are for.
+    pub fn l(&self) -> usize {
+        self.H.len() - 1
+    }
+
+    /// Checks that `H` are the generators hashed from `dst`.
+    pub fn verify(dst: &[u8], H: &[G1Projective]) -> bool {
+        !H.is_empty() && Self::from_seed(dst, H.len() - 1).H == H
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generators() {
+        let generators = Generators::from_seed(b"test_generators", 4);
+        assert_eq!(generators.l(), 4);
+        assert_eq!(generators, Generators::from_seed(b"test_generators", 4));
+        for (j, H_j) in generators.H.iter().enumerate() {
+            assert_eq!(false, bool::from(H_j.is_identity()));
+            assert!(generators.H[j + 1..].iter().all(|H_k| H_k != H_j));
+        }
+
+        assert!(Generators::verify(b"test_generators", &generators.H));
+        assert!(!Generators::verify(b"other_generators", &generators.H));
+        assert!(!Generators::verify(b"test_generators", &generators.H[1..]));
+    }
+}
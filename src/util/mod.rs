@@ -1,16 +1,45 @@
-use bicycl::QFI;
+use bicycl::{Mpz, RandGen, QFI};
 use bls12_381::{G1Affine, G2Affine};
+use rand_core::CryptoRngCore;
 use sha2::{Digest, Sha256};
 
+use crate::ProtocolError;
+
+pub mod blind;
+pub use blind::*;
+
+pub mod encoder;
+pub use encoder::*;
+
+pub mod generators;
+pub use generators::*;
+
 pub mod pvss;
 pub use pvss::*;
 
 pub mod pvss_g;
 pub use pvss_g::*;
 
+pub mod secret;
+pub use secret::*;
+
+pub mod transcript;
+pub use transcript::*;
+
 pub mod zero_shares;
 pub use zero_shares::*;
 
+/// A [`RandGen`] for the class group, seeded from `csprng`. The protocols take
+/// one of each, so a caller only needs a single cryptographic RNG such as
+/// `OsRng`; seeding from a fixed value is for tests only.
+pub fn rand_gen(csprng: &mut impl CryptoRngCore) -> RandGen {
+    let mut seed = [0u8; 64];
+    csprng.fill_bytes(&mut seed);
+    let mut rng = RandGen::new();
+    rng.set_seed(&Mpz::from_bytes(&seed));
+    rng
+}
+
 pub fn commit_G2(B: &G2Affine) -> Vec<u8> {
     let mut hasher = Sha256::new();
     for item in &[B.to_compressed()] {
@@ -44,3 +73,15 @@ pub fn commit_QFI(B: &QFI) -> Vec<u8> {
 
     res
 }
+
+/// Checks that `signers` lists at least `t` distinct parties out of `1..=n`, in
+/// increasing order.
+pub fn check_signers(signers: &[usize], t: usize, n: usize) -> Result<(), ProtocolError> {
+    if signers.len() < t
+        || signers.iter().any(|i| *i == 0 || *i > n)
+        || signers.windows(2).any(|w| w[0] >= w[1])
+    {
+        return Err(ProtocolError::InvalidSignerSet(signers.to_vec()));
+    }
+    Ok(())
+}
@@ -1,9 +1,9 @@
 use std::collections::BTreeMap;
 
 use bicycl::{CL_HSMqk, Mpz, RandGen, QFI};
-use rand_chacha::ChaChaRng;
 
-use crate::ComZkDlComClproof;
+use crate::error::blame;
+use crate::{ComZkDlComClproof, ProtocolError, ResponseBound, Transcript};
 
 pub struct PVSS {
     pub A: BTreeMap<usize, Vec<QFI>>,
@@ -12,21 +12,24 @@ pub struct PVSS {
 }
 
 impl PVSS {
+    /// The parties in `cl_keys` deal their values, with coefficients below `b`, to
+    /// the `n` parties `1..=n`, which need not be the same ones.
     pub fn share(
         cl: &CL_HSMqk,
+        session_id: &[u8],
         rng: &mut RandGen,
-        chacharng: &mut ChaChaRng,
         cl_keys: &BTreeMap<usize, Mpz>,
         b: &Mpz,
         t: usize,
         n: usize,
         n_factorial: &Mpz,
-    ) -> Self {
+    ) -> Result<Self, ProtocolError> {
         let mut msgs = BTreeMap::new();
         let mut A = BTreeMap::new();
         let mut ss = BTreeMap::new();
         let mut a = BTreeMap::new();
         let n_sq = n_factorial.clone() * n_factorial;
+        let bound = ResponseBound::new(&(n_factorial.clone() * b));
         for (i, item) in cl_keys.clone() {
             let mut coes = Vec::with_capacity(t);
             let mut As = Vec::with_capacity(t);
@@ -34,14 +37,29 @@ impl PVSS {
             let mut Asproofs = Vec::with_capacity(t);
             let s = item.clone();
             let mut tmp;
+            let transcript = Transcript::new(session_id, i);
             coes.push(n_factorial.clone() * &s);
             As.push(cl.power_of_h(&s));
-            Asproofs.push(ComZkDlComClproof::prove(&cl, chacharng, &As[0], &s));
+            Asproofs.push(ComZkDlComClproof::prove(
+                &cl,
+                &transcript,
+                &bound,
+                rng,
+                &As[0],
+                &s,
+            ));
             for j in 1..=(t - 1) {
                 coes.push(rng.random_mpz(b));
                 tmp = n_factorial.clone() * &coes[j];
                 As.push(cl.power_of_h(&tmp));
-                Asproofs.push(ComZkDlComClproof::prove(&cl, chacharng, &As[j], &tmp));
+                Asproofs.push(ComZkDlComClproof::prove(
+                    &cl,
+                    &transcript,
+                    &bound,
+                    rng,
+                    &As[j],
+                    &tmp,
+                ));
             }
             for j in 1..=n {
                 let mut sj = Mpz::from(0u64);
@@ -58,69 +76,113 @@ impl PVSS {
 
         for i in 1..=n {
             let exp = Mpz::from(i as i64);
-            for j in 1..=n {
+            let mut bad_proofs = Vec::new();
+            let mut bad_shares = Vec::new();
+            for j in cl_keys.keys().cloned() {
                 let mut As = A.get(&j).unwrap().clone();
                 let Asproofs = msgs.get(&j).unwrap().clone();
                 let si = ss.get(&j).unwrap().clone().get(&i).unwrap().clone();
                 let zero = Mpz::from(0u64);
                 let tmp_A0 = As[0].clone();
-                assert_eq!(true, Asproofs[0].verify(&cl, &As[0]));
+                let transcript = Transcript::new(session_id, j);
+                if !Asproofs
+                    .iter()
+                    .zip(As.iter())
+                    .all(|(proof, A_k)| proof.verify(&cl, &transcript, &bound, A_k))
+                {
+                    bad_proofs.push(j);
+                    continue;
+                }
                 As[0] = As[0].exp(&cl, &n_sq);
                 let mut pro = cl.power_of_h(&zero);
                 for k in (0..t).rev() {
-                    if k != 0 {
-                        assert_eq!(true, Asproofs[k].verify(&cl, &As[k]));
-                    }
                     pro = pro.exp(&cl, &exp).compose(&cl, &As[k]);
                 }
                 As[0] = tmp_A0;
                 let left_exp = si * n_factorial;
                 let left = cl.power_of_h(&left_exp);
-                assert_eq!(left, pro);
+                if left != pro {
+                    bad_shares.push(j);
+                }
             }
+            blame(bad_proofs, |culprits| ProtocolError::InvalidProof {
+                proof: "ComZkDlComClproof",
+                culprits,
+            })?;
+            blame(bad_shares, ProtocolError::InvalidShare)?;
         }
-        return PVSS { A, a, ss };
+        return Ok(PVSS { A, a, ss });
     }
 
     pub fn recover(
         cl: &CL_HSMqk,
         pv: &PVSS,
-        t: usize,
+        signers: &[usize],
         n: usize,
         n_factorial: &Mpz,
     ) -> (BTreeMap<usize, Mpz>, BTreeMap<usize, QFI>) {
         let mut dis = BTreeMap::new();
         let mut update_pub_shares = BTreeMap::new();
-        let lag_coes = Self::lagrange_coeffs_times_n_factorial(t, n_factorial);
-        for i in 1..=t {
-            let lagi = lag_coes.get(&i).unwrap().clone();
-            // lagi = lagi.clone() * n_factorial ;
+        let lag_coes = Self::lagrange_coeffs_times_n_factorial(signers, n_factorial);
+        let poly_shares = Self::poly_shares(pv, n, n_factorial);
+        for i in signers {
+            let lagi = lag_coes.get(i).unwrap().clone();
+            let di = poly_shares.get(i).unwrap().clone() * lagi;
+            dis.insert(*i, di.clone());
+            update_pub_shares.insert(*i, cl.power_of_h(&di));
+        }
+        (dis, update_pub_shares)
+    }
+
+    /// Every party's point on the sum of the shared polynomials, scaled by `n!`.
+    pub fn poly_shares(pv: &PVSS, n: usize, n_factorial: &Mpz) -> BTreeMap<usize, Mpz> {
+        let mut shares = BTreeMap::new();
+        for i in 1..=n {
             let mut sum = Mpz::from(0u64);
-            for j in 1..=n {
-                let si = pv.ss.get(&j).unwrap().get(&i).unwrap().clone();
+            for ss in pv.ss.values() {
+                let si = ss.get(&i).unwrap().clone();
                 sum = sum + si * n_factorial;
             }
-            let di = sum * lagi;
-            dis.insert(i, di.clone());
-            update_pub_shares.insert(i, cl.power_of_h(&di));
+            shares.insert(i, sum);
         }
-        (dis, update_pub_shares)
+        shares
     }
 
-    pub fn lagrange_coeffs_times_n_factorial(t: usize, n_factorial: &Mpz) -> BTreeMap<usize, Mpz> {
-        let mut coeffs = BTreeMap::new();
-        for i in 1..=t {
-            let mut result = n_factorial.clone();
-            let i_mpz = Mpz::from(i as u64);
-            for j in 1..=t {
-                if i != j {
-                    let j_mpz = Mpz::from(j as u64);
-                    result = result * &j_mpz / (&j_mpz - &i_mpz);
-                }
+    pub fn lagrange_coeffs_times_n_factorial(
+        signers: &[usize],
+        n_factorial: &Mpz,
+    ) -> BTreeMap<usize, Mpz> {
+        signers
+            .iter()
+            .map(|i| {
+                (
+                    *i,
+                    Self::lagrange_coeff_times_n_factorial(*i, signers, n_factorial),
+                )
+            })
+            .collect()
+    }
+
+    /// The Lagrange coefficient of party `i` at zero for the set `signers`, times
+    /// `n!` so that it is an integer.
+    pub fn lagrange_coeff_times_n_factorial(i: usize, signers: &[usize], n_factorial: &Mpz) -> Mpz {
+        let mut result = n_factorial.clone();
+        let i_mpz = Mpz::from(i as u64);
+        for j in signers {
+            if i != *j {
+                let j_mpz = Mpz::from(*j as u64);
+                result = result * &j_mpz / (&j_mpz - &i_mpz);
             }
-            coeffs.insert(i, result);
         }
-        coeffs
+        result
+    }
+
+    /// A bound on the absolute value of a poly share times a Lagrange coefficient
+    /// times `n!`, for coefficients drawn below `b`. Each party's point is below
+    /// `t * n! * b * n^(t-1)`, the poly share below `n * n!` times that and the
+    /// coefficient below `n! * n^(t-1)`.
+    pub fn lagrange_share_bound(b: &Mpz, t: usize, n: usize, n_factorial: &Mpz) -> Mpz {
+        n_factorial.pow(3) * b * Mpz::from(t as u64) * Mpz::from(n as u64).pow(2 * t as u64)
     }
 }
 
@@ -130,6 +192,7 @@ mod tests {
     use curv::{arithmetic::Converter, BigInt};
     use ff::{Field, PrimeField};
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use crate::{n_out_of_n::setbbsplus::KeyGen, MODULUS};
 
@@ -141,7 +204,7 @@ mod tests {
         let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -157,23 +220,24 @@ mod tests {
         for i in 1..=n {
             n_factorial = Mpz::from(i as u64) * &n_factorial;
         }
-        let key_msg = KeyGen::keygen(&cl, n, n, &mut rng, &mut scalr_rng);
+        let key_msg = KeyGen::keygen(&cl, b"test_pvss", n, n, &mut rng, &mut scalr_rng).unwrap();
         let pvssmsg = PVSS::share(
             &cl,
+            b"test_pvss",
             &mut rng,
-            &mut scalr_rng,
             &key_msg.cl_keys.sk_shares,
             &cl.encrypt_randomness_bound(),
             t,
             n,
             &n_factorial,
-        );
+        )
+        .unwrap();
         let mut left_sum = Mpz::from(0u64);
         for (_, item) in key_msg.cl_keys.sk_shares.clone() {
             left_sum = left_sum + item;
         }
         left_sum = left_sum * n_factorial.clone() * n_factorial.clone() * n_factorial.clone();
-        let dis = PVSS::recover(&cl, &pvssmsg, t, n, &n_factorial);
+        let dis = PVSS::recover(&cl, &pvssmsg, &[2, 3, 5], n, &n_factorial);
         let mut right_sum = Mpz::from(0u64);
         for (_, item) in dis.0 {
             right_sum = right_sum + item;
@@ -183,11 +247,11 @@ mod tests {
         let cube = Mpz::from(17u64);
 
         let mut f = cl.power_of_f(&Mpz::from(1u64));
-        for (i, item) in key_msg.cl_keys.sk_shares {
-            if i == 1 {
-                f = cl.power_of_f(&item);
+        for (i, item) in &key_msg.cl_keys.sk_shares {
+            if *i == 1 {
+                f = cl.power_of_f(item);
             } else {
-                f = f.compose(&cl, &cl.power_of_f(&item));
+                f = f.compose(&cl, &cl.power_of_f(item));
             }
         }
         f = f.exp(&cl, &cube);
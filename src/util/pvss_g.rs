@@ -1,7 +1,8 @@
-use crate::ComZkDlComEgproof;
+use crate::error::blame;
+use crate::{ComZkDlComEgproof, ProtocolError, Transcript};
 use bls12_381::{G1Projective, Scalar};
 use ff::Field;
-use rand_chacha::ChaChaRng;
+use rand_core::CryptoRngCore;
 use std::collections::BTreeMap;
 
 pub struct PVSSG {
@@ -12,11 +13,12 @@ pub struct PVSSG {
 
 impl PVSSG {
     pub fn share(
-        chacharng: &mut ChaChaRng,
+        session_id: &[u8],
+        csprng: &mut impl CryptoRngCore,
         eg_keys: &BTreeMap<usize, Scalar>,
         t: usize,
         n: usize,
-    ) -> Self {
+    ) -> Result<Self, ProtocolError> {
         let mut msgs = BTreeMap::new();
         let mut A = BTreeMap::new();
         let mut ss = BTreeMap::new();
@@ -27,18 +29,21 @@ impl PVSSG {
             let mut sjs = BTreeMap::new();
             let mut Asproofs = Vec::with_capacity(t);
             let s = item.clone();
+            let transcript = Transcript::new(session_id, i);
             coes.push(s);
             As.push(s * G1Projective::generator());
             Asproofs.push(ComZkDlComEgproof::prove(
-                &mut chacharng.clone(),
+                &transcript,
+                csprng,
                 &As[0].into(),
                 &coes[0],
             ));
             for j in 1..=(t - 1) {
-                coes.push(Scalar::random(chacharng.clone()));
+                coes.push(Scalar::random(&mut *csprng));
                 As.push(coes[j] * G1Projective::generator());
                 Asproofs.push(ComZkDlComEgproof::prove(
-                    &mut chacharng.clone(),
+                    &transcript,
+                    csprng,
                     &As[j].into(),
                     &coes[j],
                 ));
@@ -58,59 +63,90 @@ impl PVSSG {
 
         for i in 1..=n {
             let exp = Scalar::from(i as u64);
+            let mut bad_proofs = Vec::new();
+            let mut bad_shares = Vec::new();
             for j in 1..=n {
                 let As = A.get(&j).unwrap().clone();
                 let Asproofs = msgs.get(&j).unwrap().clone();
                 let si = ss.get(&j).unwrap().clone().get(&i).unwrap().clone();
+                let transcript = Transcript::new(session_id, j);
+                if !Asproofs
+                    .iter()
+                    .zip(As.iter())
+                    .all(|(proof, A_k)| proof.verify(&transcript, &A_k.into()))
+                {
+                    bad_proofs.push(j);
+                    continue;
+                }
                 let mut pro = G1Projective::identity();
                 for k in (0..t).rev() {
-                    assert_eq!(true, Asproofs[k].verify(&As[k].into()));
                     pro = pro * exp + As[k];
                 }
                 let left = si * G1Projective::generator();
-                assert_eq!(left, pro);
+                if left != pro {
+                    bad_shares.push(j);
+                }
             }
+            blame(bad_proofs, |culprits| ProtocolError::InvalidProof {
+                proof: "ComZkDlComEgproof",
+                culprits,
+            })?;
+            blame(bad_shares, ProtocolError::InvalidShare)?;
         }
-        return PVSSG { A, a, ss };
+        return Ok(PVSSG { A, a, ss });
     }
 
     pub fn recover(
         pv: &PVSSG,
-        t: usize,
+        signers: &[usize],
         n: usize,
     ) -> (BTreeMap<usize, Scalar>, BTreeMap<usize, G1Projective>) {
         let mut dis = BTreeMap::new();
         let mut update_pub_shares = BTreeMap::new();
-        let lag_coes = Self::lagrange_coeffs(t);
-        for i in 1..=t {
-            let lagi = lag_coes.get(&i).unwrap().clone();
+        let lag_coes = Self::lagrange_coeffs(signers);
+        let poly_shares = Self::poly_shares(pv, n);
+        for i in signers {
+            let lagi = lag_coes.get(i).unwrap().clone();
+            let di = poly_shares.get(i).unwrap() * lagi;
+            dis.insert(*i, di);
+            update_pub_shares.insert(*i, di * G1Projective::generator());
+        }
+        (dis, update_pub_shares)
+    }
+
+    /// Every party's point on the sum of the shared polynomials.
+    pub fn poly_shares(pv: &PVSSG, n: usize) -> BTreeMap<usize, Scalar> {
+        let mut shares = BTreeMap::new();
+        for i in 1..=n {
             let mut sum = Scalar::from(0u64);
             for j in 1..=n {
                 let si = pv.ss.get(&j).unwrap().get(&i).unwrap().clone();
                 sum = sum + si;
             }
-            let di = sum * lagi;
-            dis.insert(i, di);
-            update_pub_shares.insert(i, di * G1Projective::generator());
+            shares.insert(i, sum);
         }
-        (dis, update_pub_shares)
+        shares
     }
 
-    pub fn lagrange_coeffs(t: usize) -> BTreeMap<usize, Scalar> {
-        let mut coeffs = BTreeMap::new();
-        for i in 1..=t {
-            let mut result = Scalar::from(1u64);
-            let i_mpz = Scalar::from(i as u64);
-            for j in 1..=t {
-                if i != j {
-                    let j_mpz = Scalar::from(j as u64);
-                    let j_i_inv = (j_mpz - &i_mpz).invert().unwrap();
-                    result = result * &j_mpz * &j_i_inv;
-                }
+    pub fn lagrange_coeffs(signers: &[usize]) -> BTreeMap<usize, Scalar> {
+        signers
+            .iter()
+            .map(|i| (*i, Self::lagrange_coeff(*i, signers)))
+            .collect()
+    }
+
+    /// The Lagrange coefficient of party `i` at zero for the set `signers`.
+    pub fn lagrange_coeff(i: usize, signers: &[usize]) -> Scalar {
+        let mut result = Scalar::from(1u64);
+        let i_mpz = Scalar::from(i as u64);
+        for j in signers {
+            if i != *j {
+                let j_mpz = Scalar::from(*j as u64);
+                let j_i_inv = (j_mpz - &i_mpz).invert().unwrap();
+                result = result * &j_mpz * &j_i_inv;
             }
-            coeffs.insert(i, result);
         }
-        coeffs
+        result
     }
 }
 
@@ -133,7 +169,7 @@ mod tests {
         let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -145,16 +181,24 @@ mod tests {
         );
         let n = 5;
         let t = 3;
-        let key_msg = KeyGen::keygen(&cl, n, t, 5, &mut rng, &mut scalr_rng);
+        let key_msg =
+            KeyGen::keygen(&cl, b"test_pvss_g", n, t, 5, &mut rng, &mut scalr_rng).unwrap();
 
-        let pvssmsg = PVSSG::share(&mut scalr_rng, &key_msg.eg_keys.sk_shares, t, n);
+        let pvssmsg = PVSSG::share(
+            b"test_pvss_g",
+            &mut scalr_rng,
+            &key_msg.eg_keys.sk_shares,
+            t,
+            n,
+        )
+        .unwrap();
 
         let mut left_sum = Scalar::from(0u64);
         for (_, item) in key_msg.eg_keys.sk_shares.clone() {
             left_sum = left_sum + item;
         }
 
-        let dis = PVSSG::recover(&pvssmsg, t, n);
+        let dis = PVSSG::recover(&pvssmsg, &[1, 4, 5], n);
         let mut right_sum = Scalar::from(0u64);
         for (_, item) in dis.0 {
             right_sum = right_sum + item;
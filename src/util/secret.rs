@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use bicycl::Mpz;
+use bls12_381::Scalar;
+use zeroize::Zeroize;
+
+use crate::{n_out_of_n, t_out_of_n, NodeKey, RequestMask, ZeroShare};
+
+/// Secret values that can be overwritten in place. The key material below
+/// wipes its secret fields when dropped.
+pub trait Wipe {
+    fn wipe(&mut self);
+}
+
+impl Wipe for Scalar {
+    fn wipe(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl Wipe for Mpz {
+    fn wipe(&mut self) {
+        Mpz::wipe(self);
+    }
+}
+
+impl<K, V: Wipe> Wipe for BTreeMap<K, V> {
+    fn wipe(&mut self) {
+        self.values_mut().for_each(Wipe::wipe);
+    }
+}
+
+/// Printed in place of the secret fields.
+struct Redacted;
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+fn type_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// Implements `Drop`, wiping the `secret` fields, and a `Debug` that prints the
+/// `public` fields and redacts the secret ones.
+macro_rules! impl_secret {
+    ($($ty:ty { secret: [$($secret:ident),* $(,)?], public: [$($public:ident),* $(,)?] })*) => {
+        $(
+            impl Drop for $ty {
+                fn drop(&mut self) {
+                    $(self.$secret.wipe();)*
+                }
+            }
+
+            impl fmt::Debug for $ty {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    f.debug_struct(type_name::<Self>())
+                        $(.field(stringify!($public), &self.$public))*
+                        $(.field(stringify!($secret), &Redacted))*
+                        .finish_non_exhaustive()
+                }
+            }
+        )*
+    };
+}
+
+impl_secret! {
+    ZeroShare { secret: [beta_ijs], public: [] }
+    NodeKey { secret: [sk], public: [] }
+    RequestMask { secret: [r, s], public: [] }
+
+    n_out_of_n::BBSPlusKey { secret: [x], public: [X, H] }
+    n_out_of_n::setbbsplus::CLKeys { secret: [sk_shares], public: [] }
+    n_out_of_n::setbbsplus::SignKeys { secret: [sk_shares], public: [pub_shares, pub_key, H] }
+    n_out_of_n::KeyShare { secret: [cl_sk_share, x_share], public: [i, x_ciphertext, X, H] }
+    n_out_of_n::wmc24::CLKeys { secret: [sk_shares], public: [pk_shares] }
+    n_out_of_n::wmc24::SignKeys { secret: [sk_shares], public: [pub_shares, pub_key, H] }
+    n_out_of_n::wmc24::SignerKey {
+        secret: [cl_sk_share, eg_sk_share],
+        public: [i, n, x_ciphertext, eg_pub_key, H],
+    }
+    n_out_of_n::EgKeys { secret: [sk_shares], public: [pub_shares, pub_key] }
+
+    t_out_of_n::BBSPlusKey { secret: [x], public: [X, H] }
+    t_out_of_n::setbbsplus::CLKeys {
+        secret: [sk_shares, zero_shares],
+        public: [n_factorial, t, bound],
+    }
+    t_out_of_n::setbbsplus::SignKeys { secret: [sk_shares], public: [pub_shares, pub_key, H] }
+    t_out_of_n::KeyShare {
+        secret: [cl_sk_share, zero_shares, x_share],
+        public: [i, t, n, n_factorial, x_ciphertext, X, H],
+    }
+    t_out_of_n::SignPartyRound1<'_> { secret: [gamma_i], public: [signers] }
+    t_out_of_n::SignPartyRound2<'_> { secret: [gamma_i], public: [signers] }
+    t_out_of_n::Presignature { secret: [gamma_i], public: [signers] }
+    t_out_of_n::wmc24::CLKeys { secret: [sk_shares], public: [pk_shares, n_factorial, t] }
+    t_out_of_n::wmc24::SignKeys { secret: [sk_shares], public: [pub_shares, pub_key, H] }
+    t_out_of_n::wmc24::SignerKey {
+        secret: [cl_sk_share, eg_sk_share],
+        public: [i, t, n, n_factorial, x_ciphertext, eg_pub_key, H],
+    }
+    t_out_of_n::EgKeys { secret: [sk_shares], public: [pub_shares, pub_key] }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wipe() {
+        let mut x = Mpz::from(0x1234_5678_9abc_def0u64) * Mpz::from(0x1234_5678_9abc_def0u64);
+        x.wipe();
+        assert_eq!(x, Mpz::from(0u64));
+
+        let mut shares = BTreeMap::from([(1, Scalar::one()), (2, Scalar::one().double())]);
+        shares.wipe();
+        assert!(shares.values().all(|x_i| *x_i == Scalar::zero()));
+
+        let key = n_out_of_n::BBSPlusKey {
+            x: Scalar::one(),
+            X: bls12_381::G2Projective::generator(),
+            H: vec![],
+        };
+        let debug = format!("{:?}", key);
+        assert!(debug.starts_with("BBSPlusKey {"));
+        assert!(debug.contains("x: <redacted>"));
+    }
+}
@@ -0,0 +1,101 @@
+use bicycl::Mpz;
+use bls12_381::Scalar;
+use sha2::{Digest, Sha512};
+
+use crate::Wire;
+
+/// Fiat–Shamir transcript shared by the proofs in [`crate::zk`] and
+/// [`crate::comzk`]. It is bound to the session and to the party making the
+/// proofs when created; each proof then starts from a copy with
+/// [`Transcript::proof`], appends its whole statement and its commitments, and
+/// draws its challenge. Every item is framed with its label and length, so a
+/// proof made in one session, by one party or for one statement never verifies
+/// for another.
+#[derive(Clone)]
+pub struct Transcript {
+    hasher: Sha512,
+}
+
+impl Transcript {
+    /// The transcript of the proofs made by party `party` in session
+    /// `session_id`. Verifiers build the same one from the prover's id.
+    pub fn new(session_id: &[u8], party: usize) -> Self {
+        let mut transcript = Self {
+            hasher: Sha512::new(),
+        };
+        transcript.append_message(b"protocol", b"threshold_bbsp");
+        transcript.append_message(b"session", session_id);
+        transcript.append_u64(b"party", party as u64);
+        transcript
+    }
+
+    /// The transcript from which a batch verification of `label` proofs draws
+    /// its coefficients, after absorbing all of them.
+    pub fn batch(label: &'static [u8]) -> Self {
+        let mut transcript = Self {
+            hasher: Sha512::new(),
+        };
+        transcript.append_message(b"protocol", b"threshold_bbsp");
+        transcript.append_message(b"batch", label);
+        transcript
+    }
+
+    /// A copy of the transcript, domain separated for the proof `label`.
+    pub fn proof(&self, label: &'static [u8]) -> Self {
+        let mut transcript = self.clone();
+        transcript.append_message(b"proof", label);
+        transcript
+    }
+
+    pub fn append_message(&mut self, label: &'static [u8], message: &[u8]) {
+        self.hasher.update((label.len() as u64).to_be_bytes());
+        self.hasher.update(label);
+        self.hasher.update((message.len() as u64).to_be_bytes());
+        self.hasher.update(message);
+    }
+
+    pub fn append_u64(&mut self, label: &'static [u8], x: u64) {
+        self.append_message(label, &x.to_be_bytes());
+    }
+
+    /// Appends the canonical encoding of `item`.
+    pub fn append<T: Wire>(&mut self, label: &'static [u8], item: &T) {
+        let mut bytes = Vec::new();
+        item.encode(&mut bytes);
+        self.append_message(label, &bytes);
+    }
+
+    /// 64 challenge bytes. The transcript absorbs them, so that successive
+    /// challenges differ.
+    pub fn challenge_bytes(&mut self, label: &'static [u8]) -> [u8; 64] {
+        self.append_message(b"challenge", label);
+        let mut bytes = [0u8; 64];
+        bytes.copy_from_slice(&self.hasher.clone().finalize());
+        self.hasher.update(bytes);
+        bytes
+    }
+
+    pub fn challenge_scalar(&mut self, label: &'static [u8]) -> Scalar {
+        Scalar::from_bytes_wide(&self.challenge_bytes(label))
+    }
+
+    /// A challenge of `nbytes` bytes, at most 64.
+    pub fn challenge_mpz(&mut self, label: &'static [u8], nbytes: usize) -> Mpz {
+        Mpz::from_bytes(&self.challenge_bytes(label)[..nbytes])
+    }
+
+    /// A prime of exactly `8 * nbytes` bits, `nbytes` at most 64. Candidates
+    /// with their top and bottom bits set are drawn until one is prime, so the
+    /// prime is a deterministic function of the transcript.
+    pub fn challenge_prime(&mut self, label: &'static [u8], nbytes: usize) -> Mpz {
+        loop {
+            let mut bytes = self.challenge_bytes(label)[..nbytes].to_vec();
+            bytes[0] |= 0x80;
+            bytes[nbytes - 1] |= 1;
+            let candidate = Mpz::from_bytes(&bytes);
+            if candidate.is_prime() {
+                return candidate;
+            }
+        }
+    }
+}
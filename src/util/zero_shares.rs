@@ -2,14 +2,16 @@ use std::collections::BTreeMap;
 
 use bicycl::{CL_HSMqk, Mpz, RandGen};
 
+/// Pairwise zero-sharing among `n` parties. Party `i` keeps `beta_ijs[&i]`, the
+/// values it shares with every other party, and derives its share of zero for a
+/// given signing set with [`ZeroShare::combine`].
 pub struct ZeroShare {
-    pub beta_t_is: BTreeMap<usize, Mpz>,
+    pub beta_ijs: BTreeMap<usize, BTreeMap<usize, Mpz>>,
 }
 
 impl ZeroShare {
-    pub fn share(cl: &CL_HSMqk, rng: &mut RandGen, t: usize, n: usize) -> Self {
+    pub fn share(cl: &CL_HSMqk, rng: &mut RandGen, n: usize) -> Self {
         let mut beta_primei = BTreeMap::new();
-        let mut beta_t_is = BTreeMap::new();
         for i in 1..=n {
             let mut beta_prime = BTreeMap::new();
             for j in 1..=n {
@@ -19,42 +21,63 @@ impl ZeroShare {
             }
             beta_primei.insert(i, beta_prime);
         }
-        let mut beta_ijss = BTreeMap::new();
+        let mut beta_ijs = BTreeMap::new();
         for i in 1..=n {
             let beta_prime_ijs = beta_primei.get(&i).unwrap().clone();
-            let mut beta_ijs = BTreeMap::new();
+            let mut beta_is = BTreeMap::new();
             for j in 1..=n {
-                let beta_ij;
                 if j != i {
-                    let beta_prime_ij = beta_prime_ijs.get(&j).unwrap().clone();
-                    let beta_prime_ji = beta_primei.get(&j).unwrap().get(&i).unwrap().clone();
-                    if i > j {
-                        beta_ij = beta_prime_ij - beta_prime_ji;
-                    } else {
-                        beta_ij = beta_prime_ji - beta_prime_ij;
-                    }
-                    beta_ijs.insert(j, beta_ij);
+                    beta_is.insert(
+                        j,
+                        Self::pairwise(
+                            i,
+                            j,
+                            beta_prime_ijs.get(&j).unwrap().clone(),
+                            beta_primei.get(&j).unwrap().get(&i).unwrap().clone(),
+                        ),
+                    );
                 }
             }
-            beta_ijss.insert(i, beta_ijs);
+            beta_ijs.insert(i, beta_is);
         }
-        let mut sum = Mpz::from(0u64);
-        for i in 1..=t {
-            let beta_ijs = beta_ijss.get(&i).unwrap().clone();
-            let mut beta_t_i = Mpz::from(0u64);
-            for j in 1..=t {
-                if j < i {
-                    beta_t_i = beta_t_i + beta_ijs.get(&j).unwrap().clone();
-                }
-                if j > i {
-                    beta_t_i = beta_t_i - beta_ijs.get(&j).unwrap().clone();
-                }
+        ZeroShare { beta_ijs }
+    }
+
+    /// The value parties `i` and `j` share, from the seed `i` sent to `j` and
+    /// the one `j` sent to `i`. It is the same from both sides.
+    pub fn pairwise(i: usize, j: usize, beta_prime_ij: Mpz, beta_prime_ji: Mpz) -> Mpz {
+        if i > j {
+            beta_prime_ij - beta_prime_ji
+        } else {
+            beta_prime_ji - beta_prime_ij
+        }
+    }
+
+    /// Party `i`'s share of zero for the signing set `signers`. The shares of
+    /// all the parties in `signers` sum to zero.
+    pub fn combine(i: usize, beta_ijs: &BTreeMap<usize, Mpz>, signers: &[usize]) -> Mpz {
+        let mut beta_i = Mpz::from(0u64);
+        for j in signers {
+            if *j < i {
+                beta_i = beta_i + beta_ijs.get(j).unwrap().clone();
+            }
+            if *j > i {
+                beta_i = beta_i - beta_ijs.get(j).unwrap().clone();
             }
-            sum = sum + beta_t_i.clone();
-            beta_t_is.insert(i, beta_t_i);
         }
-        assert_eq!(Mpz::from(0u64), sum);
-        ZeroShare { beta_t_is }
+        beta_i
+    }
+
+    pub fn for_signers(&self, signers: &[usize]) -> BTreeMap<usize, Mpz> {
+        signers
+            .iter()
+            .map(|i| {
+                (
+                    *i,
+                    Self::combine(*i, self.beta_ijs.get(i).unwrap(), signers),
+                )
+            })
+            .collect()
     }
 }
 
@@ -72,10 +95,10 @@ mod tests {
     #[test]
     fn test_zero_share() {
         let seed: [u8; 32] = [0u8; 32];
-        let scalr_rng = ChaChaRng::from_seed(seed);
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -86,12 +109,13 @@ mod tests {
             false,
         );
         let n = 5;
-        let t = 3;
-        let beta_t_is = ZeroShare::share(&cl, &mut rng, t, n);
-        let mut sum = Mpz::from(0u64);
-        for i in 1..=t {
-            sum = sum + beta_t_is.beta_t_is.get(&i).unwrap().clone();
+        let zero_shares = ZeroShare::share(&cl, &mut rng, n);
+        for signers in [vec![1, 2, 3], vec![2, 4, 5], vec![1, 3, 4, 5]] {
+            let mut sum = Mpz::from(0u64);
+            for (_, beta_i) in zero_shares.for_signers(&signers) {
+                sum = sum + beta_i;
+            }
+            assert_eq!(Mpz::from(0u64), sum);
         }
-        assert_eq!(Mpz::from(0u64), sum);
     }
 }
@@ -0,0 +1,789 @@
+//! Canonical binary encoding of protocol messages, proofs, key shares and
+//! ciphertexts.
+//!
+//! An encoding is the byte [`WIRE_VERSION`] followed by the fields of the value
+//! in declaration order:
+//!
+//! - integers (`u8`, party ids) are big-endian, `usize` as a `u64`;
+//! - sequences, sets and maps are prefixed with their number of elements as a
+//!   `u32`, set elements and map keys are strictly increasing;
+//! - an [`Mpz`] is a sign byte (`1` if negative) followed by its magnitude as
+//!   length-prefixed big-endian bytes without leading zeros;
+//! - a [`QFI`] is its coefficients `a`, `b`, `c`;
+//! - scalars are their canonical 32 byte encoding, and curve points their
+//!   compressed encoding.
+//!
+//! There is exactly one encoding of every value, and decoding rejects anything
+//! else: lengths running past the input, trailing bytes, non-canonical integers
+//! and scalars, curve points outside the prime order subgroup and forms that are
+//! not positive definite and normal. [`Wire::from_wire_bytes`] also checks that
+//! forms have the discriminant of the class group they are used in. Serde goes
+//! through the same encoding, but cannot see the class group, so it skips that
+//! last check, and it is not available for types holding a CL [`PublicKey`].
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::marker::PhantomData;
+
+use bicycl::{CLParams, CL_HSMqk, CipherText, Mpz, PublicKey, QFI};
+use bls12_381::{G1Affine, G1Projective, G2Affine, G2Projective, Scalar};
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::*;
+
+pub const WIRE_VERSION: u8 = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum WireError {
+    #[error("unsupported wire version {0}")]
+    UnsupportedVersion(u8),
+    #[error("unexpected end of input")]
+    Truncated,
+    #[error("{0} trailing bytes")]
+    TrailingBytes(usize),
+    #[error("length {0} runs past the end of the input")]
+    InvalidLength(usize),
+    #[error("non-canonical encoding of {0}")]
+    NonCanonical(&'static str),
+    #[error("invalid {0}")]
+    InvalidElement(&'static str),
+    #[error("decoding a {0} needs the class group")]
+    MissingGroup(&'static str),
+}
+
+/// Input of [`Wire::decode`]. The class group, if any, is used to validate the
+/// decoded forms.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    cl: Option<&'a CL_HSMqk>,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8], cl: Option<&'a CL_HSMqk>) -> Self {
+        Self { bytes, cl }
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], WireError> {
+        if len > self.bytes.len() {
+            return Err(WireError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    /// Reads the number of elements of a sequence. Every element takes at least
+    /// one byte, so a count larger than the remaining input is rejected before
+    /// anything gets allocated.
+    pub fn read_len(&mut self) -> Result<usize, WireError> {
+        let len = u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as usize;
+        if len > self.bytes.len() {
+            return Err(WireError::InvalidLength(len));
+        }
+        Ok(len)
+    }
+
+    pub fn finish(self) -> Result<(), WireError> {
+        match self.bytes.len() {
+            0 => Ok(()),
+            rest => Err(WireError::TrailingBytes(rest)),
+        }
+    }
+}
+
+fn put_len(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&u32::try_from(len).unwrap().to_be_bytes());
+}
+
+pub trait Wire: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError>;
+
+    fn to_wire_bytes(&self) -> Vec<u8> {
+        let mut out = vec![WIRE_VERSION];
+        self.encode(&mut out);
+        out
+    }
+
+    /// Decodes a value received from or stored by a party using `cl`.
+    fn from_wire_bytes(bytes: &[u8], cl: &CL_HSMqk) -> Result<Self, WireError> {
+        decode_versioned(bytes, Some(cl))
+    }
+
+    /// Decodes a value without a class group at hand, e.g. the [`CLParams`] the
+    /// group is built from. Forms are not checked against a discriminant, and
+    /// [`PublicKey`]s cannot be decoded.
+    fn from_wire_bytes_without_group(bytes: &[u8]) -> Result<Self, WireError> {
+        decode_versioned(bytes, None)
+    }
+}
+
+fn decode_versioned<T: Wire>(bytes: &[u8], cl: Option<&CL_HSMqk>) -> Result<T, WireError> {
+    let mut r = Reader::new(bytes, cl);
+    match r.take(1)?[0] {
+        WIRE_VERSION => {}
+        version => return Err(WireError::UnsupportedVersion(version)),
+    }
+    let value = T::decode(&mut r)?;
+    r.finish()?;
+    Ok(value)
+}
+
+impl Wire for u8 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        Ok(r.take(1)?[0])
+    }
+}
+
+impl Wire for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        match u8::decode(r)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(WireError::NonCanonical("bool")),
+        }
+    }
+}
+
+impl Wire for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_bytes().to_vec().encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        String::from_utf8(Vec::decode(r)?).map_err(|_| WireError::InvalidElement("string"))
+    }
+}
+
+impl Wire for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(*self as u64).to_be_bytes());
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        let n = u64::from_be_bytes(r.take(8)?.try_into().unwrap());
+        usize::try_from(n).map_err(|_| WireError::InvalidElement("usize"))
+    }
+}
+
+impl<T: Wire> Wire for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        put_len(out, self.len());
+        for item in self {
+            item.encode(out);
+        }
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        let len = r.read_len()?;
+        (0..len).map(|_| T::decode(r)).collect()
+    }
+}
+
+impl<K: Wire + Ord, V: Wire> Wire for BTreeMap<K, V> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        put_len(out, self.len());
+        for (k, v) in self {
+            k.encode(out);
+            v.encode(out);
+        }
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        let len = r.read_len()?;
+        let mut map = BTreeMap::new();
+        for _ in 0..len {
+            let k = K::decode(r)?;
+            if map.last_key_value().is_some_and(|(last, _)| *last >= k) {
+                return Err(WireError::NonCanonical("map"));
+            }
+            map.insert(k, V::decode(r)?);
+        }
+        Ok(map)
+    }
+}
+
+impl<T: Wire + Ord> Wire for BTreeSet<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        put_len(out, self.len());
+        for item in self {
+            item.encode(out);
+        }
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        let len = r.read_len()?;
+        let mut set = BTreeSet::new();
+        for _ in 0..len {
+            let item = T::decode(r)?;
+            if set.last().is_some_and(|last| *last >= item) {
+                return Err(WireError::NonCanonical("set"));
+            }
+            set.insert(item);
+        }
+        Ok(set)
+    }
+}
+
+impl Wire for Mpz {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let negative = self.sgn() < 0;
+        let magnitude = if negative {
+            -self.clone()
+        } else {
+            self.clone()
+        }
+        .to_bytes();
+        let start = magnitude
+            .iter()
+            .position(|b| *b != 0)
+            .unwrap_or(magnitude.len());
+        out.push(negative as u8);
+        magnitude[start..].to_vec().encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        let sign = u8::decode(r)?;
+        let magnitude = Vec::<u8>::decode(r)?;
+        if sign > 1 || magnitude.first() == Some(&0) || (sign == 1 && magnitude.is_empty()) {
+            return Err(WireError::NonCanonical("Mpz"));
+        }
+        let abs = Mpz::from_bytes(&magnitude);
+        Ok(if sign == 1 { -abs } else { abs })
+    }
+}
+
+fn discriminant(a: &Mpz, b: &Mpz, c: &Mpz) -> Mpz {
+    b.clone() * b.clone() - Mpz::from(4i64) * a.clone() * c.clone()
+}
+
+impl Wire for QFI {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.a().encode(out);
+        self.b().encode(out);
+        self.c().encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        let a = Mpz::decode(r)?;
+        let b = Mpz::decode(r)?;
+        let c = Mpz::decode(r)?;
+
+        // positive definite and reduced, i.e. -a < b <= a <= c with b >= 0 if
+        // a = c, so that every class has exactly one encoding. Mpz ordering
+        // compares absolute values.
+        if a.sgn() <= 0
+            || c.sgn() <= 0
+            || b > a
+            || b == -a.clone()
+            || a > c
+            || (a == c && b.sgn() < 0)
+        {
+            return Err(WireError::InvalidElement("QFI"));
+        }
+        if let Some(cl) = r.cl {
+            let h = cl.h();
+            let disc = discriminant(&a, &b, &c);
+            if disc != cl.discriminant() && disc != discriminant(&h.a(), &h.b(), &h.c()) {
+                return Err(WireError::InvalidElement("QFI"));
+            }
+        }
+        Ok(QFI::from_mpz(&a, &b, &c))
+    }
+}
+
+impl Wire for CipherText {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.c1().encode(out);
+        self.c2().encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        let c1 = QFI::decode(r)?;
+        let c2 = QFI::decode(r)?;
+        Ok(CipherText::new(&c1, &c2))
+    }
+}
+
+impl Wire for PublicKey {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.elt().encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        let cl = r.cl.ok_or(WireError::MissingGroup("PublicKey"))?;
+        Ok(PublicKey::from_qfi(cl, &QFI::decode(r)?))
+    }
+}
+
+impl Wire for Scalar {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_bytes());
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        let bytes = r.take(32)?.try_into().unwrap();
+        Option::from(Scalar::from_bytes(bytes)).ok_or(WireError::NonCanonical("Scalar"))
+    }
+}
+
+impl Wire for G1Affine {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_compressed());
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        let bytes = r.take(48)?.try_into().unwrap();
+        Option::from(G1Affine::from_compressed(bytes)).ok_or(WireError::InvalidElement("G1"))
+    }
+}
+
+impl Wire for G2Affine {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_compressed());
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        let bytes = r.take(96)?.try_into().unwrap();
+        Option::from(G2Affine::from_compressed(bytes)).ok_or(WireError::InvalidElement("G2"))
+    }
+}
+
+impl Wire for G1Projective {
+    fn encode(&self, out: &mut Vec<u8>) {
+        G1Affine::from(self).encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        G1Affine::decode(r).map(Self::from)
+    }
+}
+
+impl Wire for G2Projective {
+    fn encode(&self, out: &mut Vec<u8>) {
+        G2Affine::from(self).encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Result<Self, WireError> {
+        G2Affine::decode(r).map(Self::from)
+    }
+}
+
+/// Implements [`Wire`] for a struct by encoding the listed fields, which must be
+/// all of them, in order.
+macro_rules! impl_wire {
+    ($($ty:ty { $($field:ident),* $(,)? })*) => {
+        $(
+            impl Wire for $ty {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    $(self.$field.encode(out);)*
+                }
+
+                fn decode(r: &mut Reader) -> Result<Self, WireError> {
+                    Ok(Self {
+                        $($field: Wire::decode(r)?,)*
+                    })
+                }
+            }
+        )*
+    };
+}
+
+/// Implements [`Wire`] for a protocol message enum, one tag byte per round.
+macro_rules! impl_wire_enum {
+    ($($ty:ty { $($tag:literal => $variant:ident),* $(,)? })*) => {
+        $(
+            impl Wire for $ty {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    match self {
+                        $(Self::$variant(msg) => {
+                            out.push($tag);
+                            msg.encode(out);
+                        })*
+                    }
+                }
+
+                fn decode(r: &mut Reader) -> Result<Self, WireError> {
+                    match u8::decode(r)? {
+                        $($tag => Ok(Self::$variant(Wire::decode(r)?)),)*
+                        _ => Err(WireError::InvalidElement("message tag")),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+/// Implements serde for types with a [`Wire`] encoding, as the versioned bytes.
+macro_rules! impl_serde_via_wire {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl Serialize for $ty {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.serialize_bytes(&self.to_wire_bytes())
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $ty {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    deserializer.deserialize_bytes(WireVisitor(PhantomData))
+                }
+            }
+        )*
+    };
+}
+
+struct WireVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Wire> Visitor<'de> for WireVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a wire encoded {}", std::any::type_name::<T>())
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<T, E> {
+        decode_versioned(bytes, None).map_err(E::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(1 << 16));
+        while let Some(b) = seq.next_element()? {
+            bytes.push(b);
+        }
+        self.visit_bytes(&bytes)
+    }
+}
+
+impl_wire! {
+    CLParams { q, k, p, fud_factor, compact_variant }
+
+    CLEncProof { U1, U2, U3, z1, z2 }
+    CLEncSProof { e, z1, z2 }
+    CLRandProof { e, z1, z2 }
+    CLRandYuanProof { com, R, e_1, e_2, K, e_1_, e_2_, K_, alpha }
+    CLELProof { U1, U2, U3, U4, z_1, z_2, z_3 }
+    CLPDProof { U1, U2, z_1 }
+    ELPDProof { e, z }
+    ComZkDlComClproof { com, e, z_1 }
+    ComZkDlComElproof { com, e, z_1 }
+    ComZkDlComEgproof { com, e, z_1 }
+    ComZkDlYuanComClproof { com, e, K, e_, K_ }
+    PedersenOpeningProof { e, z }
+    BlindRequest { hidden, C, proof }
+    MaskedRequest { hidden, B, proof }
+    Generators { H }
+    ElGCiphertext { c1, c2 }
+    t_out_of_n::ElGCiphertext { c1, c2 }
+
+    n_out_of_n::BBSPlusKey { x, X, H }
+    n_out_of_n::BBSPlusPublicKey { X, H }
+    n_out_of_n::BBSPlusSig { A, e, s }
+    n_out_of_n::BBSSig { A, e }
+    n_out_of_n::DkgMsg1 { com }
+    n_out_of_n::DkgMsg2 { cl_pk_i, cl_proof, X_i, x_proof, H_i }
+    n_out_of_n::DkgMsg3 { x_ciphertext, proof }
+    n_out_of_n::setbbsplus::Sign { H, X, e, s, zis, Bis, pdis }
+    n_out_of_n::KeyShare { i, cl_sk_share, cl_pub_key, x_share, x_ciphertext, X, H }
+    n_out_of_n::wmc24::SignerMsg1 { e_ciphertext, s_ciphertext, e_proof, s_proof }
+    n_out_of_n::wmc24::SignerMsg2 { pd_e, pd_s }
+    n_out_of_n::wmc24::SignerMsg3 { ct_pow, eg_ciphertext, proof }
+    n_out_of_n::wmc24::SignerPartial {
+        e, s, gamma_e_x_ciphertext, eg_ciphertext, pd_gamma_e_x, pd_gamma_e_x_proof, pd_eg,
+        pd_eg_proof,
+    }
+
+    t_out_of_n::BBSPlusKey { x, X, H }
+    t_out_of_n::BBSPlusPublicKey { X, H }
+    t_out_of_n::BBSPlusSig { A, e, s }
+    t_out_of_n::BBSSig { A, e }
+    t_out_of_n::DkgMsg1 { com }
+    t_out_of_n::DkgMsg2 { cl_pk_i, cl_proof, A, A_proofs, X_i, x_proof, H_i }
+    t_out_of_n::DkgMsg2P2P { s_ij, beta_prime_ij }
+    t_out_of_n::DkgMsg3 { x_ciphertext, proof }
+    t_out_of_n::SignMsg1 { com }
+    t_out_of_n::SignMsg2 { e_i, s_i, ct1_pow, proof }
+    t_out_of_n::PartialSig { e, s, B_i, z_i, pd_i }
+    t_out_of_n::setbbsplus::Sign { H, X, e, s, zis, Bis, pdis, n_cube }
+    t_out_of_n::KeyShare {
+        i, t, n, cl_sk_share, zero_shares, cl_pub_key, n_factorial, x_share, x_ciphertext, X, H,
+    }
+    t_out_of_n::Refresh { cl_commitments, x_deltas, x_shares }
+    t_out_of_n::Reshare { dealers, cl_commitments, x_masks, pds, pd_proofs, x_offset }
+    t_out_of_n::EncryptedShares { pub_shares, proofs, ciphertexts, ciphertext_proofs }
+    t_out_of_n::wmc24::SignerMsg1 { e_ciphertext, s_ciphertext, e_proof, s_proof }
+    t_out_of_n::wmc24::SignerMsg2 { pd_e, pd_s }
+    t_out_of_n::wmc24::SignerMsg3 { ct_pow, eg_ciphertext, proof }
+    t_out_of_n::wmc24::SignerPartial {
+        e, s, gamma_e_x_ciphertext, eg_ciphertext, pd_gamma_e_x, pd_gamma_e_x_proof, pd_eg,
+        pd_eg_proof,
+    }
+
+    NodeKey { sk }
+    NodeState { key_share, claimed }
+    KeygenRequest { session_id, t, l }
+    CommitRequest { session_id, digest }
+    KeygenOutput { public_key, digest }
+    SignRequest { session_id, signers, msg }
+    Abort { reason, culprits }
+}
+
+impl_wire_enum! {
+    n_out_of_n::DkgMsg { 1 => Round1, 2 => Round2, 3 => Round3 }
+    t_out_of_n::DkgMsg { 1 => Round1, 2 => Round2, 3 => Round2P2P, 4 => Round3 }
+    t_out_of_n::SignMsg { 1 => Round1, 2 => Round2 }
+    n_out_of_n::wmc24::SignerMsg { 1 => Round1, 2 => Round2, 3 => Round3 }
+    t_out_of_n::wmc24::SignerMsg { 1 => Round1, 2 => Round2, 3 => Round3 }
+    Request { 1 => Keygen, 2 => Sign, 3 => Commit }
+    Response { 1 => Keygen, 2 => Sign, 3 => Aborted, 4 => Committed }
+}
+
+impl_serde_via_wire!(
+    CLEncProof,
+    CLEncSProof,
+    CLRandProof,
+    CLRandYuanProof,
+    CLELProof,
+    CLPDProof,
+    ELPDProof,
+    ComZkDlComClproof,
+    ComZkDlComElproof,
+    ComZkDlComEgproof,
+    ComZkDlYuanComClproof,
+    PedersenOpeningProof,
+    BlindRequest,
+    MaskedRequest,
+    Generators,
+    ElGCiphertext,
+    t_out_of_n::ElGCiphertext,
+    n_out_of_n::BBSPlusPublicKey,
+    n_out_of_n::BBSPlusSig,
+    n_out_of_n::BBSSig,
+    n_out_of_n::DkgMsg1,
+    n_out_of_n::DkgMsg2,
+    n_out_of_n::DkgMsg3,
+    n_out_of_n::setbbsplus::Sign,
+    n_out_of_n::wmc24::SignerMsg1,
+    n_out_of_n::wmc24::SignerMsg2,
+    n_out_of_n::wmc24::SignerMsg3,
+    n_out_of_n::wmc24::SignerPartial,
+    t_out_of_n::BBSPlusPublicKey,
+    t_out_of_n::BBSPlusSig,
+    t_out_of_n::BBSSig,
+    t_out_of_n::DkgMsg1,
+    t_out_of_n::DkgMsg2,
+    t_out_of_n::DkgMsg2P2P,
+    t_out_of_n::DkgMsg3,
+    t_out_of_n::SignMsg1,
+    t_out_of_n::SignMsg2,
+    t_out_of_n::PartialSig,
+    t_out_of_n::setbbsplus::Sign,
+    t_out_of_n::wmc24::SignerMsg1,
+    t_out_of_n::wmc24::SignerMsg2,
+    t_out_of_n::wmc24::SignerMsg3,
+    t_out_of_n::wmc24::SignerPartial,
+);
+
+#[cfg(test)]
+mod tests {
+    use curv::{arithmetic::Converter, BigInt};
+    use ff::Field;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    use bicycl::{InvalidCLParams, RandGen};
+
+    use super::*;
+
+    fn setup() -> (CL_HSMqk, RandGen, ChaChaRng) {
+        let mut csprng = ChaChaRng::from_seed([0u8; 32]);
+        let mut rng = RandGen::new();
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut csprng)));
+
+        let cl = CL_HSMqk::with_rand_gen(
+            &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+            1,
+            1827,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        (cl, rng, csprng)
+    }
+
+    #[test]
+    fn test_mpz_round_trip() {
+        let (cl, _, _) = setup();
+        for x in [0i64, 1, -1, 255, 256, -65537, i64::MIN + 1] {
+            let x = Mpz::from(x);
+            assert_eq!(Mpz::from_wire_bytes(&x.to_wire_bytes(), &cl).unwrap(), x);
+        }
+        // negative zero and leading zeros
+        assert_eq!(
+            Mpz::from_wire_bytes(&[WIRE_VERSION, 1, 0, 0, 0, 0], &cl),
+            Err(WireError::NonCanonical("Mpz"))
+        );
+        assert_eq!(
+            Mpz::from_wire_bytes(&[WIRE_VERSION, 0, 0, 0, 0, 2, 0, 1], &cl),
+            Err(WireError::NonCanonical("Mpz"))
+        );
+    }
+
+    #[test]
+    fn test_key_share_and_messages_round_trip() {
+        let (cl, mut rng, mut csprng) = setup();
+        let (n, t, l) = (4, 3, 5);
+        let key_msg =
+            t_out_of_n::setbbsplus::KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut csprng)
+                .unwrap();
+
+        let key_share = key_msg.key_share(2);
+        let bytes = key_share.to_wire_bytes();
+        let decoded = t_out_of_n::KeyShare::from_wire_bytes(&bytes, &cl).unwrap();
+        assert_eq!(decoded.to_wire_bytes(), bytes);
+        assert_eq!(decoded.zero_shares, key_share.zero_shares);
+        assert!(decoded.cl_pub_key.elt() == key_share.cl_pub_key.elt());
+
+        let signers = [1, 2, 4];
+        let (party, msg1) = t_out_of_n::SignParty::new(&cl, b"sign", &key_share, &signers)
+            .round1(&mut rng, &mut csprng);
+        let (_, msg2) = party.round2(&BTreeMap::from([(2, msg1)]));
+        let msg = t_out_of_n::SignMsg::Round2(msg2);
+        let bytes = msg.to_wire_bytes();
+        assert_eq!(
+            t_out_of_n::SignMsg::from_wire_bytes(&bytes, &cl),
+            Ok(msg.clone())
+        );
+
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert_eq!(
+            t_out_of_n::SignMsg::from_wire_bytes(&extended, &cl),
+            Err(WireError::TrailingBytes(1))
+        );
+        assert_eq!(
+            t_out_of_n::SignMsg::from_wire_bytes(&bytes[..bytes.len() - 1], &cl),
+            Err(WireError::Truncated)
+        );
+        let mut versioned = bytes;
+        versioned[0] = WIRE_VERSION + 1;
+        assert_eq!(
+            t_out_of_n::SignMsg::from_wire_bytes(&versioned, &cl),
+            Err(WireError::UnsupportedVersion(WIRE_VERSION + 1))
+        );
+
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(
+            serde_json::from_str::<t_out_of_n::SignMsg>(&json).unwrap(),
+            msg
+        );
+    }
+
+    #[test]
+    fn test_cl_params() {
+        let (cl, mut rng, _) = setup();
+        let q = crate::modulus();
+        let params = CLParams::from_wire_bytes_without_group(&cl.params().to_wire_bytes()).unwrap();
+        let imported = CL_HSMqk::from_params(&params, &q).unwrap();
+        assert_eq!(imported.discriminant(), cl.discriminant());
+        assert!(imported.h() == cl.h());
+        assert_eq!(
+            imported.encrypt_randomness_bound(),
+            cl.encrypt_randomness_bound()
+        );
+
+        let mut bad = params.clone();
+        bad.q = bad.q * Mpz::from(3u64);
+        assert!(CL_HSMqk::from_params(&bad, &q).is_err());
+        let mut bad = params.clone();
+        bad.k = 0;
+        assert!(CL_HSMqk::from_params(&bad, &q).is_err());
+
+        // A valid group, but over another prime.
+        let mut bad = params.clone();
+        bad.q = Mpz::from((1u64 << 61) - 1);
+        assert_eq!(
+            CL_HSMqk::from_params(&bad, &q).err(),
+            Some(InvalidCLParams("q must be the expected prime"))
+        );
+        assert_eq!(
+            CL_HSMqk::from_params(&params, &Mpz::from((1u64 << 61) - 1)).err(),
+            Some(InvalidCLParams("q must be the expected prime"))
+        );
+
+        let small = CL_HSMqk::with_rand_gen(
+            &q,
+            1,
+            1024,
+            &mut rng,
+            &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+            false,
+        );
+        assert_eq!(
+            CL_HSMqk::from_params(&small.params(), &q).err(),
+            Some(InvalidCLParams("the discriminant is too small"))
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_elements() {
+        let (cl, _, mut csprng) = setup();
+
+        let x = Scalar::random(&mut csprng);
+        let proof = ComZkDlComElproof::prove(
+            &Transcript::new(b"test_wire", 1),
+            &mut csprng,
+            &(G2Projective::generator() * x).into(),
+            &x,
+        );
+        let bytes = proof.to_wire_bytes();
+        assert_eq!(ComZkDlComElproof::from_wire_bytes(&bytes, &cl), Ok(proof));
+
+        // the scalar right after the 32 byte commitment
+        let mut bad_scalar = bytes;
+        bad_scalar[1 + 4 + 32..1 + 4 + 64].copy_from_slice(&[0xff; 32]);
+        assert_eq!(
+            ComZkDlComElproof::from_wire_bytes(&bad_scalar, &cl),
+            Err(WireError::NonCanonical("Scalar"))
+        );
+
+        let mut bad_point = vec![WIRE_VERSION];
+        bad_point.extend_from_slice(&[0xff; 48]);
+        assert_eq!(
+            G1Affine::from_wire_bytes(&bad_point, &cl),
+            Err(WireError::InvalidElement("G1"))
+        );
+
+        // a form of another discriminant
+        let h = cl.h();
+        let c = h.c() + Mpz::from(1i64);
+        let bytes = QFI::from_mpz(&h.a(), &h.b(), &c).to_wire_bytes();
+        assert_eq!(
+            QFI::from_wire_bytes(&bytes, &cl),
+            Err(WireError::InvalidElement("QFI"))
+        );
+        assert!(QFI::from_wire_bytes(&h.to_wire_bytes(), &cl).unwrap() == h);
+
+        // (c, -b, a) is equivalent to h and of the same discriminant, but not
+        // reduced
+        let mut bytes = vec![WIRE_VERSION];
+        h.c().encode(&mut bytes);
+        (-h.b()).encode(&mut bytes);
+        h.a().encode(&mut bytes);
+        assert_eq!(
+            QFI::from_wire_bytes(&bytes, &cl),
+            Err(WireError::InvalidElement("QFI"))
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use bicycl::CL_HSMqk;
-use bls12_381::{G1Affine, G1Projective};
+use bls12_381::G1Projective;
 use ff::PrimeField;
 
 // use crate::n_out_of_n::wmc24::ElGCiphertext;
@@ -10,7 +10,10 @@ use super::*;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct CLELProof {
-    pub e: Scalar,
+    pub U1: QFI,
+    pub U2: QFI,
+    pub U3: G1Projective,
+    pub U4: G1Projective,
     pub z_1: Mpz,
     pub z_2: Mpz,
     pub z_3: Mpz,
@@ -19,8 +22,10 @@ pub struct CLELProof {
 impl CLELProof {
     pub fn prove(
         pp: &CL_HSMqk,
+        transcript: &Transcript,
+        bound: &ResponseBound,
         rng: &mut RandGen,
-        chacharng: &mut ChaChaRng,
+        csprng: &mut impl CryptoRngCore,
         cl_pk: &PublicKey,
         eg_pk: &G1Projective,
         pow1: &CipherText,
@@ -31,40 +36,42 @@ impl CLELProof {
         eg_rand: &Scalar,
         cl_rand: &Mpz,
     ) -> Self {
-        let u_1 = Scalar::random(chacharng.clone()); //gamma
-        let u_2 = Scalar::random(chacharng.clone()); //random
-        let u_3 = rng.random_mpz(&pp.encrypt_randomness_bound());
-
-        let U1 = gen1
-            .c1()
-            .exp(&pp, &Mpz::from(&u_1))
-            .compose(&pp, &pp.power_of_h(&u_3));
+        let u_1 = ResponseBound::scalar().sample_mask(rng, SCALAR_BITS); //gamma
+        let u_2 = Scalar::random(&mut *csprng); //random
+        let u_3 = bound.sample_mask(rng, SCALAR_BITS);
+        let u_1_scalar = Scalar::from_str_vartime(&u_1.to_string()).unwrap();
+
+        let U1 = gen1.c1().exp(&pp, &u_1).compose(&pp, &pp.power_of_h(&u_3));
         let U2 = gen1
             .c2()
-            .exp(&pp, &Mpz::from(&u_1))
+            .exp(&pp, &u_1)
             .compose(&pp, &cl_pk.exponentiation(&pp, &u_3));
         let U3 = G1Projective::generator() * &u_2;
-        let U4 = gen2 * &u_1 + eg_pk * &u_2;
+        let U4 = gen2 * &u_1_scalar + eg_pk * &u_2;
 
-        let e = Self::challenge(pow1, pow2, &U1, &U2, &U3.into(), &U4.into());
-        let z_1 = Mpz::from(&u_1) + Mpz::from(&e) * Mpz::from(gammai);
-        let z_2 = Mpz::from(&u_2) + Mpz::from(&e) * Mpz::from(eg_rand);
+        let e = Self::challenge(
+            transcript, cl_pk, eg_pk, pow1, gen1, pow2, gen2, &U1, &U2, &U3, &U4,
+        );
+        let z_1 = u_1 + Mpz::from(&e) * Mpz::from(gammai);
+        let z_2 = scalar_response(&u_2, &e, eg_rand);
         let z_3 = u_3 + Mpz::from(&e) * cl_rand;
 
         Self {
-            e,
+            U1,
+            U2,
+            U3,
+            U4,
             z_1,
             z_2,
             z_3,
-            // z_1s,
-            // z_2s,
         }
     }
 
     pub fn verify(
         &self,
         pp: &CL_HSMqk,
-        _: &mut RandGen,
+        transcript: &Transcript,
+        bound: &ResponseBound,
         cl_pk: &PublicKey,
         eg_pk: &G1Projective,
         pow1: &CipherText,
@@ -72,62 +79,138 @@ impl CLELProof {
         pow2: &ElGCiphertext,
         gen2: &G1Projective,
     ) -> bool {
-        let U1 = gen1
-            .c1()
+        let e = match self.check(transcript, bound, cl_pk, eg_pk, pow1, gen1, pow2, gen2) {
+            Some(e) => Mpz::from(&e),
+            None => return false,
+        };
+
+        gen1.c1()
             .exp(&pp, &self.z_1)
             .compose(&pp, &pp.power_of_h(&self.z_3))
-            .compose(&pp, &pow1.c1().exp(&pp, &-Mpz::from(&self.e)));
-        let U2 = gen1
-            .c2()
-            .exp(&pp, &self.z_1)
-            .compose(&pp, &cl_pk.exponentiation(&pp, &self.z_3))
-            .compose(&pp, &pow1.c2().exp(&pp, &-Mpz::from(&self.e)));
-
-        // let mut z_2_bytes:[u8; 64] = self.z_2.to_bytes().try_into().unwrap();
-        // z_2_bytes.reverse();
-        // let z_2_scalar = Scalar::from_bytes_wide(&z_2_bytes);
-        let z_2_scalar = Scalar::from_str_vartime(&self.z_2.to_string()).unwrap();
+            == self.U1.compose(&pp, &pow1.c1().exp(&pp, &e))
+            && gen1
+                .c2()
+                .exp(&pp, &self.z_1)
+                .compose(&pp, &cl_pk.exponentiation(&pp, &self.z_3))
+                == self.U2.compose(&pp, &pow1.c2().exp(&pp, &e))
+    }
 
-        // let mut z_1_bytes:[u8; 64] = self.z_1.to_bytes().try_into().unwrap();
-        // z_1_bytes.reverse();
-        // let z_1_scalar = Scalar::from_bytes_wide(&z_1_bytes);
-        let z_1_scalar = Scalar::from_str_vartime(&self.z_1.to_string()).unwrap();
+    /// Verifies the items `(transcript, bound, cl_pk, eg_pk, pow1, gen1, pow2,
+    /// gen2, proof)` at once, raising the class group equations of each to
+    /// random coefficients hashed from all of them. On failure, returns the
+    /// indices of the items whose proof does not verify.
+    pub fn batch_verify(
+        pp: &CL_HSMqk,
+        items: &[(
+            Transcript,
+            &ResponseBound,
+            &PublicKey,
+            &G1Projective,
+            &CipherText,
+            &CipherText,
+            &ElGCiphertext,
+            &G1Projective,
+            &CLELProof,
+        )],
+    ) -> Result<(), Vec<usize>> {
+        let one_by_one = || {
+            failed_items(
+                items,
+                |(transcript, bound, cl_pk, eg_pk, pow1, gen1, pow2, gen2, proof)| {
+                    proof.verify(pp, transcript, bound, cl_pk, eg_pk, pow1, gen1, pow2, gen2)
+                },
+            )
+        };
+        let mut coefficients = Transcript::batch(b"CLELProof");
+        let mut es = Vec::with_capacity(items.len());
+        for (transcript, bound, cl_pk, eg_pk, pow1, gen1, pow2, gen2, proof) in items {
+            match proof.check(transcript, bound, cl_pk, eg_pk, pow1, gen1, pow2, gen2) {
+                Some(e) => {
+                    coefficients.append(b"e", &e);
+                    coefficients.append(b"proof", *proof);
+                    es.push(Mpz::from(&e));
+                }
+                None => return one_by_one(),
+            }
+        }
 
-        let U3 = G1Projective::generator() * &z_2_scalar - &pow2.c1 * &self.e;
+        // gen1.c1^z_1 h^z_3 = U1 pow1.c1^e and gen1.c2^z_1 pk^z_3 = U2 pow1.c2^e
+        let mut lhs = Powers::new();
+        let mut rhs = Powers::new();
+        for ((_, _, cl_pk, _, pow1, gen1, _, _, proof), e) in items.iter().zip(&es) {
+            let rho_1 = batch_coefficient(&mut coefficients);
+            let rho_2 = batch_coefficient(&mut coefficients);
+            lhs.push(&gen1.c1(), rho_1.clone() * &proof.z_1);
+            lhs.h(rho_1.clone() * &proof.z_3);
+            rhs.push(&proof.U1, rho_1.clone());
+            rhs.push(&pow1.c1(), rho_1 * e);
+            lhs.push(&gen1.c2(), rho_2.clone() * &proof.z_1);
+            lhs.push(&cl_pk.elt(), rho_2.clone() * &proof.z_3);
+            rhs.push(&proof.U2, rho_2.clone());
+            rhs.push(&pow1.c2(), rho_2 * e);
+        }
+        if lhs.eval(pp) == rhs.eval(pp) {
+            Ok(())
+        } else {
+            one_by_one()
+        }
+    }
 
-        let U4 = gen2 * &z_1_scalar + eg_pk.clone() * &z_2_scalar - &pow2.c2 * &self.e;
+    /// The checks outside of the class group: the response bounds and the
+    /// equations in G1. Returns the challenge if they pass.
+    fn check(
+        &self,
+        transcript: &Transcript,
+        bound: &ResponseBound,
+        cl_pk: &PublicKey,
+        eg_pk: &G1Projective,
+        pow1: &CipherText,
+        gen1: &CipherText,
+        pow2: &ElGCiphertext,
+        gen2: &G1Projective,
+    ) -> Option<Scalar> {
+        if !ResponseBound::scalar().check(&self.z_1, SCALAR_BITS)
+            || !bound.check(&self.z_3, SCALAR_BITS)
+        {
+            return None;
+        }
+        let z_1_scalar = Scalar::from_str_vartime(&self.z_1.to_string())?;
+        let z_2_scalar = Scalar::from_str_vartime(&self.z_2.to_string())?;
+        let e = Self::challenge(
+            transcript, cl_pk, eg_pk, pow1, gen1, pow2, gen2, &self.U1, &self.U2, &self.U3,
+            &self.U4,
+        );
 
-        let e = Self::challenge(pow1, pow2, &U1, &U2, &U3.into(), &U4.into());
-        e == self.e
+        (G1Projective::generator() * &z_2_scalar == self.U3 + &pow2.c1 * &e
+            && gen2 * &z_1_scalar + eg_pk * &z_2_scalar == self.U4 + &pow2.c2 * &e)
+            .then_some(e)
     }
 
     fn challenge(
+        transcript: &Transcript,
+        cl_pk: &PublicKey,
+        eg_pk: &G1Projective,
         pow1: &CipherText,
+        gen1: &CipherText,
         pow2: &ElGCiphertext,
+        gen2: &G1Projective,
         U1: &QFI,
         U2: &QFI,
-        U3: &G1Affine,
-        U4: &G1Affine,
+        U3: &G1Projective,
+        U4: &G1Projective,
     ) -> Scalar {
-        let mut hasher = Sha512::new();
-
-        for item in &[
-            pow1.c1().to_bytes(),
-            pow1.c2().to_bytes(),
-            pow2.c1.to_compressed().into(),
-            pow2.c2.to_compressed().into(),
-            U1.to_bytes(),
-            U2.to_bytes(),
-            U3.to_compressed().into(),
-            U4.to_compressed().into(),
-        ] {
-            hasher.update(item);
-        }
-
-        let tmp = hasher.finalize()[0..64].to_vec();
-        let tmp_2: [u8; 64] = tmp.try_into().unwrap();
-
-        Scalar::from_bytes_wide(&tmp_2)
+        let mut transcript = transcript.proof(b"CLELProof");
+        transcript.append(b"cl_pk", cl_pk);
+        transcript.append(b"eg_pk", eg_pk);
+        transcript.append(b"pow1", pow1);
+        transcript.append(b"gen1", gen1);
+        transcript.append(b"pow2", pow2);
+        transcript.append(b"gen2", gen2);
+        transcript.append(b"U1", U1);
+        transcript.append(b"U2", U2);
+        transcript.append(b"U3", U3);
+        transcript.append(b"U4", U4);
+        transcript.challenge_scalar(b"e")
     }
 }
 
@@ -135,6 +218,7 @@ impl CLELProof {
 mod tests {
     use curv::{arithmetic::Converter, BigInt};
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use crate::MODULUS;
 
@@ -143,10 +227,10 @@ mod tests {
     #[test]
     fn test_clel_nizk() {
         let seed: [u8; 32] = [0u8; 32];
-        let scalr_rng = ChaChaRng::from_seed(seed);
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -159,10 +243,10 @@ mod tests {
         let cl_sk = rng.random_mpz(&cl.encrypt_randomness_bound());
         let cl_pk = PublicKey::from_qfi(&cl, &cl.power_of_h(&cl_sk));
 
-        let eg_sk = Scalar::random(scalr_rng.clone());
+        let eg_sk = Scalar::random(&mut scalr_rng);
         let eg_pk = G1Projective::generator() * eg_sk;
 
-        let m = Scalar::random(scalr_rng.clone());
+        let m = Scalar::random(&mut scalr_rng);
         let cl_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
         let c1 = cl.power_of_h(&cl_rand);
         let c2 = cl
@@ -171,7 +255,7 @@ mod tests {
 
         let ct = CipherText::new(&c1, &c2);
 
-        let gamma = Scalar::random(scalr_rng.clone());
+        let gamma = Scalar::random(&mut scalr_rng);
         let cl_rand1 = rng.random_mpz(&cl.encrypt_randomness_bound());
 
         let ct_pow = CipherText::new(
@@ -183,10 +267,10 @@ mod tests {
                 .compose(&cl, &cl_pk.exponentiation(&cl, &cl_rand1)),
         );
 
-        let k_i = Scalar::random(scalr_rng.clone());
+        let k_i = Scalar::random(&mut scalr_rng);
         let B = G1Projective::generator() * k_i;
 
-        let eg_rand = Scalar::random(scalr_rng.clone());
+        let eg_rand = Scalar::random(&mut scalr_rng);
         let U1 = G1Projective::generator() * &eg_rand;
         let U2 = B * &gamma + eg_pk * &eg_rand;
 
@@ -195,10 +279,14 @@ mod tests {
             c2: U2.into(),
         };
 
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+        let transcript = Transcript::new(b"test_clel_nizk", 1);
         let proof = CLELProof::prove(
             &cl,
+            &transcript,
+            &bound,
             &mut rng,
-            &mut scalr_rng.clone(),
+            &mut scalr_rng,
             &cl_pk,
             &eg_pk,
             &ct_pow,
@@ -214,7 +302,8 @@ mod tests {
             true,
             proof.verify(
                 &cl,
-                &mut rng,
+                &transcript,
+                &bound,
                 &cl_pk,
                 &eg_pk,
                 &ct_pow,
@@ -222,6 +311,51 @@ mod tests {
                 &eg_ciphertext,
                 &B,
             )
-        )
+        );
+
+        let transcript_2 = Transcript::new(b"test_clel_nizk", 2);
+        let proof_2 = CLELProof::prove(
+            &cl,
+            &transcript_2,
+            &bound,
+            &mut rng,
+            &mut scalr_rng,
+            &cl_pk,
+            &eg_pk,
+            &ct_pow,
+            &ct,
+            &eg_ciphertext,
+            &B,
+            &gamma,
+            &eg_rand,
+            &cl_rand1,
+        );
+        let mut items = vec![
+            (
+                transcript.clone(),
+                &bound,
+                &cl_pk,
+                &eg_pk,
+                &ct_pow,
+                &ct,
+                &eg_ciphertext,
+                &B,
+                &proof,
+            ),
+            (
+                transcript_2.clone(),
+                &bound,
+                &cl_pk,
+                &eg_pk,
+                &ct_pow,
+                &ct,
+                &eg_ciphertext,
+                &B,
+                &proof_2,
+            ),
+        ];
+        assert_eq!(Ok(()), CLELProof::batch_verify(&cl, &items));
+        items[1].4 = &ct;
+        assert_eq!(Err(vec![1]), CLELProof::batch_verify(&cl, &items));
     }
 }
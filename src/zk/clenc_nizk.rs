@@ -10,29 +10,41 @@ pub struct CLEncSProof {
 impl CLEncSProof {
     pub fn prove(
         pp: &CL_HSMqk,
+        transcript: &Transcript,
+        bound: &ResponseBound,
         rng: &mut RandGen,
         clpk: &PublicKey,
         clct: &CipherText,
         m: &Scalar,
         cl_rand: &Mpz,
-        chacharng: &mut ChaChaRng,
+        csprng: &mut impl CryptoRngCore,
     ) -> Self {
-        let u1 = rng.random_mpz(&pp.encrypt_randomness_bound());
-        let u2 = Scalar::random(chacharng);
+        let u1 = bound.sample_mask(rng, SCALAR_BITS);
+        let u2 = Scalar::random(&mut *csprng);
 
         let U1 = pp.power_of_h(&u1);
         let U2 = pp
             .power_of_f(&Mpz::from(&u2))
             .compose(&pp, &clpk.exponentiation(&pp, &u1));
 
-        let e = Self::challenge(clpk, clct, &U1, &U2);
+        let e = Self::challenge(transcript, clpk, clct, &U1, &U2);
         let z1 = &u1 + Mpz::from(&e) * cl_rand;
-        let z2 = Mpz::from(&u2) + Mpz::from(&e) * Mpz::from(m);
+        let z2 = scalar_response(&u2, &e, m);
 
         Self { e, z1, z2 }
     }
 
-    pub fn verify(&self, pp: &CL_HSMqk, clpk: &PublicKey, clct: &CipherText) -> bool {
+    pub fn verify(
+        &self,
+        pp: &CL_HSMqk,
+        transcript: &Transcript,
+        bound: &ResponseBound,
+        clpk: &PublicKey,
+        clct: &CipherText,
+    ) -> bool {
+        if !bound.check(&self.z1, SCALAR_BITS) {
+            return false;
+        }
         let U1 = pp
             .power_of_h(&self.z1)
             .compose(&pp, &clct.c1().exp(&pp, &-Mpz::from(&self.e)));
@@ -42,7 +54,7 @@ impl CLEncSProof {
             .compose(&pp, &clpk.exponentiation(&pp, &self.z1))
             .compose(&pp, &clct.c2().exp(&pp, &-Mpz::from(&self.e)));
 
-        let e = Self::challenge(clpk, clct, &U1, &U2);
+        let e = Self::challenge(transcript, clpk, clct, &U1, &U2);
         e == self.e
     }
 
@@ -59,23 +71,19 @@ impl CLEncSProof {
     //     e == self.e
     // }
 
-    fn challenge(clpk: &PublicKey, clct: &CipherText, U1: &QFI, U2: &QFI) -> Scalar {
-        let mut hasher = Sha512::new();
-
-        for item in &[
-            clpk.to_bytes(),
-            clct.c1().to_bytes(),
-            clct.c2().to_bytes(),
-            U1.to_bytes(),
-            U2.to_bytes(),
-        ] {
-            hasher.update(item);
-        }
-
-        let tmp = hasher.finalize()[0..64].to_vec();
-        let tmp_2: [u8; 64] = tmp.try_into().unwrap();
-
-        Scalar::from_bytes_wide(&tmp_2)
+    fn challenge(
+        transcript: &Transcript,
+        clpk: &PublicKey,
+        clct: &CipherText,
+        U1: &QFI,
+        U2: &QFI,
+    ) -> Scalar {
+        let mut transcript = transcript.proof(b"CLEncSProof");
+        transcript.append(b"clpk", clpk);
+        transcript.append(b"clct", clct);
+        transcript.append(b"U1", U1);
+        transcript.append(b"U2", U2);
+        transcript.challenge_scalar(b"e")
     }
     // fn challenge(clpk: &PublicKey, clct: &CipherText, U1: &QFI) -> Scalar {
     //     let mut hasher = Sha512::new();
@@ -101,6 +109,7 @@ mod tests {
     use bls12_381::G2Projective;
     use curv::{arithmetic::Converter, BigInt};
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use crate::MODULUS;
 
@@ -109,10 +118,10 @@ mod tests {
     #[test]
     fn test_clenc_nizk() {
         let seed: [u8; 32] = [0u8; 32];
-        let scalr_rng = ChaChaRng::from_seed(seed);
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -125,7 +134,7 @@ mod tests {
         let sk = rng.random_mpz(&cl.encrypt_randomness_bound());
         let pk = PublicKey::from_qfi(&cl, &cl.power_of_h(&sk));
 
-        let m = Scalar::random(scalr_rng.clone());
+        let m = Scalar::random(&mut scalr_rng);
         let _: G2Projective = G2Projective::generator() * m;
         let cl_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
         let c1 = cl.power_of_h(&cl_rand);
@@ -135,15 +144,33 @@ mod tests {
 
         let ct = CipherText::new(&c1, &c2);
 
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+        let transcript = Transcript::new(b"test_clenc_nizk", 1);
         let proof = CLEncSProof::prove(
             &cl,
+            &transcript,
+            &bound,
             &mut rng,
             &pk,
             &ct,
             &m,
             &cl_rand,
-            &mut scalr_rng.clone(),
+            &mut scalr_rng,
+        );
+        assert_eq!(true, proof.verify(&cl, &transcript, &bound, &pk, &ct));
+        assert_eq!(
+            false,
+            proof.verify(
+                &cl,
+                &Transcript::new(b"test_clenc_nizk", 2),
+                &bound,
+                &pk,
+                &ct
+            )
+        );
+        assert_eq!(
+            false,
+            proof.verify(&cl, &transcript, &ResponseBound::scalar(), &pk, &ct)
         );
-        assert_eq!(true, proof.verify(&cl, &pk, &ct));
     }
 }
@@ -1,11 +1,13 @@
-use bls12_381::{G2Affine, G2Projective};
+use bls12_381::G2Projective;
 use ff::PrimeField;
 
 use super::*;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct CLEncProof {
-    pub e: Scalar,
+    pub U1: QFI,
+    pub U2: QFI,
+    pub U3: G2Projective,
     pub z1: Mpz,
     pub z2: Mpz,
 }
@@ -13,16 +15,18 @@ pub struct CLEncProof {
 impl CLEncProof {
     pub fn prove(
         pp: &CL_HSMqk,
+        transcript: &Transcript,
+        bound: &ResponseBound,
         rng: &mut RandGen,
         clpk: &PublicKey,
         clct: &CipherText,
         pubk: &G2Projective,
         m: &Scalar,
         cl_rand: &Mpz,
-        chacharng: &mut ChaChaRng,
+        csprng: &mut impl CryptoRngCore,
     ) -> Self {
-        let u1 = rng.random_mpz(&pp.encrypt_randomness_bound());
-        let u2 = Scalar::random(chacharng);
+        let u1 = bound.sample_mask(rng, SCALAR_BITS);
+        let u2 = Scalar::random(&mut *csprng);
 
         let U1 = pp.power_of_h(&u1);
         let U2 = pp
@@ -31,37 +35,105 @@ impl CLEncProof {
 
         let U3 = G2Projective::generator() * u2;
 
-        let e = Self::challenge(clpk, clct, &U1, &U2, &pubk.into(), &U3.into());
+        let e = Self::challenge(transcript, clpk, clct, &U1, &U2, pubk, &U3);
         let z1 = &u1 + Mpz::from(&e) * cl_rand;
-        let z2 = Mpz::from(&u2) + Mpz::from(&e) * Mpz::from(m);
+        let z2 = scalar_response(&u2, &e, m);
 
-        Self { e, z1, z2 }
+        Self { U1, U2, U3, z1, z2 }
     }
 
     pub fn verify(
         &self,
         pp: &CL_HSMqk,
+        transcript: &Transcript,
+        bound: &ResponseBound,
         clpk: &PublicKey,
         clct: &CipherText,
         pubk: &G2Projective,
     ) -> bool {
-        let U1 = pp
-            .power_of_h(&self.z1)
-            .compose(&pp, &clct.c1().exp(&pp, &-Mpz::from(&self.e)));
-        let U2 = pp
-            .power_of_f(&self.z2)
-            .compose(&pp, &clpk.exponentiation(&pp, &self.z1))
-            .compose(&pp, &clct.c2().exp(&pp, &-Mpz::from(&self.e)));
+        let e = match self.check(transcript, bound, clpk, clct, pubk) {
+            Some(e) => Mpz::from(&e),
+            None => return false,
+        };
+
+        pp.power_of_h(&self.z1) == self.U1.compose(&pp, &clct.c1().exp(&pp, &e))
+            && pp
+                .power_of_f(&self.z2)
+                .compose(&pp, &clpk.exponentiation(&pp, &self.z1))
+                == self.U2.compose(&pp, &clct.c2().exp(&pp, &e))
+    }
 
-        // let mut z_2_bytes:[u8; 64] = self.z2.to_bytes().try_into().unwrap();
-        // z_2_bytes.reverse();
-        // let z_2_scalar = Scalar::from_bytes_wide(&z_2_bytes);
-        let z_2_scalar = Scalar::from_str_vartime(&self.z2.to_string()).unwrap();
+    /// Verifies the items `(transcript, bound, clpk, clct, pubk, proof)` at once,
+    /// raising the class group equations of each to random coefficients hashed
+    /// from all of them. On failure, returns the indices of the items whose
+    /// proof does not verify.
+    pub fn batch_verify(
+        pp: &CL_HSMqk,
+        items: &[(
+            Transcript,
+            &ResponseBound,
+            &PublicKey,
+            &CipherText,
+            &G2Projective,
+            &CLEncProof,
+        )],
+    ) -> Result<(), Vec<usize>> {
+        let one_by_one = || {
+            failed_items(items, |(transcript, bound, clpk, clct, pubk, proof)| {
+                proof.verify(pp, transcript, bound, clpk, clct, pubk)
+            })
+        };
+        let mut coefficients = Transcript::batch(b"CLEncProof");
+        let mut es = Vec::with_capacity(items.len());
+        for (transcript, bound, clpk, clct, pubk, proof) in items {
+            match proof.check(transcript, bound, clpk, clct, pubk) {
+                Some(e) => {
+                    coefficients.append(b"e", &e);
+                    coefficients.append(b"proof", *proof);
+                    es.push(Mpz::from(&e));
+                }
+                None => return one_by_one(),
+            }
+        }
+
+        // h^z1 = U1 c1^e and f^z2 pk^z1 = U2 c2^e
+        let mut lhs = Powers::new();
+        let mut rhs = Powers::new();
+        for ((_, _, clpk, clct, _, proof), e) in items.iter().zip(&es) {
+            let rho_1 = batch_coefficient(&mut coefficients);
+            let rho_2 = batch_coefficient(&mut coefficients);
+            lhs.h(rho_1.clone() * &proof.z1);
+            rhs.push(&proof.U1, rho_1.clone());
+            rhs.push(&clct.c1(), rho_1 * e);
+            lhs.f(rho_2.clone() * &proof.z2);
+            lhs.push(&clpk.elt(), rho_2.clone() * &proof.z1);
+            rhs.push(&proof.U2, rho_2.clone());
+            rhs.push(&clct.c2(), rho_2 * e);
+        }
+        if lhs.eval(pp) == rhs.eval(pp) {
+            Ok(())
+        } else {
+            one_by_one()
+        }
+    }
 
-        let U3 = G2Projective::generator() * &z_2_scalar - pubk * self.e;
+    /// The checks outside of the class group: the response bound and the
+    /// equation in G2. Returns the challenge if they pass.
+    fn check(
+        &self,
+        transcript: &Transcript,
+        bound: &ResponseBound,
+        clpk: &PublicKey,
+        clct: &CipherText,
+        pubk: &G2Projective,
+    ) -> Option<Scalar> {
+        if !bound.check(&self.z1, SCALAR_BITS) {
+            return None;
+        }
+        let z_2_scalar = Scalar::from_str_vartime(&self.z2.to_string())?;
+        let e = Self::challenge(transcript, clpk, clct, &self.U1, &self.U2, pubk, &self.U3);
 
-        let e = Self::challenge(clpk, clct, &U1, &U2, &pubk.into(), &U3.into());
-        e == self.e
+        (G2Projective::generator() * &z_2_scalar == self.U3 + pubk * e).then_some(e)
     }
 
     // pub fn verify(&self, pp: &CL_HSMqk, clpk: &PublicKey, clct: &CipherText) -> bool {
@@ -78,31 +150,22 @@ impl CLEncProof {
     // }
 
     fn challenge(
+        transcript: &Transcript,
         clpk: &PublicKey,
         clct: &CipherText,
         U1: &QFI,
         U2: &QFI,
-        pubk: &G2Affine,
-        U3: &G2Affine,
+        pubk: &G2Projective,
+        U3: &G2Projective,
     ) -> Scalar {
-        let mut hasher = Sha512::new();
-
-        for item in &[
-            clpk.to_bytes(),
-            clct.c1().to_bytes(),
-            clct.c2().to_bytes(),
-            U1.to_bytes(),
-            U2.to_bytes(),
-            pubk.to_compressed().into(),
-            U3.to_compressed().into(),
-        ] {
-            hasher.update(item);
-        }
-
-        let tmp = hasher.finalize()[0..64].to_vec();
-        let tmp_2: [u8; 64] = tmp.try_into().unwrap();
-
-        Scalar::from_bytes_wide(&tmp_2)
+        let mut transcript = transcript.proof(b"CLEncProof");
+        transcript.append(b"clpk", clpk);
+        transcript.append(b"clct", clct);
+        transcript.append(b"pubk", pubk);
+        transcript.append(b"U1", U1);
+        transcript.append(b"U2", U2);
+        transcript.append(b"U3", U3);
+        transcript.challenge_scalar(b"e")
     }
     // fn challenge(clpk: &PublicKey, clct: &CipherText, U1: &QFI) -> Scalar {
     //     let mut hasher = Sha512::new();
@@ -127,6 +190,7 @@ impl CLEncProof {
 mod tests {
     use curv::{arithmetic::Converter, BigInt};
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use crate::MODULUS;
 
@@ -135,10 +199,10 @@ mod tests {
     #[test]
     fn test_clenc_nizk() {
         let seed: [u8; 32] = [0u8; 32];
-        let scalr_rng = ChaChaRng::from_seed(seed);
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -151,7 +215,7 @@ mod tests {
         let sk = rng.random_mpz(&cl.encrypt_randomness_bound());
         let pk = PublicKey::from_qfi(&cl, &cl.power_of_h(&sk));
 
-        let m = Scalar::random(scalr_rng.clone());
+        let m = Scalar::random(&mut scalr_rng);
         let pubk: G2Projective = G2Projective::generator() * m;
         let cl_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
         let c1 = cl.power_of_h(&cl_rand);
@@ -161,16 +225,63 @@ mod tests {
 
         let ct = CipherText::new(&c1, &c2);
 
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+        let transcript = Transcript::new(b"test_clencdl_nizk", 1);
         let proof = CLEncProof::prove(
             &cl,
+            &transcript,
+            &bound,
             &mut rng,
             &pk,
             &ct,
             &pubk,
             &m,
             &cl_rand,
-            &mut scalr_rng.clone(),
+            &mut scalr_rng,
+        );
+        assert_eq!(
+            true,
+            proof.verify(&cl, &transcript, &bound, &pk, &ct, &pubk)
+        );
+        assert_eq!(
+            false,
+            proof.verify(
+                &cl,
+                &Transcript::new(b"test_clencdl_nizk_2", 1),
+                &bound,
+                &pk,
+                &ct,
+                &pubk
+            )
+        );
+
+        let m_2 = Scalar::random(&mut scalr_rng);
+        let pubk_2: G2Projective = G2Projective::generator() * m_2;
+        let cl_rand_2 = rng.random_mpz(&cl.encrypt_randomness_bound());
+        let ct_2 = CipherText::new(
+            &cl.power_of_h(&cl_rand_2),
+            &cl.power_of_f(&Mpz::from(&m_2))
+                .compose(&cl, &pk.exponentiation(&cl, &cl_rand_2)),
+        );
+        let transcript_2 = Transcript::new(b"test_clencdl_nizk", 2);
+        let proof_2 = CLEncProof::prove(
+            &cl,
+            &transcript_2,
+            &bound,
+            &mut rng,
+            &pk,
+            &ct_2,
+            &pubk_2,
+            &m_2,
+            &cl_rand_2,
+            &mut scalr_rng,
         );
-        assert_eq!(true, proof.verify(&cl, &pk, &ct, &pubk));
+        let mut items = vec![
+            (transcript.clone(), &bound, &pk, &ct, &pubk, &proof),
+            (transcript_2.clone(), &bound, &pk, &ct_2, &pubk_2, &proof_2),
+        ];
+        assert_eq!(Ok(()), CLEncProof::batch_verify(&cl, &items));
+        items[1].3 = &ct;
+        assert_eq!(Err(vec![1]), CLEncProof::batch_verify(&cl, &items));
     }
 }
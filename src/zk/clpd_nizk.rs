@@ -4,51 +4,125 @@ use super::*;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct CLPDProof {
-    pub e: Scalar,
+    pub U1: QFI,
+    pub U2: QFI,
     pub z_1: Mpz,
 }
 
 impl CLPDProof {
-    pub fn prove(pp: &CL_HSMqk, rng: &mut RandGen, _: &QFI, pd: &QFI, c1: &QFI, d_i: &Mpz) -> Self {
-        let u_1 = rng.random_mpz(&pp.encrypt_randomness_bound());
+    pub fn prove(
+        pp: &CL_HSMqk,
+        transcript: &Transcript,
+        bound: &ResponseBound,
+        rng: &mut RandGen,
+        cl_pub_share: &QFI,
+        pd: &QFI,
+        c1: &QFI,
+        d_i: &Mpz,
+    ) -> Self {
+        let u_1 = bound.sample_mask(rng, SCALAR_BITS);
 
         let U1 = c1.exp(&pp, &u_1);
         let U2 = pp.power_of_h(&u_1);
 
-        let e = Self::challenge(pd, c1, &U1, &U2);
+        let e = Self::challenge(transcript, cl_pub_share, pd, c1, &U1, &U2);
         let z_1 = &u_1 + Mpz::from(&e) * d_i;
 
-        Self { e, z_1 }
+        Self { U1, U2, z_1 }
     }
 
-    pub fn verify(&self, pp: &CL_HSMqk, cl_pub_share: &QFI, pd: &QFI, c1: &QFI) -> bool {
-        let U1 = c1
-            .exp(&pp, &self.z_1)
-            .compose(&pp, &pd.exp(&pp, &-Mpz::from(&self.e)));
-        let U2 = pp
-            .power_of_h(&self.z_1)
-            .compose(&pp, &cl_pub_share.exp(&pp, &-Mpz::from(&self.e)));
-
-        let e = Self::challenge(pd, c1, &U1, &U2);
-        e == self.e
+    pub fn verify(
+        &self,
+        pp: &CL_HSMqk,
+        transcript: &Transcript,
+        bound: &ResponseBound,
+        cl_pub_share: &QFI,
+        pd: &QFI,
+        c1: &QFI,
+    ) -> bool {
+        if !bound.check(&self.z_1, SCALAR_BITS) {
+            return false;
+        }
+        let e = Mpz::from(&Self::challenge(
+            transcript,
+            cl_pub_share,
+            pd,
+            c1,
+            &self.U1,
+            &self.U2,
+        ));
+
+        c1.exp(&pp, &self.z_1) == self.U1.compose(&pp, &pd.exp(&pp, &e))
+            && pp.power_of_h(&self.z_1) == self.U2.compose(&pp, &cl_pub_share.exp(&pp, &e))
     }
 
-    fn challenge(pow1: &QFI, gen1: &QFI, U1: &QFI, U2: &QFI) -> Scalar {
-        let mut hasher = Sha512::new();
-
-        for item in &[
-            pow1.to_bytes(),
-            gen1.to_bytes(),
-            U1.to_bytes(),
-            U2.to_bytes(),
-        ] {
-            hasher.update(item);
+    /// Verifies the items `(transcript, bound, cl_pub_share, pd, c1, proof)` at
+    /// once, raising the equations of each to random coefficients hashed from
+    /// all of them. On failure, returns the indices of the items whose proof
+    /// does not verify.
+    pub fn batch_verify(
+        pp: &CL_HSMqk,
+        items: &[(Transcript, &ResponseBound, &QFI, &QFI, &QFI, &CLPDProof)],
+    ) -> Result<(), Vec<usize>> {
+        let one_by_one = || {
+            failed_items(items, |(transcript, bound, cl_pub_share, pd, c1, proof)| {
+                proof.verify(pp, transcript, bound, cl_pub_share, pd, c1)
+            })
+        };
+        if items
+            .iter()
+            .any(|(_, bound, .., proof)| !bound.check(&proof.z_1, SCALAR_BITS))
+        {
+            return one_by_one();
         }
 
-        let tmp = hasher.finalize()[0..64].to_vec();
-        let tmp_2: [u8; 64] = tmp.try_into().unwrap();
+        let mut coefficients = Transcript::batch(b"CLPDProof");
+        let es: Vec<Scalar> = items
+            .iter()
+            .map(|(transcript, _, cl_pub_share, pd, c1, proof)| {
+                let e = Self::challenge(transcript, cl_pub_share, pd, c1, &proof.U1, &proof.U2);
+                coefficients.append(b"e", &e);
+                coefficients.append(b"proof", *proof);
+                e
+            })
+            .collect();
+
+        // c1^z_1 = U1 pd^e and h^z_1 = U2 cl_pub_share^e
+        let mut lhs = Powers::new();
+        let mut rhs = Powers::new();
+        for ((_, _, cl_pub_share, pd, c1, proof), e) in items.iter().zip(&es) {
+            let e = Mpz::from(e);
+            let rho_1 = batch_coefficient(&mut coefficients);
+            let rho_2 = batch_coefficient(&mut coefficients);
+            lhs.push(c1, rho_1.clone() * &proof.z_1);
+            rhs.push(&proof.U1, rho_1.clone());
+            rhs.push(pd, rho_1 * &e);
+            lhs.h(rho_2.clone() * &proof.z_1);
+            rhs.push(&proof.U2, rho_2.clone());
+            rhs.push(cl_pub_share, rho_2 * &e);
+        }
+        if lhs.eval(pp) == rhs.eval(pp) {
+            Ok(())
+        } else {
+            one_by_one()
+        }
+    }
 
-        Scalar::from_bytes_wide(&tmp_2)
+    fn challenge(
+        transcript: &Transcript,
+        cl_pub_share: &QFI,
+        pow1: &QFI,
+        gen1: &QFI,
+        U1: &QFI,
+        U2: &QFI,
+    ) -> Scalar {
+        let mut transcript = transcript.proof(b"CLPDProof");
+        transcript.append(b"cl_pub_share", cl_pub_share);
+        transcript.append(b"pd", pow1);
+        transcript.append(b"c1", gen1);
+        transcript.append(b"U1", U1);
+        transcript.append(b"U2", U2);
+        transcript.challenge_scalar(b"e")
     }
 }
 
@@ -56,6 +130,7 @@ impl CLPDProof {
 mod tests {
     use curv::{arithmetic::Converter, BigInt};
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use crate::MODULUS;
 
@@ -64,10 +139,10 @@ mod tests {
     #[test]
     fn test_clel_nizk() {
         let seed: [u8; 32] = [0u8; 32];
-        let scalr_rng = ChaChaRng::from_seed(seed);
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -85,8 +160,87 @@ mod tests {
 
         let pd = c1.exp(&cl, &cl_sk);
 
-        let proof = CLPDProof::prove(&cl, &mut rng, &cl_pk, &pd, &c1, &cl_sk);
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+        let transcript = Transcript::new(b"test_clpd_nizk", 1);
+        let proof = CLPDProof::prove(&cl, &transcript, &bound, &mut rng, &cl_pk, &pd, &c1, &cl_sk);
 
-        assert_eq!(true, proof.verify(&cl, &cl_pk, &pd, &c1));
+        assert_eq!(
+            true,
+            proof.verify(&cl, &transcript, &bound, &cl_pk, &pd, &c1)
+        );
+        assert_eq!(
+            false,
+            proof.verify(
+                &cl,
+                &Transcript::new(b"test_clpd_nizk", 2),
+                &bound,
+                &cl_pk,
+                &pd,
+                &c1
+            )
+        );
+        assert_eq!(
+            false,
+            proof.verify(&cl, &transcript, &bound.with_stat_bits(0), &cl_pk, &pd, &c1)
+        );
+
+        let cl_sk_2 = rng.random_mpz(&cl.encrypt_randomness_bound());
+        let cl_pk_2 = cl.power_of_h(&cl_sk_2);
+        let pd_2 = c1.exp(&cl, &cl_sk_2);
+        let transcript_2 = Transcript::new(b"test_clpd_nizk", 2);
+        let proof_2 = CLPDProof::prove(
+            &cl,
+            &transcript_2,
+            &bound,
+            &mut rng,
+            &cl_pk_2,
+            &pd_2,
+            &c1,
+            &cl_sk_2,
+        );
+        let mut items = vec![
+            (transcript.clone(), &bound, &cl_pk, &pd, &c1, &proof),
+            (transcript_2.clone(), &bound, &cl_pk_2, &pd_2, &c1, &proof_2),
+        ];
+        assert_eq!(Ok(()), CLPDProof::batch_verify(&cl, &items));
+        items[1].3 = &pd;
+        assert_eq!(Err(vec![1]), CLPDProof::batch_verify(&cl, &items));
+
+        // An element of order 2 is the ambiguous form (a, a, (a + p) / 4) for
+        // the divisor a = q^(2k + 1) of the discriminant -p q^(2k + 1).
+        let params = cl.params();
+        let a = params.q.pow(2 * params.k as u64 + 1);
+        let c = (&a + params.p.clone()) / Mpz::from(4u64);
+        assert_eq!(
+            cl.discriminant(),
+            a.clone() * &a - Mpz::from(4u64) * &a * &c
+        );
+        let t = QFI::from_mpz(&a, &a, &c);
+        let one = cl.power_of_h(&Mpz::from(0u64));
+        assert_ne!(one, t);
+        assert_eq!(one, t.compose(&cl, &t));
+
+        let u_1 = bound.sample_mask(&mut rng, SCALAR_BITS);
+        let U1 = c1.exp(&cl, &u_1).compose(&cl, &t);
+        let U2 = cl.power_of_h(&u_1);
+        let e = CLPDProof::challenge(&transcript_2, &cl_pk_2, &pd_2, &c1, &U1, &U2);
+        let z_1 = &u_1 + Mpz::from(&e) * &cl_sk_2;
+        let tampered = CLPDProof { U1, U2, z_1 };
+        assert_eq!(
+            false,
+            tampered.verify(&cl, &transcript_2, &bound, &cl_pk_2, &pd_2, &c1)
+        );
+        let items = vec![
+            (transcript.clone(), &bound, &cl_pk, &pd, &c1, &proof),
+            (
+                transcript_2.clone(),
+                &bound,
+                &cl_pk_2,
+                &pd_2,
+                &c1,
+                &tampered,
+            ),
+        ];
+        assert_eq!(Err(vec![1]), CLPDProof::batch_verify(&cl, &items));
     }
 }
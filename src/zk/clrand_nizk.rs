@@ -10,34 +10,46 @@ pub struct CLRandProof {
 impl CLRandProof {
     pub fn prove(
         pp: &CL_HSMqk,
+        transcript: &Transcript,
+        bound: &ResponseBound,
         rng: &mut RandGen,
         ct1_pow: &QFI,
         ct1_gen: &QFI,
         m: &Scalar,
         cl_rand: &Mpz,
-        chacharng: &mut ChaChaRng,
+        _: &mut impl CryptoRngCore,
     ) -> Self {
-        let u1 = rng.random_mpz(&pp.encrypt_randomness_bound());
-        let u2 = Scalar::random(chacharng);
+        let u1 = bound.sample_mask(rng, SCALAR_BITS);
+        let u2 = ResponseBound::scalar().sample_mask(rng, SCALAR_BITS);
 
-        let U1 = ct1_gen
-            .exp(&pp, &Mpz::from(&u2))
-            .compose(&pp, &pp.power_of_h(&u1));
+        let U1 = ct1_gen.exp(&pp, &u2).compose(&pp, &pp.power_of_h(&u1));
 
-        let e = Self::challenge(ct1_pow, ct1_gen, &U1);
+        let e = Self::challenge(transcript, ct1_pow, ct1_gen, &U1);
         let z1 = &u1 + Mpz::from(&e) * cl_rand;
-        let z2 = Mpz::from(&u2) + Mpz::from(&e) * Mpz::from(m);
+        let z2 = u2 + Mpz::from(&e) * Mpz::from(m);
 
         Self { e, z1, z2 }
     }
 
-    pub fn verify(&self, pp: &CL_HSMqk, ct1_pow: &QFI, ct1_gen: &QFI) -> bool {
+    pub fn verify(
+        &self,
+        pp: &CL_HSMqk,
+        transcript: &Transcript,
+        bound: &ResponseBound,
+        ct1_pow: &QFI,
+        ct1_gen: &QFI,
+    ) -> bool {
+        if !bound.check(&self.z1, SCALAR_BITS)
+            || !ResponseBound::scalar().check(&self.z2, SCALAR_BITS)
+        {
+            return false;
+        }
         let U1 = ct1_gen
             .exp(&pp, &self.z2)
             .compose(&pp, &pp.power_of_h(&self.z1))
             .compose(&pp, &ct1_pow.exp(&pp, &-Mpz::from(&self.e)));
 
-        let e = Self::challenge(ct1_pow, ct1_gen, &U1);
+        let e = Self::challenge(transcript, ct1_pow, ct1_gen, &U1);
         e == self.e
     }
 
@@ -54,17 +66,12 @@ impl CLRandProof {
     //     e == self.e
     // }
 
-    fn challenge(ct1_pow: &QFI, ct1_gen: &QFI, U1: &QFI) -> Scalar {
-        let mut hasher = Sha512::new();
-
-        for item in &[ct1_pow.to_bytes(), ct1_gen.to_bytes(), U1.to_bytes()] {
-            hasher.update(item);
-        }
-
-        let tmp = hasher.finalize()[0..64].to_vec();
-        let tmp_2: [u8; 64] = tmp.try_into().unwrap();
-
-        Scalar::from_bytes_wide(&tmp_2)
+    fn challenge(transcript: &Transcript, ct1_pow: &QFI, ct1_gen: &QFI, U1: &QFI) -> Scalar {
+        let mut transcript = transcript.proof(b"CLRandProof");
+        transcript.append(b"ct1_pow", ct1_pow);
+        transcript.append(b"ct1_gen", ct1_gen);
+        transcript.append(b"U1", U1);
+        transcript.challenge_scalar(b"e")
     }
     // fn challenge(clpk: &PublicKey, clct: &CipherText, U1: &QFI) -> Scalar {
     //     let mut hasher = Sha512::new();
@@ -90,6 +97,7 @@ mod tests {
     use bls12_381::G2Projective;
     use curv::{arithmetic::Converter, BigInt};
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use crate::MODULUS;
 
@@ -98,10 +106,10 @@ mod tests {
     #[test]
     fn test_clenc_nizk() {
         let seed: [u8; 32] = [0u8; 32];
-        let scalr_rng = ChaChaRng::from_seed(seed);
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
 
         let cl = CL_HSMqk::with_rand_gen(
             &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
@@ -114,7 +122,7 @@ mod tests {
         let sk = rng.random_mpz(&cl.encrypt_randomness_bound());
         let pk = PublicKey::from_qfi(&cl, &cl.power_of_h(&sk));
 
-        let m = Scalar::random(scalr_rng.clone());
+        let m = Scalar::random(&mut scalr_rng);
         let _: G2Projective = G2Projective::generator() * m;
         let cl_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
         let c1 = cl.power_of_h(&cl_rand);
@@ -124,7 +132,7 @@ mod tests {
 
         let ct = CipherText::new(&c1, &c2);
 
-        let alpha = Scalar::random(scalr_rng.clone());
+        let alpha = Scalar::random(&mut scalr_rng);
         let r = rng.random_mpz(&cl.encrypt_randomness_bound());
 
         let ct1_pow = ct
@@ -132,15 +140,26 @@ mod tests {
             .exp(&cl, &Mpz::from(&alpha))
             .compose(&cl, &cl.power_of_h(&r));
 
+        let bound = ResponseBound::new(&cl.encrypt_randomness_bound());
+        let transcript = Transcript::new(b"test_clrand_nizk", 1);
         let proof = CLRandProof::prove(
             &cl,
+            &transcript,
+            &bound,
             &mut rng,
             &ct1_pow,
             &ct.c1(),
             &alpha,
             &r,
-            &mut scalr_rng.clone(),
+            &mut scalr_rng,
+        );
+        assert_eq!(
+            true,
+            proof.verify(&cl, &transcript, &bound, &ct1_pow, &ct.c1())
+        );
+        assert_eq!(
+            false,
+            proof.verify(&cl, &transcript, &bound, &ct1_pow, &ct1_pow)
         );
-        assert_eq!(true, proof.verify(&cl, &ct1_pow, &ct.c1()));
     }
 }
@@ -1,10 +1,4 @@
-use std::{
-    collections::BTreeMap,
-    ops::{Div, Mul},
-};
-
-use cgenprime::cgenprime::genprime;
-use sha2::Sha256;
+use std::ops::{Div, Mul};
 
 use crate::{commit_QFI, LAMBDA};
 
@@ -15,39 +9,61 @@ use super::*;
 #[derive(Clone, Debug, PartialEq)]
 pub struct CLRandYuanProof {
     pub com: Vec<u8>,
+    pub R: QFI,
     pub e_1: Mpz,
     pub e_2: Mpz,
     pub K: QFI,
     pub e_1_: Mpz,
     pub e_2_: Mpz,
     pub K_: QFI,
-    pub map: BTreeMap<Vec<u8>, Mpz>,
     pub alpha: Mpz,
 }
 
 impl CLRandYuanProof {
     pub fn prove(
         pp: &CL_HSMqk,
+        transcript: &Transcript,
         rng: &mut RandGen,
         ct1_pow: &QFI,
         ct1_gen: &QFI,
         m: &Scalar,
         cl_rand: &Mpz,
-        // chacharng: &mut ChaChaRng,
+        // csprng: &mut impl CryptoRngCore,
+        q: &Mpz,
+        B: &Mpz,
+    ) -> Self {
+        Self::prove_mpz(
+            pp,
+            transcript,
+            rng,
+            ct1_pow,
+            ct1_gen,
+            &Mpz::from(m),
+            cl_rand,
+            q,
+            B,
+        )
+    }
+
+    fn prove_mpz(
+        pp: &CL_HSMqk,
+        transcript: &Transcript,
+        rng: &mut RandGen,
+        ct1_pow: &QFI,
+        ct1_gen: &QFI,
+        m_mpz: &Mpz,
+        cl_rand: &Mpz,
         q: &Mpz,
         B: &Mpz,
     ) -> Self {
         let r1 = rng.random_mpz(&B);
         let r2 = rng.random_mpz(&B);
-        let mut map = BTreeMap::new();
-
-        let m_mpz = Mpz::from(m);
 
         let R = ct1_gen.exp(&pp, &r1).compose(&pp, &pp.power_of_h(&r2));
 
-        let alpha = Self::challenge_e(&R);
+        let alpha = Self::challenge_e(transcript, ct1_pow, ct1_gen, &R);
 
-        let beta_1 = &r1 + alpha.clone() * &m_mpz;
+        let beta_1 = &r1 + alpha.clone() * m_mpz;
         let beta_2 = &r2 + alpha.clone() * cl_rand;
 
         let k_1 = beta_1.clone().div(q);
@@ -58,16 +74,7 @@ impl CLRandYuanProof {
 
         let K = ct1_gen.exp(&pp, &k_1).compose(&pp, &pp.power_of_h(&k_2));
 
-        let p_bits: u32 = LAMBDA;
-        let p_min_bits: u32 = LAMBDA - 1;
-        let k: u32 = 1;
-        let tmp_seed: u32 = 3423;
-        let cq_: gmp::mpz::Mpz = genprime(p_bits, p_min_bits, k, tmp_seed);
-        let q_ = Mpz::from(cq_.to_string().as_str());
-        // println!("{:?}", q_.to_string());
-        // let q_ = Mpz::from(q_str);
-
-        map.insert(Self::challenge(ct1_pow, &K, &e_1, &e_2), q_.clone());
+        let q_ = Self::challenge_prime(transcript, ct1_pow, ct1_gen, &K, &e_1, &e_2);
 
         let k_1_ = beta_1.clone().div(&q_);
         let e_1_ = beta_1.clone() - k_1_.clone().mul(&q_);
@@ -79,42 +86,166 @@ impl CLRandYuanProof {
 
         Self {
             com: commit_QFI(ct1_pow),
+            R,
             e_1,
             e_2,
             K,
             e_1_,
             e_2_,
             K_,
-            map,
             alpha,
         }
     }
 
-    pub fn verify(&self, pp: &CL_HSMqk, ct1_pow: &QFI, ct1_gen: &QFI, q: &Mpz) {
-        let R = self
+    pub fn verify(
+        &self,
+        pp: &CL_HSMqk,
+        transcript: &Transcript,
+        ct1_pow: &QFI,
+        ct1_gen: &QFI,
+        q: &Mpz,
+    ) -> Result<(), ProofError> {
+        if Self::challenge_e(transcript, ct1_pow, ct1_gen, &self.R) != self.alpha {
+            return Err(ProofError::Challenge);
+        }
+        let lhs = self.R.compose(&pp, &ct1_pow.exp(&pp, &self.alpha));
+        let rhs = self
             .K
             .exp(&pp, &q)
             .compose(&pp, &ct1_gen.exp(&pp, &self.e_1))
-            .compose(&pp, &pp.power_of_h(&self.e_2))
-            .compose(&pp, &ct1_pow.exp(&pp, &-self.alpha.clone()));
-
-        let alpha_ = Self::challenge_e(&R);
-
-        assert_eq!(alpha_, self.alpha);
-        if let Some(q_) = self
-            .map
-            .get(&Self::challenge(ct1_pow, &self.K, &self.e_1, &self.e_2))
-        {
-            let R_ = self
-                .K_
-                .exp(&pp, &q_)
-                .compose(&pp, &ct1_gen.exp(&pp, &self.e_1_))
-                .compose(&pp, &pp.power_of_h(&self.e_2_))
-                .compose(&pp, &ct1_pow.exp(&pp, &-self.alpha.clone()));
-            assert_eq!(R, R_);
+            .compose(&pp, &pp.power_of_h(&self.e_2));
+        if lhs != rhs {
+            return Err(ProofError::Equation("q"));
+        }
+        let q_ = Self::challenge_prime(transcript, ct1_pow, ct1_gen, &self.K, &self.e_1, &self.e_2);
+        let rhs_ = self
+            .K_
+            .exp(&pp, &q_)
+            .compose(&pp, &ct1_gen.exp(&pp, &self.e_1_))
+            .compose(&pp, &pp.power_of_h(&self.e_2_));
+        if lhs != rhs_ {
+            return Err(ProofError::Equation("q'"));
+        }
+        Ok(())
+    }
+
+    /// Verifies the items `(transcript, ct1_pow, ct1_gen, proof)` at once,
+    /// raising the equations of each to random coefficients hashed from all of
+    /// them. On failure, returns the indices of the items whose proof does not
+    /// verify.
+    pub fn batch_verify(
+        pp: &CL_HSMqk,
+        q: &Mpz,
+        items: &[(Transcript, &QFI, &QFI, &CLRandYuanProof)],
+    ) -> Result<(), Vec<usize>> {
+        let one_by_one = || {
+            failed_items(items, |(transcript, ct1_pow, ct1_gen, proof)| {
+                proof.verify(pp, transcript, ct1_pow, ct1_gen, q).is_ok()
+            })
+        };
+        let mut coefficients = Transcript::batch(b"CLRandYuanProof");
+        let mut q_s = Vec::with_capacity(items.len());
+        for (transcript, ct1_pow, ct1_gen, proof) in items {
+            if Self::challenge_e(transcript, ct1_pow, ct1_gen, &proof.R) != proof.alpha {
+                return one_by_one();
+            }
+            let q_ = Self::challenge_prime(
+                transcript, ct1_pow, ct1_gen, &proof.K, &proof.e_1, &proof.e_2,
+            );
+            coefficients.append(b"q'", &q_);
+            coefficients.append(b"proof", *proof);
+            q_s.push(q_);
+        }
+
+        // R ct1_pow^alpha = K^q ct1_gen^e_1 h^e_2 = K_^q' ct1_gen^e_1_ h^e_2_
+        let mut lhs = Powers::new();
+        let mut rhs = Powers::new();
+        for ((_, ct1_pow, ct1_gen, proof), q_) in items.iter().zip(&q_s) {
+            let rho_1 = batch_coefficient(&mut coefficients);
+            let rho_2 = batch_coefficient(&mut coefficients);
+            let rho = &rho_1 + rho_2.clone();
+            lhs.push(&proof.R, rho.clone());
+            lhs.push(ct1_pow, rho * &proof.alpha);
+            rhs.push(&proof.K, rho_1.clone() * q);
+            rhs.push(
+                ct1_gen,
+                rho_1.clone() * &proof.e_1 + rho_2.clone() * &proof.e_1_,
+            );
+            rhs.h(rho_1 * &proof.e_2 + rho_2.clone() * &proof.e_2_);
+            rhs.push(&proof.K_, rho_2 * q_);
+        }
+        if lhs.eval(pp) == rhs.eval(pp) {
+            Ok(())
         } else {
-            panic!("No q_");
+            one_by_one()
+        }
+    }
+
+    /// Proves the re-randomizations `ct1_pows[j] = ct1_gen^ms[j] h^cl_rands[j]`
+    /// all at once, as the single statement obtained by raising them to random
+    /// 128 bit coefficients derived from the statements and multiplying.
+    pub fn prove_batch(
+        pp: &CL_HSMqk,
+        transcript: &Transcript,
+        rng: &mut RandGen,
+        ct1_pows: &[QFI],
+        ct1_gen: &QFI,
+        ms: &[Scalar],
+        cl_rands: &[Mpz],
+        q: &Mpz,
+        B: &Mpz,
+    ) -> Self {
+        let (ct1_pow, coeffs) = Self::batch_statement(pp, transcript, ct1_pows, ct1_gen);
+        let m: Mpz = coeffs
+            .iter()
+            .zip(ms)
+            .map(|(c, m)| c.clone() * Mpz::from(m))
+            .sum();
+        let cl_rand: Mpz = coeffs
+            .iter()
+            .zip(cl_rands)
+            .map(|(c, r)| c.clone() * r.clone())
+            .sum();
+        Self::prove_mpz(pp, transcript, rng, &ct1_pow, ct1_gen, &m, &cl_rand, q, B)
+    }
+
+    pub fn verify_batch(
+        &self,
+        pp: &CL_HSMqk,
+        transcript: &Transcript,
+        ct1_pows: &[QFI],
+        ct1_gen: &QFI,
+        q: &Mpz,
+    ) -> Result<(), ProofError> {
+        if ct1_pows.is_empty() {
+            return Err(ProofError::EmptyBatch);
         }
+        let (ct1_pow, _) = Self::batch_statement(pp, transcript, ct1_pows, ct1_gen);
+        self.verify(pp, transcript, &ct1_pow, ct1_gen, q)
+    }
+
+    /// The single statement [`CLRandYuanProof::prove_batch`] proves, so that
+    /// batch proofs can be checked together with [`CLRandYuanProof::batch_verify`].
+    pub(crate) fn batch_statement(
+        pp: &CL_HSMqk,
+        transcript: &Transcript,
+        ct1_pows: &[QFI],
+        ct1_gen: &QFI,
+    ) -> (QFI, Vec<Mpz>) {
+        let mut transcript = transcript.proof(b"CLRandYuanProof batch");
+        transcript.append(b"ct1_gen", ct1_gen);
+        transcript.append(b"ct1_pows", &ct1_pows.to_vec());
+        let coeffs: Vec<Mpz> = ct1_pows
+            .iter()
+            .map(|_| transcript.challenge_mpz(b"coefficient", 16))
+            .collect();
+        let ct1_pow = ct1_pows
+            .iter()
+            .zip(&coeffs)
+            .map(|(ct1_pow, c)| ct1_pow.exp(pp, c))
+            .reduce(|acc, ct| acc.compose(pp, &ct))
+            .unwrap_or_else(|| pp.one());
+        (ct1_pow, coeffs)
     }
 
     // pub fn verify(&self, pp: &CL_HSMqk, clpk: &PublicKey, clct: &CipherText) -> bool {
@@ -130,34 +261,31 @@ impl CLRandYuanProof {
     //     e == self.e
     // }
 
-    fn challenge(pow1: &QFI, U1: &QFI, e_1: &Mpz, e_2: &Mpz) -> Vec<u8> {
-        let mut hasher = Sha256::new();
-
-        for item in &[
-            pow1.to_bytes(),
-            U1.to_bytes(),
-            e_1.to_bytes(),
-            e_2.to_bytes(),
-        ] {
-            hasher.update(item);
-        }
-
-        // Mpz::from_bytes(&hasher.finalize()[..16])
-        hasher.finalize()[..16].to_vec()
+    /// The prime `q'` of the second decomposition, hashed from the first one so
+    /// that the prover cannot choose it.
+    fn challenge_prime(
+        transcript: &Transcript,
+        pow1: &QFI,
+        gen1: &QFI,
+        U1: &QFI,
+        e_1: &Mpz,
+        e_2: &Mpz,
+    ) -> Mpz {
+        let mut transcript = transcript.proof(b"CLRandYuanProof prime");
+        transcript.append(b"ct1_pow", pow1);
+        transcript.append(b"ct1_gen", gen1);
+        transcript.append(b"K", U1);
+        transcript.append(b"e_1", e_1);
+        transcript.append(b"e_2", e_2);
+        transcript.challenge_prime(b"q'", LAMBDA as usize / 8)
     }
 
-    fn challenge_e(R: &QFI) -> Mpz {
-        let mut hasher = Sha512::new();
-
-        for item in &[R.to_bytes()] {
-            hasher.update(item);
-        }
-
-        // let tmp = hasher.finalize()[0..16].to_vec();
-        // let tmp_2: [u8; 64] = tmp.try_into().unwrap();
-
-        // Scalar::from_bytes_wide(&tmp_2)
-        Mpz::from_bytes(&hasher.finalize()[0..16])
+    fn challenge_e(transcript: &Transcript, pow1: &QFI, gen1: &QFI, R: &QFI) -> Mpz {
+        let mut transcript = transcript.proof(b"CLRandYuanProof");
+        transcript.append(b"ct1_pow", pow1);
+        transcript.append(b"ct1_gen", gen1);
+        transcript.append(b"R", R);
+        transcript.challenge_mpz(b"alpha", 16)
     }
     // fn challenge(clpk: &PublicKey, clct: &CipherText, U1: &QFI) -> Scalar {
     //     let mut hasher = Sha512::new();
@@ -183,6 +311,7 @@ mod tests {
     use bls12_381::G2Projective;
     use curv::{arithmetic::Converter, BigInt};
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use crate::MODULUS;
 
@@ -191,10 +320,10 @@ mod tests {
     #[test]
     fn test_clenc_nizk() {
         let seed: [u8; 32] = [0u8; 32];
-        let scalr_rng = ChaChaRng::from_seed(seed);
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
 
         let mut rng = RandGen::new();
-        rng.set_seed(&Mpz::from(&Scalar::random(scalr_rng.clone())));
+        rng.set_seed(&Mpz::from(&Scalar::random(&mut scalr_rng)));
         let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
         let cl = CL_HSMqk::with_rand_gen(
             &q,
@@ -207,7 +336,7 @@ mod tests {
         let sk = rng.random_mpz(&cl.encrypt_randomness_bound());
         let pk = PublicKey::from_qfi(&cl, &cl.power_of_h(&sk));
 
-        let m = Scalar::random(scalr_rng.clone());
+        let m = Scalar::random(&mut scalr_rng);
         let _: G2Projective = G2Projective::generator() * m;
         let cl_rand = rng.random_mpz(&cl.encrypt_randomness_bound());
         let c1 = cl.power_of_h(&cl_rand);
@@ -217,7 +346,7 @@ mod tests {
 
         let ct = CipherText::new(&c1, &c2);
 
-        let alpha = Scalar::random(scalr_rng.clone());
+        let alpha = Scalar::random(&mut scalr_rng);
         let r = rng.random_mpz(&cl.encrypt_randomness_bound());
 
         let ct1_pow = ct
@@ -227,7 +356,92 @@ mod tests {
 
         let B = cl.encrypt_randomness_bound();
 
-        let proof = CLRandYuanProof::prove(&cl, &mut rng, &ct1_pow, &ct.c1(), &alpha, &r, &q, &B);
-        proof.verify(&cl, &ct1_pow, &ct.c1(), &q);
+        let transcript = Transcript::new(b"test_clrandyuan_nizk", 1);
+        let proof = CLRandYuanProof::prove(
+            &cl,
+            &transcript,
+            &mut rng,
+            &ct1_pow,
+            &ct.c1(),
+            &alpha,
+            &r,
+            &q,
+            &B,
+        );
+        assert_eq!(
+            Ok(()),
+            proof.verify(&cl, &transcript, &ct1_pow, &ct.c1(), &q)
+        );
+        assert_eq!(
+            Err(ProofError::Challenge),
+            proof.verify(
+                &cl,
+                &Transcript::new(b"test_clrandyuan_nizk", 2),
+                &ct1_pow,
+                &ct.c1(),
+                &q
+            )
+        );
+
+        let r_2 = rng.random_mpz(&B);
+        let ct1_pow_2 = ct
+            .c1()
+            .exp(&cl, &Mpz::from(&m))
+            .compose(&cl, &cl.power_of_h(&r_2));
+        let transcript_2 = Transcript::new(b"test_clrandyuan_nizk", 2);
+        let proof_2 = CLRandYuanProof::prove(
+            &cl,
+            &transcript_2,
+            &mut rng,
+            &ct1_pow_2,
+            &ct.c1(),
+            &m,
+            &r_2,
+            &q,
+            &B,
+        );
+        let c1 = ct.c1();
+        let mut items = vec![
+            (transcript.clone(), &ct1_pow, &c1, &proof),
+            (transcript_2.clone(), &ct1_pow_2, &c1, &proof_2),
+        ];
+        assert_eq!(Ok(()), CLRandYuanProof::batch_verify(&cl, &q, &items));
+        items[0].1 = &ct1_pow_2;
+        assert_eq!(Err(vec![0]), CLRandYuanProof::batch_verify(&cl, &q, &items));
+
+        let mut csprng = scalr_rng;
+        let gammas: Vec<Scalar> = (0..3).map(|_| Scalar::random(&mut csprng)).collect();
+        let rs: Vec<Mpz> = (0..3).map(|_| rng.random_mpz(&B)).collect();
+        let mut ct1_pows: Vec<QFI> = gammas
+            .iter()
+            .zip(&rs)
+            .map(|(gamma, r)| {
+                ct.c1()
+                    .exp(&cl, &Mpz::from(gamma))
+                    .compose(&cl, &cl.power_of_h(r))
+            })
+            .collect();
+        let proof = CLRandYuanProof::prove_batch(
+            &cl,
+            &transcript,
+            &mut rng,
+            &ct1_pows,
+            &ct.c1(),
+            &gammas,
+            &rs,
+            &q,
+            &B,
+        );
+        assert!(proof
+            .verify_batch(&cl, &transcript, &ct1_pows, &ct.c1(), &q)
+            .is_ok());
+        ct1_pows.swap(0, 1);
+        assert!(proof
+            .verify_batch(&cl, &transcript, &ct1_pows, &ct.c1(), &q)
+            .is_err());
+        assert_eq!(
+            Err(ProofError::EmptyBatch),
+            proof.verify_batch(&cl, &transcript, &[], &ct.c1(), &q)
+        );
     }
 }
@@ -1,8 +1,10 @@
 use bicycl::{CL_HSMqk, CipherText, Mpz, PublicKey, RandGen, QFI};
 use bls12_381::Scalar;
 use ff::Field;
-use rand_chacha::ChaChaRng;
-use sha2::{Digest, Sha512};
+use rand_core::CryptoRngCore;
+use thiserror::Error;
+
+use crate::Transcript;
 
 pub mod clencdl_nizk;
 pub use clencdl_nizk::*;
@@ -24,3 +26,144 @@ pub use pdel_nizk::*;
 
 pub mod clrandyuan_nizk;
 pub use clrandyuan_nizk::*;
+
+pub mod pedersen_nizk;
+pub use pedersen_nizk::*;
+
+/// Why the verification of a proof failed.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum ProofError {
+    #[error("the challenge does not match the transcript")]
+    Challenge,
+    #[error("the verification equation modulo {0} does not hold")]
+    Equation(&'static str),
+    #[error("empty batch")]
+    EmptyBatch,
+}
+
+/// Default statistical security parameter of [`ResponseBound`].
+pub const STAT_SECURITY: usize = 80;
+
+/// Bit length of the scalar challenges, and of the scalar witnesses.
+pub const SCALAR_BITS: usize = 255;
+
+/// Range of the masks and responses for an integer witness of a proof in the
+/// class group, whose order is unknown so responses cannot be reduced. Masks are
+/// drawn below `2^(witness_bits + challenge_bits + stat_bits)`, which hides
+/// `e * witness` up to a statistical distance of `2^-stat_bits`, and verifiers
+/// reject responses of more than one extra bit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResponseBound {
+    pub witness_bits: usize,
+    pub stat_bits: usize,
+}
+
+impl ResponseBound {
+    /// For witnesses whose absolute value is below `witness_bound`.
+    pub fn new(witness_bound: &Mpz) -> Self {
+        Self {
+            witness_bits: witness_bound.nbits(),
+            stat_bits: STAT_SECURITY,
+        }
+    }
+
+    /// For witnesses in the BLS12-381 scalar field.
+    pub fn scalar() -> Self {
+        Self {
+            witness_bits: SCALAR_BITS,
+            stat_bits: STAT_SECURITY,
+        }
+    }
+
+    pub fn with_stat_bits(self, stat_bits: usize) -> Self {
+        Self { stat_bits, ..self }
+    }
+
+    fn mask_bits(&self, challenge_bits: usize) -> usize {
+        self.witness_bits + challenge_bits + self.stat_bits
+    }
+
+    pub fn sample_mask(&self, rng: &mut RandGen, challenge_bits: usize) -> Mpz {
+        rng.random_mpz(&Mpz::from(2u64).pow(self.mask_bits(challenge_bits) as u64))
+    }
+
+    /// Whether `z` is in the range of honest responses.
+    pub fn check(&self, z: &Mpz, challenge_bits: usize) -> bool {
+        z.nbits() <= self.mask_bits(challenge_bits) + 1
+    }
+}
+
+/// The response `u + e * w` computed in the scalar field, for witnesses only
+/// used in groups of order `q`. It is uniform, so it hides `w` perfectly.
+pub fn scalar_response(u: &Scalar, e: &Scalar, w: &Scalar) -> Mpz {
+    Mpz::from(&(u + e * w))
+}
+
+/// Byte length of the coefficients of a batch verification.
+pub const BATCH_COEFFICIENT_BYTES: usize = 16;
+
+/// A coefficient of a batch verification. It is odd: the class group has
+/// elements of order 2, which an even coefficient would cancel, so an item off
+/// by one of them would pass the batch but not its own verification.
+pub(crate) fn batch_coefficient(coefficients: &mut Transcript) -> Mpz {
+    let mut bytes = coefficients.challenge_bytes(b"rho")[..BATCH_COEFFICIENT_BYTES].to_vec();
+    bytes[BATCH_COEFFICIENT_BYTES - 1] |= 1;
+    Mpz::from_bytes(&bytes)
+}
+
+/// A product of powers in the class group, computed with one exponentiation
+/// per distinct base. Batch verifications accumulate both sides of their
+/// combined equation in one.
+pub(crate) struct Powers {
+    h: Mpz,
+    f: Mpz,
+    terms: Vec<(QFI, Mpz)>,
+}
+
+impl Powers {
+    pub fn new() -> Self {
+        Self {
+            h: Mpz::from(0u64),
+            f: Mpz::from(0u64),
+            terms: Vec::new(),
+        }
+    }
+
+    pub fn h(&mut self, exp: Mpz) {
+        self.h = &self.h + exp;
+    }
+
+    pub fn f(&mut self, exp: Mpz) {
+        self.f = &self.f + exp;
+    }
+
+    pub fn push(&mut self, base: &QFI, exp: Mpz) {
+        match self.terms.iter_mut().find(|(b, _)| b == base) {
+            Some((_, e)) => *e = &*e + exp,
+            None => self.terms.push((base.clone(), exp)),
+        }
+    }
+
+    pub fn eval(&self, pp: &CL_HSMqk) -> QFI {
+        self.terms.iter().fold(
+            pp.power_of_h(&self.h).compose(pp, &pp.power_of_f(&self.f)),
+            |acc, (base, exp)| acc.compose(pp, &base.exp(pp, exp)),
+        )
+    }
+}
+
+/// Fallback of a failed batch verification: the indices of the items that do
+/// not verify on their own.
+pub(crate) fn failed_items<T>(items: &[T], verify: impl Fn(&T) -> bool) -> Result<(), Vec<usize>> {
+    let failed: Vec<usize> = items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| !verify(item))
+        .map(|(k, _)| k)
+        .collect();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(failed)
+    }
+}
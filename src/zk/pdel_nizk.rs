@@ -1,4 +1,4 @@
-use bls12_381::{G1Affine, G1Projective};
+use bls12_381::G1Projective;
 
 use super::*;
 
@@ -10,47 +10,59 @@ pub struct ELPDProof {
 
 impl ELPDProof {
     pub fn prove(
-        chacharng: &mut ChaChaRng,
+        transcript: &Transcript,
+        csprng: &mut impl CryptoRngCore,
         pow: &G1Projective,
         gen: &G1Projective,
-        _: &G1Projective,
+        pubkey: &G1Projective,
         xi: &Scalar,
     ) -> Self {
-        let u = Scalar::random(chacharng.clone());
+        let u = Scalar::random(&mut *csprng);
         let U1 = gen * &u;
         let U2 = G1Projective::generator() * &u;
 
-        let e = Self::challenge(&pow.into(), &U1.into(), &U2.into());
+        let e = Self::challenge(transcript, pow, gen, pubkey, &U1, &U2);
         let z = &u + &e * xi;
 
         Self { e, z }
     }
 
-    pub fn verify(&self, pow: &G1Projective, gen: &G1Projective, pubkey: &G1Projective) -> bool {
+    pub fn verify(
+        &self,
+        transcript: &Transcript,
+        pow: &G1Projective,
+        gen: &G1Projective,
+        pubkey: &G1Projective,
+    ) -> bool {
         let U1 = gen * &self.z - pow * &self.e;
         let U2 = G1Projective::generator() * &self.z - pubkey * &self.e;
 
-        let e = Self::challenge(&pow.into(), &U1.into(), &U2.into());
+        let e = Self::challenge(transcript, pow, gen, pubkey, &U1, &U2);
         e == self.e
     }
 
-    fn challenge(pow: &G1Affine, U1: &G1Affine, U2: &G1Affine) -> Scalar {
-        let mut hasher = Sha512::new();
-
-        for item in &[pow.to_compressed(), U1.to_compressed(), U2.to_compressed()] {
-            hasher.update(item);
-        }
-
-        let tmp = hasher.finalize()[0..64].to_vec();
-        let tmp_2: [u8; 64] = tmp.try_into().unwrap();
-
-        Scalar::from_bytes_wide(&tmp_2)
+    fn challenge(
+        transcript: &Transcript,
+        pow: &G1Projective,
+        gen: &G1Projective,
+        pubkey: &G1Projective,
+        U1: &G1Projective,
+        U2: &G1Projective,
+    ) -> Scalar {
+        let mut transcript = transcript.proof(b"ELPDProof");
+        transcript.append(b"pd", pow);
+        transcript.append(b"c1", gen);
+        transcript.append(b"pubkey", pubkey);
+        transcript.append(b"U1", U1);
+        transcript.append(b"U2", U2);
+        transcript.challenge_scalar(b"e")
     }
 }
 
 #[cfg(test)]
 mod tests {
     use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
 
     use super::*;
 
@@ -59,16 +71,25 @@ mod tests {
         let seed: [u8; 32] = [0u8; 32];
         let mut scalr_rng = ChaChaRng::from_seed(seed);
 
-        let eg_rand = Scalar::random(scalr_rng.clone());
+        let eg_rand = Scalar::random(&mut scalr_rng);
         let U1 = G1Projective::generator() * &eg_rand;
 
-        let eg_sk = Scalar::random(scalr_rng.clone());
+        let eg_sk = Scalar::random(&mut scalr_rng);
         let eg_pk = G1Projective::generator() * eg_sk;
 
         let pd = U1 * eg_sk;
 
-        let proof = ELPDProof::prove(&mut scalr_rng, &pd, &U1, &eg_pk, &eg_sk);
+        let transcript = Transcript::new(b"test_pd_nizk", 1);
+        let proof = ELPDProof::prove(&transcript, &mut scalr_rng, &pd, &U1, &eg_pk, &eg_sk);
+
+        assert_eq!(true, proof.verify(&transcript, &pd, &U1, &eg_pk));
+        assert_eq!(
+            false,
+            proof.verify(&Transcript::new(b"test_pd_nizk", 2), &pd, &U1, &eg_pk)
+        );
 
-        assert_eq!(true, proof.verify(&pd, &U1, &eg_pk));
+        // A second proof of the same statement draws a fresh nonce.
+        let proof_2 = ELPDProof::prove(&transcript, &mut scalr_rng, &pd, &U1, &eg_pk, &eg_sk);
+        assert_ne!(proof, proof_2);
     }
 }
@@ -0,0 +1,106 @@
+use bls12_381::G1Projective;
+
+use super::*;
+
+/// Proof of knowledge of an opening `w` of the Pedersen commitment
+/// `C = sum_k bases[k] * w[k]` in G1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PedersenOpeningProof {
+    pub e: Scalar,
+    pub z: Vec<Scalar>,
+}
+
+impl PedersenOpeningProof {
+    pub fn prove(
+        transcript: &Transcript,
+        csprng: &mut impl CryptoRngCore,
+        bases: &[G1Projective],
+        C: &G1Projective,
+        w: &[Scalar],
+    ) -> Self {
+        let u: Vec<Scalar> = bases.iter().map(|_| Scalar::random(&mut *csprng)).collect();
+        let U = combine(bases, &u);
+
+        let e = Self::challenge(transcript, bases, C, &U);
+        let z = u.iter().zip(w).map(|(u_k, w_k)| u_k + &e * w_k).collect();
+
+        Self { e, z }
+    }
+
+    pub fn verify(
+        &self,
+        transcript: &Transcript,
+        bases: &[G1Projective],
+        C: &G1Projective,
+    ) -> bool {
+        if self.z.len() != bases.len() {
+            return false;
+        }
+        let U = combine(bases, &self.z) - C * &self.e;
+
+        let e = Self::challenge(transcript, bases, C, &U);
+        e == self.e
+    }
+
+    fn challenge(
+        transcript: &Transcript,
+        bases: &[G1Projective],
+        C: &G1Projective,
+        U: &G1Projective,
+    ) -> Scalar {
+        let mut transcript = transcript.proof(b"PedersenOpeningProof");
+        for base in bases {
+            transcript.append(b"base", base);
+        }
+        transcript.append(b"C", C);
+        transcript.append(b"U", U);
+        transcript.challenge_scalar(b"e")
+    }
+}
+
+fn combine(bases: &[G1Projective], scalars: &[Scalar]) -> G1Projective {
+    bases
+        .iter()
+        .zip(scalars)
+        .fold(G1Projective::identity(), |acc, (base, scalar)| {
+            acc + base * scalar
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    use super::*;
+
+    #[test]
+    fn test_pedersen_opening_nizk() {
+        let seed: [u8; 32] = [0u8; 32];
+        let mut scalr_rng = ChaChaRng::from_seed(seed);
+
+        let bases: Vec<G1Projective> = (0..3)
+            .map(|_| G1Projective::generator() * Scalar::random(&mut scalr_rng))
+            .collect();
+        let w: Vec<Scalar> = (0..3).map(|_| Scalar::random(&mut scalr_rng)).collect();
+        let C = combine(&bases, &w);
+
+        let transcript = Transcript::new(b"test_pedersen_opening_nizk", 0);
+        let proof = PedersenOpeningProof::prove(&transcript, &mut scalr_rng, &bases, &C, &w);
+
+        assert_eq!(true, proof.verify(&transcript, &bases, &C));
+        assert_eq!(
+            false,
+            proof.verify(
+                &Transcript::new(b"test_pedersen_opening_nizk", 1),
+                &bases,
+                &C
+            )
+        );
+        assert_eq!(
+            false,
+            proof.verify(&transcript, &bases, &(C + G1Projective::generator()))
+        );
+        assert_eq!(false, proof.verify(&transcript, &bases[..2], &C));
+    }
+}
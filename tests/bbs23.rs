@@ -0,0 +1,123 @@
+//! Checks that the threshold BBS signatures of the `setbbs` modules verify
+//! under the vendored `bbs_plus::signature_23::Signature23G1::verify`. Points
+//! and scalars are moved between the two libraries by their serialization,
+//! which is the same for both.
+
+use ark_bls12_381::{Bls12_381, Fr, G1Affine, G2Affine};
+use ark_serialize::CanonicalDeserialize;
+use bbs_plus::setup::{PublicKeyG2, SignatureParams23G1};
+use bbs_plus::signature_23::Signature23G1;
+use bicycl::{CL_HSMqk, Mpz, RandGen};
+use bls12_381::{G1Projective, G2Projective, Scalar};
+use curv::{arithmetic::Converter, BigInt};
+use ff::Field;
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+
+use threshold_bbsp::n_out_of_n::{setbbs as n_setbbs, setbbsplus as n_setbbsplus};
+use threshold_bbsp::t_out_of_n::{setbbs as t_setbbs, setbbsplus as t_setbbsplus};
+use threshold_bbsp::{rand_gen, MODULUS};
+
+fn setup() -> (CL_HSMqk, Mpz, RandGen, ChaChaRng) {
+    let mut csprng = ChaChaRng::from_seed([0u8; 32]);
+    let mut rng = rand_gen(&mut csprng);
+    let q = Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes());
+    let cl = CL_HSMqk::with_rand_gen(
+        &q,
+        1,
+        1827,
+        &mut rng,
+        &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+        false,
+    );
+    (cl, q, rng, csprng)
+}
+
+fn g1(point: &G1Projective) -> G1Affine {
+    let bytes = bls12_381::G1Affine::from(point).to_compressed();
+    G1Affine::deserialize_compressed(&bytes[..]).unwrap()
+}
+
+fn g2(point: &G2Projective) -> G2Affine {
+    let bytes = bls12_381::G2Affine::from(point).to_compressed();
+    G2Affine::deserialize_compressed(&bytes[..]).unwrap()
+}
+
+fn fr(scalar: &Scalar) -> Fr {
+    Fr::deserialize_compressed(&scalar.to_bytes()[..]).unwrap()
+}
+
+/// Verifies `(A, e)` on `msg` with `Signature23G1::verify`, for the params
+/// `g1`, `g2` the generators and `h` the first `msg.len()` of `H`.
+fn verify23(
+    X: &G2Projective,
+    H: &[G1Projective],
+    msg: &[Scalar],
+    A: &G1Projective,
+    e: &Scalar,
+) -> bool {
+    let params = SignatureParams23G1::<Bls12_381> {
+        g1: g1(&G1Projective::generator()),
+        g2: g2(&G2Projective::generator()),
+        h: H[..msg.len()].iter().map(g1).collect(),
+    };
+    let pk = PublicKeyG2::<Bls12_381>(g2(X));
+    let sig = Signature23G1::<Bls12_381> { A: g1(A), e: fr(e) };
+    let msg: Vec<Fr> = msg.iter().map(fr).collect();
+    sig.verify(&msg, pk, params).is_ok()
+}
+
+#[test]
+fn n_out_of_n_signature23() {
+    let (cl, q, mut rng, mut csprng) = setup();
+    let (n, l) = (3, 4);
+    let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut csprng)).collect();
+    let key_msg =
+        n_setbbsplus::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut csprng).unwrap();
+    let sign_msg = n_setbbs::Sign::sign(
+        &cl,
+        b"sign",
+        n,
+        l,
+        &mut rng,
+        &mut csprng,
+        &key_msg,
+        &msg,
+        &q,
+    )
+    .unwrap();
+    let sig = n_setbbs::Sign::client(&cl, &sign_msg, &msg, l).unwrap();
+
+    let (X, H) = (&key_msg.sign_keys.pub_key, &key_msg.sign_keys.H);
+    assert!(verify23(X, H, &msg, &sig.A, &sig.e));
+    assert!(!verify23(X, H, &msg, &sig.A, &(sig.e + Scalar::one())));
+}
+
+#[test]
+fn t_out_of_n_signature23() {
+    let (cl, q, mut rng, mut csprng) = setup();
+    let (n, t, l) = (4, 2, 4);
+    let signers = [1, 3];
+    let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut csprng)).collect();
+    let key_msg =
+        t_setbbsplus::KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut csprng).unwrap();
+    let sign_msg = t_setbbs::Sign::sign(
+        &cl,
+        b"sign",
+        &signers,
+        l,
+        &mut rng,
+        &mut csprng,
+        &key_msg,
+        &msg,
+        &q,
+    )
+    .unwrap();
+    let sig = t_setbbs::Sign::client(&cl, &sign_msg, &msg, l).unwrap();
+
+    let (X, H) = (&key_msg.sign_keys.pub_key, &key_msg.sign_keys.H);
+    assert!(verify23(X, H, &msg, &sig.A, &sig.e));
+    let mut other = msg.clone();
+    other[0] += Scalar::one();
+    assert!(!verify23(X, H, &other, &sig.A, &sig.e));
+}
@@ -0,0 +1,148 @@
+//! Runs signer nodes as separate processes on loopback, and drives a DKG and
+//! signing sessions among them from a coordinator.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::net::{SocketAddr, TcpListener};
+use std::process::{Child, Command};
+
+use bicycl::{CL_HSMqk, Mpz};
+use bls12_381::{G1Affine, G1Projective, Scalar};
+use curv::{arithmetic::Converter, BigInt};
+use ff::Field;
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+
+use threshold_bbsp::t_out_of_n::BBSPlusSig;
+use threshold_bbsp::{
+    load_key_share, modulus, rand_gen, save_key_share, Coordinator, NodeError, NodeKey, NodeState,
+    Peer, Wire, MODULUS,
+};
+
+const PASSPHRASE: &str = "correct horse";
+
+/// Kills the nodes when the test ends, however it ends.
+struct Nodes(Vec<Child>);
+
+impl Drop for Nodes {
+    fn drop(&mut self) {
+        for node in &mut self.0 {
+            let _ = node.kill();
+            let _ = node.wait();
+        }
+    }
+}
+
+fn hex(point: &G1Projective) -> String {
+    G1Affine::from(point)
+        .to_compressed()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn free_addr() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_nodes() {
+    let n = 3;
+    let t = 2;
+    let l = 4;
+    let dir = std::env::temp_dir().join(format!("threshold_bbsp_nodes_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut csprng = ChaChaRng::from_seed([0u8; 32]);
+    let mut rng = rand_gen(&mut csprng);
+    let cl = CL_HSMqk::with_rand_gen(
+        &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+        1,
+        1827,
+        &mut rng,
+        &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+        false,
+    );
+    let cl_params = cl.params();
+    let params = dir.join("cl_params");
+    fs::write(&params, cl_params.to_wire_bytes()).unwrap();
+
+    let coordinator_key = NodeKey::generate(&mut csprng);
+    let mut peers = BTreeMap::new();
+    let mut peers_file = String::new();
+    for i in 1..=n {
+        let key = NodeKey::generate(&mut csprng);
+        save_key_share(
+            dir.join(format!("identity_{i}")),
+            &cl,
+            &key,
+            PASSPHRASE.as_bytes(),
+        )
+        .unwrap();
+        let peer = Peer {
+            addr: free_addr(),
+            public_key: key.public_key(),
+        };
+        writeln!(peers_file, "{i} {} {}", peer.addr, hex(&peer.public_key)).unwrap();
+        peers.insert(i, peer);
+    }
+    let peers_path = dir.join("peers");
+    fs::write(&peers_path, peers_file).unwrap();
+
+    let _nodes = Nodes(
+        peers
+            .iter()
+            .map(|(i, peer)| {
+                Command::new(env!("CARGO_BIN_EXE_threshold-bbsp-node"))
+                    .env("THRESHOLD_BBSP_PASSPHRASE", PASSPHRASE)
+                    .arg("run")
+                    .args(["--id", &i.to_string()])
+                    .args(["--listen", &peer.addr.to_string()])
+                    .arg("--identity")
+                    .arg(dir.join(format!("identity_{i}")))
+                    .arg("--params")
+                    .arg(&params)
+                    .arg("--peers")
+                    .arg(&peers_path)
+                    .args(["--coordinator", &hex(&coordinator_key.public_key())])
+                    .arg("--key-share")
+                    .arg(dir.join(format!("key_share_{i}")))
+                    .spawn()
+                    .unwrap()
+            })
+            .collect(),
+    );
+
+    let coordinator = Coordinator::new(cl, coordinator_key, peers);
+    let public_key = coordinator.keygen(t, l).await.unwrap();
+    for i in 1..=n {
+        assert!(dir.join(format!("key_share_{i}")).exists());
+    }
+    assert!(matches!(
+        coordinator.keygen(t, l).await,
+        Err(NodeError::Aborted { .. })
+    ));
+
+    let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut csprng)).collect();
+    for signers in [vec![1, 3], vec![2, 3], vec![1, 2, 3]] {
+        let sig = coordinator.sign(&public_key, &signers, &msg).await.unwrap();
+        BBSPlusSig::verify(&public_key, &msg, l, &sig).unwrap();
+    }
+    assert!(matches!(
+        coordinator.sign(&public_key, &[2], &msg).await,
+        Err(NodeError::Aborted { party: 2, .. })
+    ));
+
+    // Node 3 keeps the ids of the DKG and of its three signing sessions with its
+    // key share.
+    let cl = CL_HSMqk::from_params(&cl_params, &modulus()).unwrap();
+    let state: NodeState =
+        load_key_share(dir.join("key_share_3"), &cl, PASSPHRASE.as_bytes()).unwrap();
+    assert_eq!(state.claimed.len(), 4);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,493 @@
+//! Runs the per-party protocols over a [`SimNetwork`] that reorders messages,
+//! crashes parties and lets byzantine parties tamper with what they send.
+
+use std::collections::BTreeMap;
+
+use bicycl::{CL_HSMqk, Mpz, RandGen};
+use bls12_381::Scalar;
+use curv::{arithmetic::Converter, BigInt};
+use ff::Field;
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+
+use threshold_bbsp::n_out_of_n::setbbsplus as n_setbbsplus;
+use threshold_bbsp::n_out_of_n::wmc24 as n_wmc24;
+use threshold_bbsp::t_out_of_n::setbbsplus::{
+    keygen_party, sign_party, BBSPlusSig, DkgMsg, KeyGen, KeyGenPartyError, KeyShare, PartialSig,
+    Sign, SignMsg, SignPartyError,
+};
+use threshold_bbsp::t_out_of_n::wmc24;
+use threshold_bbsp::{rand_gen, ProtocolError, SimError, SimNetwork, MODULUS};
+
+fn setup() -> (CL_HSMqk, RandGen, ChaChaRng) {
+    let mut csprng = ChaChaRng::from_seed([0u8; 32]);
+    let mut rng = rand_gen(&mut csprng);
+    let cl = CL_HSMqk::with_rand_gen(
+        &Mpz::from_bytes(&BigInt::from_hex(MODULUS).unwrap().to_bytes()),
+        1,
+        1827,
+        &mut rng,
+        &(Mpz::from_bytes(&(BigInt::from(1) << 40).to_bytes())),
+        false,
+    );
+    (cl, rng, csprng)
+}
+
+async fn n_out_of_n_dkg(
+    mut network: SimNetwork<n_setbbsplus::DkgMsg>,
+    cl: &CL_HSMqk,
+    n: usize,
+    l: usize,
+) -> Vec<Result<n_setbbsplus::KeyShare, n_setbbsplus::KeyGenPartyError<SimError, SimError>>> {
+    let mut parties = Vec::with_capacity(n);
+    for i in 0..n {
+        let party = network.add_party();
+        let mut party_csprng = ChaChaRng::from_seed([i as u8 + 1; 32]);
+        let mut party_rng = rand_gen(&mut party_csprng);
+        parties.push(async move {
+            n_setbbsplus::keygen_party(
+                party,
+                i as u16,
+                n as u16,
+                cl,
+                b"n_out_of_n_dkg",
+                l,
+                &mut party_rng,
+                &mut party_csprng,
+            )
+            .await
+        });
+    }
+    network.run(parties).await
+}
+
+async fn t_out_of_n_dkg(
+    mut network: SimNetwork<DkgMsg>,
+    cl: &CL_HSMqk,
+    t: usize,
+    n: usize,
+    l: usize,
+) -> Vec<Result<KeyShare, KeyGenPartyError<SimError, SimError>>> {
+    let mut parties = Vec::with_capacity(n);
+    for i in 0..n {
+        let party = network.add_party();
+        let mut party_csprng = ChaChaRng::from_seed([i as u8 + 1; 32]);
+        let mut party_rng = rand_gen(&mut party_csprng);
+        parties.push(async move {
+            keygen_party(
+                party,
+                i as u16,
+                t as u16,
+                n as u16,
+                cl,
+                b"t_out_of_n_dkg",
+                l,
+                &mut party_rng,
+                &mut party_csprng,
+            )
+            .await
+        });
+    }
+    network.run(parties).await
+}
+
+async fn t_out_of_n_sign(
+    mut network: SimNetwork<SignMsg>,
+    cl: &CL_HSMqk,
+    key_shares: &[KeyShare],
+    signers: &[usize],
+    msg: &[Scalar],
+) -> Vec<Result<PartialSig, SignPartyError<SimError, SimError>>> {
+    let mut parties = Vec::with_capacity(signers.len());
+    for (i, j) in signers.iter().enumerate() {
+        let party = network.add_party();
+        let key_share = &key_shares[j - 1];
+        let mut party_csprng = ChaChaRng::from_seed([i as u8 + 11; 32]);
+        let mut party_rng = rand_gen(&mut party_csprng);
+        parties.push(async move {
+            sign_party(
+                party,
+                i as u16,
+                signers,
+                cl,
+                b"t_out_of_n_sign",
+                key_share,
+                &mut party_rng,
+                &mut party_csprng,
+                msg,
+                msg.len(),
+            )
+            .await
+        });
+    }
+    network.run(parties).await
+}
+
+async fn n_out_of_n_wmc24_sign(
+    mut network: SimNetwork<n_wmc24::SignerMsg>,
+    cl: &CL_HSMqk,
+    keys: &[n_wmc24::SignerKey],
+    msg: &[Scalar],
+) -> Vec<Result<n_wmc24::SignerPartial, n_wmc24::SignerError<SimError, SimError>>> {
+    let n = keys.len();
+    let mut parties = Vec::with_capacity(n);
+    for (i, key) in keys.iter().enumerate() {
+        let party = network.add_party();
+        let mut party_csprng = ChaChaRng::from_seed([i as u8 + 21; 32]);
+        let mut party_rng = rand_gen(&mut party_csprng);
+        parties.push(async move {
+            n_wmc24::signer_party(
+                party,
+                i as u16,
+                n as u16,
+                cl,
+                b"n_out_of_n_wmc24_sign",
+                key,
+                &mut party_rng,
+                &mut party_csprng,
+                msg,
+                msg.len(),
+            )
+            .await
+        });
+    }
+    network.run(parties).await
+}
+
+async fn t_out_of_n_wmc24_sign(
+    mut network: SimNetwork<wmc24::SignerMsg>,
+    cl: &CL_HSMqk,
+    keys: &[wmc24::SignerKey],
+    signers: &[usize],
+    msg: &[Scalar],
+) -> Vec<Result<wmc24::SignerPartial, wmc24::SignerError<SimError, SimError>>> {
+    let mut parties = Vec::with_capacity(signers.len());
+    for (i, j) in signers.iter().enumerate() {
+        let party = network.add_party();
+        let key = &keys[j - 1];
+        let mut party_csprng = ChaChaRng::from_seed([i as u8 + 31; 32]);
+        let mut party_rng = rand_gen(&mut party_csprng);
+        parties.push(async move {
+            wmc24::signer_party(
+                party,
+                i as u16,
+                signers,
+                cl,
+                b"t_out_of_n_wmc24_sign",
+                key,
+                &mut party_rng,
+                &mut party_csprng,
+                msg,
+                msg.len(),
+            )
+            .await
+        });
+    }
+    network.run(parties).await
+}
+
+#[tokio::test]
+async fn test_n_out_of_n_dkg_reordered() {
+    let (cl, _, _) = setup();
+    let network = SimNetwork::new(1).with_delays(16);
+    let key_shares: Vec<_> = n_out_of_n_dkg(network, &cl, 3, 4)
+        .await
+        .into_iter()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    for key_share in &key_shares {
+        assert_eq!(key_share.X, key_shares[0].X);
+        assert_eq!(key_share.x_ciphertext, key_shares[0].x_ciphertext);
+    }
+}
+
+#[tokio::test]
+async fn test_n_out_of_n_dkg_bad_cl_enc_proof() {
+    let (cl, _, _) = setup();
+    let network = SimNetwork::new(2).with_delays(4).tamper(1, |outgoing| {
+        if let n_setbbsplus::DkgMsg::Round3(msg) = &mut outgoing.msg {
+            msg.proof.z1 = msg.proof.z1.clone() + Mpz::from(1u64);
+        }
+    });
+    let outputs = n_out_of_n_dkg(network, &cl, 3, 4).await;
+    for i in [0, 2] {
+        match &outputs[i] {
+            Err(n_setbbsplus::KeyGenPartyError::Protocol(err)) => assert_eq!(
+                err,
+                &ProtocolError::InvalidProof {
+                    proof: "CLEncProof",
+                    culprits: vec![2],
+                }
+            ),
+            output => panic!("party {i} ended with {output:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_t_out_of_n_dkg_reordered() {
+    let (cl, _, _) = setup();
+    let network = SimNetwork::new(3).with_delays(16);
+    let key_shares: Vec<_> = t_out_of_n_dkg(network, &cl, 2, 3, 4)
+        .await
+        .into_iter()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    for key_share in &key_shares {
+        assert_eq!(key_share.X, key_shares[0].X);
+        assert_eq!(key_share.x_ciphertext, key_shares[0].x_ciphertext);
+    }
+}
+
+#[tokio::test]
+async fn test_t_out_of_n_dkg_bad_share() {
+    let (cl, _, _) = setup();
+    let network = SimNetwork::new(4).tamper(0, |outgoing| {
+        if let DkgMsg::Round2P2P(msg) = &mut outgoing.msg {
+            msg.s_ij = msg.s_ij.clone() + Mpz::from(1u64);
+        }
+    });
+    let outputs = t_out_of_n_dkg(network, &cl, 2, 3, 4).await;
+    for i in [1, 2] {
+        match &outputs[i] {
+            Err(KeyGenPartyError::Protocol(err)) => {
+                assert_eq!(err, &ProtocolError::InvalidShare(vec![1]))
+            }
+            output => panic!("party {i} ended with {output:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_t_out_of_n_dkg_crash() {
+    let (cl, _, _) = setup();
+    // Party 3 commits in round 1 and is never heard from again.
+    let network = SimNetwork::new(5).with_delays(4).crash(2, 1);
+    let outputs = t_out_of_n_dkg(network, &cl, 2, 3, 4).await;
+    for i in [0, 1] {
+        assert!(
+            matches!(outputs[i], Err(KeyGenPartyError::Round2Receive(_))),
+            "party {i} ended with {:?}",
+            outputs[i]
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_t_out_of_n_sign() {
+    let (cl, mut rng, mut csprng) = setup();
+    let (n, t, l) = (3, 2, 4);
+    let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut csprng).unwrap();
+    let key_shares: Vec<_> = (1..=n).map(|i| key_msg.key_share(i)).collect();
+    let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut csprng)).collect();
+
+    let signers = [1, 3];
+    let network = SimNetwork::new(6).with_delays(16);
+    let mut partials: BTreeMap<usize, PartialSig> =
+        t_out_of_n_sign(network, &cl, &key_shares, &signers, &msg)
+            .await
+            .into_iter()
+            .zip(signers)
+            .map(|(partial, j)| (j, partial.unwrap()))
+            .collect();
+    let sign_msg = Sign::from_partials(
+        &key_shares[0].H,
+        &key_shares[0].X,
+        &key_shares[0].n_factorial,
+        &partials,
+    )
+    .unwrap();
+    let sig = Sign::client(&cl, &sign_msg, &msg, l).unwrap();
+    BBSPlusSig::verify(&key_shares[0].public_key(), &msg, l, &sig).unwrap();
+
+    // Signer 3 hands the client a wrong partial decryption.
+    let partial: &mut PartialSig = partials.get_mut(&3).unwrap();
+    partial.pd_i = partial.pd_i.compose(&cl, &cl.power_of_h(&Mpz::from(1u64)));
+    let sign_msg = Sign::from_partials(
+        &key_shares[0].H,
+        &key_shares[0].X,
+        &key_shares[0].n_factorial,
+        &partials,
+    )
+    .unwrap();
+    assert_eq!(
+        Sign::client(&cl, &sign_msg, &msg, l),
+        Err(ProtocolError::InvalidSignature)
+    );
+}
+
+#[tokio::test]
+async fn test_t_out_of_n_sign_byzantine() {
+    let (cl, mut rng, mut csprng) = setup();
+    let (n, t, l) = (3, 2, 4);
+    let key_msg = KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut csprng).unwrap();
+    let key_shares: Vec<_> = (1..=n).map(|i| key_msg.key_share(i)).collect();
+    let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut csprng)).collect();
+    let signers = [1, 2, 3];
+
+    let network = SimNetwork::new(7).with_delays(4).tamper(1, |outgoing| {
+        if let SignMsg::Round2(msg) = &mut outgoing.msg {
+            msg.proof.e_1 = msg.proof.e_1.clone() + Mpz::from(1u64);
+        }
+    });
+    let outputs = t_out_of_n_sign(network, &cl, &key_shares, &signers, &msg).await;
+    for i in [0, 2] {
+        match &outputs[i] {
+            Err(SignPartyError::Protocol(err)) => assert_eq!(
+                err,
+                &ProtocolError::InvalidProof {
+                    proof: "CLRandYuanProof",
+                    culprits: vec![2],
+                }
+            ),
+            output => panic!("signer {i} ended with {output:?}"),
+        }
+    }
+
+    // A signer that picks its share of e after seeing the others' can't open its
+    // commitment to it.
+    let network = SimNetwork::new(9).with_delays(4).tamper(2, |outgoing| {
+        if let SignMsg::Round2(msg) = &mut outgoing.msg {
+            msg.e_i += Scalar::one();
+        }
+    });
+    let outputs = t_out_of_n_sign(network, &cl, &key_shares, &signers, &msg).await;
+    for i in [0, 1] {
+        match &outputs[i] {
+            Err(SignPartyError::Protocol(err)) => {
+                assert_eq!(err, &ProtocolError::InvalidCommitment(vec![3]))
+            }
+            output => panic!("signer {i} ended with {output:?}"),
+        }
+    }
+
+    let network = SimNetwork::new(8).with_delays(4).crash(0, 0);
+    let outputs = t_out_of_n_sign(network, &cl, &key_shares, &signers, &msg).await;
+    for i in [1, 2] {
+        assert!(
+            matches!(outputs[i], Err(SignPartyError::Round1Receive(_))),
+            "signer {i} ended with {:?}",
+            outputs[i]
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_n_out_of_n_wmc24_sign() {
+    let (cl, mut rng, mut csprng) = setup();
+    let (n, l) = (3, 4);
+    let key_msg = n_wmc24::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut csprng).unwrap();
+    let keys: Vec<_> = (1..=n).map(|i| key_msg.signer_key(i)).collect();
+    let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut csprng)).collect();
+
+    let network = SimNetwork::new(10).with_delays(16);
+    let partials: BTreeMap<usize, n_wmc24::SignerPartial> =
+        n_out_of_n_wmc24_sign(network, &cl, &keys, &msg)
+            .await
+            .into_iter()
+            .enumerate()
+            .map(|(i, partial)| (i + 1, partial.unwrap()))
+            .collect();
+    let sign_msg = n_wmc24::Sign::from_partials(&partials).unwrap();
+    n_wmc24::Sign::client(&cl, b"n_out_of_n_wmc24_sign", &sign_msg, &key_msg, &msg, l).unwrap();
+}
+
+#[tokio::test]
+async fn test_n_out_of_n_wmc24_sign_byzantine() {
+    let (cl, mut rng, mut csprng) = setup();
+    let (n, l) = (3, 4);
+    let key_msg = n_wmc24::KeyGen::keygen(&cl, b"keygen", n, l, &mut rng, &mut csprng).unwrap();
+    let keys: Vec<_> = (1..=n).map(|i| key_msg.signer_key(i)).collect();
+    let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut csprng)).collect();
+
+    let network = SimNetwork::new(11).with_delays(4).tamper(1, |outgoing| {
+        if let n_wmc24::SignerMsg::Round3(msg) = &mut outgoing.msg {
+            msg.proof.z_1 = msg.proof.z_1.clone() + Mpz::from(1u64);
+        }
+    });
+    let outputs = n_out_of_n_wmc24_sign(network, &cl, &keys, &msg).await;
+    for i in [0, 2] {
+        match &outputs[i] {
+            Err(n_wmc24::SignerError::Protocol(err)) => assert_eq!(
+                err,
+                &ProtocolError::InvalidProof {
+                    proof: "CLELProof",
+                    culprits: vec![2],
+                }
+            ),
+            output => panic!("party {i} ended with {output:?}"),
+        }
+    }
+
+    let network = SimNetwork::new(12).with_delays(4).crash(0, 0);
+    let outputs = n_out_of_n_wmc24_sign(network, &cl, &keys, &msg).await;
+    for i in [1, 2] {
+        assert!(
+            matches!(outputs[i], Err(n_wmc24::SignerError::Round1Receive(_))),
+            "party {i} ended with {:?}",
+            outputs[i]
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_t_out_of_n_wmc24_sign() {
+    let (cl, mut rng, mut csprng) = setup();
+    let (n, t, l) = (3, 2, 4);
+    let key_msg = wmc24::KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut csprng).unwrap();
+    let keys: Vec<_> = (1..=n).map(|i| key_msg.signer_key(i)).collect();
+    let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut csprng)).collect();
+
+    let signers = [1, 3];
+    let network = SimNetwork::new(13).with_delays(16);
+    let partials: BTreeMap<usize, wmc24::SignerPartial> =
+        t_out_of_n_wmc24_sign(network, &cl, &keys, &signers, &msg)
+            .await
+            .into_iter()
+            .zip(signers)
+            .map(|(partial, j)| (j, partial.unwrap()))
+            .collect();
+    let sign_msg = wmc24::Sign::from_partials(&partials).unwrap();
+    wmc24::Sign::client(&cl, b"t_out_of_n_wmc24_sign", &sign_msg, &key_msg, &msg, l).unwrap();
+}
+
+#[tokio::test]
+async fn test_t_out_of_n_wmc24_sign_byzantine() {
+    let (cl, mut rng, mut csprng) = setup();
+    let (n, t, l) = (3, 2, 4);
+    let key_msg = wmc24::KeyGen::keygen(&cl, b"keygen", n, t, l, &mut rng, &mut csprng).unwrap();
+    let keys: Vec<_> = (1..=n).map(|i| key_msg.signer_key(i)).collect();
+    let msg: Vec<Scalar> = (0..l).map(|_| Scalar::random(&mut csprng)).collect();
+    let signers = [1, 2, 3];
+
+    let network = SimNetwork::new(14).with_delays(4).tamper(1, |outgoing| {
+        if let wmc24::SignerMsg::Round1(msg) = &mut outgoing.msg {
+            msg.e_proof.z1 = msg.e_proof.z1.clone() + Mpz::from(1u64);
+        }
+    });
+    let outputs = t_out_of_n_wmc24_sign(network, &cl, &keys, &signers, &msg).await;
+    for i in [0, 2] {
+        match &outputs[i] {
+            Err(wmc24::SignerError::Protocol(err)) => assert_eq!(
+                err,
+                &ProtocolError::InvalidProof {
+                    proof: "CLEncSProof",
+                    culprits: vec![2],
+                }
+            ),
+            output => panic!("signer {i} ended with {output:?}"),
+        }
+    }
+
+    // Signer 3 is gone after round 1, so nobody gets all the partial decryptions
+    // of e and s.
+    let network = SimNetwork::new(15).with_delays(4).crash(2, 1);
+    let outputs = t_out_of_n_wmc24_sign(network, &cl, &keys, &signers, &msg).await;
+    for i in [0, 1] {
+        assert!(
+            matches!(outputs[i], Err(wmc24::SignerError::Round2Receive(_))),
+            "signer {i} ended with {:?}",
+            outputs[i]
+        );
+    }
+}